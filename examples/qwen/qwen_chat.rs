@@ -25,7 +25,7 @@
 //! ```
 
 use anyhow::{Error as E, Result};
-use candle_coreml::{QwenModel, UnifiedModelLoader};
+use candle_coreml::{ConfigOverride, QwenModel, UnifiedModelLoader};
 use clap::Parser;
 use std::io::{self, Write};
 use std::time::Instant;
@@ -72,6 +72,10 @@ struct Args {
     #[arg(long)]
     model_path: Option<String>,
 
+    /// Override a generated config value, e.g. `--config-override shapes.context_length=1024`
+    #[arg(long = "config-override", value_name = "PATH=VALUE")]
+    config_overrides: Vec<String>,
+
     /// Enable single-token mode (like TDD test - predicts one token)
     #[arg(long)]
     single_token: bool,
@@ -226,8 +230,12 @@ fn load_model_with_unified_loader(args: &Args) -> Result<QwenModel> {
     );
 
     // Use the new UnifiedModelLoader
-    let loader = UnifiedModelLoader::new()
+    let mut loader = UnifiedModelLoader::new()
         .map_err(|e| E::msg(format!("Failed to create UnifiedModelLoader: {e}")))?;
+    if !args.config_overrides.is_empty() {
+        loader =
+            loader.with_config_override(ConfigOverride::from_assignments(&args.config_overrides)?);
+    }
 
     let model = loader
         .load_model(&args.model_id)
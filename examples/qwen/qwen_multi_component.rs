@@ -22,7 +22,10 @@
 
 use anyhow::{Error as E, Result};
 use candle_core::{Device, Tensor};
-use candle_coreml::{download_model, Config as CoreMLConfig, CoreMLModel};
+use candle_coreml::{
+    download_model, ChatMessage, ChatTemplate, ChatTemplateOptions, Config as CoreMLConfig,
+    CoreMLModel,
+};
 use clap::Parser;
 use std::io::{self, Write};
 use std::time::Instant;
@@ -59,6 +62,7 @@ struct MultiComponentQwen {
     ffn: CoreMLModel,
     lm_head: CoreMLModel,
     tokenizer: Tokenizer,
+    chat_template: Option<ChatTemplate>,
     device: Device,
     verbose: bool,
     // Add state tracking for autoregressive generation
@@ -110,6 +114,9 @@ impl MultiComponentQwen {
         let tokenizer = Tokenizer::from_file(&tokenizer_path)
             .map_err(|e| E::msg(format!("Failed to load tokenizer: {e}")))?;
 
+        // Prompt format from the model's tokenizer_config.json
+        let chat_template = ChatTemplate::from_model_dir(&cache_dir)?;
+
         // Configure and load embeddings model
         let embeddings_config = CoreMLConfig {
             input_names: vec!["input_ids".to_string()],
@@ -163,6 +170,7 @@ impl MultiComponentQwen {
             ffn,
             lm_head,
             tokenizer,
+            chat_template,
             device,
             verbose: args.verbose,
             ffn_state: Some(ffn_state),
@@ -170,6 +178,19 @@ impl MultiComponentQwen {
         })
     }
 
+    /// Format a user message with the model's chat template
+    fn format_prompt(&self, input: &str) -> Result<String> {
+        match &self.chat_template {
+            Some(template) => Ok(
+                template.render(&[ChatMessage::user(input)], &ChatTemplateOptions::default())?
+            ),
+            // Qwen chat format for models without a template
+            None => Ok(format!(
+                "<|im_start|>user\n{input}<|im_end|>\n<|im_start|>assistant\n"
+            )),
+        }
+    }
+
     fn tokenize(&self, text: &str) -> Result<Vec<i64>> {
         let encoding = self
             .tokenizer
@@ -494,8 +515,14 @@ async fn run_multi_component_chat(args: &Args) -> Result<()> {
             break;
         }
 
-        // Format as chat prompt (using Qwen chat template)
-        let prompt = format!("<|im_start|>user\n{input}<|im_end|>\n<|im_start|>assistant\n");
+        // Format as chat prompt using the model's own chat template
+        let prompt = match model.format_prompt(input) {
+            Ok(prompt) => prompt,
+            Err(e) => {
+                println!("❌ Failed to format prompt: {e}");
+                continue;
+            }
+        };
 
         // Generate response
         match model.generate(&prompt, args.max_tokens, args.temperature) {
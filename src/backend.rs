@@ -0,0 +1,305 @@
+//! Execution backend abstraction for model components
+//!
+//! Multi-component pipelines (`DecoderModel`, `CoreMLPipeline`) drive each component
+//! through the [`InferenceBackend`] trait instead of calling CoreML directly.
+//! `CoreMLModel` is the production implementation; scripted or pure-Candle
+//! executors can implement the same contract so that prefill/infer orchestration,
+//! masks and sampling can be exercised on platforms without CoreML.
+
+use crate::config::basic::Config;
+use crate::{CoreMLModel, CoreMLState};
+use candle_core::{Error as CandleError, Tensor};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Execution contract for a single model component (embeddings, FFN, LM head).
+pub trait InferenceBackend: Sized {
+    /// Per-sequence state (e.g. KV cache) shared across stateful predictions.
+    type State;
+
+    /// Load a component from `path`, optionally selecting a named function
+    /// (e.g. "prefill" / "infer") inside a multi-function package.
+    fn load(path: &Path, config: &Config, function_name: Option<&str>)
+        -> Result<Self, CandleError>;
+
+    /// Component configuration (input order and primary output name).
+    fn config(&self) -> &Config;
+
+    /// Run a stateless prediction and return every named output.
+    fn forward_all(&self, inputs: &[&Tensor]) -> Result<HashMap<String, Tensor>, CandleError>;
+
+    /// Run a stateless prediction and return the configured primary output.
+    fn forward(&self, inputs: &[&Tensor]) -> Result<Tensor, CandleError> {
+        let output_name = &self.config().output_name;
+        self.forward_all(inputs)?
+            .remove(output_name)
+            .ok_or_else(|| {
+                CandleError::Msg(format!(
+                    "Output '{output_name}' not produced by {}",
+                    self.config().model_type
+                ))
+            })
+    }
+
+    /// Create a fresh state object for stateful prediction.
+    fn make_state(&self) -> Result<Self::State, CandleError>;
+
+    /// Run a stateful prediction, updating `state` in place.
+    fn predict_with_state(
+        &self,
+        inputs: &[&Tensor],
+        state: &mut Self::State,
+    ) -> Result<Tensor, CandleError>;
+}
+
+impl InferenceBackend for CoreMLModel {
+    type State = CoreMLState;
+
+    fn load(
+        path: &Path,
+        config: &Config,
+        function_name: Option<&str>,
+    ) -> Result<Self, CandleError> {
+        CoreMLModel::load_from_file_with_function(path, config, function_name)
+    }
+
+    fn config(&self) -> &Config {
+        CoreMLModel::config(self)
+    }
+
+    fn forward_all(&self, inputs: &[&Tensor]) -> Result<HashMap<String, Tensor>, CandleError> {
+        CoreMLModel::forward_all(self, inputs)
+    }
+
+    fn forward(&self, inputs: &[&Tensor]) -> Result<Tensor, CandleError> {
+        CoreMLModel::forward(self, inputs)
+    }
+
+    fn make_state(&self) -> Result<CoreMLState, CandleError> {
+        CoreMLModel::make_state(self)
+    }
+
+    fn predict_with_state(
+        &self,
+        inputs: &[&Tensor],
+        state: &mut CoreMLState,
+    ) -> Result<Tensor, CandleError> {
+        CoreMLModel::predict_with_state(self, inputs, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::model::{ComponentConfig, ModelConfig, TensorConfig};
+    use crate::config::TensorDataType;
+    use crate::utils::sampling;
+    use crate::{CoreMLPipeline, DecoderConfig, DecoderModel};
+    use candle_core::Device;
+    use tokenizers::models::wordlevel::WordLevel;
+    use tokenizers::Tokenizer;
+
+    const HIDDEN: usize = 8;
+    const VOCAB: usize = 5;
+
+    /// Backend that returns fixed-shape outputs and counts stateful calls in its state.
+    struct StubBackend {
+        config: Config,
+    }
+
+    impl InferenceBackend for StubBackend {
+        type State = usize;
+
+        fn load(_: &Path, config: &Config, _: Option<&str>) -> Result<Self, CandleError> {
+            Ok(Self {
+                config: config.clone(),
+            })
+        }
+
+        fn config(&self) -> &Config {
+            &self.config
+        }
+
+        fn forward_all(&self, inputs: &[&Tensor]) -> Result<HashMap<String, Tensor>, CandleError> {
+            let mut outputs = HashMap::new();
+            if self.config.model_type.ends_with("embeddings") {
+                let seq_len = inputs[0].dim(1)?;
+                let hidden =
+                    Tensor::zeros((1, seq_len, HIDDEN), candle_core::DType::F32, &Device::Cpu)?;
+                outputs.insert(self.config.output_name.clone(), hidden);
+            } else {
+                let logits = Tensor::new(&[[[0.1f32, 0.2, 0.3, 0.9, 0.0]]], &Device::Cpu)?;
+                outputs.insert("logits1".to_string(), logits);
+            }
+            Ok(outputs)
+        }
+
+        fn make_state(&self) -> Result<usize, CandleError> {
+            Ok(0)
+        }
+
+        fn predict_with_state(
+            &self,
+            _inputs: &[&Tensor],
+            state: &mut usize,
+        ) -> Result<Tensor, CandleError> {
+            *state += 1;
+            Tensor::zeros((1, 1, HIDDEN), candle_core::DType::F32, &Device::Cpu)
+        }
+    }
+
+    fn tensor(name: &str, shape: &[usize]) -> (String, TensorConfig) {
+        (
+            name.to_string(),
+            TensorConfig {
+                name: name.to_string(),
+                shape: shape.to_vec(),
+                data_type: TensorDataType::Float32,
+                flexible_shape: None,
+            },
+        )
+    }
+
+    fn component(
+        inputs: Vec<(String, TensorConfig)>,
+        outputs: Vec<(String, TensorConfig)>,
+    ) -> ComponentConfig {
+        ComponentConfig {
+            file_path: None,
+            inputs: inputs.into_iter().collect(),
+            outputs: outputs.into_iter().collect(),
+            functions: vec![],
+            input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
+        }
+    }
+
+    fn stub_model() -> DecoderModel<StubBackend> {
+        let mut model_config = ModelConfig::default_qwen();
+        model_config.shapes.batch_size = 4;
+        model_config.shapes.context_length = 16;
+        model_config.shapes.hidden_size = HIDDEN;
+        model_config.shapes.vocab_size = VOCAB;
+        model_config.components.insert(
+            "embeddings".to_string(),
+            component(
+                vec![tensor("input_ids", &[1, 4])],
+                vec![tensor("hidden_states", &[1, 4, HIDDEN])],
+            ),
+        );
+        model_config.components.insert(
+            "ffn_prefill".to_string(),
+            component(
+                vec![
+                    tensor("hidden_states", &[1, 4, HIDDEN]),
+                    tensor("position_ids", &[4]),
+                    tensor("causal_mask", &[1, 1, 4, 16]),
+                    tensor("current_pos", &[1]),
+                ],
+                vec![tensor("output_hidden_states", &[1, 1, HIDDEN])],
+            ),
+        );
+        model_config.components.insert(
+            "lm_head".to_string(),
+            component(
+                vec![tensor("hidden_states", &[1, 1, HIDDEN])],
+                vec![tensor("logits1", &[1, 1, VOCAB])],
+            ),
+        );
+
+        let stub = |model_type: &str, output_name: &str| StubBackend {
+            config: Config {
+                input_names: vec![],
+                output_name: output_name.to_string(),
+                max_sequence_length: 16,
+                vocab_size: VOCAB,
+                model_type: model_type.to_string(),
+            },
+        };
+        let tokenizer = Tokenizer::new(
+            WordLevel::builder()
+                .vocab([("<unk>".to_string(), 0)].into_iter().collect())
+                .unk_token("<unk>".to_string())
+                .build()
+                .unwrap(),
+        );
+
+        DecoderModel::from_components(
+            stub("qwen-embeddings", "hidden_states"),
+            stub("qwen-ffn", "output_hidden_states"),
+            stub("qwen-ffn-infer", "output_hidden_states"),
+            stub("qwen-lm-head", "logits1"),
+            tokenizer,
+            DecoderConfig::from_model_config(model_config),
+        )
+    }
+
+    #[test]
+    fn test_qwen_model_runs_prefill_and_infer_on_custom_backend() {
+        let mut model = stub_model();
+        model.initialize_states().unwrap();
+        assert_eq!(model.unified_state, Some(0));
+
+        let embeddings = model.compute_embeddings(&[1, 2, 3]).unwrap();
+        assert_eq!(embeddings.dims(), &[1, 4, HIDDEN]);
+
+        model.run_prefill_phase(&embeddings, 3).unwrap();
+        let last = embeddings.narrow(1, 2, 1).unwrap();
+        let logits = model.generate_next_token_with_infer(&last, 2).unwrap();
+
+        // One prefill call plus one infer call share the same backend state
+        assert_eq!(model.unified_state, Some(2));
+        let flat = logits.squeeze(0).unwrap().squeeze(0).unwrap();
+        assert_eq!(sampling::greedy_sample(&flat).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_sequential_prefill_plan_rejects_overlong_input() {
+        let model = stub_model();
+        let plan = model.plan_sequential_prefill(16, 4, 0).unwrap();
+        assert_eq!(plan.last_window_start, 12);
+
+        let err = model.plan_sequential_prefill(17, 4, 0).unwrap_err();
+        assert!(
+            err.to_string().contains("exceed the context length of 16"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_decoder_model_from_pipeline() {
+        let parts = stub_model();
+        let tokenizer = parts.tokenizer.clone();
+        let pipeline = CoreMLPipeline::new(parts.config.model_config.clone())
+            .with_loaded_components(
+                Some(parts.embeddings),
+                Some(parts.ffn_prefill),
+                None,
+                Some(parts.lm_head),
+            );
+        let err = DecoderModel::from_pipeline(pipeline, tokenizer.clone()).unwrap_err();
+        assert!(err.to_string().contains("ffn_infer"));
+
+        let parts = stub_model();
+        let pipeline = CoreMLPipeline::new(parts.config.model_config.clone())
+            .with_loaded_components(
+                Some(parts.embeddings),
+                Some(parts.ffn_prefill),
+                Some(parts.ffn_infer),
+                Some(parts.lm_head),
+            );
+        let mut model = DecoderModel::from_pipeline(pipeline, tokenizer).unwrap();
+        model.initialize_states().unwrap();
+        let embeddings = model.compute_embeddings(&[1, 2, 3]).unwrap();
+        assert_eq!(embeddings.dims(), &[1, 4, HIDDEN]);
+    }
+
+    #[test]
+    fn test_default_forward_selects_configured_output() {
+        let model = stub_model();
+        let input = Tensor::zeros((1, 4), candle_core::DType::I64, &Device::Cpu).unwrap();
+        let hidden = model.embeddings.forward(&[&input]).unwrap();
+        assert_eq!(hidden.dims(), &[1, 4, HIDDEN]);
+    }
+}
@@ -47,7 +47,15 @@ pub struct CacheManager {
 impl CacheManager {
     /// Create a new cache manager with default settings
     pub fn new() -> Result<Self> {
-        let cache_base = Self::default_cache_dir()?;
+        Self::with_cache_base(Self::default_cache_dir()?)
+    }
+
+    /// Create a cache manager rooted at `cache_base` instead of the user cache directory
+    ///
+    /// Only `cache_base` and its subdirectories are created, which keeps tests and build
+    /// scripts out of `~/.cache`.
+    pub fn with_cache_base<P: Into<PathBuf>>(cache_base: P) -> Result<Self> {
+        let cache_base = cache_base.into();
         let bundle_id = Self::get_current_bundle_identifier();
 
         std::fs::create_dir_all(&cache_base)?;
@@ -350,7 +358,7 @@ Generated by candle-coreml v{} at {}
         }
 
         // Sort by size (largest first)
-        caches.sort_by(|a, b| b.1.cmp(&a.1));
+        caches.sort_by_key(|b| std::cmp::Reverse(b.1));
 
         Ok(caches)
     }
@@ -489,6 +497,17 @@ mod tests {
         println!("Bundle ID: {:?}", manager.bundle_identifier());
     }
 
+    #[test]
+    fn test_cache_manager_with_cache_base() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let base = temp.path().join("cache");
+        let manager = CacheManager::with_cache_base(&base).unwrap();
+
+        assert_eq!(manager.cache_base(), base);
+        assert!(manager.configs_dir().starts_with(&base));
+        assert!(manager.configs_dir().is_dir());
+    }
+
     #[test]
     fn test_coreml_cache_location_detection() {
         let manager = CacheManager::new().expect("Failed to create cache manager");
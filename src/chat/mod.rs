@@ -0,0 +1,670 @@
+//! Chat prompt formatting from the model's own chat template
+//!
+//! Hugging Face models ship a Jinja `chat_template` in `tokenizer_config.json` (or a
+//! standalone `chat_template.jinja`) that turns role/content messages into the exact
+//! prompt format the model was trained on. [`ChatTemplate`] loads and renders that
+//! template, so each model family gets its own format instead of a hand-written
+//! `<|im_start|>` string. Qwen3's `enable_thinking` switch is passed through
+//! [`ChatTemplateOptions`]. [`ChatSession`] runs a multi-turn conversation on one model,
+//! reusing the KV cache of the turns that were already processed.
+
+pub mod session;
+
+use candle_core::Error as CandleError;
+use minijinja::value::{Kwargs, ValueKind};
+use minijinja::{Environment, Error as TemplateError, ErrorKind, Value};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::debug;
+
+pub use session::ChatSession;
+
+/// A single chat turn.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// `system`, `user`, `assistant` or `tool`
+    pub role: String,
+    pub content: String,
+    /// Reasoning behind an assistant reply, rendered by templates that support it
+    /// (Qwen3 only shows it for the turns after the latest user message)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn new(role: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: role.into(),
+            content: content.into(),
+            reasoning_content: None,
+        }
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new("system", content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new("user", content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::new("assistant", content)
+    }
+
+    /// Attach the reasoning that led to this message
+    pub fn with_reasoning_content(mut self, reasoning: impl Into<String>) -> Self {
+        self.reasoning_content = Some(reasoning.into());
+        self
+    }
+}
+
+/// Variables passed to the chat template besides the messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatTemplateOptions {
+    /// Append the header that starts the assistant's reply
+    pub add_generation_prompt: bool,
+    /// Qwen3 thinking switch (None leaves it undefined, i.e. the template's default)
+    pub enable_thinking: Option<bool>,
+    /// Tool definitions (JSON schema objects) exposed to templates that support them
+    pub tools: Option<Vec<JsonValue>>,
+    /// Additional template variables
+    pub extra: HashMap<String, JsonValue>,
+}
+
+impl Default for ChatTemplateOptions {
+    fn default() -> Self {
+        Self {
+            add_generation_prompt: true,
+            enable_thinking: None,
+            tools: None,
+            extra: HashMap::new(),
+        }
+    }
+}
+
+impl ChatTemplateOptions {
+    /// Set whether the assistant generation header is appended
+    pub fn with_add_generation_prompt(mut self, add_generation_prompt: bool) -> Self {
+        self.add_generation_prompt = add_generation_prompt;
+        self
+    }
+
+    /// Set Qwen3's `enable_thinking` flag
+    pub fn with_enable_thinking(mut self, enable_thinking: bool) -> Self {
+        self.enable_thinking = Some(enable_thinking);
+        self
+    }
+
+    /// Set the tool definitions
+    pub fn with_tools(mut self, tools: Vec<JsonValue>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    /// Set an additional template variable
+    pub fn with_extra(mut self, name: impl Into<String>, value: JsonValue) -> Self {
+        self.extra.insert(name.into(), value);
+        self
+    }
+}
+
+/// Name the template source is registered under in its environment
+const TEMPLATE_NAME: &str = "chat_template";
+
+/// A parsed chat template together with the special tokens it refers to.
+///
+/// Templates run on `minijinja` configured like `transformers` renders them: `trim_blocks`
+/// and `lstrip_blocks`, loop controls, Python string/dict methods, a `tojson` that matches
+/// `json.dumps`, and the `raise_exception`/`strftime_now` globals.
+#[derive(Debug, Clone)]
+pub struct ChatTemplate {
+    source: String,
+    env: Environment<'static>,
+    /// Value of `bos_token` inside the template
+    pub bos_token: Option<String>,
+    /// Value of `eos_token` inside the template
+    pub eos_token: Option<String>,
+}
+
+impl ChatTemplate {
+    /// Parse a Jinja chat template.
+    pub fn new(source: impl Into<String>) -> Result<Self, CandleError> {
+        let source = source.into();
+        let mut env = template_environment();
+        env.add_template_owned(TEMPLATE_NAME, source.clone())
+            .map_err(template_error)?;
+        Ok(Self {
+            source,
+            env,
+            bos_token: None,
+            eos_token: None,
+        })
+    }
+
+    /// Load the template declared in a `tokenizer_config.json` file.
+    ///
+    /// `chat_template` may be a string or a list of named templates, in which case the
+    /// one named `default` is used.
+    pub fn from_tokenizer_config<P: AsRef<Path>>(path: P) -> Result<Self, CandleError> {
+        let path = path.as_ref();
+        let json = read_json(path)?;
+        let source = template_source(&json["chat_template"]).ok_or_else(|| {
+            CandleError::Msg(format!("No chat_template found in {}", path.display()))
+        })?;
+        Ok(Self::new(source)?.with_special_tokens(&json))
+    }
+
+    /// Load the chat template shipped with a model directory.
+    ///
+    /// A standalone `chat_template.jinja` takes precedence over the `chat_template` entry
+    /// of `tokenizer_config.json`. Returns `Ok(None)` when the model declares no template.
+    pub fn from_model_dir<P: AsRef<Path>>(model_dir: P) -> Result<Option<Self>, CandleError> {
+        let model_dir = model_dir.as_ref();
+        let config_path = model_dir.join("tokenizer_config.json");
+        let config = if config_path.exists() {
+            read_json(&config_path)?
+        } else {
+            JsonValue::Null
+        };
+
+        let jinja_path = model_dir.join("chat_template.jinja");
+        let source = if jinja_path.exists() {
+            std::fs::read_to_string(&jinja_path).map_err(|e| {
+                CandleError::Msg(format!("Failed to read {}: {e}", jinja_path.display()))
+            })?
+        } else {
+            match template_source(&config["chat_template"]) {
+                Some(source) => source,
+                None => return Ok(None),
+            }
+        };
+        debug!("Loaded chat template from {}", model_dir.display());
+        Ok(Some(Self::new(source)?.with_special_tokens(&config)))
+    }
+
+    /// The template's Jinja source
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Render messages into a prompt string.
+    pub fn render(
+        &self,
+        messages: &[ChatMessage],
+        options: &ChatTemplateOptions,
+    ) -> Result<String, CandleError> {
+        let mut globals: HashMap<String, Value> = options
+            .extra
+            .iter()
+            .map(|(name, value)| (name.clone(), Value::from_serialize(value)))
+            .collect();
+        globals.insert("messages".to_string(), Value::from_serialize(messages));
+        globals.insert(
+            "add_generation_prompt".to_string(),
+            Value::from(options.add_generation_prompt),
+        );
+        if let Some(enable_thinking) = options.enable_thinking {
+            globals.insert("enable_thinking".to_string(), Value::from(enable_thinking));
+        }
+        if let Some(tools) = &options.tools {
+            globals.insert("tools".to_string(), Value::from_serialize(tools));
+        }
+        for (name, token) in [
+            ("bos_token", &self.bos_token),
+            ("eos_token", &self.eos_token),
+        ] {
+            globals.insert(
+                name.to_string(),
+                Value::from(token.clone().unwrap_or_default()),
+            );
+        }
+        self.env
+            .get_template(TEMPLATE_NAME)
+            .and_then(|template| template.render(globals))
+            .map_err(template_error)
+    }
+
+    fn with_special_tokens(mut self, tokenizer_config: &JsonValue) -> Self {
+        self.bos_token = special_token(&tokenizer_config["bos_token"]);
+        self.eos_token = special_token(&tokenizer_config["eos_token"]);
+        self
+    }
+}
+
+fn template_environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.set_unknown_method_callback(minijinja_contrib::pycompat::unknown_method_callback);
+    env.set_formatter(python_formatter);
+    env.add_filter("tojson", tojson);
+    env.add_function("raise_exception", raise_exception);
+    env.add_function("strftime_now", strftime_now);
+    env
+}
+
+fn template_error(e: TemplateError) -> CandleError {
+    CandleError::Msg(format!("Chat template error: {e:#}"))
+}
+
+/// Print booleans and `none` the way Python does (`True`, `None`)
+fn python_formatter(
+    out: &mut minijinja::Output,
+    state: &minijinja::State,
+    value: &Value,
+) -> Result<(), TemplateError> {
+    let text = match value.kind() {
+        ValueKind::Bool if value.is_true() => "True",
+        ValueKind::Bool => "False",
+        ValueKind::None => "None",
+        _ => return minijinja::escape_formatter(out, state, value),
+    };
+    std::fmt::Write::write_str(out, text)
+        .map_err(|e| TemplateError::new(ErrorKind::WriteFailure, e.to_string()))
+}
+
+fn raise_exception(message: String) -> Result<Value, TemplateError> {
+    Err(TemplateError::new(ErrorKind::InvalidOperation, message))
+}
+
+fn strftime_now(format: String) -> Result<String, TemplateError> {
+    use std::fmt::Write;
+    let mut text = String::new();
+    write!(text, "{}", chrono::Local::now().format(&format)).map_err(|_| {
+        TemplateError::new(
+            ErrorKind::InvalidOperation,
+            format!("invalid strftime format {format:?}"),
+        )
+    })?;
+    Ok(text)
+}
+
+/// `json.dumps(value, ensure_ascii=False, indent=indent)`, as `transformers` defines `tojson`
+fn tojson(value: Value, kwargs: Kwargs) -> Result<Value, TemplateError> {
+    let indent: Option<usize> = kwargs.get("indent")?;
+    kwargs.assert_all_used()?;
+    let value = serde_json::to_value(&value)
+        .map_err(|e| TemplateError::new(ErrorKind::BadSerialization, e.to_string()))?;
+    let mut out = Vec::new();
+    let written = match indent {
+        Some(width) => {
+            let indent = vec![b' '; width];
+            let formatter = serde_json::ser::PrettyFormatter::with_indent(&indent);
+            value.serialize(&mut serde_json::Serializer::with_formatter(
+                &mut out, formatter,
+            ))
+        }
+        None => value.serialize(&mut serde_json::Serializer::with_formatter(
+            &mut out,
+            PythonJsonFormatter,
+        )),
+    };
+    written.map_err(|e| TemplateError::new(ErrorKind::BadSerialization, e.to_string()))?;
+    Ok(Value::from_safe_string(
+        String::from_utf8(out).expect("serde_json writes UTF-8"),
+    ))
+}
+
+/// Python's default `json.dumps` separators (`", "` and `": "`)
+struct PythonJsonFormatter;
+
+impl serde_json::ser::Formatter for PythonJsonFormatter {
+    fn begin_array_value<W: ?Sized + std::io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+
+    fn begin_object_key<W: ?Sized + std::io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+
+    fn begin_object_value<W: ?Sized + std::io::Write>(
+        &mut self,
+        writer: &mut W,
+    ) -> std::io::Result<()> {
+        writer.write_all(b": ")
+    }
+}
+
+fn read_json(path: &Path) -> Result<JsonValue, CandleError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| CandleError::Msg(format!("Failed to read {}: {e}", path.display())))?;
+    serde_json::from_str(&text)
+        .map_err(|e| CandleError::Msg(format!("Failed to parse {}: {e}", path.display())))
+}
+
+fn template_source(value: &JsonValue) -> Option<String> {
+    match value {
+        JsonValue::String(source) => Some(source.clone()),
+        JsonValue::Array(templates) => templates
+            .iter()
+            .find(|t| t["name"] == "default")
+            .and_then(|t| t["template"].as_str())
+            .map(String::from),
+        _ => None,
+    }
+}
+
+fn special_token(value: &JsonValue) -> Option<String> {
+    match value {
+        JsonValue::String(token) => Some(token.clone()),
+        JsonValue::Object(token) => token
+            .get("content")
+            .and_then(JsonValue::as_str)
+            .map(String::from),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Qwen3's chat template as shipped in `tokenizer_config.json` (tool handling trimmed).
+    const QWEN3_TEMPLATE: &str = r#"{%- if messages[0].role == 'system' %}
+    {{- '<|im_start|>system\n' + messages[0].content + '<|im_end|>\n' }}
+{%- endif %}
+{%- set ns = namespace(multi_step_tool=true, last_query_index=messages|length - 1) %}
+{%- for message in messages[::-1] %}
+    {%- set index = (messages|length - 1) - loop.index0 %}
+    {%- if ns.multi_step_tool and message.role == "user" and message.content is string and not(message.content.startswith('<tool_response>') and message.content.endswith('</tool_response>')) %}
+        {%- set ns.multi_step_tool = false %}
+        {%- set ns.last_query_index = index %}
+    {%- endif %}
+{%- endfor %}
+{%- for message in messages %}
+    {%- if message.content is string %}
+        {%- set content = message.content %}
+    {%- else %}
+        {%- set content = '' %}
+    {%- endif %}
+    {%- if (message.role == "user") or (message.role == "system" and not loop.first) %}
+        {{- '<|im_start|>' + message.role + '\n' + content + '<|im_end|>' + '\n' }}
+    {%- elif message.role == "assistant" %}
+        {%- set reasoning_content = '' %}
+        {%- if message.reasoning_content is string %}
+            {%- set reasoning_content = message.reasoning_content %}
+        {%- else %}
+            {%- if '</think>' in content %}
+                {%- set reasoning_content = content.split('</think>')[0].rstrip('\n').split('<think>')[-1].lstrip('\n') %}
+                {%- set content = content.split('</think>')[-1].lstrip('\n') %}
+            {%- endif %}
+        {%- endif %}
+        {%- if loop.index0 > ns.last_query_index %}
+            {%- if loop.last or (not loop.last and reasoning_content) %}
+                {{- '<|im_start|>' + message.role + '\n<think>\n' + reasoning_content.strip('\n') + '\n</think>\n\n' + content.lstrip('\n') }}
+            {%- else %}
+                {{- '<|im_start|>' + message.role + '\n' + content }}
+            {%- endif %}
+        {%- else %}
+            {{- '<|im_start|>' + message.role + '\n' + content }}
+        {%- endif %}
+        {{- '<|im_end|>\n' }}
+    {%- endif %}
+{%- endfor %}
+{%- if add_generation_prompt %}
+    {{- '<|im_start|>assistant\n' }}
+    {%- if enable_thinking is defined and enable_thinking is false %}
+        {{- '<think>\n\n</think>\n\n' }}
+    {%- endif %}
+{%- endif %}"#;
+
+    #[test]
+    fn test_qwen3_generation_prompt_and_thinking_switch() {
+        let template = ChatTemplate::new(QWEN3_TEMPLATE).unwrap();
+        let messages = [
+            ChatMessage::system("You fix typos."),
+            ChatMessage::user("teh cat"),
+        ];
+
+        let prompt = template
+            .render(&messages, &ChatTemplateOptions::default())
+            .unwrap();
+        assert_eq!(
+            prompt,
+            "<|im_start|>system\nYou fix typos.<|im_end|>\n\
+             <|im_start|>user\nteh cat<|im_end|>\n\
+             <|im_start|>assistant\n"
+        );
+
+        let options = ChatTemplateOptions::default().with_enable_thinking(false);
+        let prompt = template.render(&messages, &options).unwrap();
+        assert!(prompt.ends_with("<|im_start|>assistant\n<think>\n\n</think>\n\n"));
+
+        let options = ChatTemplateOptions::default().with_add_generation_prompt(false);
+        let prompt = template.render(&messages, &options).unwrap();
+        assert!(prompt.ends_with("teh cat<|im_end|>\n"));
+    }
+
+    #[test]
+    fn test_qwen3_strips_reasoning_from_earlier_turns() {
+        let template = ChatTemplate::new(QWEN3_TEMPLATE).unwrap();
+        let messages = [
+            ChatMessage::user("hi"),
+            ChatMessage::assistant("<think>\nbe polite\n</think>\n\nhello"),
+            ChatMessage::user("bye"),
+        ];
+        let prompt = template
+            .render(&messages, &ChatTemplateOptions::default())
+            .unwrap();
+        assert_eq!(
+            prompt,
+            "<|im_start|>user\nhi<|im_end|>\n\
+             <|im_start|>assistant\nhello<|im_end|>\n\
+             <|im_start|>user\nbye<|im_end|>\n\
+             <|im_start|>assistant\n"
+        );
+
+        // Structured reasoning is shown for the turn after the latest user message
+        let messages = [
+            ChatMessage::user("hi"),
+            ChatMessage::assistant("hello").with_reasoning_content("be polite"),
+        ];
+        let options = ChatTemplateOptions::default().with_add_generation_prompt(false);
+        let prompt = template.render(&messages, &options).unwrap();
+        assert!(prompt
+            .ends_with("<|im_start|>assistant\n<think>\nbe polite\n</think>\n\nhello<|im_end|>\n"));
+    }
+
+    #[test]
+    fn test_from_model_dir_reads_tokenizer_config() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(ChatTemplate::from_model_dir(dir.path()).unwrap().is_none());
+
+        let config = serde_json::json!({
+            "bos_token": {"content": "<s>"},
+            "eos_token": "</s>",
+            "chat_template": [
+                {"name": "tool_use", "template": "unused"},
+                {"name": "default", "template": "{{ bos_token }}{% for m in messages %}[{{ m.role }}] {{ m.content }}{{ eos_token }}{% endfor %}"}
+            ]
+        });
+        std::fs::write(dir.path().join("tokenizer_config.json"), config.to_string()).unwrap();
+        let template = ChatTemplate::from_model_dir(dir.path()).unwrap().unwrap();
+        let prompt = template
+            .render(&[ChatMessage::user("hi")], &ChatTemplateOptions::default())
+            .unwrap();
+        assert_eq!(prompt, "<s>[user] hi</s>");
+    }
+
+    /// Llama 3.2's chat template as shipped in `tokenizer_config.json` (tool calls trimmed).
+    const LLAMA32_TEMPLATE: &str = r#"{{- bos_token }}
+{%- if custom_tools is defined %}
+    {%- set tools = custom_tools %}
+{%- endif %}
+{%- if not tools_in_user_message is defined %}
+    {%- set tools_in_user_message = true %}
+{%- endif %}
+{%- if not date_string is defined %}
+    {%- if strftime_now is defined %}
+        {%- set date_string = strftime_now("%d %b %Y") %}
+    {%- else %}
+        {%- set date_string = "26 Jul 2024" %}
+    {%- endif %}
+{%- endif %}
+{%- if not tools is defined %}
+    {%- set tools = none %}
+{%- endif %}
+
+{#- This block extracts the system message, so we can slot it into the right place. #}
+{%- if messages[0]['role'] == 'system' %}
+    {%- set system_message = messages[0]['content']|trim %}
+    {%- set messages = messages[1:] %}
+{%- else %}
+    {%- set system_message = "" %}
+{%- endif %}
+
+{#- System message #}
+{{- "<|start_header_id|>system<|end_header_id|>\n\n" }}
+{%- if tools is not none %}
+    {{- "Environment: ipython\n" }}
+{%- endif %}
+{{- "Cutting Knowledge Date: December 2023\n" }}
+{{- "Today Date: " + date_string + "\n\n" }}
+{%- if tools is not none and not tools_in_user_message %}
+    {{- "You have access to the following functions. To call a function, please respond with JSON for a function call." }}
+    {{- 'Respond in the format {"name": function name, "parameters": dictionary of argument name and its value}.' }}
+    {{- "Do not use variables.\n\n" }}
+    {%- for t in tools %}
+        {{- t | tojson(indent=4) }}
+        {{- "\n\n" }}
+    {%- endfor %}
+{%- endif %}
+{{- system_message }}
+{{- "<|eot_id|>" }}
+
+{%- for message in messages %}
+    {%- if not (message.role == 'ipython' or message.role == 'tool' or 'tool_calls' in message) %}
+        {{- '<|start_header_id|>' + message['role'] + '<|end_header_id|>\n\n'+ message['content'] | trim + '<|eot_id|>' }}
+    {%- endif %}
+{%- endfor %}
+{%- if add_generation_prompt %}
+    {{- '<|start_header_id|>assistant<|end_header_id|>\n\n' }}
+{%- endif %}
+"#;
+
+    /// Gemma 2's chat template as shipped in `tokenizer_config.json`.
+    const GEMMA2_TEMPLATE: &str = "{{ bos_token }}{% if messages[0]['role'] == 'system' %}{{ raise_exception('System role not supported') }}{% endif %}{% for message in messages %}{% if (message['role'] == 'user') != (loop.index0 % 2 == 0) %}{{ raise_exception('Conversation roles must alternate user/assistant/user/assistant/...') }}{% endif %}{% if (message['role'] == 'assistant') %}{% set role = 'model' %}{% else %}{% set role = message['role'] %}{% endif %}{{ '<start_of_turn>' + role + '\n' + message['content'] | trim + '<end_of_turn>\n' }}{% endfor %}{% if add_generation_prompt %}{{'<start_of_turn>model\n'}}{% endif %}";
+
+    #[test]
+    fn test_llama32_template_matches_transformers() {
+        let mut template = ChatTemplate::new(LLAMA32_TEMPLATE).unwrap();
+        template.bos_token = Some("<|begin_of_text|>".to_string());
+        let messages = [
+            ChatMessage::system("You are helpful. "),
+            ChatMessage::user("Hi"),
+        ];
+
+        let options = ChatTemplateOptions::default()
+            .with_extra("date_string", JsonValue::from("26 Jul 2024"));
+        assert_eq!(
+            template.render(&messages, &options).unwrap(),
+            "<|begin_of_text|><|start_header_id|>system<|end_header_id|>\n\n\
+             Cutting Knowledge Date: December 2023\nToday Date: 26 Jul 2024\n\n\
+             You are helpful.<|eot_id|>\
+             <|start_header_id|>user<|end_header_id|>\n\nHi<|eot_id|>\
+             <|start_header_id|>assistant<|end_header_id|>\n\n"
+        );
+
+        // Without a date_string the template asks strftime_now for today's date
+        let today = chrono::Local::now().format("%d %b %Y").to_string();
+        let prompt = template
+            .render(&messages, &ChatTemplateOptions::default())
+            .unwrap();
+        assert!(
+            prompt.contains(&format!("Today Date: {today}\n")),
+            "{prompt}"
+        );
+    }
+
+    #[test]
+    fn test_gemma2_template_matches_transformers() {
+        let mut template = ChatTemplate::new(GEMMA2_TEMPLATE).unwrap();
+        template.bos_token = Some("<bos>".to_string());
+        let messages = [
+            ChatMessage::user("Hi "),
+            ChatMessage::assistant("Hello"),
+            ChatMessage::user("Bye"),
+        ];
+        assert_eq!(
+            template
+                .render(&messages, &ChatTemplateOptions::default())
+                .unwrap(),
+            "<bos><start_of_turn>user\nHi<end_of_turn>\n\
+             <start_of_turn>model\nHello<end_of_turn>\n\
+             <start_of_turn>user\nBye<end_of_turn>\n\
+             <start_of_turn>model\n"
+        );
+
+        let err = template
+            .render(
+                &[ChatMessage::system("no"), ChatMessage::user("Hi")],
+                &ChatTemplateOptions::default(),
+            )
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("System role not supported"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_tojson_macros_and_python_values() {
+        let render = |source: &str, options: &ChatTemplateOptions| {
+            ChatTemplate::new(source)
+                .unwrap()
+                .render(&[], options)
+                .unwrap()
+        };
+        let tool = serde_json::json!({"name": "f", "args": [1, 2.5, null], "note": "<é>"});
+        let options = ChatTemplateOptions::default().with_tools(vec![tool]);
+        assert_eq!(
+            render("{{ tools[0]|tojson }}", &options),
+            r#"{"args": [1, 2.5, null], "name": "f", "note": "<é>"}"#
+        );
+        assert_eq!(
+            render("{{ tools[0].args|tojson(indent=2) }}", &options),
+            "[\n  1,\n  2.5,\n  null\n]"
+        );
+        assert_eq!(
+            render(
+                "{% macro turn(role) %}<{{ role|upper }}>{% endmacro %}{{ turn('user') }}",
+                &options
+            ),
+            "<USER>"
+        );
+        assert_eq!(
+            render(
+                "{{ 'b' in ['a', 'b'] }} {{ none }} {{ ' x '.strip() }}{{ 'a,b'.split(',')[-1] }}",
+                &options
+            ),
+            "True None xb"
+        );
+        assert_eq!(
+            render(
+                "{% for x in [1, 2, 3] %}{% if x == 2 %}{% continue %}{% endif %}{{ x }}{% endfor %}",
+                &options
+            ),
+            "13"
+        );
+        assert!(ChatTemplate::new("{% if x %}unterminated").is_err());
+    }
+}
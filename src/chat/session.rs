@@ -0,0 +1,215 @@
+//! Multi-turn chat on top of a single model state
+//!
+//! [`ChatSession`] keeps the conversation history and the model's KV cache together.
+//! Each turn re-renders the whole conversation with the chat template, but only the
+//! tokens past the prefix already in the cache are prefilled, so a reply costs the new
+//! turn rather than the whole history. Replies are stored without their `<think>` block
+//! unless `keep_thinking` is set.
+
+use crate::backend::InferenceBackend;
+use crate::chat::{ChatMessage, ChatTemplateOptions};
+use crate::decoder::DecoderModel;
+use crate::generation::{split_thinking, GenerationConfig, GenerationOutput, OverflowPolicy};
+use crate::model::CoreMLModel;
+use candle_core::Error as CandleError;
+use tracing::debug;
+
+/// A conversation that owns its model and reuses the KV cache across turns.
+///
+/// # Example
+/// ```rust,ignore
+/// let model = DecoderModel::load_from_directory("models/qwen3", None)?;
+/// let mut session = ChatSession::new(model).with_system_prompt("You are terse.");
+/// let reply = session.send("What is the capital of France?")?;
+/// let follow_up = session.send("And of Spain?")?; // only this turn is prefilled
+/// ```
+pub struct ChatSession<B: InferenceBackend = CoreMLModel> {
+    model: DecoderModel<B>,
+    messages: Vec<ChatMessage>,
+    /// Template variables used for every turn
+    pub options: ChatTemplateOptions,
+    /// Sampling and stop settings used for every reply
+    pub generation_config: GenerationConfig,
+    /// Drop the oldest turns when the conversation outgrows the context window
+    /// (otherwise `send` returns an error)
+    pub truncate_history: bool,
+    /// Keep each reply's reasoning in the history (as `reasoning_content`) instead of
+    /// dropping it; Qwen3 templates still omit it for turns before the latest user message
+    pub keep_thinking: bool,
+}
+
+impl<B: InferenceBackend> std::fmt::Debug for ChatSession<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChatSession")
+            .field("messages", &self.messages)
+            .field("cached_tokens", &self.model.kv_cache_tokens.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B: InferenceBackend> ChatSession<B> {
+    /// Start an empty conversation using the model's chat template and generation config.
+    pub fn new(model: DecoderModel<B>) -> Self {
+        let generation_config = model.generation_config.clone();
+        Self {
+            model,
+            messages: Vec::new(),
+            options: ChatTemplateOptions::default(),
+            generation_config,
+            truncate_history: true,
+            keep_thinking: false,
+        }
+    }
+
+    /// Set (or replace) the system prompt
+    pub fn with_system_prompt(mut self, content: impl Into<String>) -> Self {
+        self.messages.retain(|m| m.role != "system");
+        self.messages.insert(0, ChatMessage::system(content));
+        self
+    }
+
+    /// Set the template variables
+    pub fn with_options(mut self, options: ChatTemplateOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set the generation settings
+    pub fn with_generation_config(mut self, generation_config: GenerationConfig) -> Self {
+        self.generation_config = generation_config;
+        self
+    }
+
+    /// Set whether old turns are dropped when the context window is full
+    pub fn with_history_truncation(mut self, truncate_history: bool) -> Self {
+        self.truncate_history = truncate_history;
+        self
+    }
+
+    /// Set whether replies keep their reasoning in the history
+    pub fn with_keep_thinking(mut self, keep_thinking: bool) -> Self {
+        self.keep_thinking = keep_thinking;
+        self
+    }
+
+    /// Conversation so far
+    pub fn messages(&self) -> &[ChatMessage] {
+        &self.messages
+    }
+
+    /// Number of conversation tokens currently held in the KV cache
+    pub fn cached_tokens(&self) -> usize {
+        self.model.kv_cache_tokens.len()
+    }
+
+    pub fn model(&self) -> &DecoderModel<B> {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut DecoderModel<B> {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> DecoderModel<B> {
+        self.model
+    }
+
+    /// Forget every turn except the system prompt.
+    ///
+    /// The KV cache is kept, so the system prompt is not prefilled again.
+    pub fn clear(&mut self) {
+        self.messages.retain(|m| m.role == "system");
+    }
+
+    /// Add a user turn and generate the assistant's reply, which is appended to the history.
+    ///
+    /// On error the history is restored as it was before the call, including any turns
+    /// dropped to fit the context window.
+    pub fn send(&mut self, content: impl Into<String>) -> Result<GenerationOutput, CandleError> {
+        let history = self.messages.clone();
+        self.messages.push(ChatMessage::user(content));
+        let result = self.fit_prompt().and_then(|prompt_tokens| {
+            self.model
+                .continue_from_tokens(&prompt_tokens, self.generation_config.clone())
+        });
+        match result {
+            Ok(output) => {
+                let (reasoning, answer) = match &output.reasoning {
+                    Some(reasoning) => (Some(reasoning.clone()), output.text.clone()),
+                    None => split_thinking(&output.text),
+                };
+                let mut reply = ChatMessage::assistant(answer);
+                if let Some(reasoning) = reasoning.filter(|_| self.keep_thinking) {
+                    reply = reply.with_reasoning_content(reasoning);
+                }
+                self.messages.push(reply);
+                Ok(output)
+            }
+            Err(e) => {
+                self.messages = history;
+                Err(e)
+            }
+        }
+    }
+
+    /// Tokenize the conversation so that the prompt plus the reply budget
+    /// (`max_new_tokens`) fits the context window.
+    ///
+    /// Whole turns are dropped first (oldest first) when `truncate_history` is set; a
+    /// prompt that still does not fit is handed to `generation_config.overflow_policy`.
+    fn fit_prompt(&mut self) -> Result<Vec<i64>, CandleError> {
+        let context_length = self.model.config.context_length();
+        let reply_budget = self.generation_config.max_new_tokens;
+        let limit = context_length.saturating_sub(reply_budget);
+        loop {
+            let prompt = self
+                .model
+                .apply_chat_template(&self.messages, &self.options)?;
+            let prompt_tokens = self.model.encode(&prompt, false)?;
+            if prompt_tokens.len() <= limit {
+                return Ok(prompt_tokens);
+            }
+            if self.truncate_history && self.drop_oldest_turn() {
+                continue;
+            }
+            if self.generation_config.overflow_policy != OverflowPolicy::Error && limit > 0 {
+                return self
+                    .generation_config
+                    .overflow_policy
+                    .fit(&prompt_tokens, limit);
+            }
+            return Err(CandleError::Msg(format!(
+                "Conversation does not fit the context window: {} prompt tokens + {} reply \
+                 tokens (max_new_tokens) exceeds context length {}{}",
+                prompt_tokens.len(),
+                reply_budget,
+                context_length,
+                if self.truncate_history {
+                    ", even with only the latest message kept"
+                } else {
+                    "; enable history truncation or an OverflowPolicy, or start a new session"
+                }
+            )));
+        }
+    }
+
+    /// Remove the oldest user turn and the replies that followed it, keeping the system
+    /// prompt and the latest user message. Returns false when nothing can be dropped.
+    fn drop_oldest_turn(&mut self) -> bool {
+        let Some(first) = self.messages.iter().position(|m| m.role != "system") else {
+            return false;
+        };
+        let next_user = self.messages[first + 1..]
+            .iter()
+            .position(|m| m.role == "user")
+            .map(|offset| first + 1 + offset);
+        match next_user {
+            Some(end) => {
+                debug!("Context full: dropping {} oldest messages", end - first);
+                self.messages.drain(first..end);
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Typed tensor element types for `TensorConfig`
+//!
+//! Configs reach us from several sources that spell element types differently: the
+//! protobuf decoders report `FLOAT16`/`DOUBLE`, MIL text uses `fp16`/`int32`, ANEMLL
+//! metadata says `Float16`/`Int32`, and coremltools dumps enum names such as
+//! `ArrayDataType.FLOAT16`. [`TensorDataType`] accepts all of them and always writes the
+//! canonical upper-case name back out.
+
+use candle_core::DType;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Element type of a model input, output or state tensor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TensorDataType {
+    Float16,
+    Float32,
+    Float64,
+    BFloat16,
+    Int4,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Bool,
+    String,
+    /// Not reported by the model or not recognised
+    #[default]
+    Unknown,
+}
+
+impl TensorDataType {
+    /// Every known type, in the order used by the published JSON Schema
+    pub const ALL: [TensorDataType; 15] = [
+        Self::Float16,
+        Self::Float32,
+        Self::Float64,
+        Self::BFloat16,
+        Self::Int4,
+        Self::Int8,
+        Self::Int16,
+        Self::Int32,
+        Self::Int64,
+        Self::UInt8,
+        Self::UInt16,
+        Self::UInt32,
+        Self::UInt64,
+        Self::Bool,
+        Self::String,
+    ];
+
+    /// Canonical name written to config files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Float16 => "FLOAT16",
+            Self::Float32 => "FLOAT32",
+            Self::Float64 => "FLOAT64",
+            Self::BFloat16 => "BFLOAT16",
+            Self::Int4 => "INT4",
+            Self::Int8 => "INT8",
+            Self::Int16 => "INT16",
+            Self::Int32 => "INT32",
+            Self::Int64 => "INT64",
+            Self::UInt8 => "UINT8",
+            Self::UInt16 => "UINT16",
+            Self::UInt32 => "UINT32",
+            Self::UInt64 => "UINT64",
+            Self::Bool => "BOOL",
+            Self::String => "STRING",
+            Self::Unknown => "UNKNOWN",
+        }
+    }
+
+    /// Lenient parse; anything unrecognised becomes [`TensorDataType::Unknown`]
+    ///
+    /// Case, `_`/`-` separators and enum prefixes (`ArrayDataType.FLOAT16`) are ignored,
+    /// and the short MIL/numpy spellings (`fp16`, `f32`, `half`, `double`, `i64`) are accepted.
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        let text = text.rsplit('.').next().unwrap_or(text);
+        let key: String = text
+            .chars()
+            .filter(|c| !matches!(c, '_' | '-' | ' '))
+            .flat_map(char::to_lowercase)
+            .collect();
+        match key.as_str() {
+            "float16" | "fp16" | "f16" | "half" => Self::Float16,
+            "float32" | "fp32" | "f32" => Self::Float32,
+            "float64" | "fp64" | "f64" | "double" => Self::Float64,
+            "bfloat16" | "bf16" => Self::BFloat16,
+            "int4" | "i4" => Self::Int4,
+            "int8" | "i8" => Self::Int8,
+            "int16" | "i16" => Self::Int16,
+            "int32" | "i32" => Self::Int32,
+            "int64" | "i64" => Self::Int64,
+            "uint8" | "u8" => Self::UInt8,
+            "uint16" | "u16" => Self::UInt16,
+            "uint32" | "u32" => Self::UInt32,
+            "uint64" | "u64" => Self::UInt64,
+            "bool" | "boolean" => Self::Bool,
+            "string" => Self::String,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Candle dtype holding values of this type, if candle has one
+    pub fn candle_dtype(self) -> Option<DType> {
+        match self {
+            Self::Float16 => Some(DType::F16),
+            Self::Float32 => Some(DType::F32),
+            Self::Float64 => Some(DType::F64),
+            Self::BFloat16 => Some(DType::BF16),
+            Self::Int16 => Some(DType::I16),
+            Self::Int32 => Some(DType::I32),
+            Self::Int64 => Some(DType::I64),
+            Self::UInt8 => Some(DType::U8),
+            Self::UInt32 => Some(DType::U32),
+            _ => None,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(
+            self,
+            Self::Float16 | Self::Float32 | Self::Float64 | Self::BFloat16
+        )
+    }
+
+    pub fn is_integer(self) -> bool {
+        matches!(
+            self,
+            Self::Int4
+                | Self::Int8
+                | Self::Int16
+                | Self::Int32
+                | Self::Int64
+                | Self::UInt8
+                | Self::UInt16
+                | Self::UInt32
+                | Self::UInt64
+        )
+    }
+}
+
+impl fmt::Display for TensorDataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TensorDataType {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::parse(s))
+    }
+}
+
+impl From<&str> for TensorDataType {
+    fn from(s: &str) -> Self {
+        Self::parse(s)
+    }
+}
+
+impl Serialize for TensorDataType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for TensorDataType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = std::borrow::Cow::<'de, str>::deserialize(deserializer)?;
+        Ok(Self::parse(&text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_generator_spellings() {
+        for (text, expected) in [
+            ("FLOAT16", TensorDataType::Float16),
+            ("Float16", TensorDataType::Float16),
+            ("fp16", TensorDataType::Float16),
+            ("ArrayDataType.FLOAT16", TensorDataType::Float16),
+            ("DOUBLE", TensorDataType::Float64),
+            ("INT32", TensorDataType::Int32),
+            ("Int32", TensorDataType::Int32),
+            ("int32", TensorDataType::Int32),
+            ("bf16", TensorDataType::BFloat16),
+            ("uint8", TensorDataType::UInt8),
+            ("UNKNOWN", TensorDataType::Unknown),
+            ("complex64", TensorDataType::Unknown),
+        ] {
+            assert_eq!(TensorDataType::parse(text), expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn test_canonical_names_round_trip() {
+        for data_type in TensorDataType::ALL {
+            assert_eq!(TensorDataType::parse(data_type.as_str()), data_type);
+            let json = serde_json::to_string(&data_type).unwrap();
+            assert_eq!(json, format!("\"{}\"", data_type.as_str()));
+            assert_eq!(
+                serde_json::from_str::<TensorDataType>(&json).unwrap(),
+                data_type
+            );
+        }
+        for data_type in TensorDataType::ALL {
+            assert!(crate::config::schema::KNOWN_DATA_TYPES.contains(&data_type.as_str()));
+        }
+        let legacy: TensorDataType = serde_json::from_str("\"Float16\"").unwrap();
+        assert_eq!(serde_json::to_string(&legacy).unwrap(), "\"FLOAT16\"");
+    }
+
+    #[test]
+    fn test_candle_dtypes() {
+        assert_eq!(TensorDataType::Int32.candle_dtype(), Some(DType::I32));
+        assert_eq!(TensorDataType::Float16.candle_dtype(), Some(DType::F16));
+        assert_eq!(TensorDataType::Int8.candle_dtype(), None);
+        assert_eq!(TensorDataType::Unknown.candle_dtype(), None);
+    }
+}
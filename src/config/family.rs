@@ -0,0 +1,118 @@
+//! Model families recognised by the config generator
+//!
+//! `ModelInfo.model_type` stays a plain string so configs for families this release does not
+//! know still load; [`ModelFamily`] is the typed view of the names the generator writes.
+
+use std::fmt;
+
+/// Decoder family a CoreML conversion was made from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModelFamily {
+    /// Qwen of unknown version; also the `model_type` written before detection existed
+    Qwen,
+    Qwen2,
+    Qwen2_5,
+    Qwen3,
+    Llama,
+    Gemma,
+    Gemma2,
+    Gemma3,
+    Phi,
+    Phi3,
+    Mistral,
+}
+
+impl ModelFamily {
+    pub const ALL: [ModelFamily; 11] = [
+        Self::Qwen,
+        Self::Qwen2,
+        Self::Qwen2_5,
+        Self::Qwen3,
+        Self::Llama,
+        Self::Gemma,
+        Self::Gemma2,
+        Self::Gemma3,
+        Self::Phi,
+        Self::Phi3,
+        Self::Mistral,
+    ];
+
+    /// Name written to `ModelInfo.model_type`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Qwen => "qwen",
+            Self::Qwen2 => "qwen2",
+            Self::Qwen2_5 => "qwen2.5",
+            Self::Qwen3 => "qwen3",
+            Self::Llama => "llama",
+            Self::Gemma => "gemma",
+            Self::Gemma2 => "gemma2",
+            Self::Gemma3 => "gemma3",
+            Self::Phi => "phi",
+            Self::Phi3 => "phi3",
+            Self::Mistral => "mistral",
+        }
+    }
+
+    /// Map a Hugging Face `model_type`, an architecture class name (`Qwen3ForCausalLM`) or a
+    /// `ModelInfo.model_type` back to a family
+    pub fn parse(text: &str) -> Option<Self> {
+        let key = text.trim().to_lowercase();
+        let key = ["forcausallm", "forconditionalgeneration", "model"]
+            .iter()
+            .find_map(|suffix| key.strip_suffix(suffix))
+            .unwrap_or(&key);
+        let key: String = key.chars().filter(|c| !matches!(c, '_' | '-')).collect();
+        let family = match key.as_str() {
+            "qwen" => Self::Qwen,
+            "qwen2" => Self::Qwen2,
+            "qwen2.5" | "qwen25" => Self::Qwen2_5,
+            "qwen3" | "qwen3moe" => Self::Qwen3,
+            "llama" | "llama3" => Self::Llama,
+            "gemma" => Self::Gemma,
+            "gemma2" => Self::Gemma2,
+            "gemma3" | "gemma3text" => Self::Gemma3,
+            "phi" | "phi2" => Self::Phi,
+            "phi3" | "phi4" => Self::Phi3,
+            "mistral" => Self::Mistral,
+            _ => return None,
+        };
+        Some(family)
+    }
+
+    pub fn is_qwen(self) -> bool {
+        matches!(self, Self::Qwen | Self::Qwen2 | Self::Qwen2_5 | Self::Qwen3)
+    }
+}
+
+impl fmt::Display for ModelFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_family_names() {
+        for family in ModelFamily::ALL {
+            assert_eq!(ModelFamily::parse(family.as_str()), Some(family));
+        }
+        assert_eq!(
+            ModelFamily::parse("Qwen3ForCausalLM"),
+            Some(ModelFamily::Qwen3)
+        );
+        assert_eq!(
+            ModelFamily::parse("Gemma3ForConditionalGeneration"),
+            Some(ModelFamily::Gemma3)
+        );
+        assert_eq!(ModelFamily::parse("gemma3_text"), Some(ModelFamily::Gemma3));
+        assert_eq!(
+            ModelFamily::parse("LlamaForCausalLM"),
+            Some(ModelFamily::Llama)
+        );
+        assert_eq!(ModelFamily::parse("bert"), None);
+    }
+}
@@ -3,9 +3,10 @@
 //! Handles saving and loading generated model configurations
 
 use crate::cache::manager::CacheManager;
+use crate::config::migration;
 use crate::config::model::ModelConfig;
 use anyhow::Result;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 pub struct ConfigCaching {
     cache_manager: CacheManager,
@@ -43,7 +44,22 @@ impl ConfigCaching {
         }
 
         let config_json = std::fs::read_to_string(&config_path)?;
-        let config: ModelConfig = serde_json::from_str(&config_json)?;
+        let mut value: serde_json::Value = serde_json::from_str(&config_json)?;
+        let cached_version = migration::schema_version_of(&value);
+        let warnings = migration::migrate_to_latest(&mut value)?;
+        let config: ModelConfig = serde_json::from_value(value)?;
+
+        // Upgrade older caches in place instead of regenerating them
+        if cached_version != config.schema_version {
+            for warning in &warnings {
+                warn!("Cached config for {}: {}", model_id, warning);
+            }
+            self.cache_config(model_id, &config)?;
+            info!(
+                "♻️  Upgraded cached config for {} from schema v{} to v{}",
+                model_id, cached_version, config.schema_version
+            );
+        }
 
         debug!("📖 Loaded cached config for: {}", model_id);
         Ok(Some(config))
@@ -175,3 +191,31 @@ impl CacheStats {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::model::CURRENT_SCHEMA_VERSION;
+
+    #[test]
+    fn test_legacy_cached_config_is_upgraded_on_load() -> Result<()> {
+        let temp = tempfile::TempDir::new()?;
+        let caching = ConfigCaching::new(CacheManager::with_cache_base(temp.path())?);
+        let model_id = "candle-coreml-tests/legacy-schema-config";
+        let configs_dir = caching.cache_manager.configs_dir();
+        let path = configs_dir.join(caching.normalize_model_id_for_filename(model_id));
+        std::fs::copy("configs/anemll-qwen3-0.6b.json", &path)?;
+
+        let config = caching.load_cached_config(model_id)?.unwrap();
+        assert_eq!(config.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(config.ffn_execution.as_deref(), Some("unified"));
+
+        // The cache file itself now holds the upgraded layout
+        let rewritten: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+        assert_eq!(
+            migration::schema_version_of(&rewritten),
+            CURRENT_SCHEMA_VERSION
+        );
+        Ok(())
+    }
+}
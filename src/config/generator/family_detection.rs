@@ -0,0 +1,386 @@
+//! Model family detection
+//!
+//! ANEMLL conversions ship the original Hugging Face `config.json` and tokenizer files next
+//! to the CoreML components. The family is taken from the first source that names it:
+//! 1. `config.json` (`model_type`, then `architectures`)
+//! 2. `tokenizer_config.json` (`tokenizer_class` and chat template markers)
+//! 3. the component file prefix ANEMLL writes (`qwen25_embeddings.mlmodelc`, `llama_lm_head...`)
+//! 4. the vocabulary size
+//!
+//! Family-specific settings (rope theta, EOS ids, chat template file) are collected
+//! whichever source decided the family.
+
+use crate::config::family::ModelFamily;
+use crate::config::model::{ComponentConfig, FamilyConfig};
+use crate::generation::{model_eos_token_ids, read_json};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use tokenizers::Tokenizer;
+use tracing::debug;
+
+/// `model_type` hint asking the generator to detect the family
+pub const AUTO_MODEL_TYPE: &str = "auto";
+
+/// Outcome of [`FamilyDetector::detect`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FamilyDetection {
+    /// `None` when no source names a known family
+    pub family: Option<ModelFamily>,
+    /// Which source decided the family, for logging
+    pub source: Option<&'static str>,
+    pub config: FamilyConfig,
+}
+
+pub struct FamilyDetector;
+
+impl Default for FamilyDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FamilyDetector {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Detect the family of the model in `model_dir` from its files and discovered components
+    pub fn detect(
+        &self,
+        model_dir: &Path,
+        model_id: &str,
+        components: &HashMap<String, ComponentConfig>,
+        vocab_size: usize,
+    ) -> FamilyDetection {
+        let hf_config = read_json(&model_dir.join("config.json"));
+        let tokenizer_config = read_json(&model_dir.join("tokenizer_config.json"));
+        // EOS tokens resolve the same way as when the model is loaded
+        let tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json")).ok();
+        let chat_template = self.chat_template(model_dir, tokenizer_config.as_ref());
+        let template_text = chat_template
+            .as_ref()
+            .map(|(_, text)| text.as_str())
+            .unwrap_or_default();
+
+        let candidates = [
+            (
+                "config.json",
+                hf_config
+                    .as_ref()
+                    .and_then(|c| self.family_from_hf_config(c)),
+            ),
+            (
+                "tokenizer_config.json",
+                tokenizer_config
+                    .as_ref()
+                    .and_then(|c| self.family_from_tokenizer(c, template_text)),
+            ),
+            (
+                "component files",
+                self.family_from_component_files(components),
+            ),
+            ("vocab size", self.family_from_vocab_size(vocab_size)),
+        ];
+        let (source, family) = candidates
+            .into_iter()
+            .find_map(|(source, family)| family.map(|f| (source, f)))
+            .unzip();
+
+        // The version is often only visible in the model name (Qwen2.5 reports `qwen2`)
+        let family = family.map(|family| {
+            let name_hints = [
+                Some(model_id.to_string()),
+                hf_config
+                    .as_ref()
+                    .and_then(|c| c["_name_or_path"].as_str().map(str::to_string)),
+                self.component_prefix(components),
+            ];
+            self.refine_version(family, template_text, name_hints.iter().flatten())
+        });
+        if let Some(family) = family {
+            debug!("🧬 Detected model family {} from {:?}", family, source);
+        }
+
+        FamilyDetection {
+            family,
+            source,
+            config: FamilyConfig {
+                rope_theta: hf_config.as_ref().and_then(rope_theta),
+                eos_token_ids: model_eos_token_ids(model_dir, tokenizer.as_ref()),
+                chat_template: chat_template.map(|(file, _)| file),
+            },
+        }
+    }
+
+    fn family_from_hf_config(&self, config: &Value) -> Option<ModelFamily> {
+        let model_type = config["model_type"]
+            .as_str()
+            .or_else(|| config["text_config"]["model_type"].as_str());
+        model_type.and_then(ModelFamily::parse).or_else(|| {
+            config["architectures"]
+                .as_array()?
+                .iter()
+                .filter_map(Value::as_str)
+                .find_map(ModelFamily::parse)
+        })
+    }
+
+    fn family_from_tokenizer(
+        &self,
+        tokenizer_config: &Value,
+        template: &str,
+    ) -> Option<ModelFamily> {
+        let class = tokenizer_config["tokenizer_class"]
+            .as_str()
+            .unwrap_or_default()
+            .to_lowercase();
+        if class.starts_with("qwen2") || template.contains("<|im_start|>") {
+            Some(ModelFamily::Qwen)
+        } else if class.starts_with("gemma") || template.contains("<start_of_turn>") {
+            Some(ModelFamily::Gemma)
+        } else if template.contains("<|start_header_id|>") {
+            Some(ModelFamily::Llama)
+        } else if template.contains("<|end|>") && template.contains("<|assistant|>") {
+            Some(ModelFamily::Phi3)
+        } else if template.contains("[INST]") {
+            Some(ModelFamily::Mistral)
+        } else if class.starts_with("llama") {
+            Some(ModelFamily::Llama)
+        } else {
+            None
+        }
+    }
+
+    fn family_from_component_files(
+        &self,
+        components: &HashMap<String, ComponentConfig>,
+    ) -> Option<ModelFamily> {
+        ModelFamily::parse(&self.component_prefix(components)?)
+    }
+
+    /// Common ANEMLL file prefix (`qwen25` in `qwen25_FFN_PF_lut4_chunk_01of01.mlmodelc`)
+    fn component_prefix(&self, components: &HashMap<String, ComponentConfig>) -> Option<String> {
+        let mut prefixes = components.values().filter_map(|component| {
+            let stem = Path::new(component.file_path.as_deref()?).file_stem()?;
+            let (prefix, _) = stem.to_str()?.split_once('_')?;
+            Some(prefix.to_lowercase())
+        });
+        let first = prefixes.next()?;
+        prefixes.all(|prefix| prefix == first).then_some(first)
+    }
+
+    fn family_from_vocab_size(&self, vocab_size: usize) -> Option<ModelFamily> {
+        match vocab_size {
+            151_643..=152_064 => Some(ModelFamily::Qwen),
+            128_256 => Some(ModelFamily::Llama),
+            256_000 => Some(ModelFamily::Gemma),
+            262_144 | 262_208 => Some(ModelFamily::Gemma3),
+            32_064 | 100_352 => Some(ModelFamily::Phi3),
+            51_200 => Some(ModelFamily::Phi),
+            _ => None,
+        }
+    }
+
+    fn refine_version<'a>(
+        &self,
+        family: ModelFamily,
+        template: &str,
+        name_hints: impl Iterator<Item = &'a String>,
+    ) -> ModelFamily {
+        let names: Vec<String> = name_hints
+            .map(|name| name.to_lowercase().replace(['_', '-'], ""))
+            .collect();
+        let named = |needles: &[&str]| {
+            names
+                .iter()
+                .any(|name| needles.iter().any(|needle| name.contains(needle)))
+        };
+        match family {
+            ModelFamily::Qwen | ModelFamily::Qwen2 if named(&["qwen2.5", "qwen25"]) => {
+                ModelFamily::Qwen2_5
+            }
+            ModelFamily::Qwen if named(&["qwen3"]) || template.contains("enable_thinking") => {
+                ModelFamily::Qwen3
+            }
+            ModelFamily::Qwen if named(&["qwen2"]) => ModelFamily::Qwen2,
+            ModelFamily::Gemma if named(&["gemma3"]) => ModelFamily::Gemma3,
+            ModelFamily::Gemma if named(&["gemma2"]) => ModelFamily::Gemma2,
+            family => family,
+        }
+    }
+
+    /// Chat template file and its text; a standalone `chat_template.jinja` wins
+    fn chat_template(
+        &self,
+        model_dir: &Path,
+        tokenizer_config: Option<&Value>,
+    ) -> Option<(String, String)> {
+        let jinja = "chat_template.jinja";
+        if let Ok(text) = std::fs::read_to_string(model_dir.join(jinja)) {
+            return Some((jinja.to_string(), text));
+        }
+        let text = match &tokenizer_config?["chat_template"] {
+            Value::String(text) => text.clone(),
+            Value::Array(named) => named
+                .iter()
+                .filter_map(|entry| entry["template"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => return None,
+        };
+        Some(("tokenizer_config.json".to_string(), text))
+    }
+}
+
+fn rope_theta(config: &Value) -> Option<f64> {
+    config["rope_theta"]
+        .as_f64()
+        .or_else(|| config["text_config"]["rope_theta"].as_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+    use tokenizers::models::wordlevel::WordLevel;
+
+    fn component(file: &str) -> ComponentConfig {
+        ComponentConfig {
+            file_path: Some(format!("/models/{file}")),
+            inputs: HashMap::new(),
+            outputs: HashMap::new(),
+            functions: vec![],
+            input_order: None,
+            states: HashMap::new(),
+            chunks: vec![],
+        }
+    }
+
+    fn write_json(dir: &Path, file: &str, value: Value) {
+        std::fs::write(dir.join(file), value.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_detects_qwen3_from_hf_files() {
+        let dir = TempDir::new().unwrap();
+        write_json(
+            dir.path(),
+            "config.json",
+            json!({
+                "architectures": ["Qwen3ForCausalLM"],
+                "model_type": "qwen3",
+                "rope_theta": 1000000,
+                "eos_token_id": 151645
+            }),
+        );
+        write_json(
+            dir.path(),
+            "generation_config.json",
+            json!({"eos_token_id": [151645, 151643]}),
+        );
+        write_json(
+            dir.path(),
+            "tokenizer_config.json",
+            json!({
+                "tokenizer_class": "Qwen2Tokenizer",
+                "eos_token": "<|im_end|>",
+                "added_tokens_decoder": {"151645": {"content": "<|im_end|>"}},
+                "chat_template": "{% for m in messages %}<|im_start|>{{ m.role }}{% endfor %}"
+            }),
+        );
+
+        let detection = FamilyDetector::new().detect(dir.path(), "", &HashMap::new(), 151_936);
+        assert_eq!(detection.family, Some(ModelFamily::Qwen3));
+        assert_eq!(detection.source, Some("config.json"));
+        assert_eq!(detection.config.rope_theta, Some(1_000_000.0));
+        assert_eq!(detection.config.eos_token_ids, vec![151645, 151643]);
+        assert_eq!(
+            detection.config.chat_template.as_deref(),
+            Some("tokenizer_config.json")
+        );
+
+        // A standalone template file wins
+        std::fs::write(dir.path().join("chat_template.jinja"), "<|im_start|>").unwrap();
+        let detection = FamilyDetector::new().detect(dir.path(), "", &HashMap::new(), 0);
+        assert_eq!(
+            detection.config.chat_template.as_deref(),
+            Some("chat_template.jinja")
+        );
+    }
+
+    #[test]
+    fn test_falls_back_to_tokenizer_files_and_names() {
+        let detector = FamilyDetector::new();
+
+        // Llama 3 template, EOS resolved through tokenizer.json
+        let dir = TempDir::new().unwrap();
+        write_json(
+            dir.path(),
+            "tokenizer_config.json",
+            json!({
+                "tokenizer_class": "PreTrainedTokenizerFast",
+                "eos_token": "<|eot_id|>",
+                "chat_template": "<|start_header_id|>{{ role }}<|end_header_id|>"
+            }),
+        );
+        let vocab = [("<unk>", 0), ("<|eot_id|>", 128009)]
+            .into_iter()
+            .map(|(token, id)| (token.to_string(), id))
+            .collect();
+        let model = WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("<unk>".to_string())
+            .build()
+            .unwrap();
+        Tokenizer::new(model)
+            .save(dir.path().join("tokenizer.json"), false)
+            .unwrap();
+        let detection = detector.detect(dir.path(), "", &HashMap::new(), 0);
+        assert_eq!(detection.family, Some(ModelFamily::Llama));
+        assert_eq!(detection.config.eos_token_ids, vec![128009]);
+        // The loader resolves the same ids at runtime
+        let tokenizer = Tokenizer::from_file(dir.path().join("tokenizer.json")).unwrap();
+        assert_eq!(
+            crate::GenerationConfig::from_model_dir(dir.path(), &tokenizer).stop_token_ids,
+            detection.config.eos_token_ids
+        );
+
+        // Qwen2 `model_type` with a Qwen2.5 repository name
+        let dir = TempDir::new().unwrap();
+        write_json(dir.path(), "config.json", json!({"model_type": "qwen2"}));
+        let detection = detector.detect(
+            dir.path(),
+            "anemll/anemll-Qwen-Qwen2.5-0.5B-ctx512",
+            &HashMap::new(),
+            0,
+        );
+        assert_eq!(detection.family, Some(ModelFamily::Qwen2_5));
+    }
+
+    #[test]
+    fn test_falls_back_to_component_signatures() {
+        let detector = FamilyDetector::new();
+        let dir = TempDir::new().unwrap();
+
+        let components: HashMap<String, ComponentConfig> = [
+            ("embeddings", "gemma3_embeddings.mlmodelc"),
+            ("lm_head", "gemma3_lm_head_lut6.mlmodelc"),
+        ]
+        .into_iter()
+        .map(|(name, file)| (name.to_string(), component(file)))
+        .collect();
+        let detection = detector.detect(dir.path(), "", &components, 0);
+        assert_eq!(detection.family, Some(ModelFamily::Gemma3));
+        assert_eq!(detection.source, Some("component files"));
+        assert!(detection.config.is_empty());
+
+        let detection = detector.detect(dir.path(), "", &HashMap::new(), 128_256);
+        assert_eq!(detection.family, Some(ModelFamily::Llama));
+        assert_eq!(detection.source, Some("vocab size"));
+
+        let detection = detector.detect(dir.path(), "", &HashMap::new(), 1234);
+        assert_eq!(detection.family, None);
+    }
+}
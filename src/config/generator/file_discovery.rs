@@ -103,6 +103,15 @@ impl FileDiscovery {
         filename.replace(['-', '.'], "_").to_lowercase()
     }
 
+    /// Chunk position `(index, count)` parsed from an ANEMLL `_chunk_XXofYY` filename
+    pub fn chunk_position(&self, package_path: &Path) -> Option<(usize, usize)> {
+        let stem = package_path.file_stem()?.to_string_lossy().to_lowercase();
+        let (_, suffix) = stem.rsplit_once("chunk_")?;
+        let (index, rest) = suffix.split_once("of")?;
+        let count: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+        Some((index.parse().ok()?, count.parse().ok()?))
+    }
+
     /// Detect if a package appears to be a typo-fixer style .mlpackage
     pub fn is_typo_fixer_style(&self, package_path: &Path) -> bool {
         package_path
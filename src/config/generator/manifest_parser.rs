@@ -1,10 +1,15 @@
 //! CoreML manifest parsing utilities
 //!
-//! Handles different CoreML package formats and function-based components
+//! Handles different CoreML package formats and function-based components. Compiled
+//! `.mlmodelc` bundles are read through their MIL program text (`model.mil`), which
+//! declares every function and its state (KV cache) inputs.
 
 use super::coreml_metadata::CoreMLMetadataExtractor;
 use super::file_discovery::ManifestSource;
-use super::schema_extractor::{ComponentRole, SchemaExtractor};
+use super::mil_program::MilProgram;
+use super::mlmodel_spec::TensorMap;
+use super::schema_extractor::{flexible_shape_from_metadata, ComponentRole, SchemaExtractor};
+use crate::config::data_type::TensorDataType;
 use crate::config::model::{ComponentConfig, TensorConfig};
 use anyhow::Result;
 use serde_json::Value;
@@ -34,7 +39,18 @@ impl ManifestParser {
         schema_extractor: &SchemaExtractor,
     ) -> Result<Vec<(String, ComponentConfig)>> {
         match manifest_source {
-            ManifestSource::MetadataJson(_) | ManifestSource::ManifestJson(_) => {
+            ManifestSource::MetadataJson(_) => {
+                // Compiled bundles: prefer the MIL program, which lists every function
+                match self.parse_compiled_program(package_path, manifest, schema_extractor)? {
+                    Some(components) => Ok(components),
+                    None => self.parse_package_with_metadata_detection(
+                        package_path,
+                        manifest,
+                        schema_extractor,
+                    ),
+                }
+            }
+            ManifestSource::ManifestJson(_) => {
                 // Use existing manifest-based parsing
                 self.parse_package_with_metadata_detection(package_path, manifest, schema_extractor)
             }
@@ -63,54 +79,24 @@ impl ManifestParser {
 
         // Use CoreML metadata extractor to get per-function tensor signatures when available
         let metadata_extractor = CoreMLMetadataExtractor::new();
-        match metadata_extractor.extract_full_metadata(model_path) {
-            Ok((model_inputs, model_outputs, functions)) => {
+        match metadata_extractor.extract_spec(model_path) {
+            Ok(mut spec) => {
+                let (model_inputs, model_outputs) = (spec.inputs, spec.outputs);
                 // If per-function metadata exists, build components accordingly
-                if !functions.is_empty() {
-                    let mut components: Vec<(String, ComponentConfig)> = Vec::new();
-
-                    for (fname, (inputs, outputs)) in functions {
-                        // Determine role from tensors first
-                        let mut role = schema_extractor.detect_component_role(&inputs, &outputs);
-                        // Fallback: infer from common function names
-                        if matches!(role, ComponentRole::Unknown) {
-                            let lname = fname.to_lowercase();
-                            if lname.contains("prefill") {
-                                role = ComponentRole::FfnPrefill;
-                            } else if lname.contains("infer") || lname.contains("decode") {
-                                role = ComponentRole::FfnInfer;
-                            }
-                        }
-
-                        let component_name = self.role_to_component_name(&role);
-                        let functions_vec = vec![fname];
-                        let config = ComponentConfig {
-                            file_path: Some(package_path.to_string_lossy().to_string()),
-                            inputs,
-                            outputs,
-                            functions: functions_vec,
-                            input_order: None,
-                        };
-                        debug!(
-                            "🏷️ Function component: {} (role: {:?})",
-                            component_name, role
-                        );
-                        components.push((component_name, config));
-                    }
-
-                    // If we ended up with duplicate names (e.g., both map to ffn_prefill), keep both by disambiguating
-                    // with function names appended.
-                    let mut seen: HashMap<String, usize> = HashMap::new();
-                    for (name, _) in &mut components {
-                        let count = seen.entry(name.clone()).or_insert(0);
-                        if *count > 0 {
-                            // append index
-                            name.push_str(&format!("_{count}"));
-                        }
-                        *count += 1;
-                    }
-
-                    return Ok(components);
+                if !spec.functions.is_empty() {
+                    let functions = spec
+                        .functions
+                        .into_iter()
+                        .map(|(name, (inputs, outputs))| {
+                            let states = spec
+                                .function_states
+                                .remove(&name)
+                                .filter(|states| !states.is_empty())
+                                .unwrap_or_else(|| spec.states.clone());
+                            (name, inputs, outputs, states)
+                        })
+                        .collect();
+                    return Ok(self.function_components(package_path, functions, schema_extractor));
                 }
 
                 // No per-function info; fall back to model-level IO
@@ -137,6 +123,8 @@ impl ManifestParser {
                     outputs: model_outputs,
                     functions: Vec::new(),
                     input_order: None,
+                    states: spec.states,
+                    chunks: Vec::new(),
                 };
 
                 let component_name = self.role_to_component_name(&role);
@@ -156,6 +144,138 @@ impl ManifestParser {
         }
     }
 
+    /// Parse a compiled .mlmodelc bundle from its MIL program text (`model.mil`).
+    ///
+    /// Multifunction bundles become one component per function. A single-function bundle
+    /// keeps the metadata.json parsing and gains the program's state declarations.
+    /// Returns None when the bundle has no readable program.
+    fn parse_compiled_program(
+        &self,
+        package_path: &Path,
+        manifest: &Value,
+        schema_extractor: &SchemaExtractor,
+    ) -> Result<Option<Vec<(String, ComponentConfig)>>> {
+        let mil_path = package_path.join("model.mil");
+        if !mil_path.exists() {
+            return Ok(None);
+        }
+        let program = match MilProgram::from_file(&mil_path) {
+            Ok(program) => program,
+            Err(e) => {
+                debug!("⚠️ Failed to parse model.mil: {}", e);
+                return Ok(None);
+            }
+        };
+        debug!(
+            "📖 model.mil declares functions {:?}",
+            program
+                .functions
+                .iter()
+                .map(|f| f.name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        if let [function] = program.functions.as_slice() {
+            let mut components = self.parse_package_with_metadata_detection(
+                package_path,
+                manifest,
+                schema_extractor,
+            )?;
+            for (_, config) in &mut components {
+                config.states = function.states.clone();
+            }
+            return Ok(Some(components));
+        }
+
+        let functions = program
+            .functions
+            .into_iter()
+            .map(|f| (f.name, f.inputs, f.outputs, f.states))
+            .collect();
+        Ok(Some(self.function_components(
+            package_path,
+            functions,
+            schema_extractor,
+        )))
+    }
+
+    /// One component per function of a multifunction model:
+    /// (function name, inputs, outputs, states).
+    fn function_components(
+        &self,
+        package_path: &Path,
+        functions: Vec<(String, TensorMap, TensorMap, TensorMap)>,
+        schema_extractor: &SchemaExtractor,
+    ) -> Vec<(String, ComponentConfig)> {
+        let mut components: Vec<(String, ComponentConfig)> = Vec::new();
+
+        for (fname, inputs, outputs, states) in functions {
+            let role = self.function_role(&fname, &inputs, &outputs, schema_extractor);
+            let component_name = self.role_to_component_name(&role);
+            let config = ComponentConfig {
+                file_path: Some(package_path.to_string_lossy().to_string()),
+                inputs,
+                outputs,
+                functions: vec![fname],
+                input_order: None,
+                states,
+                chunks: Vec::new(),
+            };
+            debug!(
+                "🏷️ Function component: {} (role: {:?})",
+                component_name, role
+            );
+            components.push((component_name, config));
+        }
+
+        // If we ended up with duplicate names (e.g., both map to ffn_prefill), keep both by disambiguating
+        // with function names appended.
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for (name, _) in &mut components {
+            let count = seen.entry(name.clone()).or_insert(0);
+            if *count > 0 {
+                // append index
+                name.push_str(&format!("_{count}"));
+            }
+            *count += 1;
+        }
+
+        components
+    }
+
+    /// Role of one function of a multifunction model.
+    ///
+    /// Tensor signatures identify embeddings and LM heads. FFN functions whose signature
+    /// is ambiguous are told apart by name (`prefill`, `infer`/`decode`), then by the
+    /// sequence length of their `hidden_states` input (1 for infer).
+    fn function_role(
+        &self,
+        name: &str,
+        inputs: &TensorMap,
+        outputs: &TensorMap,
+        schema_extractor: &SchemaExtractor,
+    ) -> ComponentRole {
+        let role = schema_extractor.detect_component_role(inputs, outputs);
+        if !matches!(role, ComponentRole::Unknown | ComponentRole::FfnUnified) {
+            return role;
+        }
+        let lname = name.to_lowercase();
+        if lname.contains("prefill") {
+            return ComponentRole::FfnPrefill;
+        }
+        if lname.contains("infer") || lname.contains("decode") {
+            return ComponentRole::FfnInfer;
+        }
+        match inputs
+            .get("hidden_states")
+            .and_then(|tensor| tensor.shape.get(1))
+        {
+            Some(1) => ComponentRole::FfnInfer,
+            Some(&len) if len > 1 => ComponentRole::FfnPrefill,
+            _ => role,
+        }
+    }
+
     /// Parse package using only filename patterns (ultimate fallback)
     pub fn parse_package_filename_only(
         &self,
@@ -203,6 +323,8 @@ impl ManifestParser {
             outputs,
             functions: Vec::new(),
             input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
         };
 
         let component_name = self.role_to_component_name(&role);
@@ -245,6 +367,8 @@ impl ManifestParser {
                 outputs,
                 functions: Vec::new(),
                 input_order: None,
+                states: HashMap::new(),
+                chunks: Vec::new(),
             };
 
             let component_name = self.role_to_component_name(&role);
@@ -369,6 +493,8 @@ impl ManifestParser {
             outputs,
             functions: Vec::new(),
             input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
         })
     }
 
@@ -402,6 +528,8 @@ impl ManifestParser {
             outputs,
             functions: vec![function_name.to_string()],
             input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
         })
     }
 
@@ -473,7 +601,8 @@ impl ManifestParser {
         Ok(Some(TensorConfig {
             name: name.to_string(),
             shape,
-            data_type: data_type.to_uppercase(),
+            data_type: TensorDataType::parse(data_type),
+            flexible_shape: flexible_shape_from_metadata(tensor_def),
         }))
     }
 
@@ -0,0 +1,386 @@
+//! Signature parser for the MIL program text (model.mil) of compiled .mlmodelc bundles
+//!
+//! A compiled bundle stores the model as MIL text next to `metadata.json`, which only
+//! describes the default function. The text declares every function with its typed
+//! inputs, including `state<...>` inputs for the KV cache, and ends each body with
+//! `} -> (outputs);`. Only these signatures are read; operation bodies are skipped
+//! apart from the statements that produce a function's outputs.
+//!
+//! ```text
+//! func infer<ios18>(tensor<fp16, [1, 1, 1024]> hidden_states, state<tensor<fp16, [56, 8, 512, 128]>> kv_cache_0) {
+//!     tensor<fp16, [1, 1, 1024]> output_hidden_states = add(x = a, y = b)[name = string("output_hidden_states")];
+//! } -> (output_hidden_states);
+//! ```
+
+use super::mlmodel_spec::TensorMap;
+use crate::config::data_type::TensorDataType;
+use crate::config::model::TensorConfig;
+use anyhow::{Error as E, Result};
+use std::path::Path;
+
+/// Function signatures of a MIL program.
+#[derive(Debug, Clone, Default)]
+pub struct MilProgram {
+    /// Functions in declaration order
+    pub functions: Vec<MilFunction>,
+}
+
+/// Signature of one MIL function.
+#[derive(Debug, Clone, Default)]
+pub struct MilFunction {
+    pub name: String,
+    /// Opset the function is written against (e.g. `ios18`)
+    pub opset: String,
+    pub inputs: TensorMap,
+    pub outputs: TensorMap,
+    /// `state<...>` inputs (KV cache), reported with their wrapped tensor type
+    pub states: TensorMap,
+}
+
+impl MilProgram {
+    /// Read and parse a `model.mil` file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| E::msg(format!("Failed to read {}: {e}", path.display())))?;
+        Self::parse(&text).map_err(|e| E::msg(format!("Failed to parse {}: {e}", path.display())))
+    }
+
+    /// Parse the function signatures out of MIL program text.
+    pub fn parse(text: &str) -> Result<Self> {
+        let bytes = text.as_bytes();
+        let mut functions = Vec::new();
+        let mut depth = 0usize;
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'"' => {
+                    i = skip_string(bytes, i);
+                    continue;
+                }
+                b'{' => depth += 1,
+                b'}' => depth = depth.saturating_sub(1),
+                // Functions are declared directly inside the program block
+                b'f' if depth == 1 && is_keyword(text, i, "func") => {
+                    let (function, end) = parse_function(text, i + "func".len())?;
+                    functions.push(function);
+                    i = end;
+                    continue;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        if functions.is_empty() {
+            return Err(E::msg("no functions found in MIL program"));
+        }
+        Ok(Self { functions })
+    }
+
+    pub fn function(&self, name: &str) -> Option<&MilFunction> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+}
+
+/// Parse `name<opset>(params) [attributes] { body } -> (outputs);` starting right after
+/// the `func` keyword. Returns the function and the index just past the declaration.
+fn parse_function(text: &str, start: usize) -> Result<(MilFunction, usize)> {
+    let bytes = text.as_bytes();
+    let mut function = MilFunction::default();
+
+    let open_paren = find_byte(bytes, start, b'(')
+        .ok_or_else(|| E::msg("function declaration without a parameter list"))?;
+    let header = text[start..open_paren].trim();
+    match header.split_once('<') {
+        Some((name, opset)) => {
+            function.name = name.trim().to_string();
+            function.opset = opset.trim_end_matches('>').trim().to_string();
+        }
+        None => function.name = header.to_string(),
+    }
+
+    let close_paren = matching_close(bytes, open_paren)
+        .ok_or_else(|| E::msg(format!("unterminated parameters of '{}'", function.name)))?;
+    for param in split_top_level(&text[open_paren + 1..close_paren]) {
+        let Some((type_text, name)) = param.trim().rsplit_once(char::is_whitespace) else {
+            continue;
+        };
+        let (tensor, is_state) = parse_type(name, type_text.trim());
+        if is_state {
+            function.states.insert(name.to_string(), tensor);
+        } else {
+            function.inputs.insert(name.to_string(), tensor);
+        }
+    }
+
+    // Attributes may sit between the parameters and the body
+    let mut open_brace = close_paren + 1;
+    while open_brace < bytes.len() && bytes[open_brace] != b'{' {
+        if bytes[open_brace] == b'[' {
+            open_brace = matching_close(bytes, open_brace).unwrap_or(bytes.len());
+        }
+        open_brace += 1;
+    }
+    let close_brace = matching_close(bytes, open_brace)
+        .ok_or_else(|| E::msg(format!("unterminated body of '{}'", function.name)))?;
+    let body = &text[open_brace + 1..close_brace];
+
+    // `-> (a, b);`
+    let rest = text[close_brace + 1..].trim_start();
+    let Some(outputs) = rest.strip_prefix("->") else {
+        return Err(E::msg(format!("missing outputs of '{}'", function.name)));
+    };
+    let outputs = outputs.trim_start();
+    let list_end = outputs
+        .find(')')
+        .ok_or_else(|| E::msg(format!("unterminated outputs of '{}'", function.name)))?;
+    let output_names: Vec<&str> = outputs[..list_end]
+        .trim_start_matches('(')
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    let end = text.len() - outputs.len() + list_end + 1;
+
+    let produced = declared_values(body, &output_names);
+    for name in output_names {
+        let tensor = produced
+            .get(name)
+            .or_else(|| function.inputs.get(name))
+            .cloned()
+            .unwrap_or_else(|| unknown_tensor(name));
+        function.outputs.insert(name.to_string(), tensor);
+    }
+    Ok((function, end))
+}
+
+/// Types of the values named in `wanted`, from `TYPE NAME = op(...)` statements.
+fn declared_values(body: &str, wanted: &[&str]) -> TensorMap {
+    let mut values = TensorMap::new();
+    for statement in body.lines().map(str::trim) {
+        let Some((declaration, _)) = statement.split_once(" = ") else {
+            continue;
+        };
+        let Some((type_text, name)) = declaration.rsplit_once(char::is_whitespace) else {
+            continue;
+        };
+        if wanted.contains(&name) {
+            values.insert(name.to_string(), parse_type(name, type_text.trim()).0);
+        }
+    }
+    values
+}
+
+/// Parse `tensor<fp16, [1, 64]>`, `state<tensor<...>>` or a scalar type such as `int32`.
+/// Symbolic dimensions are reported as 0. Returns the tensor and whether it is a state.
+fn parse_type(name: &str, type_text: &str) -> (TensorConfig, bool) {
+    if let Some(inner) = strip_wrapper(type_text, "state") {
+        return (parse_type(name, inner).0, true);
+    }
+    let Some(inner) = strip_wrapper(type_text, "tensor") else {
+        let data_type = if type_text.contains('<') {
+            TensorDataType::Unknown
+        } else {
+            TensorDataType::parse(type_text)
+        };
+        return (
+            TensorConfig {
+                name: name.to_string(),
+                shape: Vec::new(),
+                data_type,
+                flexible_shape: None,
+            },
+            false,
+        );
+    };
+    let mut parts = split_top_level(inner).into_iter();
+    let data_type = parts
+        .next()
+        .map_or(TensorDataType::Unknown, TensorDataType::parse);
+    let shape = parts
+        .next()
+        .map(|dims| {
+            dims.trim()
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .split(',')
+                .map(str::trim)
+                .filter(|dim| !dim.is_empty())
+                .map(|dim| dim.parse().unwrap_or(0))
+                .collect()
+        })
+        .unwrap_or_default();
+    (
+        TensorConfig {
+            name: name.to_string(),
+            shape,
+            data_type,
+            flexible_shape: None,
+        },
+        false,
+    )
+}
+
+fn strip_wrapper<'a>(type_text: &'a str, wrapper: &str) -> Option<&'a str> {
+    type_text
+        .strip_prefix(wrapper)?
+        .trim_start()
+        .strip_prefix('<')?
+        .strip_suffix('>')
+}
+
+fn unknown_tensor(name: &str) -> TensorConfig {
+    TensorConfig {
+        name: name.to_string(),
+        shape: Vec::new(),
+        data_type: TensorDataType::Unknown,
+        flexible_shape: None,
+    }
+}
+
+fn is_keyword(text: &str, i: usize, keyword: &str) -> bool {
+    let bytes = text.as_bytes();
+    text[i..].starts_with(keyword)
+        && (i == 0 || !is_ident(bytes[i - 1]))
+        && bytes
+            .get(i + keyword.len())
+            .is_some_and(|b| b.is_ascii_whitespace())
+}
+
+fn is_ident(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+fn find_byte(bytes: &[u8], start: usize, target: u8) -> Option<usize> {
+    bytes[start..]
+        .iter()
+        .position(|&b| b == target)
+        .map(|offset| start + offset)
+}
+
+/// Index just past the closing quote of the string literal starting at `start`
+fn skip_string(bytes: &[u8], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'"' => return i + 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// Index of the bracket closing the one at `open` (`(`, `[` or `{`), skipping strings.
+///
+/// Only brackets of the same kind are counted: nested blocks end in `} -> (...)`, whose
+/// `>` would unbalance a count over all bracket kinds.
+fn matching_close(bytes: &[u8], open: usize) -> Option<usize> {
+    let close = match bytes.get(open)? {
+        b'(' => b')',
+        b'[' => b']',
+        b'{' => b'}',
+        _ => return None,
+    };
+    let mut depth = 0usize;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i = skip_string(bytes, i);
+                continue;
+            }
+            b if b == bytes[open] => depth += 1,
+            b if b == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Split on commas that are not nested inside brackets.
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '[' | '{' | '<' => depth += 1,
+            ')' | ']' | '}' | '>' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !text[start..].trim().is_empty() {
+        parts.push(&text[start..]);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = r#"program(1.3)
+[buildInfo = dict<string, string>({{"coremlc-component-MIL", "3402.3.2"}})]
+{
+    func infer<ios18>(tensor<fp16, [1, 1, 1024]> hidden_states, tensor<int32, [1]> position_ids, state<tensor<fp16, [56, 8, 512, 128]>> model_model_kv_cache_0) {
+            tensor<int32, []> var_9 = const()[name = string("var_9"), val = int32(1)];
+            tensor<fp16, [1, 1, 1024]> output_hidden_states = add(x = hidden_states, y = hidden_states)[name = string("func x(")];
+        } -> (output_hidden_states);
+    func prefill<ios18>(tensor<fp16, [1, 64, 1024]> hidden_states, tensor<int32, [is0]> position_ids, state<tensor<fp16, [56, 8, 512, 128]>> model_model_kv_cache_0) [FlexibleShapeInformation = tuple<tuple<string, dict<string, tensor<int32, [?]>>>>((("DefaultShapes", {{"position_ids", [64]}})))] {
+            tensor<fp16, [1, 64, 1024]> output_hidden_states = add(x = hidden_states, y = hidden_states)[name = string("output_hidden_states")];
+        } -> (output_hidden_states);
+}
+"#;
+
+    #[test]
+    fn test_parses_functions_inputs_states_and_outputs() {
+        let program = MilProgram::parse(PROGRAM).unwrap();
+        let names: Vec<&str> = program.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["infer", "prefill"]);
+
+        let infer = program.function("infer").unwrap();
+        assert_eq!(infer.opset, "ios18");
+        assert_eq!(infer.inputs["hidden_states"].shape, vec![1, 1, 1024]);
+        assert_eq!(
+            infer.inputs["position_ids"].data_type,
+            TensorDataType::Int32
+        );
+        assert!(!infer.inputs.contains_key("model_model_kv_cache_0"));
+        let cache = &infer.states["model_model_kv_cache_0"];
+        assert_eq!(cache.shape, vec![56, 8, 512, 128]);
+        assert_eq!(cache.data_type, TensorDataType::Float16);
+        assert_eq!(
+            infer.outputs["output_hidden_states"].shape,
+            vec![1, 1, 1024]
+        );
+
+        let prefill = program.function("prefill").unwrap();
+        assert_eq!(prefill.inputs["hidden_states"].shape, vec![1, 64, 1024]);
+        // Symbolic dimensions are unknown
+        assert_eq!(prefill.inputs["position_ids"].shape, vec![0]);
+        assert_eq!(
+            prefill.outputs["output_hidden_states"].shape,
+            vec![1, 64, 1024]
+        );
+    }
+
+    #[test]
+    fn test_rejects_text_without_functions() {
+        assert!(MilProgram::parse("program(1.0)\n{\n}\n").is_err());
+        assert!(MilProgram::parse(
+            "program(1.0)\n{\n    func main<ios17>(tensor<fp16, [1]> x) {\n"
+        )
+        .is_err());
+    }
+}
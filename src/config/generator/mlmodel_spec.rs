@@ -0,0 +1,746 @@
+//! Native decoder for CoreML model specifications (model.mlmodel)
+//!
+//! A `model.mlmodel` file is a protobuf-encoded `CoreML.Specification.Model`. Only the
+//! parts needed for configuration generation are decoded: the model description
+//! (inputs, outputs, states and, for multifunction models, per-function descriptions) and
+//! the function signatures of an MLProgram. Everything else, including the MIL operations
+//! and weights, is skipped, so no generated protobuf code or Python is required.
+
+use crate::config::data_type::TensorDataType;
+use crate::config::model::{DimRange, FlexibleShape, TensorConfig};
+use anyhow::{bail, Error as E, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Tensor signatures keyed by name
+pub type TensorMap = HashMap<String, TensorConfig>;
+
+// CoreML.Specification.Model
+const MODEL_SPECIFICATION_VERSION: u32 = 1;
+const MODEL_DESCRIPTION: u32 = 2;
+const MODEL_ML_PROGRAM: u32 = 502;
+
+// ModelDescription / FunctionDescription
+const DESCRIPTION_INPUT: u32 = 1;
+const DESCRIPTION_OUTPUT: u32 = 10;
+const DESCRIPTION_STATE: u32 = 13;
+const DESCRIPTION_FUNCTIONS: u32 = 20;
+const FUNCTION_NAME: u32 = 1;
+const FUNCTION_INPUT: u32 = 2;
+const FUNCTION_OUTPUT: u32 = 3;
+const FUNCTION_STATE: u32 = 6;
+
+// FeatureDescription / FeatureType
+const FEATURE_NAME: u32 = 1;
+const FEATURE_TYPE: u32 = 3;
+const FEATURE_TYPE_INT64: u32 = 1;
+const FEATURE_TYPE_DOUBLE: u32 = 2;
+const FEATURE_TYPE_STRING: u32 = 3;
+const FEATURE_TYPE_MULTI_ARRAY: u32 = 5;
+const FEATURE_TYPE_STATE: u32 = 8;
+
+// StateFeatureType
+const STATE_ARRAY_TYPE: u32 = 1;
+
+// ArrayFeatureType
+const ARRAY_SHAPE: u32 = 1;
+const ARRAY_DATA_TYPE: u32 = 2;
+const ARRAY_ENUMERATED_SHAPES: u32 = 21;
+const ARRAY_SHAPE_RANGE: u32 = 31;
+
+// MILSpec.Program / Function / Block / Operation
+const PROGRAM_FUNCTIONS: u32 = 2;
+const MIL_FUNCTION_INPUTS: u32 = 1;
+const MIL_FUNCTION_OPSET: u32 = 2;
+const MIL_FUNCTION_BLOCKS: u32 = 3;
+const BLOCK_OUTPUTS: u32 = 2;
+const BLOCK_OPERATIONS: u32 = 3;
+const OPERATION_OUTPUTS: u32 = 3;
+
+// MILSpec.NamedValueType / ValueType / TensorType / Dimension
+const NAMED_VALUE_NAME: u32 = 1;
+const NAMED_VALUE_TYPE: u32 = 2;
+const VALUE_TYPE_TENSOR: u32 = 1;
+const VALUE_TYPE_STATE: u32 = 5;
+const TENSOR_DATA_TYPE: u32 = 1;
+const TENSOR_DIMENSIONS: u32 = 3;
+const DIMENSION_CONSTANT: u32 = 1;
+
+/// Decoded parts of a CoreML model specification.
+#[derive(Debug, Clone, Default)]
+pub struct ModelSpec {
+    pub specification_version: i64,
+    pub inputs: TensorMap,
+    pub outputs: TensorMap,
+    /// State features (KV caches) of the model description
+    pub states: TensorMap,
+    /// Per-function signatures of multifunction models: name -> (inputs, outputs)
+    pub functions: HashMap<String, (TensorMap, TensorMap)>,
+    /// States of each function in `functions`
+    pub function_states: HashMap<String, TensorMap>,
+}
+
+/// Inputs, outputs and states of one function
+#[derive(Debug, Clone, Default)]
+struct FunctionSignature {
+    inputs: TensorMap,
+    outputs: TensorMap,
+    states: TensorMap,
+}
+
+impl ModelSpec {
+    /// Read and decode a `model.mlmodel` file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| E::msg(format!("Failed to read {}: {e}", path.display())))?;
+        Self::decode(&bytes)
+            .map_err(|e| E::msg(format!("Failed to decode {}: {e}", path.display())))
+    }
+
+    /// Decode a protobuf-encoded `CoreML.Specification.Model`.
+    ///
+    /// Functions come from the model description when it lists them; otherwise from the
+    /// MLProgram, whose output types are taken from the operations producing them. A
+    /// program with a single function is an ordinary model described by the model-level
+    /// IO, so no functions are reported for it. States come from the description's state
+    /// features, or from the `state<...>` inputs of the program when it declares none.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut spec = ModelSpec::default();
+        let mut described_functions = HashMap::new();
+        let mut program_functions = HashMap::new();
+
+        let mut reader = ProtoReader::new(bytes);
+        while let Some((field, value)) = reader.next_field()? {
+            match field {
+                MODEL_SPECIFICATION_VERSION => spec.specification_version = value.as_int()?,
+                MODEL_DESCRIPTION => {
+                    let mut description = ProtoReader::new(value.as_bytes()?);
+                    while let Some((field, value)) = description.next_field()? {
+                        match field {
+                            DESCRIPTION_INPUT => insert(&mut spec.inputs, feature(&value)?),
+                            DESCRIPTION_OUTPUT => insert(&mut spec.outputs, feature(&value)?),
+                            DESCRIPTION_STATE => insert(&mut spec.states, feature(&value)?),
+                            DESCRIPTION_FUNCTIONS => {
+                                let (name, signature) = function_description(value.as_bytes()?)?;
+                                described_functions.insert(name, signature);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                MODEL_ML_PROGRAM => program_functions = program(value.as_bytes()?)?,
+                _ => {}
+            }
+        }
+
+        if spec.states.is_empty() {
+            if let Some(function) = program_functions.values().next() {
+                if program_functions.len() == 1 {
+                    spec.states = function.states.clone();
+                }
+            }
+        }
+        let functions = if !described_functions.is_empty() {
+            // Descriptions written before states existed still have them in the program
+            for (name, signature) in &mut described_functions {
+                if signature.states.is_empty() {
+                    if let Some(function) = program_functions.get(name) {
+                        signature.states = function.states.clone();
+                    }
+                }
+            }
+            described_functions
+        } else if program_functions.len() > 1 {
+            program_functions
+        } else {
+            HashMap::new()
+        };
+        for (name, signature) in functions {
+            spec.function_states.insert(name.clone(), signature.states);
+            spec.functions
+                .insert(name, (signature.inputs, signature.outputs));
+        }
+        Ok(spec)
+    }
+}
+
+fn insert(map: &mut TensorMap, tensor: TensorConfig) {
+    map.insert(tensor.name.clone(), tensor);
+}
+
+/// FunctionDescription -> (name, signature)
+fn function_description(bytes: &[u8]) -> Result<(String, FunctionSignature)> {
+    let mut name = String::new();
+    let mut signature = FunctionSignature::default();
+    let mut reader = ProtoReader::new(bytes);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            FUNCTION_NAME => name = value.as_string()?,
+            FUNCTION_INPUT => insert(&mut signature.inputs, feature(&value)?),
+            FUNCTION_OUTPUT => insert(&mut signature.outputs, feature(&value)?),
+            FUNCTION_STATE => insert(&mut signature.states, feature(&value)?),
+            _ => {}
+        }
+    }
+    Ok((name, signature))
+}
+
+/// FeatureDescription -> TensorConfig
+fn feature(value: &WireValue) -> Result<TensorConfig> {
+    let mut tensor = TensorConfig {
+        name: String::new(),
+        shape: Vec::new(),
+        data_type: TensorDataType::Unknown,
+        flexible_shape: None,
+    };
+    let mut reader = ProtoReader::new(value.as_bytes()?);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            FEATURE_NAME => tensor.name = value.as_string()?,
+            FEATURE_TYPE => {
+                let mut feature_type = ProtoReader::new(value.as_bytes()?);
+                while let Some((field, value)) = feature_type.next_field()? {
+                    match field {
+                        FEATURE_TYPE_INT64 => tensor.data_type = TensorDataType::Int64,
+                        FEATURE_TYPE_DOUBLE => tensor.data_type = TensorDataType::Float64,
+                        FEATURE_TYPE_STRING => tensor.data_type = TensorDataType::String,
+                        FEATURE_TYPE_MULTI_ARRAY => {
+                            let (shape, data_type, flexible_shape) = array_type(value.as_bytes()?)?;
+                            tensor.shape = shape;
+                            tensor.data_type = data_type;
+                            tensor.flexible_shape = flexible_shape;
+                        }
+                        // StateFeatureType wraps the array it holds
+                        FEATURE_TYPE_STATE => {
+                            let mut state = ProtoReader::new(value.as_bytes()?);
+                            while let Some((field, value)) = state.next_field()? {
+                                if field == STATE_ARRAY_TYPE {
+                                    let (shape, data_type, _) = array_type(value.as_bytes()?)?;
+                                    tensor.shape = shape;
+                                    tensor.data_type = data_type;
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(tensor)
+}
+
+/// ArrayFeatureType -> (shape, data type, flexible shapes).
+///
+/// The default shape is used when present; flexible inputs without one report their
+/// largest enumerated shape, or the upper bounds of their shape range (the lower bound
+/// for unbounded dimensions). Enumerated shapes and ranges are kept as [`FlexibleShape`].
+fn array_type(bytes: &[u8]) -> Result<(Vec<usize>, TensorDataType, Option<FlexibleShape>)> {
+    let mut shape = Vec::new();
+    let mut data_type = TensorDataType::Unknown;
+    let mut enumerated: Vec<Vec<usize>> = Vec::new();
+    let mut range: Vec<DimRange> = Vec::new();
+    let mut reader = ProtoReader::new(bytes);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            ARRAY_SHAPE => shape.extend(value.as_packed_ints()?.into_iter().map(dim)),
+            ARRAY_DATA_TYPE => data_type = array_data_type(value.as_int()?),
+            ARRAY_ENUMERATED_SHAPES => {
+                let mut shapes = ProtoReader::new(value.as_bytes()?);
+                while let Some((_, value)) = shapes.next_field()? {
+                    let mut dims = Vec::new();
+                    let mut shape_message = ProtoReader::new(value.as_bytes()?);
+                    while let Some((_, value)) = shape_message.next_field()? {
+                        dims.extend(value.as_packed_ints()?.into_iter().map(dim));
+                    }
+                    enumerated.push(dims);
+                }
+            }
+            ARRAY_SHAPE_RANGE => {
+                let mut ranges = ProtoReader::new(value.as_bytes()?);
+                while let Some((_, value)) = ranges.next_field()? {
+                    let (mut lower, mut upper) = (0, -1);
+                    let mut size_range = ProtoReader::new(value.as_bytes()?);
+                    while let Some((field, value)) = size_range.next_field()? {
+                        match field {
+                            1 => lower = value.as_int()?,
+                            2 => upper = value.as_int()?,
+                            _ => {}
+                        }
+                    }
+                    range.push(DimRange {
+                        lower: dim(lower),
+                        upper: (upper >= 0).then(|| dim(upper)),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    if shape.is_empty() {
+        shape = enumerated
+            .iter()
+            .max_by_key(|dims| dims.iter().product::<usize>())
+            .cloned()
+            .unwrap_or_else(|| range.iter().map(|r| r.upper.unwrap_or(r.lower)).collect());
+    }
+    let flexible_shape = if !enumerated.is_empty() {
+        Some(FlexibleShape::Enumerated(enumerated))
+    } else if !range.is_empty() {
+        Some(FlexibleShape::Range(range))
+    } else {
+        None
+    };
+    Ok((shape, data_type, flexible_shape))
+}
+
+fn array_data_type(value: i64) -> TensorDataType {
+    match value {
+        65568 => TensorDataType::Float32,
+        65600 => TensorDataType::Float64,
+        65552 => TensorDataType::Float16,
+        131104 => TensorDataType::Int32,
+        131080 => TensorDataType::Int8,
+        _ => TensorDataType::Unknown,
+    }
+}
+
+/// MILSpec.Program -> function name -> signature
+fn program(bytes: &[u8]) -> Result<HashMap<String, FunctionSignature>> {
+    let mut functions = HashMap::new();
+    let mut reader = ProtoReader::new(bytes);
+    while let Some((field, value)) = reader.next_field()? {
+        if field == PROGRAM_FUNCTIONS {
+            let (name, function) = map_entry(value.as_bytes()?)?;
+            functions.insert(name, mil_function(function)?);
+        }
+    }
+    Ok(functions)
+}
+
+/// MILSpec.Function -> signature; `state<...>` inputs are reported as states
+fn mil_function(bytes: &[u8]) -> Result<FunctionSignature> {
+    let mut inputs = TensorMap::new();
+    let mut states = TensorMap::new();
+    let mut opset = String::new();
+    let mut blocks: Vec<(String, &[u8])> = Vec::new();
+    let mut reader = ProtoReader::new(bytes);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            MIL_FUNCTION_INPUTS => match named_value_type(value.as_bytes()?)? {
+                (tensor, true) => insert(&mut states, tensor),
+                (tensor, false) => insert(&mut inputs, tensor),
+            },
+            MIL_FUNCTION_OPSET => opset = value.as_string()?,
+            MIL_FUNCTION_BLOCKS => blocks.push(map_entry(value.as_bytes()?)?),
+            _ => {}
+        }
+    }
+
+    let block = blocks
+        .iter()
+        .find(|(name, _)| *name == opset)
+        .or(blocks.first());
+    let outputs = match block {
+        Some((_, block)) => block_outputs(block, &inputs)?,
+        None => TensorMap::new(),
+    };
+    Ok(FunctionSignature {
+        inputs,
+        outputs,
+        states,
+    })
+}
+
+/// Types of a block's outputs, looked up among the values produced by its operations
+/// (or the function inputs it passes through).
+fn block_outputs(bytes: &[u8], inputs: &TensorMap) -> Result<TensorMap> {
+    let mut output_names = Vec::new();
+    let mut produced = TensorMap::new();
+    let mut reader = ProtoReader::new(bytes);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            BLOCK_OUTPUTS => output_names.push(value.as_string()?),
+            BLOCK_OPERATIONS => {
+                let mut operation = ProtoReader::new(value.as_bytes()?);
+                while let Some((field, value)) = operation.next_field()? {
+                    if field == OPERATION_OUTPUTS {
+                        insert(&mut produced, named_value_type(value.as_bytes()?)?.0);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(output_names
+        .into_iter()
+        .map(|name| {
+            let tensor = produced
+                .remove(&name)
+                .or_else(|| inputs.get(&name).cloned())
+                .unwrap_or_else(|| TensorConfig {
+                    name: name.clone(),
+                    shape: Vec::new(),
+                    data_type: TensorDataType::Unknown,
+                    flexible_shape: None,
+                });
+            (name, tensor)
+        })
+        .collect())
+}
+
+/// MILSpec.NamedValueType -> (TensorConfig, whether it is a state)
+///
+/// State values report their wrapped tensor.
+fn named_value_type(bytes: &[u8]) -> Result<(TensorConfig, bool)> {
+    let mut tensor = TensorConfig {
+        name: String::new(),
+        shape: Vec::new(),
+        data_type: TensorDataType::Unknown,
+        flexible_shape: None,
+    };
+    let mut is_state = false;
+    let mut reader = ProtoReader::new(bytes);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            NAMED_VALUE_NAME => tensor.name = value.as_string()?,
+            NAMED_VALUE_TYPE => {
+                if let Some((shape, data_type, state)) = value_type(value.as_bytes()?)? {
+                    tensor.shape = shape;
+                    tensor.data_type = data_type;
+                    is_state = state;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok((tensor, is_state))
+}
+
+/// MILSpec.ValueType -> (shape, data type, is state) for tensor and state types.
+///
+/// Unknown dimensions are reported as 0.
+fn value_type(bytes: &[u8]) -> Result<Option<(Vec<usize>, TensorDataType, bool)>> {
+    let mut reader = ProtoReader::new(bytes);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            VALUE_TYPE_TENSOR => {
+                let mut shape = Vec::new();
+                let mut data_type = TensorDataType::Unknown;
+                let mut tensor = ProtoReader::new(value.as_bytes()?);
+                while let Some((field, value)) = tensor.next_field()? {
+                    match field {
+                        TENSOR_DATA_TYPE => data_type = mil_data_type(value.as_int()?),
+                        TENSOR_DIMENSIONS => {
+                            let mut size = 0;
+                            let mut dimension = ProtoReader::new(value.as_bytes()?);
+                            while let Some((field, value)) = dimension.next_field()? {
+                                if field == DIMENSION_CONSTANT {
+                                    let mut constant = ProtoReader::new(value.as_bytes()?);
+                                    while let Some((_, value)) = constant.next_field()? {
+                                        size = dim(value.as_int()?);
+                                    }
+                                }
+                            }
+                            shape.push(size);
+                        }
+                        _ => {}
+                    }
+                }
+                return Ok(Some((shape, data_type, false)));
+            }
+            // StateType { ValueType wrappedType = 1; }
+            VALUE_TYPE_STATE => {
+                let mut state = ProtoReader::new(value.as_bytes()?);
+                if let Some((_, wrapped)) = state.next_field()? {
+                    return Ok(value_type(wrapped.as_bytes()?)?
+                        .map(|(shape, data_type, _)| (shape, data_type, true)));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+fn mil_data_type(value: i64) -> TensorDataType {
+    match value {
+        1 => TensorDataType::Bool,
+        2 => TensorDataType::String,
+        10 => TensorDataType::Float16,
+        11 => TensorDataType::Float32,
+        12 => TensorDataType::Float64,
+        13 => TensorDataType::BFloat16,
+        21 => TensorDataType::Int8,
+        22 => TensorDataType::Int16,
+        23 => TensorDataType::Int32,
+        24 => TensorDataType::Int64,
+        25 => TensorDataType::Int4,
+        31 => TensorDataType::UInt8,
+        32 => TensorDataType::UInt16,
+        33 => TensorDataType::UInt32,
+        34 => TensorDataType::UInt64,
+        _ => TensorDataType::Unknown,
+    }
+}
+
+/// map<string, Message> entry -> (key, value bytes)
+fn map_entry(bytes: &[u8]) -> Result<(String, &[u8])> {
+    let mut key = String::new();
+    let mut message: &[u8] = &[];
+    let mut reader = ProtoReader::new(bytes);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            1 => key = value.as_string()?,
+            2 => message = value.as_bytes()?,
+            _ => {}
+        }
+    }
+    Ok((key, message))
+}
+
+fn dim(value: i64) -> usize {
+    value.max(0) as usize
+}
+
+/// A decoded protobuf field value.
+#[derive(Debug, Clone, Copy)]
+enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> WireValue<'a> {
+    fn as_int(&self) -> Result<i64> {
+        match *self {
+            WireValue::Varint(v) | WireValue::Fixed64(v) => Ok(v as i64),
+            WireValue::Fixed32(v) => Ok(v as i64),
+            WireValue::Bytes(_) => bail!("expected an integer, found a length-delimited field"),
+        }
+    }
+
+    fn as_bytes(&self) -> Result<&'a [u8]> {
+        match *self {
+            WireValue::Bytes(bytes) => Ok(bytes),
+            _ => bail!("expected a length-delimited field"),
+        }
+    }
+
+    fn as_string(&self) -> Result<String> {
+        String::from_utf8(self.as_bytes()?.to_vec())
+            .map_err(|e| E::msg(format!("invalid UTF-8 in string field: {e}")))
+    }
+
+    /// Repeated integer field, packed or as a single unpacked element
+    fn as_packed_ints(&self) -> Result<Vec<i64>> {
+        match *self {
+            WireValue::Bytes(bytes) => {
+                let mut reader = ProtoReader::new(bytes);
+                let mut values = Vec::new();
+                while !reader.is_empty() {
+                    values.push(reader.varint()? as i64);
+                }
+                Ok(values)
+            }
+            _ => Ok(vec![self.as_int()?]),
+        }
+    }
+}
+
+/// Minimal protobuf wire-format reader.
+struct ProtoReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let Some(&byte) = self.buf.get(self.pos) else {
+                bail!("truncated varint at byte {}", self.pos);
+            };
+            self.pos += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("varint longer than 10 bytes at byte {}", self.pos)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.buf.len())
+            .ok_or_else(|| E::msg(format!("truncated field at byte {}", self.pos)))?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Next (field number, value), or None at the end of the message.
+    fn next_field(&mut self) -> Result<Option<(u32, WireValue<'a>)>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let field = (key >> 3) as u32;
+        let value = match key & 7 {
+            0 => WireValue::Varint(self.varint()?),
+            1 => {
+                let bytes = self.take(8)?;
+                WireValue::Fixed64(u64::from_le_bytes(bytes.try_into().expect("8 bytes")))
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                WireValue::Bytes(self.take(len)?)
+            }
+            5 => {
+                let bytes = self.take(4)?;
+                WireValue::Fixed32(u32::from_le_bytes(bytes.try_into().expect("4 bytes")))
+            }
+            wire_type => bail!("unsupported wire type {wire_type} for field {field}"),
+        };
+        Ok(Some((field, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut value: u64, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn bytes_field(field: u32, payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint(u64::from(field) << 3 | 2, &mut out);
+        varint(payload.len() as u64, &mut out);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn int_field(field: u32, value: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint(u64::from(field) << 3, &mut out);
+        varint(value, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_decodes_multi_array_feature_with_range_shape() {
+        // input_ids: INT32 [1, 1..=64] with no default shape
+        let size_range = |lower, upper: i64| {
+            bytes_field(
+                1,
+                &[int_field(1, lower), int_field(2, upper as u64)].concat(),
+            )
+        };
+        let array = [
+            int_field(ARRAY_DATA_TYPE, 131104),
+            bytes_field(
+                ARRAY_SHAPE_RANGE,
+                &[size_range(1, 1), size_range(1, 64)].concat(),
+            ),
+        ]
+        .concat();
+        let feature = [
+            bytes_field(FEATURE_NAME, b"input_ids"),
+            bytes_field(FEATURE_TYPE, &bytes_field(FEATURE_TYPE_MULTI_ARRAY, &array)),
+        ]
+        .concat();
+        let model = [
+            int_field(MODEL_SPECIFICATION_VERSION, 7),
+            bytes_field(MODEL_DESCRIPTION, &bytes_field(DESCRIPTION_INPUT, &feature)),
+        ]
+        .concat();
+
+        let spec = ModelSpec::decode(&model).unwrap();
+        assert_eq!(spec.specification_version, 7);
+        let input = &spec.inputs["input_ids"];
+        assert_eq!(input.shape, vec![1, 64]);
+        assert_eq!(input.data_type, TensorDataType::Int32);
+        let range = |lower, upper| DimRange {
+            lower,
+            upper: Some(upper),
+        };
+        assert_eq!(
+            input.flexible_shape,
+            Some(FlexibleShape::Range(vec![range(1, 1), range(1, 64)]))
+        );
+        assert_eq!(input.shape_for_length(1, 5), vec![1, 5]);
+        assert!(spec.outputs.is_empty() && spec.functions.is_empty());
+    }
+
+    #[test]
+    fn test_program_state_inputs_become_states() {
+        // main(hidden_states: fp16[1, 1, 8], kv_cache: state<fp16[2, 4]>) with no described states
+        let tensor = |dims: &[u64]| {
+            let mut body = [
+                int_field(TENSOR_DATA_TYPE, 10),
+                int_field(2, dims.len() as u64),
+            ]
+            .concat();
+            for &d in dims {
+                let dimension = bytes_field(DIMENSION_CONSTANT, &int_field(1, d));
+                body.extend(bytes_field(TENSOR_DIMENSIONS, &dimension));
+            }
+            bytes_field(VALUE_TYPE_TENSOR, &body)
+        };
+        let named = |name: &[u8], value_type: &[u8]| {
+            bytes_field(
+                MIL_FUNCTION_INPUTS,
+                &[
+                    bytes_field(NAMED_VALUE_NAME, name),
+                    bytes_field(NAMED_VALUE_TYPE, value_type),
+                ]
+                .concat(),
+            )
+        };
+        let state = bytes_field(VALUE_TYPE_STATE, &bytes_field(1, &tensor(&[2, 4])));
+        let function = [
+            named(b"hidden_states", &tensor(&[1, 1, 8])),
+            named(b"kv_cache", &state),
+            bytes_field(MIL_FUNCTION_OPSET, b"CoreML8"),
+        ]
+        .concat();
+        let entry = [bytes_field(1, b"main"), bytes_field(2, &function)].concat();
+        let program = [int_field(1, 1), bytes_field(PROGRAM_FUNCTIONS, &entry)].concat();
+        let model = [
+            int_field(MODEL_SPECIFICATION_VERSION, 9),
+            bytes_field(MODEL_ML_PROGRAM, &program),
+        ]
+        .concat();
+
+        let spec = ModelSpec::decode(&model).unwrap();
+        assert_eq!(spec.states["kv_cache"].shape, vec![2, 4]);
+        assert_eq!(spec.states["kv_cache"].data_type, TensorDataType::Float16);
+        assert!(spec.functions.is_empty());
+    }
+
+    #[test]
+    fn test_truncated_input_is_an_error() {
+        let feature = bytes_field(FEATURE_NAME, b"input_ids");
+        let model = bytes_field(MODEL_DESCRIPTION, &bytes_field(DESCRIPTION_INPUT, &feature));
+        assert!(ModelSpec::decode(&model[..model.len() - 3]).is_err());
+    }
+}
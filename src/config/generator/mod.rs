@@ -4,25 +4,33 @@
 //! with a clean, modular architecture that's truly model-agnostic.
 
 use crate::cache::manager::CacheManager;
-use crate::config::model::{ComponentConfig, ModelConfig, NamingConfig};
+use crate::config::family::ModelFamily;
+use crate::config::model::{
+    ComponentConfig, ModelConfig, ModelInfo, NamingConfig, CURRENT_SCHEMA_VERSION,
+};
 use anyhow::Result;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 pub mod caching;
 pub mod coreml_metadata;
+pub mod family_detection;
 pub mod file_discovery;
 pub mod manifest_parser;
+pub mod mil_program;
+pub mod mlmodel_spec;
 pub mod schema_extractor;
 pub mod shape_inference;
 
 use caching::ConfigCaching;
+use family_detection::FamilyDetector;
 use file_discovery::FileDiscovery;
 use manifest_parser::ManifestParser;
 use schema_extractor::SchemaExtractor;
 use shape_inference::ShapeInference;
 
+pub use family_detection::AUTO_MODEL_TYPE;
 // Re-export ComponentRole for external use
 pub use schema_extractor::ComponentRole;
 // Re-export CoreML metadata extractor for testing
@@ -34,32 +42,154 @@ pub struct ConfigGenerator {
     manifest_parser: ManifestParser,
     schema_extractor: SchemaExtractor,
     shape_inference: ShapeInference,
-    caching: ConfigCaching,
+    family_detector: FamilyDetector,
+    /// `None` when generated configs are neither cached nor looked up
+    caching: Option<ConfigCaching>,
+    record_discovery_time: bool,
+    model_path: ModelPathStyle,
     #[allow(dead_code)]
     metadata_extractor: CoreMLMetadataExtractor,
 }
 
-impl ConfigGenerator {
-    /// Create a new config generator with all modules initialized
-    pub fn new() -> Result<Self> {
-        let cache_manager = CacheManager::new()?;
-        let caching = ConfigCaching::new(cache_manager);
+/// Where a [`ConfigGenerator`] keeps generated configs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ConfigCacheLocation {
+    /// The user cache directory (`~/.cache/candle-coreml`)
+    #[default]
+    UserCache,
+    /// A cache rooted at the given directory
+    Root(PathBuf),
+    /// No cache: nothing is read from or written to disk besides the model directory
+    Disabled,
+}
+
+/// How a [`ConfigGenerator`] records where the model lives
+///
+/// The loader resolves component files against the model directory, so when `model_info.path`
+/// is relative or omitted the component files are written relative to the model directory.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ModelPathStyle {
+    /// `model_info.path` is the model directory as passed to the generator
+    #[default]
+    AsGiven,
+    /// `model_info.path` is relative to the given directory
+    RelativeTo(PathBuf),
+    /// No `model_info.path`
+    Omitted,
+}
+
+/// Builder for a [`ConfigGenerator`]
+///
+/// The defaults match [`ConfigGenerator::new`]. For build scripts and tests, disable the
+/// cache and the discovery timestamp, and leave out the model directory, so generation has no
+/// side effects and the same model files always produce byte-identical JSON:
+///
+/// ```rust,no_run
+/// use candle_coreml::ConfigGenerator;
+///
+/// let generator = ConfigGenerator::builder()
+///     .without_cache()
+///     .with_discovery_time(false)
+///     .without_model_path()
+///     .build()?;
+/// let config = generator.generate_config_from_directory_enhanced(
+///     std::path::Path::new("models/qwen"),
+///     "my-org/qwen",
+///     candle_coreml::config::generator::AUTO_MODEL_TYPE,
+/// )?;
+/// std::fs::write("configs/qwen.json", serde_json::to_string_pretty(&config)?)?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConfigGeneratorBuilder {
+    pub cache: ConfigCacheLocation,
+    /// Record `model_info.discovered_at` in generated configs
+    pub record_discovery_time: bool,
+    pub model_path: ModelPathStyle,
+}
+
+impl Default for ConfigGeneratorBuilder {
+    fn default() -> Self {
+        Self {
+            cache: ConfigCacheLocation::UserCache,
+            record_discovery_time: true,
+            model_path: ModelPathStyle::AsGiven,
+        }
+    }
+}
+
+impl ConfigGeneratorBuilder {
+    /// Cache generated configs under `root` instead of the user cache directory
+    pub fn with_cache_root<P: Into<PathBuf>>(mut self, root: P) -> Self {
+        self.cache = ConfigCacheLocation::Root(root.into());
+        self
+    }
+
+    /// Never read or write cached configs
+    pub fn without_cache(mut self) -> Self {
+        self.cache = ConfigCacheLocation::Disabled;
+        self
+    }
+
+    pub fn with_discovery_time(mut self, record: bool) -> Self {
+        self.record_discovery_time = record;
+        self
+    }
 
-        Ok(Self {
+    /// Record `model_info.path` relative to `base`
+    pub fn with_model_path_relative_to<P: Into<PathBuf>>(mut self, base: P) -> Self {
+        self.model_path = ModelPathStyle::RelativeTo(base.into());
+        self
+    }
+
+    /// Leave `model_info.path` out of generated configs
+    pub fn without_model_path(mut self) -> Self {
+        self.model_path = ModelPathStyle::Omitted;
+        self
+    }
+
+    pub fn build(self) -> Result<ConfigGenerator> {
+        let caching = match self.cache {
+            ConfigCacheLocation::UserCache => Some(ConfigCaching::new(CacheManager::new()?)),
+            ConfigCacheLocation::Root(root) => {
+                Some(ConfigCaching::new(CacheManager::with_cache_base(root)?))
+            }
+            ConfigCacheLocation::Disabled => None,
+        };
+
+        Ok(ConfigGenerator {
             file_discovery: FileDiscovery::new(),
             manifest_parser: ManifestParser::new(),
             schema_extractor: SchemaExtractor::new(),
             shape_inference: ShapeInference::new(),
+            family_detector: FamilyDetector::new(),
             caching,
+            record_discovery_time: self.record_discovery_time,
+            model_path: self.model_path,
             metadata_extractor: coreml_metadata::CoreMLMetadataExtractor::new(),
         })
     }
+}
+
+impl ConfigGenerator {
+    /// Create a new config generator with all modules initialized
+    pub fn new() -> Result<Self> {
+        Self::builder().build()
+    }
+
+    /// Configure the cache location and output stability of a generator
+    pub fn builder() -> ConfigGeneratorBuilder {
+        ConfigGeneratorBuilder::default()
+    }
 
     /// Generate a config from a downloaded model directory using enhanced metadata-driven detection
     ///
     /// This function inspects .mlpackage files in a directory and generates a complete
     /// ModelConfig with proper shapes and component configurations, using metadata-driven
     /// component role detection to support both unified and split FFN architectures.
+    ///
+    /// Pass [`AUTO_MODEL_TYPE`] as `model_type` to detect the model family from the
+    /// model's `config.json`, tokenizer files and component signatures.
     pub fn generate_config_from_directory_enhanced(
         &self,
         model_dir: &Path,
@@ -101,8 +231,7 @@ impl ConfigGenerator {
             config.components.len()
         );
 
-        // Cache the generated config
-        self.caching.cache_config(model_id, &config)?;
+        self.cache_generated_config(model_id, &config)?;
 
         Ok(config)
     }
@@ -149,25 +278,32 @@ impl ConfigGenerator {
             config.components.len()
         );
 
-        // Cache the generated config
-        self.caching.cache_config(model_id, &config)?;
+        self.cache_generated_config(model_id, &config)?;
 
         Ok(config)
     }
 
     /// Load a cached configuration if available
     pub fn load_cached_config(&self, model_id: &str) -> Result<Option<ModelConfig>> {
-        self.caching.load_cached_config(model_id)
+        match &self.caching {
+            Some(caching) => caching.load_cached_config(model_id),
+            None => Ok(None),
+        }
     }
 
     /// Check if a cached configuration exists
     pub fn has_cached_config(&self, model_id: &str) -> bool {
-        self.caching.has_cached_config(model_id)
+        self.caching
+            .as_ref()
+            .is_some_and(|caching| caching.has_cached_config(model_id))
     }
 
     /// Clear cached configuration for a model
     pub fn clear_cached_config(&self, model_id: &str) -> Result<()> {
-        self.caching.clear_cached_config(model_id)
+        match &self.caching {
+            Some(caching) => caching.clear_cached_config(model_id),
+            None => Ok(()),
+        }
     }
 
     // Private implementation methods
@@ -193,7 +329,7 @@ impl ConfigGenerator {
                 config.inputs.keys().collect::<Vec<_>>(),
                 config.outputs.keys().collect::<Vec<_>>()
             );
-            components.insert(name, config);
+            self.insert_component(components, name, config);
         }
 
         Ok(())
@@ -226,12 +362,65 @@ impl ConfigGenerator {
                 config.inputs.keys().collect::<Vec<_>>(),
                 config.outputs.keys().collect::<Vec<_>>()
             );
-            components.insert(name, config);
+            self.insert_component(components, name, config);
         }
 
         Ok(())
     }
 
+    /// Add a parsed component, folding `_chunk_XXofYY` packages of the same role into one
+    /// component whose `chunks` lists every file in chunk order
+    fn insert_component(
+        &self,
+        components: &mut HashMap<String, ComponentConfig>,
+        name: String,
+        config: ComponentConfig,
+    ) {
+        let position = |c: &ComponentConfig| {
+            c.file_path
+                .as_deref()
+                .and_then(|p| self.file_discovery.chunk_position(Path::new(p)))
+        };
+        let Some(existing) = components.get(&name) else {
+            components.insert(name, config);
+            return;
+        };
+        let (Some((new_index, count)), Some((old_index, old_count))) =
+            (position(&config), position(existing))
+        else {
+            components.insert(name, config);
+            return;
+        };
+        if count != old_count || new_index == old_index {
+            components.insert(name, config);
+            return;
+        }
+
+        let mut chunks: Vec<String> = existing
+            .chunk_files()
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        chunks.extend(config.file_path.clone());
+        chunks.sort_by_key(|p| {
+            self.file_discovery
+                .chunk_position(Path::new(p))
+                .map(|(index, _)| index)
+        });
+        chunks.dedup();
+        debug!("🧩 Component '{}' now has {} chunks", name, chunks.len());
+
+        // The first chunk describes the component's inputs
+        let mut merged = if new_index < old_index {
+            config
+        } else {
+            existing.clone()
+        };
+        merged.file_path = chunks.first().cloned();
+        merged.chunks = chunks;
+        components.insert(name, merged);
+    }
+
     fn validate_required_components(
         &self,
         components: &HashMap<String, ComponentConfig>,
@@ -268,21 +457,26 @@ impl ConfigGenerator {
         // Generate naming patterns (generic approach)
         let naming_config = self.generate_naming_config(packages);
 
+        let model_info = self.build_model_info(
+            model_id,
+            model_type,
+            model_dir,
+            &components,
+            shape_config.vocab_size,
+        )?;
+
         // Determine execution mode using enhanced detection
         let component_list: Vec<(String, ComponentConfig)> = components.into_iter().collect();
         let ffn_execution = self.manifest_parser.infer_execution_mode(&component_list);
         info!("🔧 Detected execution mode: {}", ffn_execution);
 
-        let final_components: HashMap<String, ComponentConfig> =
+        let mut final_components: HashMap<String, ComponentConfig> =
             component_list.into_iter().collect();
+        self.relativize_component_files(model_dir, &mut final_components)?;
 
         let model = ModelConfig {
-            model_info: crate::config::model::ModelInfo {
-                model_id: Some(model_id.to_string()),
-                path: Some(model_dir.to_string_lossy().to_string()),
-                model_type: model_type.to_string(),
-                discovered_at: Some(chrono::Utc::now().to_rfc3339()),
-            },
+            schema_version: CURRENT_SCHEMA_VERSION,
+            model_info,
             shapes: shape_config,
             components: final_components,
             naming: naming_config,
@@ -322,21 +516,26 @@ impl ConfigGenerator {
         // Generate naming patterns (generic approach - mostly empty for truly generic models)
         let naming_config = self.generate_naming_config(packages);
 
+        let model_info = self.build_model_info(
+            model_id,
+            model_type,
+            model_dir,
+            &components,
+            shape_config.vocab_size,
+        )?;
+
         // Determine execution mode
         let component_list: Vec<(String, ComponentConfig)> = components.into_iter().collect();
         let ffn_execution = self.manifest_parser.infer_execution_mode(&component_list);
         info!("🔧 Detected execution mode: {}", ffn_execution);
 
-        let final_components: HashMap<String, ComponentConfig> =
+        let mut final_components: HashMap<String, ComponentConfig> =
             component_list.into_iter().collect();
+        self.relativize_component_files(model_dir, &mut final_components)?;
 
         Ok(ModelConfig {
-            model_info: crate::config::model::ModelInfo {
-                model_id: Some(model_id.to_string()),
-                path: Some(model_dir.to_string_lossy().to_string()),
-                model_type: model_type.to_string(),
-                discovered_at: Some(chrono::Utc::now().to_rfc3339()),
-            },
+            schema_version: CURRENT_SCHEMA_VERSION,
+            model_info,
             shapes: shape_config,
             components: final_components,
             naming: naming_config,
@@ -344,6 +543,80 @@ impl ConfigGenerator {
         })
     }
 
+    /// Model identification; `model_type` [`AUTO_MODEL_TYPE`] is replaced by the detected family
+    fn build_model_info(
+        &self,
+        model_id: &str,
+        model_type: &str,
+        model_dir: &Path,
+        components: &HashMap<String, ComponentConfig>,
+        vocab_size: usize,
+    ) -> Result<ModelInfo> {
+        let detection = self
+            .family_detector
+            .detect(model_dir, model_id, components, vocab_size);
+        let model_type = if model_type == AUTO_MODEL_TYPE {
+            match detection.family {
+                Some(family) => {
+                    info!(
+                        "🧬 Detected model family: {} (from {})",
+                        family,
+                        detection.source.unwrap_or("unknown source")
+                    );
+                    family.as_str().to_string()
+                }
+                None => {
+                    warn!(
+                        "Could not detect the model family of {}, assuming {}",
+                        model_id,
+                        ModelFamily::Qwen
+                    );
+                    ModelFamily::Qwen.as_str().to_string()
+                }
+            }
+        } else {
+            model_type.to_string()
+        };
+
+        let path = match &self.model_path {
+            ModelPathStyle::AsGiven => Some(model_dir.to_path_buf()),
+            ModelPathStyle::RelativeTo(base) => Some(relative_path(model_dir, base)?),
+            ModelPathStyle::Omitted => None,
+        };
+        Ok(ModelInfo {
+            model_id: Some(model_id.to_string()),
+            path: path.map(|p| p.to_string_lossy().to_string()),
+            model_type,
+            discovered_at: self.discovery_time(),
+            family: (!detection.config.is_empty()).then_some(detection.config),
+        })
+    }
+
+    /// Rewrite component files relative to `model_dir` unless the model path is kept as given
+    fn relativize_component_files(
+        &self,
+        model_dir: &Path,
+        components: &mut HashMap<String, ComponentConfig>,
+    ) -> Result<()> {
+        if self.model_path == ModelPathStyle::AsGiven {
+            return Ok(());
+        }
+        let relative = |file: &String| -> Result<String> {
+            Ok(relative_path(Path::new(file), model_dir)?
+                .to_string_lossy()
+                .to_string())
+        };
+        for component in components.values_mut() {
+            component.file_path = component.file_path.as_ref().map(relative).transpose()?;
+            component.chunks = component
+                .chunks
+                .iter()
+                .map(relative)
+                .collect::<Result<_>>()?;
+        }
+        Ok(())
+    }
+
     fn generate_naming_config(&self, _packages: &[PathBuf]) -> NamingConfig {
         // For truly generic models, we don't assume specific naming patterns
         // Just return empty patterns since we're being model-agnostic
@@ -380,6 +653,8 @@ impl ConfigGenerator {
             outputs,
             functions: Vec::new(),
             input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
         })
     }
 
@@ -441,12 +716,38 @@ impl ConfigGenerator {
         self.manifest_parser.infer_execution_mode(&component_list)
     }
 
-    /// Cache generated config (legacy interface)
+    /// Cache generated config; a no-op when the cache is disabled
     pub fn cache_generated_config(&self, model_id: &str, config: &ModelConfig) -> Result<()> {
-        self.caching.cache_config(model_id, config)
+        match &self.caching {
+            Some(caching) => caching.cache_config(model_id, config),
+            None => {
+                debug!("Config cache disabled, not caching config for {}", model_id);
+                Ok(())
+            }
+        }
+    }
+
+    fn discovery_time(&self) -> Option<String> {
+        self.record_discovery_time
+            .then(|| chrono::Utc::now().to_rfc3339())
     }
 }
 
+/// `path` relative to `base`, which must contain it
+fn relative_path(path: &Path, base: &Path) -> Result<PathBuf> {
+    // Compare canonical forms so `./models` and `models` agree
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    let (path, base) = (canonical(path), canonical(base));
+    let relative = path
+        .strip_prefix(&base)
+        .map_err(|_| anyhow::anyhow!("{} is not inside {}", path.display(), base.display()))?;
+    Ok(if relative.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        relative.to_path_buf()
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -478,12 +779,273 @@ mod tests {
         Ok(package_path)
     }
 
+    /// Create a mock compiled .mlmodelc bundle with a metadata.json and MIL program text
+    fn create_mock_mlmodelc(temp_dir: &Path, name: &str, program: &str) -> Result<PathBuf> {
+        let bundle_path = temp_dir.join(format!("{name}.mlmodelc"));
+        std::fs::create_dir_all(&bundle_path)?;
+
+        // metadata.json only describes the default function
+        let metadata = serde_json::json!([{
+            "inputSchema": [
+                {"name": "hidden_states", "dataType": "Float16", "shape": "[1, 1, 1024]"},
+                {"name": "causal_mask", "dataType": "Float16", "shape": "[1, 1, 1, 512]"}
+            ],
+            "outputSchema": [
+                {"name": "output_hidden_states", "dataType": "Float16", "shape": "[1, 1, 1024]"}
+            ]
+        }]);
+        std::fs::write(
+            bundle_path.join("metadata.json"),
+            serde_json::to_string_pretty(&metadata)?,
+        )?;
+        std::fs::write(bundle_path.join("model.mil"), program)?;
+        Ok(bundle_path)
+    }
+
+    const FFN_PROGRAM: &str = r#"program(1.3)
+[buildInfo = dict<string, string>({{"coremlc-version", "3402.3.2"}})]
+{
+    func infer<ios18>(tensor<fp16, [1, 1, 1024]> hidden_states, tensor<fp16, [1, 1, 1, 512]> causal_mask, state<tensor<fp16, [56, 8, 512, 128]>> model_model_kv_cache_0) {
+            tensor<fp16, [1, 1, 1024]> output_hidden_states = add(x = hidden_states, y = hidden_states)[name = string("output_hidden_states")];
+        } -> (output_hidden_states);
+    func prefill<ios18>(tensor<fp16, [1, 64, 1024]> hidden_states, tensor<fp16, [1, 1, 64, 512]> causal_mask, state<tensor<fp16, [56, 8, 512, 128]>> model_model_kv_cache_0) {
+            tensor<fp16, [1, 64, 1024]> output_hidden_states = add(x = hidden_states, y = hidden_states)[name = string("output_hidden_states")];
+        } -> (output_hidden_states);
+}
+"#;
+
+    #[test]
+    fn test_compiled_bundle_functions_from_mil_program() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let generator = ConfigGenerator::builder().without_cache().build()?;
+        let bundle =
+            create_mock_mlmodelc(temp_dir.path(), "qwen_FFN_PF_chunk_01of01", FFN_PROGRAM)?;
+
+        let mut components = HashMap::new();
+        generator.process_package_with_metadata_detection(&bundle, &mut components)?;
+
+        let mut names: Vec<&String> = components.keys().collect();
+        names.sort();
+        assert_eq!(names, ["ffn_infer", "ffn_prefill"]);
+        let prefill = &components["ffn_prefill"];
+        assert_eq!(prefill.functions, ["prefill"]);
+        assert_eq!(prefill.inputs["hidden_states"].shape, vec![1, 64, 1024]);
+        assert_eq!(
+            prefill.outputs["output_hidden_states"].shape,
+            vec![1, 64, 1024]
+        );
+        assert_eq!(
+            prefill.states["model_model_kv_cache_0"].shape,
+            vec![56, 8, 512, 128]
+        );
+        assert_eq!(components["ffn_infer"].functions, ["infer"]);
+
+        // A single-function bundle keeps metadata.json parsing and gains the states
+        let single = &FFN_PROGRAM[..FFN_PROGRAM.find("    func prefill").unwrap()];
+        let bundle = create_mock_mlmodelc(
+            temp_dir.path(),
+            "qwen_infer_chunk_01of01",
+            &format!("{single}}}\n"),
+        )?;
+        let mut components = HashMap::new();
+        generator.process_package_with_metadata_detection(&bundle, &mut components)?;
+        let infer = &components["ffn_infer"];
+        assert!(infer.functions.is_empty());
+        assert_eq!(infer.inputs["hidden_states"].shape, vec![1, 1, 1024]);
+        assert!(infer.states.contains_key("model_model_kv_cache_0"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunked_ffn_bundles_are_grouped() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let generator = ConfigGenerator::builder().without_cache().build()?;
+
+        // Process out of order: the merged list still follows chunk numbering
+        let mut components = HashMap::new();
+        for index in [2, 1, 3] {
+            let bundle = create_mock_mlmodelc(
+                temp_dir.path(),
+                &format!("qwen_FFN_PF_lut8_chunk_{index:02}of03"),
+                FFN_PROGRAM,
+            )?;
+            generator.process_package_with_metadata_detection(&bundle, &mut components)?;
+        }
+
+        for name in ["ffn_prefill", "ffn_infer"] {
+            let component = &components[name];
+            let files: Vec<String> = component
+                .chunk_files()
+                .iter()
+                .map(|p| {
+                    Path::new(p)
+                        .file_stem()
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect();
+            assert_eq!(
+                files,
+                [
+                    "qwen_FFN_PF_lut8_chunk_01of03",
+                    "qwen_FFN_PF_lut8_chunk_02of03",
+                    "qwen_FFN_PF_lut8_chunk_03of03"
+                ]
+            );
+            assert_eq!(
+                component.file_path.as_deref(),
+                Some(component.chunks[0].as_str())
+            );
+        }
+        assert_eq!(
+            generator
+                .file_discovery
+                .chunk_position(Path::new("qwen_FFN_PF_lut8_chunk_02of04.mlmodelc")),
+            Some((2, 4))
+        );
+        assert_eq!(
+            generator
+                .file_discovery
+                .chunk_position(Path::new("qwen_embeddings.mlmodelc")),
+            None
+        );
+        Ok(())
+    }
+
+    /// Write a compiled bundle whose metadata.json declares the given tensors
+    fn create_schema_mlmodelc(
+        temp_dir: &Path,
+        name: &str,
+        inputs: serde_json::Value,
+        outputs: serde_json::Value,
+    ) -> Result<PathBuf> {
+        let bundle_path = temp_dir.join(format!("{name}.mlmodelc"));
+        std::fs::create_dir_all(&bundle_path)?;
+        let metadata = serde_json::json!([{"inputSchema": inputs, "outputSchema": outputs}]);
+        std::fs::write(
+            bundle_path.join("metadata.json"),
+            serde_json::to_string_pretty(&metadata)?,
+        )?;
+        Ok(bundle_path)
+    }
+
+    #[test]
+    fn test_offline_generation_is_deterministic() -> Result<()> {
+        let create_model = |dir: &Path| -> Result<()> {
+            create_schema_mlmodelc(
+                dir,
+                "qwen_embeddings",
+                serde_json::json!([{"name": "input_ids", "dataType": "Int32", "shape": "[1, 64]"}]),
+                serde_json::json!([
+                    {"name": "hidden_states", "dataType": "Float16", "shape": "[1, 64, 1024]"}
+                ]),
+            )?;
+            create_mock_mlmodelc(dir, "qwen_FFN_PF_chunk_01of01", FFN_PROGRAM)?;
+            create_schema_mlmodelc(
+                dir,
+                "qwen_lm_head",
+                serde_json::json!([
+                    {"name": "hidden_states", "dataType": "Float16", "shape": "[1, 1, 1024]"}
+                ]),
+                serde_json::json!([
+                    {"name": "logits1", "dataType": "Float16", "shape": "[1, 1, 16]"},
+                    {"name": "logits2", "dataType": "Float16", "shape": "[1, 1, 16]"}
+                ]),
+            )?;
+            Ok(())
+        };
+        let model_dir = TempDir::new()?;
+        create_model(model_dir.path())?;
+
+        let generator = ConfigGenerator::builder()
+            .without_cache()
+            .with_discovery_time(false)
+            .without_model_path()
+            .build()?;
+        let generate = |dir: &Path| -> Result<String> {
+            let config = generator.generate_config_from_directory_enhanced(
+                dir,
+                "candle-coreml-tests/deterministic",
+                "qwen",
+            )?;
+            Ok(serde_json::to_string_pretty(&config)?)
+        };
+        let first = generate(model_dir.path())?;
+        assert_eq!(first, generate(model_dir.path())?);
+        assert!(first.contains("\"discovered_at\": null"));
+        assert!(!generator.has_cached_config("candle-coreml-tests/deterministic"));
+
+        // Nothing depends on where the model directory is
+        let config: ModelConfig = serde_json::from_str(&first)?;
+        assert_eq!(config.model_info.path, None);
+        assert_eq!(
+            config.components["embeddings"].file_path.as_deref(),
+            Some("qwen_embeddings.mlmodelc")
+        );
+        let moved = TempDir::new()?;
+        create_model(moved.path())?;
+        assert_eq!(first, generate(moved.path())?);
+
+        // A relative path is recorded against the given base
+        let relative = ConfigGenerator::builder()
+            .without_cache()
+            .with_model_path_relative_to(model_dir.path().parent().unwrap())
+            .build()?
+            .generate_config_from_directory_enhanced(
+                model_dir.path(),
+                "candle-coreml-tests/deterministic",
+                "qwen",
+            )?;
+        assert_eq!(
+            relative.model_info.path.as_deref(),
+            model_dir.path().file_name().and_then(|name| name.to_str())
+        );
+
+        // The family is detected from the Hugging Face files when asked to
+        std::fs::write(
+            model_dir.path().join("config.json"),
+            r#"{"model_type": "qwen3", "rope_theta": 1000000.0, "eos_token_id": 151645}"#,
+        )?;
+        let detected = generator.generate_config_from_directory_enhanced(
+            model_dir.path(),
+            "candle-coreml-tests/deterministic",
+            AUTO_MODEL_TYPE,
+        )?;
+        assert_eq!(detected.model_info.model_type, "qwen3");
+        let family = detected.model_info.family.as_ref().unwrap();
+        assert_eq!(family.rope_theta, Some(1_000_000.0));
+        assert_eq!(family.eos_token_ids, vec![151645]);
+        let json = serde_json::to_string(&detected)?;
+        assert!(ModelConfig::from_json_str_strict(&json).is_ok());
+
+        // An injected cache root receives the config instead of the user cache
+        let cache_root = TempDir::new()?;
+        let cached = ConfigGenerator::builder()
+            .with_cache_root(cache_root.path())
+            .build()?;
+        let config = cached.generate_config_from_directory_enhanced(
+            model_dir.path(),
+            "candle-coreml-tests/deterministic",
+            "qwen",
+        )?;
+        assert!(config.model_info.discovered_at.is_some());
+        assert!(cache_root
+            .path()
+            .join("configs/candle-coreml-tests--deterministic.json")
+            .is_file());
+        assert!(cached
+            .load_cached_config("candle-coreml-tests/deterministic")?
+            .is_some());
+        Ok(())
+    }
+
     #[test]
     fn test_modular_config_generator_creation() -> Result<()> {
         let generator = ConfigGenerator::new()?;
 
         // Should have all modules initialized
-        assert!(!generator.caching.has_cached_config("nonexistent")); // Should return false but not crash
+        assert!(!generator.has_cached_config("nonexistent")); // Should return false but not crash
 
         Ok(())
     }
@@ -2,7 +2,8 @@
 //!
 //! Handles parsing of input/output tensor schemas from various CoreML manifest formats
 
-use crate::config::model::TensorConfig;
+use crate::config::data_type::TensorDataType;
+use crate::config::model::{DimRange, FlexibleShape, TensorConfig};
 use anyhow::{Error as E, Result};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -193,7 +194,8 @@ impl SchemaExtractor {
             let entry = outputs.entry(name.clone()).or_insert(TensorConfig {
                 name: name.clone(),
                 shape: vec![],
-                data_type: tensor_config.data_type.clone(),
+                data_type: tensor_config.data_type,
+                flexible_shape: None,
             });
 
             if entry.shape.is_empty() {
@@ -239,86 +241,11 @@ impl SchemaExtractor {
         Ok(Some(TensorConfig {
             name: name.to_string(),
             shape,
-            data_type: data_type.to_uppercase(),
+            data_type: TensorDataType::parse(data_type),
+            flexible_shape: flexible_shape_from_metadata(tensor_def),
         }))
     }
 
-    #[allow(dead_code)]
-    fn extract_tensor_shape(&self, input: &Value) -> Result<Vec<usize>> {
-        // Handle enumeratedShapes when available - pick largest by default
-        if let Some(enum_val) = input.get("enumeratedShapes") {
-            if let Some(shapes) = self.parse_enumerated_shapes(enum_val)? {
-                return Ok(shapes);
-            }
-        }
-
-        // Fallback to single shape string
-        if let Some(shape_str) = input.get("shape").and_then(|s| s.as_str()) {
-            return self.parse_shape_string(shape_str);
-        }
-
-        Ok(vec![])
-    }
-
-    #[allow(dead_code)]
-    fn parse_enumerated_shapes(&self, enum_val: &Value) -> Result<Option<Vec<usize>>> {
-        if let Some(enum_str) = enum_val.as_str() {
-            // enumeratedShapes is a JSON-like string, e.g. "[[1, 1], [1, 64]]"
-            match serde_json::from_str::<Vec<Vec<usize>>>(enum_str) {
-                Ok(mut shapes) => {
-                    // Generic policy: pick the largest shape by total size
-                    shapes.sort_by(|a, b| {
-                        let a_size: usize = a.iter().product();
-                        let b_size: usize = b.iter().product();
-                        a_size.cmp(&b_size)
-                    });
-                    return Ok(shapes.last().cloned());
-                }
-                Err(err) => {
-                    debug!("⚠️ Failed to parse enumeratedShapes: {}", err);
-                }
-            }
-        } else if let Some(enum_arr) = enum_val.as_array() {
-            let candidates = self.parse_enumerated_array(enum_arr)?;
-            if !candidates.is_empty() {
-                let mut sorted_candidates = candidates;
-                sorted_candidates.sort_by(|a, b| {
-                    let a_size: usize = a.iter().product();
-                    let b_size: usize = b.iter().product();
-                    a_size.cmp(&b_size)
-                });
-                return Ok(sorted_candidates.last().cloned());
-            }
-        }
-
-        Ok(None)
-    }
-
-    #[allow(dead_code)]
-    fn parse_enumerated_array(&self, enum_arr: &[Value]) -> Result<Vec<Vec<usize>>> {
-        let mut candidates = Vec::new();
-
-        for item in enum_arr {
-            if let Some(s) = item.as_str() {
-                if let Ok(v) = serde_json::from_str::<Vec<usize>>(s) {
-                    candidates.push(v);
-                }
-            } else if let Some(arr) = item.as_array() {
-                let mut v = Vec::new();
-                for d in arr {
-                    if let Some(u) = d.as_u64() {
-                        v.push(u as usize);
-                    }
-                }
-                if !v.is_empty() {
-                    candidates.push(v);
-                }
-            }
-        }
-
-        Ok(candidates)
-    }
-
     fn parse_shape_string(&self, shape_str: &str) -> Result<Vec<usize>> {
         let trimmed = shape_str.trim_start_matches('[').trim_end_matches(']');
         if trimmed.is_empty() {
@@ -491,3 +418,42 @@ impl SchemaExtractor {
         }
     }
 }
+
+/// Flexible shapes of a metadata tensor entry: `enumeratedShapes` (`"[[1, 1], [1, 64]]"`)
+/// or `shapeRange` (per-dimension `[lower, upper]`, upper `-1` when unbounded)
+pub fn flexible_shape_from_metadata(tensor_def: &Value) -> Option<FlexibleShape> {
+    // Both fields appear either as a JSON-like string or as a nested array
+    let nested = |value: &Value| -> Option<Vec<Vec<i64>>> {
+        match value {
+            Value::String(text) => serde_json::from_str(text)
+                .map_err(|err| debug!("⚠️ Failed to parse flexible shape {text:?}: {err}"))
+                .ok(),
+            other => serde_json::from_value(other.clone()).ok(),
+        }
+    };
+
+    if let Some(shapes) = tensor_def.get("enumeratedShapes").and_then(nested) {
+        let shapes: Vec<Vec<usize>> = shapes
+            .into_iter()
+            .filter(|dims| !dims.is_empty())
+            .map(|dims| dims.into_iter().map(|d| d.max(0) as usize).collect())
+            .collect();
+        if !shapes.is_empty() {
+            return Some(FlexibleShape::Enumerated(shapes));
+        }
+    }
+
+    let ranges: Vec<DimRange> = tensor_def
+        .get("shapeRange")
+        .and_then(nested)?
+        .into_iter()
+        .filter_map(|bounds| match bounds.as_slice() {
+            [lower, upper] => Some(DimRange {
+                lower: (*lower).max(0) as usize,
+                upper: (*upper >= 0).then_some(*upper as usize),
+            }),
+            _ => None,
+        })
+        .collect();
+    (!ranges.is_empty()).then_some(FlexibleShape::Range(ranges))
+}
@@ -129,18 +129,22 @@ impl ShapeInference {
     }
 
     /// Infer context/sequence length from sequence dimensions
+    ///
+    /// Flexible tensors contribute their largest accepted shape, so a model exported with
+    /// a default shape of one token still reports its full sequence length.
     fn infer_context_length(&self, components: &HashMap<String, ComponentConfig>) -> usize {
         let mut seq_lengths = Vec::new();
 
         for component in components.values() {
             for tensor in component.inputs.values().chain(component.outputs.values()) {
-                if tensor.shape.len() >= 2 && tensor.shape[1] > 1 {
+                let shape = tensor.max_shape();
+                if shape.len() >= 2 && shape[1] > 1 {
                     // 2D+ tensors: sequence dimension is usually index 1
-                    seq_lengths.push(tensor.shape[1]);
+                    seq_lengths.push(shape[1]);
                 }
                 // Also check 4D tensors (e.g., attention masks)
-                if tensor.shape.len() >= 4 {
-                    seq_lengths.push(tensor.shape[3]);
+                if shape.len() >= 4 {
+                    seq_lengths.push(shape[3]);
                 }
             }
         }
@@ -262,11 +266,13 @@ impl ShapeInference {
             }
 
             // Check for components with tensors but no shape information
+            // (flexible dimensions count when their bounds are known)
             let has_valid_shapes = component
                 .inputs
                 .values()
                 .chain(component.outputs.values())
-                .any(|tensor| !tensor.shape.is_empty() && tensor.shape.iter().all(|&dim| dim > 0));
+                .map(|tensor| tensor.max_shape())
+                .any(|shape| !shape.is_empty() && shape.iter().all(|&dim| dim > 0));
 
             if !has_valid_shapes {
                 components_with_issues.push(name.clone());
@@ -0,0 +1,322 @@
+//! Schema migrations for serialized `ModelConfig` files
+//!
+//! Every config written by this crate records its `schema_version`. Older JSON is
+//! upgraded one version at a time before it is deserialized, so configs cached or
+//! shipped by previous releases keep loading. Each step reports what it changed (or
+//! could not change) as human-readable warnings.
+//!
+//! Versions:
+//! - 1: unversioned configs locating files through glob patterns (`file_pattern` on
+//!   components, `*_pattern` in `naming`), with optional `ffn_execution` and `input_order`
+//! - 2: explicit `file_path`/`chunks` per component and an explicit `ffn_execution`
+
+use crate::config::generator::file_discovery::FileDiscovery;
+use crate::config::model::CURRENT_SCHEMA_VERSION;
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+/// Schema version assumed for JSON without a `schema_version` field
+pub const LEGACY_SCHEMA_VERSION: u32 = 1;
+
+/// Schema version recorded in a serialized config
+pub fn schema_version_of(value: &Value) -> u32 {
+    value
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .map(|v| v as u32)
+        .unwrap_or(LEGACY_SCHEMA_VERSION)
+}
+
+/// Upgrade a serialized config in place to [`CURRENT_SCHEMA_VERSION`]
+///
+/// Returns the warnings collected along the way. Configs written by a newer release
+/// are rejected rather than guessed at.
+pub fn migrate_to_latest(value: &mut Value) -> Result<Vec<String>> {
+    if !value.is_object() {
+        anyhow::bail!("ModelConfig JSON must be an object");
+    }
+    let mut version = schema_version_of(value);
+    if version > CURRENT_SCHEMA_VERSION {
+        anyhow::bail!(
+            "ModelConfig schema_version {version} is newer than the supported version {CURRENT_SCHEMA_VERSION}"
+        );
+    }
+
+    let mut warnings = Vec::new();
+    while version < CURRENT_SCHEMA_VERSION {
+        match version {
+            1 => v1_to_v2(value, &mut warnings),
+            _ => anyhow::bail!("No migration from ModelConfig schema_version {version}"),
+        }
+        version += 1;
+        value["schema_version"] = Value::from(version);
+    }
+    Ok(warnings)
+}
+
+/// Default input order the Qwen loader uses for a component, if it has one
+fn default_input_order(
+    name: &str,
+    inputs: Option<&Map<String, Value>>,
+) -> Option<Vec<&'static str>> {
+    let ffn = vec![
+        "hidden_states",
+        "position_ids",
+        "causal_mask",
+        "current_pos",
+    ];
+    match name {
+        "embeddings" => Some(vec!["input_ids"]),
+        "ffn_prefill" => Some(ffn),
+        "ffn_infer" if inputs.is_some_and(|i| i.contains_key("update_mask")) => Some(vec![
+            "hidden_states",
+            "position_ids",
+            "update_mask",
+            "causal_mask",
+            "current_pos",
+        ]),
+        "ffn_infer" => Some(ffn),
+        "lm_head" => Some(vec!["hidden_states"]),
+        _ => None,
+    }
+}
+
+/// Pattern-based naming -> explicit file paths; record `ffn_execution` and `input_order`
+fn v1_to_v2(value: &mut Value, warnings: &mut Vec<String>) {
+    let model_dir = value
+        .pointer("/model_info/path")
+        .and_then(Value::as_str)
+        .map(|p| {
+            // Same lookup as the loader: prefer a coreml/ subdirectory
+            let dir = Path::new(p);
+            let coreml = dir.join("coreml");
+            if coreml.is_dir() {
+                coreml
+            } else {
+                dir.to_path_buf()
+            }
+        });
+
+    // Deprecated naming patterns are only kept long enough to resolve file paths
+    let mut naming_patterns = Map::new();
+    if let Some(naming) = value.get_mut("naming").and_then(Value::as_object_mut) {
+        let keys: Vec<String> = naming.keys().cloned().collect();
+        for key in keys {
+            if let Some(pattern) = naming.remove(&key).filter(|v| !v.is_null()) {
+                naming_patterns.insert(key, pattern);
+            }
+        }
+        if !naming_patterns.is_empty() {
+            warnings.push(format!(
+                "Dropped deprecated naming patterns: {}",
+                naming_patterns
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    } else {
+        value["naming"] = Value::Object(Map::new());
+    }
+
+    if let Some(components) = value.get_mut("components").and_then(Value::as_object_mut) {
+        let mut names: Vec<String> = components.keys().cloned().collect();
+        names.sort();
+        for name in names {
+            let Some(component) = components.get_mut(&name).and_then(Value::as_object_mut) else {
+                continue;
+            };
+
+            let pattern = component
+                .remove("file_pattern")
+                .and_then(|v| v.as_str().map(str::to_string))
+                .or_else(|| {
+                    naming_patterns
+                        .get(&format!("{name}_pattern"))
+                        .and_then(Value::as_str)
+                        .map(str::to_string)
+                });
+            let has_file = component.get("file_path").is_some_and(|v| v.is_string());
+            if let (Some(pattern), false) = (pattern, has_file) {
+                match model_dir
+                    .as_deref()
+                    .map(|dir| resolve_pattern(dir, &pattern))
+                {
+                    Some(Ok(files)) if files.len() == 1 => {
+                        component.insert("file_path".to_string(), Value::from(files[0].clone()));
+                    }
+                    Some(Ok(files)) if !files.is_empty() && all_chunks(&files) => {
+                        component.insert("file_path".to_string(), Value::from(files[0].clone()));
+                        component.insert("chunks".to_string(), Value::from(files));
+                    }
+                    Some(Ok(files)) if !files.is_empty() => warnings.push(format!(
+                        "Component '{name}': pattern '{pattern}' matches {} files ({}); set file_path explicitly",
+                        files.len(),
+                        files.join(", ")
+                    )),
+                    Some(Ok(_)) => warnings.push(format!(
+                        "Component '{name}': pattern '{pattern}' matches no model files; set file_path explicitly"
+                    )),
+                    Some(Err(e)) => warnings.push(format!(
+                        "Component '{name}': could not resolve pattern '{pattern}': {e}"
+                    )),
+                    None => warnings.push(format!(
+                        "Component '{name}': pattern '{pattern}' cannot be resolved without model_info.path; set file_path explicitly"
+                    )),
+                }
+            }
+
+            if component.get("input_order").is_none_or(Value::is_null) {
+                let inputs = component.get("inputs").and_then(Value::as_object);
+                if let Some(order) = default_input_order(&name, inputs) {
+                    warnings.push(format!(
+                        "Component '{name}': recorded default input_order {order:?}"
+                    ));
+                    component.insert("input_order".to_string(), Value::from(order));
+                }
+            }
+        }
+    }
+
+    if value.get("ffn_execution").is_none_or(Value::is_null) {
+        let file = |name: &str| {
+            value
+                .pointer(&format!("/components/{name}/file_path"))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+        let has_infer = value.pointer("/components/ffn_infer").is_some();
+        let mode = if has_infer && file("ffn_prefill") != file("ffn_infer") {
+            "split"
+        } else {
+            "unified"
+        };
+        warnings.push(format!("Inferred ffn_execution = \"{mode}\""));
+        value["ffn_execution"] = Value::from(mode);
+    }
+}
+
+/// File names in `dir` matching a glob `pattern`, sorted
+fn resolve_pattern(dir: &Path, pattern: &str) -> Result<Vec<String>> {
+    let pattern = glob::Pattern::new(pattern)?;
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if pattern.matches(&name) {
+            files.push(name);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Whether every file is one chunk of the same `_chunk_XXofYY` export
+fn all_chunks(files: &[String]) -> bool {
+    let discovery = FileDiscovery::new();
+    let positions: Vec<_> = files
+        .iter()
+        .map(|f| discovery.chunk_position(Path::new(f)))
+        .collect();
+    positions.iter().all(|p| p.is_some())
+        && positions
+            .iter()
+            .all(|p| p.map(|(_, count)| count) == Some(files.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::model::ModelConfig;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_shipped_legacy_config_migrates() {
+        let json = std::fs::read_to_string("configs/anemll-qwen3-0.6b.json").unwrap();
+        let mut value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(schema_version_of(&value), LEGACY_SCHEMA_VERSION);
+
+        let warnings = migrate_to_latest(&mut value).unwrap();
+        assert_eq!(schema_version_of(&value), CURRENT_SCHEMA_VERSION);
+        assert!(warnings.iter().any(|w| w.contains("naming patterns")));
+        assert!(warnings
+            .iter()
+            .any(|w| w.contains("without model_info.path")));
+
+        let config: ModelConfig = serde_json::from_value(value).unwrap();
+        assert_eq!(config.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(config.ffn_execution.as_deref(), Some("unified"));
+        assert!(config.naming.embeddings_pattern.is_none());
+        assert_eq!(
+            config.components["ffn_prefill"].input_order.as_deref(),
+            Some(
+                &[
+                    "hidden_states",
+                    "position_ids",
+                    "causal_mask",
+                    "current_pos"
+                ]
+                .map(String::from)[..]
+            )
+        );
+        assert_eq!(
+            config.components["embeddings"].input_order.as_deref(),
+            Some(&["input_ids".to_string()][..])
+        );
+
+        // Already current: nothing to do
+        let mut value = serde_json::to_value(&config).unwrap();
+        assert!(migrate_to_latest(&mut value).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_patterns_resolve_against_model_directory() {
+        let dir = TempDir::new().unwrap();
+        for name in [
+            "qwen_embeddings.mlmodelc",
+            "qwen_FFN_PF_lut8_chunk_02of02.mlmodelc",
+            "qwen_FFN_PF_lut8_chunk_01of02.mlmodelc",
+            "qwen_lm_head_lut8.mlmodelc",
+        ] {
+            std::fs::create_dir(dir.path().join(name)).unwrap();
+        }
+        let mut value = json!({
+            "model_info": {"path": dir.path(), "model_type": "qwen"},
+            "shapes": {"batch_size": 64, "context_length": 512, "hidden_size": 1024, "vocab_size": 151936},
+            "components": {
+                "embeddings": {"file_pattern": "*_embeddings.mlmodelc", "inputs": {}, "outputs": {}, "functions": []},
+                "ffn_prefill": {"inputs": {}, "outputs": {}, "functions": ["prefill", "infer"]},
+                "lm_head": {"file_pattern": "*_missing_*.mlmodelc", "inputs": {}, "outputs": {}, "functions": []}
+            },
+            "naming": {"ffn_prefill_pattern": "*_FFN_PF_*_chunk_*.mlmodelc"}
+        });
+
+        let warnings = migrate_to_latest(&mut value).unwrap();
+        let config: ModelConfig = serde_json::from_value(value).unwrap();
+        let embeddings = &config.components["embeddings"];
+        assert_eq!(
+            embeddings.file_path.as_deref(),
+            Some("qwen_embeddings.mlmodelc")
+        );
+        let ffn = &config.components["ffn_prefill"];
+        assert_eq!(
+            ffn.chunk_files(),
+            [
+                "qwen_FFN_PF_lut8_chunk_01of02.mlmodelc",
+                "qwen_FFN_PF_lut8_chunk_02of02.mlmodelc"
+            ]
+        );
+        assert!(config.components["lm_head"].file_path.is_none());
+        assert!(warnings
+            .iter()
+            .any(|w| w.contains("'lm_head'") && w.contains("matches no model files")));
+    }
+
+    #[test]
+    fn test_newer_schema_is_rejected() {
+        let mut value = json!({"schema_version": CURRENT_SCHEMA_VERSION + 1});
+        assert!(migrate_to_latest(&mut value).is_err());
+    }
+}
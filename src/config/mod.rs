@@ -4,12 +4,28 @@
 //! - Basic CoreML configuration structures
 //! - Advanced model configuration with shape discovery
 //! - Automatic configuration generation from CoreML packages
+//! - Model family detection
+//! - Schema migration of configs written by older releases
+//! - JSON Schema export and strict validation
+//! - Layered JSON merge-patch overrides
 
 pub mod basic;
+pub mod data_type;
+pub mod family;
 pub mod generator;
+pub mod migration;
 pub mod model;
+pub mod overrides;
+pub mod schema;
 
 // Re-export main types for convenience
 pub use basic::Config;
-pub use generator::ConfigGenerator;
-pub use model::{ComponentConfig, ModelConfig, ModelInfo, NamingConfig, ShapeConfig, TensorConfig};
+pub use data_type::TensorDataType;
+pub use family::ModelFamily;
+pub use generator::{ConfigCacheLocation, ConfigGenerator, ConfigGeneratorBuilder, ModelPathStyle};
+pub use model::{
+    ComponentConfig, DimRange, FamilyConfig, FlexibleShape, ModelConfig, ModelInfo, NamingConfig,
+    ShapeConfig, TensorConfig, CURRENT_SCHEMA_VERSION,
+};
+pub use overrides::{ConfigOverride, CONFIG_OVERRIDE_ENV, MODEL_DIR_OVERRIDE_FILE};
+pub use schema::ConfigDiagnostic;
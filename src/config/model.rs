@@ -5,18 +5,42 @@
 //! JSON files generated by the shape discovery tool, as well as built-in configurations
 //! for known models.
 
+pub use crate::config::data_type::TensorDataType;
+use crate::config::migration;
+use crate::config::overrides::{self, ConfigOverride};
+use crate::config::schema::{self, ConfigDiagnostic};
 use anyhow::{Context, Result};
 use candle_core::{Device, Error as CandleError, Tensor};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
+
+/// Schema version written by this release; older configs are upgraded by [`crate::config::migration`]
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+fn legacy_schema_version() -> u32 {
+    crate::config::migration::LEGACY_SCHEMA_VERSION
+}
+
+/// Write maps with sorted keys so the same config always serializes to the same JSON
+fn serialize_sorted<S, V>(map: &HashMap<String, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    V: Serialize,
+{
+    serializer.collect_map(map.iter().collect::<std::collections::BTreeMap<_, _>>())
+}
 
 /// Complete model configuration including shapes, components, and naming patterns
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ModelConfig {
+    /// Layout version of the serialized config; missing in configs written before versioning
+    #[serde(default = "legacy_schema_version")]
+    pub schema_version: u32,
     pub model_info: ModelInfo,
     pub shapes: ShapeConfig,
+    #[serde(serialize_with = "serialize_sorted")]
     pub components: HashMap<String, ComponentConfig>,
     pub naming: NamingConfig,
     /// Execution mode for FFN: "unified" (single component/function) or "split" (separate prefill/infer components)
@@ -32,6 +56,30 @@ pub struct ModelInfo {
     pub path: Option<String>,
     pub model_type: String,
     pub discovered_at: Option<String>,
+    /// Family-specific settings found next to the model files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<FamilyConfig>,
+}
+
+/// Settings that differ between model families, read from the model's Hugging Face files
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct FamilyConfig {
+    /// RoPE base frequency (`rope_theta` in `config.json`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rope_theta: Option<f64>,
+    /// Token ids that end generation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub eos_token_ids: Vec<i64>,
+    /// File holding the chat template, relative to the model directory
+    /// (`chat_template.jinja` or `tokenizer_config.json`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chat_template: Option<String>,
+}
+
+impl FamilyConfig {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
 }
 
 /// Overall model shape parameters
@@ -47,20 +95,153 @@ pub struct ShapeConfig {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ComponentConfig {
     pub file_path: Option<String>,
+    #[serde(serialize_with = "serialize_sorted")]
     pub inputs: HashMap<String, TensorConfig>,
+    #[serde(serialize_with = "serialize_sorted")]
     pub outputs: HashMap<String, TensorConfig>,
     pub functions: Vec<String>,
     /// Optional deterministic input order; if absent, caller must provide correct order.
     #[serde(default)]
     pub input_order: Option<Vec<String>>,
+    /// State tensors (e.g. the KV cache) declared by stateful models
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "serialize_sorted"
+    )]
+    pub states: HashMap<String, TensorConfig>,
+    /// Ordered files of a component split into chunks (ANEMLL `_chunk_01of04` .. `_chunk_04of04`)
+    ///
+    /// Hidden states flow through the chunks in this order; `file_path` names the first one.
+    /// Empty for single-file components.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<String>,
+}
+
+impl ComponentConfig {
+    /// Files to execute in order: `chunks` when present, otherwise just `file_path`
+    pub fn chunk_files(&self) -> Vec<&str> {
+        if self.chunks.is_empty() {
+            self.file_path.iter().map(String::as_str).collect()
+        } else {
+            self.chunks.iter().map(String::as_str).collect()
+        }
+    }
 }
 
 /// Configuration for a tensor (shape and data type)
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TensorConfig {
     pub name: String,
+    /// Default shape; for flexible inputs the model's default (or largest) accepted shape
     pub shape: Vec<usize>,
-    pub data_type: String,
+    pub data_type: TensorDataType,
+    /// Other shapes a flexible input accepts; `None` for fixed-shape tensors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flexible_shape: Option<FlexibleShape>,
+}
+
+/// Shapes accepted by a flexible CoreML input besides its default `shape`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlexibleShape {
+    /// One of a fixed list of shapes (CoreML `enumeratedShapes`)
+    Enumerated(Vec<Vec<usize>>),
+    /// Any shape whose dimensions fall within per-dimension bounds (CoreML `shapeRange`)
+    Range(Vec<DimRange>),
+}
+
+/// Inclusive bounds of one flexible dimension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DimRange {
+    pub lower: usize,
+    /// `None` when the dimension is unbounded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upper: Option<usize>,
+}
+
+impl DimRange {
+    pub fn contains(&self, dim: usize) -> bool {
+        dim >= self.lower && self.upper.is_none_or(|upper| dim <= upper)
+    }
+
+    /// Closest allowed size to `dim`
+    pub fn clamp(&self, dim: usize) -> usize {
+        let dim = dim.max(self.lower);
+        self.upper.map_or(dim, |upper| dim.min(upper))
+    }
+}
+
+impl TensorConfig {
+    pub fn is_flexible(&self) -> bool {
+        self.flexible_shape.is_some()
+    }
+
+    /// Whether the model accepts `shape` for this tensor
+    pub fn accepts_shape(&self, shape: &[usize]) -> bool {
+        match &self.flexible_shape {
+            None => shape == self.shape.as_slice(),
+            Some(FlexibleShape::Enumerated(shapes)) => {
+                shape == self.shape.as_slice() || shapes.iter().any(|s| s == shape)
+            }
+            Some(FlexibleShape::Range(ranges)) => {
+                ranges.len() == shape.len()
+                    && ranges
+                        .iter()
+                        .zip(shape)
+                        .all(|(range, &dim)| range.contains(dim))
+            }
+        }
+    }
+
+    /// Largest accepted shape; unbounded range dimensions report the default `shape`
+    pub fn max_shape(&self) -> Vec<usize> {
+        match &self.flexible_shape {
+            None => self.shape.clone(),
+            Some(FlexibleShape::Enumerated(shapes)) => shapes
+                .iter()
+                .chain(std::iter::once(&self.shape))
+                .max_by_key(|s| s.iter().product::<usize>())
+                .cloned()
+                .unwrap_or_default(),
+            Some(FlexibleShape::Range(ranges)) => ranges
+                .iter()
+                .enumerate()
+                .map(|(i, range)| {
+                    let default = self.shape.get(i).copied().unwrap_or(range.lower);
+                    range.upper.unwrap_or(default.max(range.lower))
+                })
+                .collect(),
+        }
+    }
+
+    /// Smallest accepted shape whose dimension `axis` holds at least `len` elements
+    ///
+    /// Fixed tensors always report `shape`; when `len` exceeds every accepted size the
+    /// largest one is returned and the caller truncates as it would for a fixed shape.
+    pub fn shape_for_length(&self, axis: usize, len: usize) -> Vec<usize> {
+        match &self.flexible_shape {
+            None => self.shape.clone(),
+            Some(FlexibleShape::Enumerated(shapes)) => shapes
+                .iter()
+                .chain(std::iter::once(&self.shape))
+                .filter(|s| s.get(axis).is_some_and(|&dim| dim >= len))
+                .min_by_key(|s| (s[axis], s.iter().product::<usize>()))
+                .cloned()
+                .unwrap_or_else(|| self.max_shape()),
+            Some(FlexibleShape::Range(ranges)) => ranges
+                .iter()
+                .enumerate()
+                .map(|(i, range)| {
+                    if i == axis {
+                        range.clamp(len)
+                    } else {
+                        range.clamp(self.shape.get(i).copied().unwrap_or(range.lower))
+                    }
+                })
+                .collect(),
+        }
+    }
 }
 
 /// Model file naming patterns for component discovery
@@ -81,11 +262,13 @@ impl ModelConfig {
     /// Create a minimal default Qwen ModelConfig (no components). Useful for tests and fallbacks.
     pub fn default_qwen() -> Self {
         Self {
+            schema_version: CURRENT_SCHEMA_VERSION,
             model_info: ModelInfo {
                 model_id: Some("default/qwen".to_string()),
                 path: None,
                 model_type: "qwen".to_string(),
                 discovered_at: None,
+                family: None,
             },
             shapes: ShapeConfig {
                 batch_size: 1,
@@ -109,9 +292,100 @@ impl ModelConfig {
         let content = std::fs::read_to_string(path)
             .with_context(|| format!("Failed to read config file: {}", path.display()))?;
 
-        let config: ModelConfig = serde_json::from_str(&content)
-            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+        Self::from_json_str(&content)
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))
+    }
 
+    /// Parse configuration JSON, upgrading configs written with an older schema
+    pub fn from_json_str(json: &str) -> Result<Self> {
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        for warning in migration::migrate_to_latest(&mut value)? {
+            warn!("ModelConfig migration: {}", warning);
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Parse configuration JSON in strict mode
+    ///
+    /// Older schemas are migrated first; the result must then match
+    /// [`ModelConfig::json_schema`] exactly (no unknown fields, known data types, `input_order`
+    /// entries declared in `inputs`). Every problem is reported with its JSON path.
+    pub fn from_json_str_strict(json: &str) -> std::result::Result<Self, Vec<ConfigDiagnostic>> {
+        let root_error = |message: String| {
+            vec![ConfigDiagnostic {
+                path: "$".to_string(),
+                message,
+            }]
+        };
+        let mut value: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| root_error(e.to_string()))?;
+        for warning in
+            migration::migrate_to_latest(&mut value).map_err(|e| root_error(e.to_string()))?
+        {
+            warn!("ModelConfig migration: {}", warning);
+        }
+        let diagnostics = schema::validate_strict(&value);
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        for warning in schema::strict_warnings(&value) {
+            warn!("ModelConfig: {}", warning);
+        }
+        serde_json::from_value(value).map_err(|e| root_error(e.to_string()))
+    }
+
+    /// Load a configuration file in strict mode (see [`ModelConfig::from_json_str_strict`])
+    pub fn load_from_file_strict<P: AsRef<Path>>(
+        path: P,
+    ) -> std::result::Result<Self, Vec<ConfigDiagnostic>> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            vec![ConfigDiagnostic {
+                path: "$".to_string(),
+                message: format!("Failed to read config file {}: {e}", path.display()),
+            }]
+        })?;
+        Self::from_json_str_strict(&content)
+    }
+
+    /// JSON Schema describing the current config layout
+    pub fn json_schema() -> serde_json::Value {
+        schema::model_config_json_schema()
+    }
+
+    /// Upgrade this configuration to [`CURRENT_SCHEMA_VERSION`]
+    ///
+    /// Fields dropped by deserialization (such as per-component `file_pattern`) are
+    /// gone at this point; use [`ModelConfig::from_json_str`] to migrate raw JSON.
+    pub fn to_latest(self) -> Result<Self> {
+        if self.schema_version == CURRENT_SCHEMA_VERSION {
+            return Ok(self);
+        }
+        Self::from_json_str(&serde_json::to_string(&self)?)
+    }
+
+    /// Apply a partial override document (JSON merge-patch) on top of this configuration
+    ///
+    /// Patch paths follow the current schema. The merged config is validated, so an
+    /// override cannot leave the config in a state the loader would reject later.
+    pub fn merge_patch(&self, patch: &serde_json::Value) -> Result<Self> {
+        let mut value = serde_json::to_value(self).context("Failed to serialize configuration")?;
+        overrides::merge_patch(&mut value, patch);
+        let merged: Self =
+            serde_json::from_value(value).context("Override does not fit the config layout")?;
+        merged.validate()?;
+        Ok(merged)
+    }
+
+    /// Apply override layers in order, later layers winning
+    pub fn apply_overrides(&self, layers: &[ConfigOverride]) -> Result<Self> {
+        let mut config = self.clone();
+        for layer in layers {
+            debug!("Applying config override from {}", layer.source);
+            config = config
+                .merge_patch(&layer.patch)
+                .with_context(|| format!("Invalid config override from {}", layer.source))?;
+        }
         Ok(config)
     }
 
@@ -127,6 +401,21 @@ impl ModelConfig {
         Ok(())
     }
 
+    /// Get the full tensor configuration for a specific component and tensor
+    pub fn get_tensor_config(
+        &self,
+        component: &str,
+        tensor_name: &str,
+        is_input: bool,
+    ) -> Option<&TensorConfig> {
+        let component_config = self.components.get(component)?;
+        if is_input {
+            component_config.inputs.get(tensor_name)
+        } else {
+            component_config.outputs.get(tensor_name)
+        }
+    }
+
     /// Get the shape configuration for a specific component and tensor
     pub fn get_tensor_shape(
         &self,
@@ -145,6 +434,57 @@ impl ModelConfig {
         tensor_map.get(tensor_name).map(|tensor| &tensor.shape)
     }
 
+    /// Get the declared data type for a specific component and tensor
+    pub fn get_tensor_data_type(
+        &self,
+        component: &str,
+        tensor_name: &str,
+        is_input: bool,
+    ) -> Option<TensorDataType> {
+        let component_config = self.components.get(component)?;
+
+        let tensor_map = if is_input {
+            &component_config.inputs
+        } else {
+            &component_config.outputs
+        };
+
+        tensor_map.get(tensor_name).map(|tensor| tensor.data_type)
+    }
+
+    /// Cast `tensor` to the dtype `component` declares for its input `tensor_name`
+    ///
+    /// Tensors are returned unchanged when the input is undeclared, its type is unknown,
+    /// or candle has no matching dtype.
+    pub fn to_declared_dtype(
+        &self,
+        tensor: Tensor,
+        component: &str,
+        tensor_name: &str,
+    ) -> Result<Tensor, CandleError> {
+        match self
+            .get_tensor_data_type(component, tensor_name, true)
+            .and_then(TensorDataType::candle_dtype)
+        {
+            Some(dtype) if dtype != tensor.dtype() => tensor.to_dtype(dtype),
+            _ => Ok(tensor),
+        }
+    }
+
+    /// FFN component whose declaration of input `tensor_name` applies in the given mode:
+    /// `ffn_infer` for infer when it declares the input, `ffn_prefill` otherwise
+    pub fn ffn_input_component(&self, tensor_name: &str, is_prefill: bool) -> &'static str {
+        let infer_declares = self
+            .components
+            .get("ffn_infer")
+            .is_some_and(|c| c.inputs.contains_key(tensor_name));
+        if !is_prefill && infer_declares {
+            "ffn_infer"
+        } else {
+            "ffn_prefill"
+        }
+    }
+
     /// Get the expected input shape for embeddings
     pub fn embeddings_input_shape(&self) -> Option<&Vec<usize>> {
         self.get_tensor_shape("embeddings", "input_ids", true)
@@ -243,6 +583,20 @@ impl ModelConfig {
             return Err(anyhow::anyhow!("vocab_size must be greater than 0"));
         }
 
+        // Split FFNs must be chunked the same way for both phases
+        if let (Some(prefill), Some(infer)) = (
+            self.components.get("ffn_prefill"),
+            self.components.get("ffn_infer"),
+        ) {
+            if prefill.chunk_files().len() != infer.chunk_files().len() {
+                return Err(anyhow::anyhow!(
+                    "ffn_prefill has {} chunks but ffn_infer has {}",
+                    prefill.chunk_files().len(),
+                    infer.chunk_files().len()
+                ));
+            }
+        }
+
         // Validate tensor shapes make sense
         for (component_name, component) in &self.components {
             for (tensor_name, tensor) in &component.inputs {
@@ -317,6 +671,14 @@ impl ModelConfig {
         Ok(())
     }
 
+    /// Number of chunks the FFN is split into (1 for single-file exports)
+    pub fn ffn_chunk_count(&self) -> usize {
+        self.components
+            .get("ffn_prefill")
+            .map(|c| c.chunk_files().len().max(1))
+            .unwrap_or(1)
+    }
+
     /// Determine if FFN execution should be treated as split (separate infer component)
     pub fn ffn_is_split(&self) -> bool {
         if let Some(mode) = self.ffn_execution.as_deref() {
@@ -380,14 +742,35 @@ impl ModelConfig {
 
     // Tensor Creation Methods (moved from QwenConfig for consolidation)
 
+    /// Reject token sequences longer than the context window.
+    ///
+    /// Overlong prompts must be shortened with an
+    /// [`OverflowPolicy`](crate::generation::OverflowPolicy) before tensors are built.
+    pub fn check_context_length(&self, token_count: usize) -> Result<(), CandleError> {
+        let context_length = self.shapes.context_length;
+        if token_count > context_length {
+            return Err(CandleError::Msg(format!(
+                "{token_count} tokens exceed the context length of {context_length}; apply an \
+                 OverflowPolicy (e.g. GenerationConfig::overflow_policy) before building inputs"
+            )));
+        }
+        Ok(())
+    }
+
     /// Create embeddings input tensor with proper shape from configuration
+    ///
+    /// Flexible inputs get the smallest accepted sequence length that holds the tokens.
+    /// Tokens beyond the embeddings input length are cut off (callers split longer
+    /// sequences into windows); sequences longer than the context window are an error.
     pub fn create_embeddings_input_tensor(
         &self,
         tokens: &[i64],
         device: &Device,
     ) -> Result<Tensor, CandleError> {
+        self.check_context_length(tokens.len())?;
         let expected_shape = self
-            .embeddings_input_shape()
+            .get_tensor_config("embeddings", "input_ids", true)
+            .map(|tensor| tensor.shape_for_length(1, tokens.len()))
             .ok_or_else(|| CandleError::Msg("No embeddings input shape found".to_string()))?;
         let expected_len = expected_shape[1]; // [batch, seq_len] -> seq_len
 
@@ -395,29 +778,33 @@ impl ModelConfig {
         let mut padded_tokens = tokens.to_vec();
         padded_tokens.resize(expected_len, 0); // Pad with 0s
 
-        Tensor::from_vec(
+        let tensor = Tensor::from_vec(
             padded_tokens,
             (expected_shape[0], expected_shape[1]),
             device,
-        )
+        )?;
+        self.to_declared_dtype(tensor, "embeddings", "input_ids")
     }
 
     /// Create position IDs tensor for FFN prefill with proper shape
+    ///
+    /// Flexible inputs get the smallest accepted length that holds `positions`.
     pub fn create_ffn_position_ids_tensor(
         &self,
         positions: &[i64],
         device: &Device,
     ) -> Result<Tensor, CandleError> {
-        let expected_shape = self
-            .get_tensor_shape("ffn_prefill", "position_ids", true)
+        let tensor_config = self
+            .get_tensor_config("ffn_prefill", "position_ids", true)
             .ok_or_else(|| {
                 CandleError::Msg("No FFN prefill position_ids shape found".to_string())
             })?;
+        let expected_shape = tensor_config.shape_for_length(0, positions.len());
 
         // Heuristic: some manifests report position_ids length as [1] even for prefill.
         // When that happens, derive the true sequence length from other known shapes.
         let mut expected_len = expected_shape[0];
-        if expected_len == 1 {
+        if expected_len == 1 && !tensor_config.is_flexible() {
             // Prefer prefill hidden_states seq_len if available and > 1
             if let Some(hs_shape) = self.get_tensor_shape("ffn_prefill", "hidden_states", true) {
                 if hs_shape.len() == 3 && hs_shape[1] > 1 {
@@ -445,20 +832,28 @@ impl ModelConfig {
             }
         }
 
-        Tensor::from_vec(position_ids, (expected_len,), device)
+        let tensor = Tensor::from_vec(position_ids, (expected_len,), device)?;
+        self.to_declared_dtype(tensor, "ffn_prefill", "position_ids")
     }
 
     /// Create causal mask tensor for FFN with proper shape
+    ///
+    /// A flexible mask gets the smallest accepted row count of at least `batch_size`
+    /// (the number of query tokens); `0` keeps the configured default shape.
     pub fn create_ffn_causal_mask_tensor(
         &self,
-        _batch_size: usize,
+        batch_size: usize,
         _context_length: usize,
         device: &Device,
     ) -> Result<Tensor, CandleError> {
         // Prefer explicit shape from config; otherwise synthesize a reasonable default
         let expected_shape_vec =
-            if let Some(shape) = self.get_tensor_shape("ffn_prefill", "causal_mask", true) {
-                shape.clone()
+            if let Some(tensor) = self.get_tensor_config("ffn_prefill", "causal_mask", true) {
+                if batch_size > 0 {
+                    tensor.shape_for_length(2, batch_size)
+                } else {
+                    tensor.shape.clone()
+                }
             } else {
                 // Derive a default square mask [1,1,seq_len,seq_len]
                 let mut seq_len = 0usize;
@@ -491,7 +886,7 @@ impl ModelConfig {
             }
         }
 
-        Tensor::from_vec(
+        let tensor = Tensor::from_vec(
             mask_data,
             (
                 expected_shape_vec[0],
@@ -500,7 +895,8 @@ impl ModelConfig {
                 expected_shape_vec[3],
             ),
             device,
-        )
+        )?;
+        self.to_declared_dtype(tensor, "ffn_prefill", "causal_mask")
     }
 
     /// Create single token hidden states tensor for LM head
@@ -517,7 +913,8 @@ impl ModelConfig {
         let tensor_data = vec![0.0f32; expected_shape.iter().product()];
         let shape = (expected_shape[0], expected_shape[1], expected_shape[2]);
 
-        Tensor::from_vec(tensor_data, shape, device)
+        let tensor = Tensor::from_vec(tensor_data, shape, device)?;
+        self.to_declared_dtype(tensor, "lm_head", "hidden_states")
     }
 
     /// Create position IDs tensor for inference (single position)
@@ -527,20 +924,26 @@ impl ModelConfig {
         device: &Device,
     ) -> Result<Tensor, CandleError> {
         // Check if we have a dedicated ffn_infer component with specific shape
-        if let Some(infer_shape) = self.get_tensor_shape("ffn_infer", "position_ids", true) {
-            // Use the infer-specific shape
-            if infer_shape.len() == 1 {
-                Tensor::from_vec(vec![position], (infer_shape[0],), device)
+        let tensor =
+            if let Some(infer_shape) = self.get_tensor_shape("ffn_infer", "position_ids", true) {
+                // Use the infer-specific shape
+                if infer_shape.len() == 1 {
+                    Tensor::from_vec(vec![position], (infer_shape[0],), device)?
+                } else {
+                    let size = infer_shape.iter().product();
+                    let mut data = vec![0i64; size];
+                    data[0] = position;
+                    Tensor::from_vec(data, infer_shape.as_slice(), device)?
+                }
             } else {
-                let size = infer_shape.iter().product();
-                let mut data = vec![0i64; size];
-                data[0] = position;
-                Tensor::from_vec(data, infer_shape.as_slice(), device)
-            }
-        } else {
-            // No dedicated infer component - use single position for inference (original QwenConfig behavior)
-            Tensor::from_vec(vec![position], (1,), device)
-        }
+                // No dedicated infer component - use single position for inference (original QwenConfig behavior)
+                Tensor::from_vec(vec![position], (1,), device)?
+            };
+        self.to_declared_dtype(
+            tensor,
+            self.ffn_input_component("position_ids", false),
+            "position_ids",
+        )
     }
 
     /// Create current position tensor for FFN
@@ -550,7 +953,12 @@ impl ModelConfig {
         device: &Device,
     ) -> Result<Tensor, CandleError> {
         // Most models expect [1] shape for current_pos
-        Tensor::from_vec(vec![position], (1,), device)
+        let tensor = Tensor::from_vec(vec![position], (1,), device)?;
+        self.to_declared_dtype(
+            tensor,
+            self.ffn_input_component("current_pos", false),
+            "current_pos",
+        )
     }
 }
 
@@ -569,7 +977,8 @@ mod tests {
             TensorConfig {
                 name: "input_ids".to_string(),
                 shape: vec![1, 64],
-                data_type: "INT32".to_string(),
+                data_type: TensorDataType::Int32,
+                flexible_shape: None,
             },
         );
 
@@ -579,7 +988,8 @@ mod tests {
             TensorConfig {
                 name: "hidden_states".to_string(),
                 shape: vec![1, 64, 1024],
-                data_type: "FLOAT16".to_string(),
+                data_type: TensorDataType::Float16,
+                flexible_shape: None,
             },
         );
 
@@ -591,6 +1001,8 @@ mod tests {
                 outputs: embeddings_outputs,
                 functions: vec![],
                 input_order: None,
+                states: HashMap::new(),
+                chunks: Vec::new(),
             },
         );
 
@@ -601,7 +1013,8 @@ mod tests {
             TensorConfig {
                 name: "hidden_states".to_string(),
                 shape: vec![1, 1, 1024],
-                data_type: "FLOAT16".to_string(),
+                data_type: TensorDataType::Float16,
+                flexible_shape: None,
             },
         );
 
@@ -611,7 +1024,8 @@ mod tests {
             TensorConfig {
                 name: "logits".to_string(),
                 shape: vec![1, 1, 151936],
-                data_type: "FLOAT32".to_string(),
+                data_type: TensorDataType::Float32,
+                flexible_shape: None,
             },
         );
 
@@ -623,15 +1037,19 @@ mod tests {
                 outputs: lm_head_outputs,
                 functions: vec![],
                 input_order: None,
+                states: HashMap::new(),
+                chunks: Vec::new(),
             },
         );
 
         ModelConfig {
+            schema_version: CURRENT_SCHEMA_VERSION,
             model_info: ModelInfo {
                 model_id: Some("test/model".to_string()),
                 path: Some("/test/path".to_string()),
                 model_type: "qwen".to_string(),
                 discovered_at: Some("2025-08-07T00:00:00".to_string()),
+                family: None,
             },
             shapes: ShapeConfig {
                 batch_size: 1,
@@ -710,7 +1128,8 @@ mod tests {
             TensorConfig {
                 name: "logits1".to_string(),
                 shape: vec![1, 1, 9480],
-                data_type: "FLOAT32".to_string(),
+                data_type: TensorDataType::Float32,
+                flexible_shape: None,
             },
         );
         lm_head.outputs.insert(
@@ -718,7 +1137,8 @@ mod tests {
             TensorConfig {
                 name: "logits2".to_string(),
                 shape: vec![1, 1, 9479],
-                data_type: "FLOAT32".to_string(),
+                data_type: TensorDataType::Float32,
+                flexible_shape: None,
             },
         );
 
@@ -744,4 +1164,271 @@ mod tests {
         invalid_shapes.shapes.batch_size = 0;
         assert!(invalid_shapes.validate().is_err());
     }
+
+    #[test]
+    fn test_config_overrides() {
+        let config = create_test_config();
+        let layers = [
+            ConfigOverride::new(
+                "model dir",
+                serde_json::json!({
+                    "shapes": {"context_length": 1024},
+                    "components": {"embeddings": {"input_order": ["input_ids"]}}
+                }),
+            ),
+            ConfigOverride::from_assignments(&["shapes.context_length=2048"]).unwrap(),
+        ];
+        let merged = config.apply_overrides(&layers).unwrap();
+        assert_eq!(merged.shapes.context_length, 2048);
+        assert_eq!(
+            merged.components["embeddings"].input_order,
+            Some(vec!["input_ids".to_string()])
+        );
+        // Untouched fields survive
+        assert_eq!(merged.shapes.hidden_size, config.shapes.hidden_size);
+        assert_eq!(
+            merged.components["embeddings"].inputs["input_ids"].data_type,
+            TensorDataType::Int32
+        );
+
+        // Overrides that break the config are rejected with their source
+        let broken = ConfigOverride::new(
+            "broken",
+            serde_json::json!({"components": {"lm_head": null}}),
+        );
+        let err = config.apply_overrides(&[broken]).unwrap_err();
+        assert!(format!("{err:#}").contains("broken"));
+        let mistyped = serde_json::json!({"shapes": {"context_length": "long"}});
+        assert!(config.merge_patch(&mistyped).is_err());
+    }
+
+    #[test]
+    fn test_ffn_chunks() {
+        let mut config = create_test_config();
+        assert_eq!(config.ffn_chunk_count(), 1);
+
+        let mut prefill = config.components["lm_head"].clone();
+        prefill.file_path = Some("qwen_FFN_PF_lut8_chunk_01of02.mlmodelc".to_string());
+        assert_eq!(
+            prefill.chunk_files(),
+            vec!["qwen_FFN_PF_lut8_chunk_01of02.mlmodelc"]
+        );
+        prefill.chunks = vec![
+            "qwen_FFN_PF_lut8_chunk_01of02.mlmodelc".to_string(),
+            "qwen_FFN_PF_lut8_chunk_02of02.mlmodelc".to_string(),
+        ];
+        let mut infer = prefill.clone();
+        config
+            .components
+            .insert("ffn_prefill".to_string(), prefill.clone());
+        config
+            .components
+            .insert("ffn_infer".to_string(), infer.clone());
+        assert_eq!(config.ffn_chunk_count(), 2);
+        assert!(config.validate().is_ok());
+
+        // Chunks survive a JSON round trip
+        let json = serde_json::to_string(&config).unwrap();
+        let parsed: ModelConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.components["ffn_prefill"].chunks, prefill.chunks);
+
+        // Prefill and infer must agree on the chunk count
+        infer.chunks.pop();
+        config.components.insert("ffn_infer".to_string(), infer);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_tensors_use_declared_data_types() {
+        use candle_core::DType;
+
+        let tensor = |name: &str, shape: Vec<usize>, data_type| {
+            (
+                name.to_string(),
+                TensorConfig {
+                    name: name.to_string(),
+                    shape,
+                    data_type,
+                    flexible_shape: None,
+                },
+            )
+        };
+        let mut config = create_test_config();
+        let mut ffn = config.components["lm_head"].clone();
+        ffn.inputs = [
+            tensor("position_ids", vec![64], TensorDataType::Int32),
+            tensor("causal_mask", vec![1, 1, 64, 512], TensorDataType::Float16),
+            tensor("current_pos", vec![1], TensorDataType::Int32),
+        ]
+        .into_iter()
+        .collect();
+        config.components.insert("ffn_prefill".to_string(), ffn);
+        let device = Device::Cpu;
+
+        let input_ids = config
+            .create_embeddings_input_tensor(&[1, 2, 3], &device)
+            .unwrap();
+        assert_eq!(input_ids.dtype(), DType::I32);
+        assert_eq!(input_ids.dims(), &[1, 64]);
+        let positions = config
+            .create_ffn_position_ids_tensor(&[0, 1, 2], &device)
+            .unwrap();
+        assert_eq!(positions.dtype(), DType::I32);
+        let mask = config
+            .create_ffn_causal_mask_tensor(64, 512, &device)
+            .unwrap();
+        assert_eq!(mask.dtype(), DType::F16);
+        let current_pos = config.create_current_pos_tensor(3, &device).unwrap();
+        assert_eq!(current_pos.dtype(), DType::I32);
+        assert_eq!(current_pos.to_vec1::<i32>().unwrap(), vec![3]);
+        let hidden = config
+            .create_single_token_hidden_states(&[1], &device)
+            .unwrap();
+        assert_eq!(hidden.dtype(), DType::F16);
+
+        // Unknown types keep the tensor as built
+        config
+            .components
+            .get_mut("embeddings")
+            .unwrap()
+            .inputs
+            .get_mut("input_ids")
+            .unwrap()
+            .data_type = TensorDataType::Unknown;
+        let input_ids = config
+            .create_embeddings_input_tensor(&[1], &device)
+            .unwrap();
+        assert_eq!(input_ids.dtype(), DType::I64);
+    }
+
+    #[test]
+    fn test_flexible_shapes() {
+        let tensor = |flexible_shape| TensorConfig {
+            name: "input_ids".to_string(),
+            shape: vec![1, 512],
+            data_type: TensorDataType::Int32,
+            flexible_shape,
+        };
+        let enumerated = tensor(Some(FlexibleShape::Enumerated(vec![
+            vec![1, 1],
+            vec![1, 64],
+            vec![1, 512],
+        ])));
+        assert_eq!(enumerated.shape_for_length(1, 5), vec![1, 64]);
+        assert_eq!(enumerated.shape_for_length(1, 1), vec![1, 1]);
+        assert_eq!(enumerated.shape_for_length(1, 900), vec![1, 512]);
+        assert!(enumerated.accepts_shape(&[1, 64]));
+        assert!(!enumerated.accepts_shape(&[1, 5]));
+
+        let ranged = tensor(Some(FlexibleShape::Range(vec![
+            DimRange {
+                lower: 1,
+                upper: Some(1),
+            },
+            DimRange {
+                lower: 1,
+                upper: None,
+            },
+        ])));
+        assert_eq!(ranged.shape_for_length(1, 5), vec![1, 5]);
+        assert_eq!(ranged.max_shape(), vec![1, 512]);
+        assert!(ranged.accepts_shape(&[1, 2048]));
+
+        let fixed = tensor(None);
+        assert_eq!(fixed.shape_for_length(1, 5), vec![1, 512]);
+        assert!(!fixed.accepts_shape(&[1, 5]));
+
+        // Serialized as {"range": [{"lower": 1, "upper": 1}, {"lower": 1}]}
+        let json = serde_json::to_value(&ranged).unwrap();
+        assert_eq!(
+            json["flexible_shape"]["range"][1],
+            serde_json::json!({"lower": 1})
+        );
+        let parsed: TensorConfig = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.flexible_shape, ranged.flexible_shape);
+        assert!(serde_json::to_value(&fixed)
+            .unwrap()
+            .get("flexible_shape")
+            .is_none());
+    }
+
+    #[test]
+    fn test_builders_fit_flexible_shapes() {
+        let mut config = create_test_config();
+        config.shapes.context_length = 512;
+        let input_ids = config
+            .components
+            .get_mut("embeddings")
+            .unwrap()
+            .inputs
+            .get_mut("input_ids")
+            .unwrap();
+        input_ids.shape = vec![1, 512];
+        input_ids.flexible_shape = Some(FlexibleShape::Range(vec![
+            DimRange {
+                lower: 1,
+                upper: Some(1),
+            },
+            DimRange {
+                lower: 1,
+                upper: Some(512),
+            },
+        ]));
+        let device = Device::Cpu;
+
+        let tensor = config
+            .create_embeddings_input_tensor(&[7, 8, 9, 10, 11], &device)
+            .unwrap();
+        assert_eq!(tensor.dims(), &[1, 5]);
+        assert_eq!(
+            tensor
+                .to_dtype(candle_core::DType::I64)
+                .unwrap()
+                .to_vec2::<i64>()
+                .unwrap(),
+            vec![vec![7, 8, 9, 10, 11]]
+        );
+
+        let mut ffn = config.components["lm_head"].clone();
+        ffn.inputs = [
+            (
+                "position_ids".to_string(),
+                TensorConfig {
+                    name: "position_ids".to_string(),
+                    shape: vec![512],
+                    data_type: TensorDataType::Int32,
+                    flexible_shape: Some(FlexibleShape::Enumerated(vec![vec![1], vec![64]])),
+                },
+            ),
+            (
+                "causal_mask".to_string(),
+                TensorConfig {
+                    name: "causal_mask".to_string(),
+                    shape: vec![1, 1, 512, 512],
+                    data_type: TensorDataType::Float16,
+                    flexible_shape: Some(FlexibleShape::Enumerated(vec![
+                        vec![1, 1, 1, 512],
+                        vec![1, 1, 64, 512],
+                    ])),
+                },
+            ),
+        ]
+        .into_iter()
+        .collect();
+        config.components.insert("ffn_prefill".to_string(), ffn);
+
+        let positions = config
+            .create_ffn_position_ids_tensor(&[0, 1, 2, 3, 4], &device)
+            .unwrap();
+        assert_eq!(positions.dims(), &[64]);
+        let mask = config
+            .create_ffn_causal_mask_tensor(5, 512, &device)
+            .unwrap();
+        assert_eq!(mask.dims(), &[1, 1, 64, 512]);
+        // 0 rows requested: the configured default shape
+        let mask = config
+            .create_ffn_causal_mask_tensor(0, 512, &device)
+            .unwrap();
+        assert_eq!(mask.dims(), &[1, 1, 512, 512]);
+    }
 }
@@ -0,0 +1,220 @@
+//! Partial overrides layered on top of generated or cached `ModelConfig`s
+//!
+//! An override is a JSON merge-patch (RFC 7396): objects merge recursively, any other
+//! value replaces the target, and `null` removes a field. Fixing a single wrong value
+//! therefore only needs the path to it, e.g. `{"shapes": {"context_length": 1024}}`.
+//!
+//! Overrides come from, in increasing precedence:
+//! 1. [`MODEL_DIR_OVERRIDE_FILE`] in the model directory
+//! 2. the [`CONFIG_OVERRIDE_ENV`] environment variable (a file path or inline JSON)
+//! 3. overrides passed explicitly, e.g. from `key=value` command line arguments
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+/// Environment variable holding an override file path or inline JSON
+pub const CONFIG_OVERRIDE_ENV: &str = "CANDLE_COREML_CONFIG_OVERRIDE";
+
+/// Override file picked up from the model directory
+pub const MODEL_DIR_OVERRIDE_FILE: &str = "config-override.json";
+
+/// One override layer and where it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigOverride {
+    /// Human-readable origin, used in logs and errors
+    pub source: String,
+    /// JSON merge-patch applied to the serialized config
+    pub patch: Value,
+}
+
+impl ConfigOverride {
+    pub fn new(source: impl Into<String>, patch: Value) -> Self {
+        Self {
+            source: source.into(),
+            patch,
+        }
+    }
+
+    /// Parse an override document
+    pub fn from_json_str(source: impl Into<String>, json: &str) -> Result<Self> {
+        let source = source.into();
+        let patch: Value = serde_json::from_str(json)
+            .with_context(|| format!("Failed to parse config override from {source}"))?;
+        if !patch.is_object() {
+            anyhow::bail!("Config override from {source} must be a JSON object");
+        }
+        Ok(Self { source, patch })
+    }
+
+    /// Read an override document from a file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config override: {}", path.display()))?;
+        Self::from_json_str(path.display().to_string(), &json)
+    }
+
+    /// The override file in `model_dir`, if there is one
+    pub fn from_model_dir<P: AsRef<Path>>(model_dir: P) -> Result<Option<Self>> {
+        let path = model_dir.as_ref().join(MODEL_DIR_OVERRIDE_FILE);
+        if path.is_file() {
+            Self::from_file(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The override named by [`CONFIG_OVERRIDE_ENV`], if it is set and non-empty
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(CONFIG_OVERRIDE_ENV) {
+            Ok(value) if !value.trim().is_empty() => Self::from_env_value(&value).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Interpret an environment value: inline JSON when it starts with `{`, otherwise a path
+    pub fn from_env_value(value: &str) -> Result<Self> {
+        let value = value.trim();
+        if value.starts_with('{') {
+            Self::from_json_str(CONFIG_OVERRIDE_ENV, value)
+        } else {
+            Self::from_file(value)
+                .with_context(|| format!("{CONFIG_OVERRIDE_ENV} does not name a readable file"))
+        }
+    }
+
+    /// Build an override from `dotted.path=value` assignments
+    ///
+    /// Values are parsed as JSON when possible (`1024`, `null`, `["a","b"]`) and taken as
+    /// strings otherwise, so `ffn_execution=split` works without quoting.
+    pub fn from_assignments<S: AsRef<str>>(assignments: &[S]) -> Result<Self> {
+        let mut patch = Value::Object(Map::new());
+        for assignment in assignments {
+            let assignment = assignment.as_ref();
+            let (path, raw) = assignment.split_once('=').with_context(|| {
+                format!("Config override '{assignment}' is not of the form path=value")
+            })?;
+            let keys: Vec<&str> = path.trim().split('.').collect();
+            if keys.iter().any(|key| key.is_empty()) {
+                anyhow::bail!("Config override '{assignment}' has an empty path segment");
+            }
+            let value =
+                serde_json::from_str(raw.trim()).unwrap_or_else(|_| Value::from(raw.trim()));
+
+            let mut target = &mut patch;
+            for key in &keys[..keys.len() - 1] {
+                let object = target.as_object_mut().expect("patch nodes are objects");
+                target = object
+                    .entry(key.to_string())
+                    .or_insert_with(|| Value::Object(Map::new()));
+                if !target.is_object() {
+                    anyhow::bail!("Config override '{assignment}' conflicts with an earlier one");
+                }
+            }
+            target
+                .as_object_mut()
+                .expect("patch nodes are objects")
+                .insert(keys[keys.len() - 1].to_string(), value);
+        }
+        Ok(Self::new("command line", patch))
+    }
+}
+
+/// Apply a JSON merge-patch (RFC 7396) to `target` in place
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let target = target.as_object_mut().expect("just made an object");
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_merge_patch_follows_rfc_7396() {
+        let mut target = json!({
+            "title": "Goodbye!",
+            "author": {"givenName": "John", "familyName": "Doe"},
+            "tags": ["example", "sample"],
+            "content": "This will be unchanged"
+        });
+        merge_patch(
+            &mut target,
+            &json!({
+                "title": "Hello!",
+                "phoneNumber": "+01-123-456-7890",
+                "author": {"familyName": null},
+                "tags": ["example"]
+            }),
+        );
+        assert_eq!(
+            target,
+            json!({
+                "title": "Hello!",
+                "author": {"givenName": "John"},
+                "tags": ["example"],
+                "content": "This will be unchanged",
+                "phoneNumber": "+01-123-456-7890"
+            })
+        );
+    }
+
+    #[test]
+    fn test_assignments_build_nested_patch() {
+        let cli = ConfigOverride::from_assignments(&[
+            "shapes.context_length=1024",
+            "ffn_execution=split",
+            "components.ffn_prefill.input_order=[\"hidden_states\",\"position_ids\"]",
+            "model_info.discovered_at=null",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.patch,
+            json!({
+                "shapes": {"context_length": 1024},
+                "ffn_execution": "split",
+                "components": {"ffn_prefill": {"input_order": ["hidden_states", "position_ids"]}},
+                "model_info": {"discovered_at": null}
+            })
+        );
+        assert!(ConfigOverride::from_assignments(&["shapes.context_length"]).is_err());
+        assert!(ConfigOverride::from_assignments(&["a=1", "a.b=2"]).is_err());
+    }
+
+    #[test]
+    fn test_override_sources() {
+        let dir = TempDir::new().unwrap();
+        assert!(ConfigOverride::from_model_dir(dir.path())
+            .unwrap()
+            .is_none());
+
+        let path = dir.path().join(MODEL_DIR_OVERRIDE_FILE);
+        std::fs::write(&path, r#"{"shapes": {"context_length": 1024}}"#).unwrap();
+        let file = ConfigOverride::from_model_dir(dir.path()).unwrap().unwrap();
+        assert_eq!(file.patch, json!({"shapes": {"context_length": 1024}}));
+
+        let inline = ConfigOverride::from_env_value(r#" {"ffn_execution": "split"} "#).unwrap();
+        assert_eq!(inline.source, CONFIG_OVERRIDE_ENV);
+        let by_path = ConfigOverride::from_env_value(path.to_str().unwrap()).unwrap();
+        assert_eq!(by_path.patch, file.patch);
+
+        assert!(ConfigOverride::from_env_value("/does/not/exist.json").is_err());
+        assert!(ConfigOverride::from_json_str("test", "[1, 2]").is_err());
+    }
+}
@@ -0,0 +1,628 @@
+//! JSON Schema export and strict validation for `ModelConfig` files
+//!
+//! [`model_config_json_schema`] describes the current config layout (draft 2020-12) so
+//! editors and CI can check hand-written configs. [`validate_strict`] applies the same
+//! schema plus the cross-field rules a schema cannot express, and reports every problem
+//! with its JSON path instead of stopping at the first one. [`strict_warnings`] reports
+//! what is valid but worth a look, such as tensors whose data type the generator could
+//! not determine.
+
+use crate::config::model::CURRENT_SCHEMA_VERSION;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Tensor data type names accepted in `TensorConfig::data_type`: the canonical
+/// [`TensorDataType`](crate::config::TensorDataType) names plus the legacy `DOUBLE`.
+///
+/// `UNKNOWN` is what the generator writes for a type it cannot map; it is accepted with a
+/// warning (see [`strict_warnings`]).
+pub const KNOWN_DATA_TYPES: &[&str] = &[
+    "FLOAT16", "FLOAT32", "FLOAT64", "DOUBLE", "BFLOAT16", "INT4", "INT8", "INT16", "INT32",
+    "INT64", "UINT8", "UINT16", "UINT32", "UINT64", "BOOL", "STRING", "UNKNOWN",
+];
+
+/// Component keys the runtime knows how to wire together
+pub const KNOWN_COMPONENTS: &[&str] = &["embeddings", "ffn_prefill", "ffn_infer", "lm_head"];
+
+/// One problem found by strict validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDiagnostic {
+    /// JSONPath-style location, e.g. `$.components.lm_head.inputs.hidden_states.data_type`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// JSON Schema for the current `ModelConfig` layout
+pub fn model_config_json_schema() -> Value {
+    let nullable_string = json!({"type": ["string", "null"]});
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "ModelConfig",
+        "description": "candle-coreml model configuration: shapes, components and their tensors",
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["schema_version", "model_info", "shapes", "components", "naming"],
+        "properties": {
+            "schema_version": {"type": "integer", "minimum": 1, "maximum": CURRENT_SCHEMA_VERSION},
+            "model_info": {"$ref": "#/$defs/ModelInfo"},
+            "shapes": {"$ref": "#/$defs/ShapeConfig"},
+            "components": {
+                "type": "object",
+                "propertyNames": {"enum": KNOWN_COMPONENTS},
+                "additionalProperties": {"$ref": "#/$defs/ComponentConfig"}
+            },
+            "naming": {"$ref": "#/$defs/NamingConfig"},
+            "ffn_execution": {"enum": ["unified", "split", null]}
+        },
+        "$defs": {
+            "ModelInfo": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["model_type"],
+                "properties": {
+                    "model_id": nullable_string,
+                    "path": nullable_string,
+                    "model_type": {"type": "string"},
+                    "discovered_at": nullable_string,
+                    "family": {"$ref": "#/$defs/FamilyConfig"}
+                }
+            },
+            "FamilyConfig": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "rope_theta": {"type": "number", "minimum": 0},
+                    "eos_token_ids": {"type": "array", "items": {"type": "integer", "minimum": 0}},
+                    "chat_template": {"type": "string"}
+                }
+            },
+            "ShapeConfig": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["batch_size", "context_length", "hidden_size", "vocab_size"],
+                "properties": {
+                    "batch_size": {"type": "integer", "minimum": 1},
+                    "context_length": {"type": "integer", "minimum": 1},
+                    "hidden_size": {"type": "integer", "minimum": 1},
+                    "vocab_size": {"type": "integer", "minimum": 1}
+                }
+            },
+            "ComponentConfig": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["inputs", "outputs", "functions"],
+                "properties": {
+                    "file_path": nullable_string,
+                    "inputs": {"$ref": "#/$defs/TensorMap"},
+                    "outputs": {"$ref": "#/$defs/TensorMap"},
+                    "functions": {"type": "array", "items": {"type": "string"}},
+                    "input_order": {"type": ["array", "null"], "items": {"type": "string"}},
+                    "states": {"$ref": "#/$defs/TensorMap"},
+                    "chunks": {"type": "array", "items": {"type": "string"}}
+                }
+            },
+            "TensorMap": {
+                "type": "object",
+                "additionalProperties": {"$ref": "#/$defs/TensorConfig"}
+            },
+            "TensorConfig": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["name", "shape", "data_type"],
+                "properties": {
+                    "name": {"type": "string"},
+                    "shape": {"$ref": "#/$defs/Shape"},
+                    "data_type": {"enum": KNOWN_DATA_TYPES},
+                    "flexible_shape": {"$ref": "#/$defs/FlexibleShape"}
+                }
+            },
+            "Shape": {"type": "array", "items": {"type": "integer", "minimum": 0}},
+            "FlexibleShape": {
+                "type": "object",
+                "description": "Exactly one of `enumerated` or `range`",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "properties": {
+                    "enumerated": {"type": "array", "items": {"$ref": "#/$defs/Shape"}},
+                    "range": {"type": "array", "items": {"$ref": "#/$defs/DimRange"}}
+                }
+            },
+            "DimRange": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["lower"],
+                "properties": {
+                    "lower": {"type": "integer", "minimum": 0},
+                    "upper": {"type": ["integer", "null"], "minimum": 0}
+                }
+            },
+            "NamingConfig": {
+                "type": "object",
+                "additionalProperties": false,
+                "description": "Deprecated glob patterns; kept empty by current configs",
+                "properties": {
+                    "embeddings_pattern": nullable_string,
+                    "ffn_prefill_pattern": nullable_string,
+                    "ffn_infer_pattern": nullable_string,
+                    "lm_head_pattern": nullable_string
+                }
+            }
+        }
+    })
+}
+
+/// Check a serialized config against [`model_config_json_schema`] and the cross-field rules
+///
+/// Returns every problem found; an empty list means the config is valid. Expects the
+/// current schema version (run [`crate::config::migration::migrate_to_latest`] first for
+/// older files).
+pub fn validate_strict(value: &Value) -> Vec<ConfigDiagnostic> {
+    let schema = model_config_json_schema();
+    let mut diagnostics = Vec::new();
+    check_schema(value, &schema, &schema, "$", &mut diagnostics);
+    check_cross_fields(value, &mut diagnostics);
+    diagnostics
+}
+
+/// Problems that do not make a config invalid
+///
+/// Lists every tensor whose `data_type` is `UNKNOWN`; such tensors are built with the
+/// type of the values passed in.
+pub fn strict_warnings(value: &Value) -> Vec<ConfigDiagnostic> {
+    let mut warnings = Vec::new();
+    let Some(components) = value["components"].as_object() else {
+        return warnings;
+    };
+    for (component, config) in components {
+        for map in ["inputs", "outputs", "states"] {
+            let Some(tensors) = config[map].as_object() else {
+                continue;
+            };
+            for (name, tensor) in tensors {
+                if tensor["data_type"] == "UNKNOWN" {
+                    warnings.push(ConfigDiagnostic {
+                        path: format!("$.components.{component}.{map}.{name}.data_type"),
+                        message: "data type is UNKNOWN; the tensor keeps the type it is built with"
+                            .to_string(),
+                    });
+                }
+            }
+        }
+    }
+    warnings
+}
+
+/// Interpreter for the JSON Schema keywords used by [`model_config_json_schema`]
+fn check_schema(
+    value: &Value,
+    schema: &Value,
+    root: &Value,
+    path: &str,
+    out: &mut Vec<ConfigDiagnostic>,
+) {
+    let mut report = |message: String| {
+        out.push(ConfigDiagnostic {
+            path: path.to_string(),
+            message,
+        })
+    };
+
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let target = reference
+            .strip_prefix('#')
+            .and_then(|pointer| root.pointer(pointer));
+        match target {
+            Some(target) => check_schema(value, target, root, path, out),
+            None => report(format!("unresolved schema reference {reference}")),
+        }
+        return;
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            let names: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            report(format!("{value} is not one of {}", names.join(", ")));
+        }
+        return;
+    }
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.iter().any(|t| type_matches(value, t)) {
+            report(format!(
+                "expected {}, found {}",
+                types.join(" or "),
+                type_name(value)
+            ));
+            return;
+        }
+    }
+
+    if let Some(number) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+            if number < min {
+                report(format!("{value} is below the minimum {min}"));
+            }
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+            if number > max {
+                report(format!("{value} is above the maximum {max}"));
+            }
+        }
+    }
+
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        for (index, item) in array.iter().enumerate() {
+            check_schema(item, items, root, &format!("{path}[{index}]"), out);
+        }
+    }
+
+    let Some(object) = value.as_object() else {
+        return;
+    };
+    let count = object.len() as u64;
+    let min = schema.get("minProperties").and_then(Value::as_u64);
+    let max = schema.get("maxProperties").and_then(Value::as_u64);
+    if min.is_some_and(|min| count < min) || max.is_some_and(|max| count > max) {
+        out.push(ConfigDiagnostic {
+            path: path.to_string(),
+            message: format!(
+                "expected between {} and {} fields, found {count}",
+                min.unwrap_or(0),
+                max.map_or("any".to_string(), |max| max.to_string())
+            ),
+        });
+    }
+    if let Some(required) = schema.get("required").and_then(Value::as_array) {
+        for field in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(field) {
+                out.push(ConfigDiagnostic {
+                    path: path.to_string(),
+                    message: format!("missing required field '{field}'"),
+                });
+            }
+        }
+    }
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (key, field) in object {
+        let field_path = format!("{path}.{key}");
+        if let Some(names) = schema.get("propertyNames") {
+            check_schema(&Value::from(key.as_str()), names, root, &field_path, out);
+        }
+        match (
+            properties.and_then(|p| p.get(key)),
+            schema.get("additionalProperties"),
+        ) {
+            (Some(property), _) => check_schema(field, property, root, &field_path, out),
+            (None, Some(Value::Bool(false))) => out.push(ConfigDiagnostic {
+                path: field_path,
+                message: format!("unknown field '{key}'"),
+            }),
+            (None, Some(additional)) if additional.is_object() => {
+                check_schema(field, additional, root, &field_path, out)
+            }
+            _ => {}
+        }
+    }
+}
+
+fn type_matches(value: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_u64() || value.is_i64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => false,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Rules spanning several fields that the schema cannot express
+fn check_cross_fields(value: &Value, out: &mut Vec<ConfigDiagnostic>) {
+    let Some(components) = value.get("components").and_then(Value::as_object) else {
+        return;
+    };
+    for required in ["embeddings", "lm_head"] {
+        if !components.contains_key(required) {
+            out.push(ConfigDiagnostic {
+                path: "$.components".to_string(),
+                message: format!("missing required component '{required}'"),
+            });
+        }
+    }
+
+    for (name, component) in components {
+        let path = format!("$.components.{name}");
+        let inputs = component.get("inputs").and_then(Value::as_object);
+
+        if let (Some(order), Some(inputs)) = (
+            component.get("input_order").and_then(Value::as_array),
+            inputs,
+        ) {
+            let mut seen = HashSet::new();
+            for (index, entry) in order.iter().enumerate() {
+                let Some(entry) = entry.as_str() else {
+                    continue;
+                };
+                if !inputs.contains_key(entry) {
+                    out.push(ConfigDiagnostic {
+                        path: format!("{path}.input_order[{index}]"),
+                        message: format!("'{entry}' is not declared in inputs"),
+                    });
+                } else if !seen.insert(entry) {
+                    out.push(ConfigDiagnostic {
+                        path: format!("{path}.input_order[{index}]"),
+                        message: format!("'{entry}' is listed more than once"),
+                    });
+                }
+            }
+        }
+
+        for section in ["inputs", "outputs", "states"] {
+            let Some(tensors) = component.get(section).and_then(Value::as_object) else {
+                continue;
+            };
+            for (key, tensor) in tensors {
+                let tensor_path = format!("{path}.{section}.{key}");
+                if let Some(tensor_name) = tensor.get("name").and_then(Value::as_str) {
+                    if tensor_name != key {
+                        out.push(ConfigDiagnostic {
+                            path: format!("{tensor_path}.name"),
+                            message: format!("name '{tensor_name}' does not match its key '{key}'"),
+                        });
+                    }
+                }
+                check_flexible_shape(tensor, &tensor_path, out);
+                if section != "states"
+                    && tensor
+                        .get("shape")
+                        .and_then(Value::as_array)
+                        .is_some_and(Vec::is_empty)
+                {
+                    out.push(ConfigDiagnostic {
+                        path: format!("{tensor_path}.shape"),
+                        message: "shape must not be empty".to_string(),
+                    });
+                }
+            }
+        }
+
+        if let Some(chunks) = component.get("chunks").and_then(Value::as_array) {
+            let first = chunks.first().and_then(Value::as_str);
+            let file_path = component.get("file_path").and_then(Value::as_str);
+            if first.is_some() && file_path.is_some() && first != file_path {
+                out.push(ConfigDiagnostic {
+                    path: format!("{path}.file_path"),
+                    message: "file_path must name the first chunk".to_string(),
+                });
+            }
+        }
+    }
+
+    let chunk_count = |name: &str| {
+        components.get(name).map(|c| {
+            c.get("chunks")
+                .and_then(Value::as_array)
+                .map_or(1, |chunks| chunks.len().max(1))
+        })
+    };
+    if let (Some(prefill), Some(infer)) = (chunk_count("ffn_prefill"), chunk_count("ffn_infer")) {
+        if prefill != infer {
+            out.push(ConfigDiagnostic {
+                path: "$.components.ffn_infer.chunks".to_string(),
+                message: format!("{infer} chunks but ffn_prefill has {prefill}"),
+            });
+        }
+    }
+}
+
+/// Flexible shapes must have the rank of `shape`, and ranges must not be empty
+fn check_flexible_shape(tensor: &Value, tensor_path: &str, out: &mut Vec<ConfigDiagnostic>) {
+    let Some(flexible) = tensor.get("flexible_shape") else {
+        return;
+    };
+    let path = format!("{tensor_path}.flexible_shape");
+    let rank = tensor.get("shape").and_then(Value::as_array).map(Vec::len);
+    let mut check_rank = |dims: usize, path: String| {
+        if rank.is_some_and(|rank| rank != dims) {
+            out.push(ConfigDiagnostic {
+                path,
+                message: format!("{dims} dimensions but shape has {}", rank.unwrap_or(0)),
+            });
+        }
+    };
+    if let Some(shapes) = flexible.get("enumerated").and_then(Value::as_array) {
+        for (index, shape) in shapes.iter().enumerate() {
+            if let Some(dims) = shape.as_array() {
+                check_rank(dims.len(), format!("{path}.enumerated[{index}]"));
+            }
+        }
+    }
+    if let Some(ranges) = flexible.get("range").and_then(Value::as_array) {
+        check_rank(ranges.len(), format!("{path}.range"));
+        for (index, range) in ranges.iter().enumerate() {
+            let lower = range.get("lower").and_then(Value::as_u64);
+            let upper = range.get("upper").and_then(Value::as_u64);
+            if let (Some(lower), Some(upper)) = (lower, upper) {
+                if lower > upper {
+                    out.push(ConfigDiagnostic {
+                        path: format!("{path}.range[{index}]"),
+                        message: format!("lower bound {lower} exceeds upper bound {upper}"),
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::model::ModelConfig;
+
+    const PUBLISHED_SCHEMA: &str = "configs/model_config.schema.json";
+
+    fn valid_config() -> Value {
+        json!({
+            "schema_version": CURRENT_SCHEMA_VERSION,
+            "model_info": {"model_id": "custom/qwen", "path": null, "model_type": "qwen"},
+            "shapes": {"batch_size": 64, "context_length": 512, "hidden_size": 1024, "vocab_size": 151936},
+            "components": {
+                "embeddings": {
+                    "file_path": "qwen_embeddings.mlmodelc",
+                    "inputs": {"input_ids": {"name": "input_ids", "shape": [1, 64], "data_type": "INT32"}},
+                    "outputs": {"hidden_states": {"name": "hidden_states", "shape": [1, 64, 1024], "data_type": "FLOAT16"}},
+                    "functions": [],
+                    "input_order": ["input_ids"]
+                },
+                "lm_head": {
+                    "file_path": "qwen_lm_head.mlmodelc",
+                    "inputs": {"hidden_states": {"name": "hidden_states", "shape": [1, 1, 1024], "data_type": "FLOAT16"}},
+                    "outputs": {"logits": {"name": "logits", "shape": [1, 1, 151936], "data_type": "FLOAT32"}},
+                    "functions": []
+                }
+            },
+            "naming": {},
+            "ffn_execution": "unified"
+        })
+    }
+
+    #[test]
+    fn test_valid_config_has_no_diagnostics() {
+        let value = valid_config();
+        assert_eq!(validate_strict(&value), Vec::new());
+        // Configs produced by the crate validate too
+        let config: ModelConfig = serde_json::from_value(value).unwrap();
+        let round_trip = serde_json::to_value(&config).unwrap();
+        assert_eq!(validate_strict(&round_trip), Vec::new());
+    }
+
+    #[test]
+    fn test_unknown_data_types_are_warnings() {
+        let mut value = valid_config();
+        value["components"]["lm_head"]["outputs"]["logits"]["data_type"] = json!("UNKNOWN");
+        assert_eq!(validate_strict(&value), Vec::new());
+        let warnings = strict_warnings(&value);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].path,
+            "$.components.lm_head.outputs.logits.data_type"
+        );
+        assert!(ModelConfig::from_json_str_strict(&value.to_string()).is_ok());
+        assert_eq!(strict_warnings(&valid_config()), Vec::new());
+    }
+
+    #[test]
+    fn test_flexible_shapes_are_checked() {
+        let mut value = valid_config();
+        let input_ids = &mut value["components"]["embeddings"]["inputs"]["input_ids"];
+        input_ids["flexible_shape"] = json!({"range": [{"lower": 1, "upper": 1}, {"lower": 1}]});
+        assert_eq!(validate_strict(&value), Vec::new());
+
+        let input_ids = &mut value["components"]["embeddings"]["inputs"]["input_ids"];
+        input_ids["flexible_shape"] = json!({"range": [{"lower": 64, "upper": 1}]});
+        let paths: Vec<String> = validate_strict(&value)
+            .into_iter()
+            .map(|d| d.path)
+            .collect();
+        let base = "$.components.embeddings.inputs.input_ids.flexible_shape";
+        assert_eq!(paths, [format!("{base}.range"), format!("{base}.range[0]")]);
+
+        let input_ids = &mut value["components"]["embeddings"]["inputs"]["input_ids"];
+        input_ids["flexible_shape"] =
+            json!({"enumerated": [[1, 1]], "range": [{"lower": 1}, {"lower": 1}]});
+        let diagnostics = validate_strict(&value);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].path, base);
+    }
+
+    #[test]
+    fn test_published_schema_is_current() {
+        // Regenerate with: cargo test --lib schema -- --ignored write_published_schema
+        let published = std::fs::read_to_string(PUBLISHED_SCHEMA).unwrap();
+        let published: Value = serde_json::from_str(&published).unwrap();
+        assert_eq!(published, model_config_json_schema());
+    }
+
+    #[test]
+    #[ignore]
+    fn write_published_schema() {
+        let json = serde_json::to_string_pretty(&model_config_json_schema()).unwrap();
+        std::fs::write(PUBLISHED_SCHEMA, json + "\n").unwrap();
+    }
+
+    #[test]
+    fn test_strict_loading_migrates_then_validates() {
+        let legacy = std::fs::read_to_string("configs/anemll-qwen3-0.6b.json").unwrap();
+        let config = ModelConfig::from_json_str_strict(&legacy).unwrap();
+        assert_eq!(config.schema_version, CURRENT_SCHEMA_VERSION);
+
+        let mut value = valid_config();
+        value["components"]["lm_head"]["outputs"]["logits"]["data_type"] = json!("float32");
+        let diagnostics = ModelConfig::from_json_str_strict(&value.to_string()).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].path,
+            "$.components.lm_head.outputs.logits.data_type"
+        );
+    }
+
+    #[test]
+    fn test_diagnostics_are_located() {
+        let mut value = valid_config();
+        value["components"]["lm_head"]["inputs"]["hidden_states"]["data_type"] = json!("FLAOT16");
+        value["components"]["lm_head"]["file_pth"] = json!("typo.mlmodelc");
+        value["components"]["embeddings"]["input_order"] = json!(["input_ids", "position_ids"]);
+        value["components"]["ffn_prefil"] = value["components"]["lm_head"].clone();
+        value["shapes"]["batch_size"] = json!(0);
+
+        let diagnostics = validate_strict(&value);
+        let paths: Vec<&str> = diagnostics.iter().map(|d| d.path.as_str()).collect();
+        for expected in [
+            "$.components.lm_head.inputs.hidden_states.data_type",
+            "$.components.lm_head.file_pth",
+            "$.components.embeddings.input_order[1]",
+            "$.components.ffn_prefil",
+            "$.shapes.batch_size",
+        ] {
+            assert!(
+                paths.contains(&expected),
+                "{expected} missing from {paths:?}"
+            );
+        }
+        let unknown = diagnostics
+            .iter()
+            .find(|d| d.path == "$.components.lm_head.file_pth")
+            .unwrap();
+        assert_eq!(
+            unknown.to_string(),
+            "$.components.lm_head.file_pth: unknown field 'file_pth'"
+        );
+    }
+}
@@ -126,10 +126,12 @@ pub fn tensor_to_mlmultiarray(tensor: &Tensor) -> Result<Retained<MLMultiArray>,
     // Choose MLMultiArrayDataType based on tensor dtype
     let (ml_data_type, element_size) = match tensor.dtype() {
         DType::F32 => (MLMultiArrayDataType::Float32, std::mem::size_of::<f32>()),
+        DType::F16 => (MLMultiArrayDataType::Float16, std::mem::size_of::<f16>()),
+        DType::I32 => (MLMultiArrayDataType::Int32, std::mem::size_of::<i32>()),
         DType::I64 => (MLMultiArrayDataType::Int32, std::mem::size_of::<i32>()), // Convert I64 to Int32
         _ => {
             return Err(CandleError::Msg(format!(
-                "Unsupported tensor dtype {:?} for CoreML conversion. Only F32 and I64 tensors are supported.",
+                "Unsupported tensor dtype {:?} for CoreML conversion. Only F32, F16, I32 and I64 tensors are supported.",
                 tensor.dtype()
             )))
         }
@@ -171,6 +173,44 @@ pub fn tensor_to_mlmultiarray(tensor: &Tensor) -> Result<Retained<MLMultiArray>,
                         ));
                     }
                 }
+                DType::F16 => {
+                    let data_vec = flattened_tensor.to_vec1::<f16>()?;
+                    unsafe {
+                        ml_array.getMutableBytesWithHandler(&StackBlock::new(
+                            |ptr: std::ptr::NonNull<std::ffi::c_void>, len, _| {
+                                let dst = ptr.as_ptr() as *mut f16;
+                                let src = data_vec.as_ptr();
+                                let copy_elements = element_count.min(len as usize / element_size);
+
+                                if copy_elements > 0 && len as usize >= copy_elements * element_size
+                                {
+                                    // SAFETY: As above in F32 path, bounds are enforced and buffers are valid for `copy_elements`.
+                                    std::ptr::copy_nonoverlapping(src, dst, copy_elements);
+                                    copied.store(true, Ordering::Relaxed);
+                                }
+                            },
+                        ));
+                    }
+                }
+                DType::I32 => {
+                    let data_vec = flattened_tensor.to_vec1::<i32>()?;
+                    unsafe {
+                        ml_array.getMutableBytesWithHandler(&StackBlock::new(
+                            |ptr: std::ptr::NonNull<std::ffi::c_void>, len, _| {
+                                let dst = ptr.as_ptr() as *mut i32;
+                                let src = data_vec.as_ptr();
+                                let copy_elements = element_count.min(len as usize / element_size);
+
+                                if copy_elements > 0 && len as usize >= copy_elements * element_size
+                                {
+                                    // SAFETY: As above in F32 path, bounds are enforced and buffers are valid for `copy_elements`.
+                                    std::ptr::copy_nonoverlapping(src, dst, copy_elements);
+                                    copied.store(true, Ordering::Relaxed);
+                                }
+                            },
+                        ));
+                    }
+                }
                 DType::I64 => {
                     // Convert I64 to I32 for CoreML
                     let data_vec = flattened_tensor.to_vec1::<i64>()?;
@@ -1,12 +1,16 @@
-//! Qwen model configuration and validation logic
+//! Decoder model configuration and validation logic
 //!
-//! This module handles the configuration of Qwen models, including shape management,
+//! This module handles the configuration of decoder models, including shape management,
 //! model ID resolution, and factory methods for different model variants.
 
-use crate::qwen::naming::ModelNamingConfig;
+use crate::decoder::family::DecoderFamily;
+use crate::decoder::naming::ModelNamingConfig;
+use crate::generation::GenerationConfig;
 use crate::ModelConfig;
 use candle_core::{Device, Error as CandleError};
 use std::collections::HashMap;
+use std::path::Path;
+use tokenizers::Tokenizer;
 use tracing::debug;
 
 // NOTE: These constants are deprecated and will be removed.
@@ -20,12 +24,13 @@ pub const QWEN_BATCH_SIZE: usize = 64;
 #[deprecated(note = "Use ModelConfig.shapes instead")]
 pub const QWEN_CONTEXT_LENGTH: usize = 512;
 
-/// Configuration for Qwen model components
+/// Configuration for decoder model components
 #[derive(Debug, Clone)]
-pub struct QwenConfig {
+pub struct DecoderConfig {
     pub device: Device,
     pub naming: ModelNamingConfig,
     pub model_config: ModelConfig,
+    pub family: DecoderFamily,
 
     // Deprecated fields - use model_config.shapes instead
     #[deprecated(note = "Use model_config.shapes.vocab_size instead")]
@@ -38,13 +43,15 @@ pub struct QwenConfig {
     pub context_length: usize,
 }
 
-impl QwenConfig {
-    /// Create a QwenConfig from a ModelConfig (recommended approach)
+impl DecoderConfig {
+    /// Create a DecoderConfig from a ModelConfig (recommended approach)
     pub fn from_model_config(model_config: ModelConfig) -> Self {
         let naming = ModelNamingConfig::from_model_config(&model_config);
+        let family = DecoderFamily::from_model_config(&model_config);
         Self {
             device: Device::Cpu,
             naming,
+            family,
             // Copy for backward compatibility
             #[allow(deprecated)]
             vocab_size: model_config.shapes.vocab_size,
@@ -82,8 +89,27 @@ impl QwenConfig {
         batch_size: usize,
         context_length: usize,
     ) -> Result<candle_core::Tensor, CandleError> {
-        self.model_config
-            .create_ffn_causal_mask_tensor(batch_size, context_length, &self.device)
+        let mask = self.model_config.create_ffn_causal_mask_tensor(
+            batch_size,
+            context_length,
+            &self.device,
+        )?;
+        self.apply_mask_value(mask)
+    }
+
+    /// Replace the `-inf` of masked positions with the family's `mask_value`
+    pub fn apply_mask_value(
+        &self,
+        mask: candle_core::Tensor,
+    ) -> Result<candle_core::Tensor, CandleError> {
+        let mask_value = self.family.mask_value;
+        if mask_value == f32::NEG_INFINITY {
+            return Ok(mask);
+        }
+        let dtype = mask.dtype();
+        mask.to_dtype(candle_core::DType::F32)?
+            .maximum(mask_value as f64)?
+            .to_dtype(dtype)
     }
 
     /// Create single token hidden states tensor for LM head
@@ -118,7 +144,7 @@ impl QwenConfig {
         let mask_context_length = expected_shape[3];
 
         // Create causal mask for inference - only the row for current position is active
-        let mut mask_data = vec![f32::NEG_INFINITY; mask_batch_size * mask_context_length];
+        let mut mask_data = vec![self.family.mask_value; mask_batch_size * mask_context_length];
 
         // Set the row corresponding to the current position
         let row_idx = position.min(mask_batch_size - 1);
@@ -126,7 +152,7 @@ impl QwenConfig {
             mask_data[row_idx * mask_context_length + j] = 0.0;
         }
 
-        candle_core::Tensor::from_vec(
+        let mask = candle_core::Tensor::from_vec(
             mask_data,
             (
                 expected_shape[0],
@@ -135,6 +161,11 @@ impl QwenConfig {
                 expected_shape[3],
             ),
             &self.device,
+        )?;
+        self.model_config.to_declared_dtype(
+            mask,
+            self.model_config.ffn_input_component("causal_mask", false),
+            "causal_mask",
         )
     }
 
@@ -148,89 +179,102 @@ impl QwenConfig {
             // Use prefill shape (batch-sized)
             self.create_ffn_position_ids_tensor(positions)
         } else {
-            // For infer, prefer the ffn_infer expected shape if available
-            if let Some(infer_shape) =
-                self.model_config
-                    .get_tensor_shape("ffn_infer", "position_ids", true)
-            {
-                // Commonly a 1-D vector shape
-                if infer_shape.len() == 1 {
-                    // Regardless of reported length, inference expects a single position id [1]
-                    if infer_shape[0] != 1 {
-                        debug!(
-                            "⚠️ Config reports infer position_ids len={} but infer expects [1]; overriding to [1]",
-                            infer_shape[0]
-                        );
-                    }
-                    return candle_core::Tensor::from_vec(vec![positions[0]], (1,), &self.device);
-                } else {
-                    // Non 1-D shapes: fall back to create_infer_position_ids_tensor which honors configured shape
+            let position_ids = self.create_infer_position_ids(positions)?;
+            self.model_config.to_declared_dtype(
+                position_ids,
+                self.model_config.ffn_input_component("position_ids", false),
+                "position_ids",
+            )
+        }
+    }
+
+    /// Infer-mode position IDs with the shape derived from whichever config entries exist
+    fn create_infer_position_ids(
+        &self,
+        positions: &[i64],
+    ) -> Result<candle_core::Tensor, CandleError> {
+        // For infer, prefer the ffn_infer expected shape if available
+        if let Some(infer_shape) =
+            self.model_config
+                .get_tensor_shape("ffn_infer", "position_ids", true)
+        {
+            // Commonly a 1-D vector shape
+            if infer_shape.len() == 1 {
+                // Regardless of reported length, inference expects a single position id [1]
+                if infer_shape[0] != 1 {
                     debug!(
-                        "⚠️ Uncommon ffn_infer position_ids shape {:?}, using create_infer_position_ids_tensor",
-                        infer_shape
+                        "⚠️ Config reports infer position_ids len={} but infer expects [1]; overriding to [1]",
+                        infer_shape[0]
                     );
-                    return self.create_infer_position_ids_tensor(positions[0] as usize);
                 }
+                return candle_core::Tensor::from_vec(vec![positions[0]], (1,), &self.device);
+            } else {
+                // Non 1-D shapes: fall back to create_infer_position_ids_tensor which honors configured shape
+                debug!(
+                    "⚠️ Uncommon ffn_infer position_ids shape {:?}, using create_infer_position_ids_tensor",
+                    infer_shape
+                );
+                return self.create_infer_position_ids_tensor(positions[0] as usize);
+            }
+        }
+
+        // If infer shape unknown but prefill carries a vector length, use that length
+        if let Some(prefill_shape) =
+            self.model_config
+                .get_tensor_shape("ffn_prefill", "position_ids", true)
+        {
+            if prefill_shape.len() == 1 && prefill_shape[0] > 1 {
+                let len = prefill_shape[0];
+                debug!(
+                    "🔧 Using prefill position_ids length {} for infer (no infer shape found)",
+                    len
+                );
+                let vec: Vec<i64> = (0..len as i64).collect();
+                return candle_core::Tensor::from_vec(vec, (len,), &self.device);
             }
+        }
 
-            // If infer shape unknown but prefill carries a vector length, use that length
-            if let Some(prefill_shape) =
-                self.model_config
-                    .get_tensor_shape("ffn_prefill", "position_ids", true)
-            {
-                if prefill_shape.len() == 1 && prefill_shape[0] > 1 {
-                    let len = prefill_shape[0];
+        // Next best: derive expected length from prefill hidden_states seq_len if fixed (>1)
+        if let Some(hs_shape) =
+            self.model_config
+                .get_tensor_shape("ffn_prefill", "hidden_states", true)
+        {
+            if hs_shape.len() == 3 {
+                let seq_len = hs_shape[1];
+                if seq_len > 1 {
                     debug!(
-                        "🔧 Using prefill position_ids length {} for infer (no infer shape found)",
-                        len
+                        "🔧 Using prefill hidden_states seq_len {} for infer position_ids",
+                        seq_len
                     );
-                    let vec: Vec<i64> = (0..len as i64).collect();
-                    return candle_core::Tensor::from_vec(vec, (len,), &self.device);
-                }
-            }
-
-            // Next best: derive expected length from prefill hidden_states seq_len if fixed (>1)
-            if let Some(hs_shape) =
-                self.model_config
-                    .get_tensor_shape("ffn_prefill", "hidden_states", true)
-            {
-                if hs_shape.len() == 3 {
-                    let seq_len = hs_shape[1];
-                    if seq_len > 1 {
-                        debug!(
-                            "🔧 Using prefill hidden_states seq_len {} for infer position_ids",
-                            seq_len
-                        );
-                        let vec: Vec<i64> = (0..seq_len as i64).collect();
-                        return candle_core::Tensor::from_vec(vec, (seq_len,), &self.device);
-                    }
+                    let vec: Vec<i64> = (0..seq_len as i64).collect();
+                    return candle_core::Tensor::from_vec(vec, (seq_len,), &self.device);
                 }
             }
+        }
 
-            // Or from embeddings input shape [batch, seq_len]
-            if let Some(emb_in_shape) = self.model_config.embeddings_input_shape() {
-                if emb_in_shape.len() == 2 {
-                    let seq_len = emb_in_shape[1];
-                    if seq_len > 1 {
-                        debug!(
-                            "🔧 Using embeddings input seq_len {} for infer position_ids",
-                            seq_len
-                        );
-                        let vec: Vec<i64> = (0..seq_len as i64).collect();
-                        return candle_core::Tensor::from_vec(vec, (seq_len,), &self.device);
-                    }
+        // Or from embeddings input shape [batch, seq_len]
+        if let Some(emb_in_shape) = self.model_config.embeddings_input_shape() {
+            if emb_in_shape.len() == 2 {
+                let seq_len = emb_in_shape[1];
+                if seq_len > 1 {
+                    debug!(
+                        "🔧 Using embeddings input seq_len {} for infer position_ids",
+                        seq_len
+                    );
+                    let vec: Vec<i64> = (0..seq_len as i64).collect();
+                    return candle_core::Tensor::from_vec(vec, (seq_len,), &self.device);
                 }
             }
-
-            // Final fallback: build a full-length vector matching context_length
-            let len = self.model_config.shapes.context_length;
-            debug!(
-                "⚠️ No explicit infer/prefill shape for position_ids; using context-length vector of {}",
-                len
-            );
-            let vec: Vec<i64> = (0..len as i64).collect();
-            candle_core::Tensor::from_vec(vec, (len,), &self.device)
         }
+
+        // Final fallback: build a full-length vector matching context_length
+        let len = self.model_config.shapes.context_length;
+        debug!(
+            "⚠️ No explicit infer/prefill shape for position_ids; using context-length vector of {}",
+            len
+        );
+        let vec: Vec<i64> = (0..len as i64).collect();
+        candle_core::Tensor::from_vec(vec, (len,), &self.device)
     }
 
     /// Create causal mask tensor with mode detection (prefill vs infer)
@@ -253,7 +297,7 @@ impl QwenConfig {
                     if infer_mask_shape[2] == 1 {
                         // Single-row mask for infer
                         let mask_context_length = infer_mask_shape[3];
-                        let mut mask_data = vec![f32::NEG_INFINITY; mask_context_length];
+                        let mut mask_data = vec![self.family.mask_value; mask_context_length];
 
                         // Allow attention to positions up to current position
                         for item in mask_data
@@ -263,7 +307,7 @@ impl QwenConfig {
                             *item = 0.0;
                         }
 
-                        return candle_core::Tensor::from_vec(
+                        let mask = candle_core::Tensor::from_vec(
                             mask_data,
                             (
                                 infer_mask_shape[0],
@@ -272,6 +316,11 @@ impl QwenConfig {
                                 infer_mask_shape[3],
                             ),
                             &self.device,
+                        )?;
+                        return self.model_config.to_declared_dtype(
+                            mask,
+                            "ffn_infer",
+                            "causal_mask",
                         );
                     }
                 }
@@ -291,7 +340,7 @@ impl QwenConfig {
             .create_current_pos_tensor(position, &self.device)
     }
 
-    /// Create a QwenConfig for a known model ID (deprecated - use UnifiedModelLoader instead)
+    /// Create a DecoderConfig for a known model ID (deprecated - use UnifiedModelLoader instead)
     #[deprecated(
         note = "Use UnifiedModelLoader to load models dynamically instead of hardcoded configs"
     )]
@@ -303,13 +352,37 @@ impl QwenConfig {
         )))
     }
 
-    /// Create a QwenConfig for standard qwen models (legacy method)
+    /// Create a DecoderConfig for standard qwen models (legacy method)
     /// Set custom naming configuration
     pub fn with_naming(mut self, naming: ModelNamingConfig) -> Self {
         self.naming = naming;
         self
     }
 
+    /// Override the family conventions derived from `model_info.model_type`
+    pub fn with_family(mut self, family: DecoderFamily) -> Self {
+        self.family = family;
+        self
+    }
+
+    /// Generation settings for a model loaded from `model_dir`
+    ///
+    /// Stop tokens come from the model's own generation/tokenizer configs, then the ids
+    /// recorded in the generated config, and always include the family's end-of-turn tokens.
+    pub fn generation_config(&self, model_dir: &Path, tokenizer: &Tokenizer) -> GenerationConfig {
+        let mut generation_config = GenerationConfig::from_model_dir(model_dir, tokenizer);
+        let recorded = self
+            .model_config
+            .model_info
+            .family
+            .iter()
+            .flat_map(|family| family.eos_token_ids.iter().copied());
+        for id in recorded.chain(self.family.eos_token_ids(tokenizer)) {
+            generation_config = generation_config.with_stop_token(id);
+        }
+        generation_config
+    }
+
     // Convenience methods for accessing shapes from model_config
 
     /// Get the batch size for this model
@@ -353,17 +426,19 @@ impl QwenConfig {
     }
 }
 
-impl Default for QwenConfig {
+impl Default for DecoderConfig {
     fn default() -> Self {
         // Minimal default ModelConfig with standard Qwen shapes and no components.
         // Loading a model without an explicit config will still fail later if component
         // file paths are required, but providing Default maintains API compatibility.
         let model_config = crate::config::model::ModelConfig {
+            schema_version: crate::config::model::CURRENT_SCHEMA_VERSION,
             model_info: crate::config::model::ModelInfo {
                 model_id: Some("default/qwen".to_string()),
                 path: None,
                 model_type: "qwen".to_string(),
                 discovered_at: None,
+                family: None,
             },
             shapes: crate::config::model::ShapeConfig {
                 batch_size: 1,
@@ -385,6 +460,7 @@ impl Default for QwenConfig {
         Self {
             device: Device::Cpu,
             naming,
+            family: DecoderFamily::qwen(),
             #[allow(deprecated)]
             vocab_size: model_config.shapes.vocab_size,
             #[allow(deprecated)]
@@ -402,7 +478,8 @@ mod tests {
     use crate::config::model::{
         ComponentConfig, ModelConfig, ModelInfo, NamingConfig, ShapeConfig, TensorConfig,
     };
-    use crate::qwen::config::QwenConfig;
+    use crate::config::TensorDataType;
+    use crate::decoder::config::DecoderConfig;
     use std::collections::HashMap;
 
     fn create_test_model_config_standard() -> ModelConfig {
@@ -416,7 +493,8 @@ mod tests {
             TensorConfig {
                 name: "input_ids".to_string(),
                 shape: vec![1, 64],
-                data_type: "INT32".to_string(),
+                data_type: TensorDataType::Int32,
+                flexible_shape: None,
             },
         );
         let mut emb_out = HashMap::new();
@@ -425,7 +503,8 @@ mod tests {
             TensorConfig {
                 name: "hidden_states".to_string(),
                 shape: vec![1, 64, 1024],
-                data_type: "FLOAT16".to_string(),
+                data_type: TensorDataType::Float16,
+                flexible_shape: None,
             },
         );
         components.insert(
@@ -436,6 +515,8 @@ mod tests {
                 outputs: emb_out,
                 functions: vec![],
                 input_order: None,
+                states: HashMap::new(),
+                chunks: Vec::new(),
             },
         );
 
@@ -446,7 +527,8 @@ mod tests {
             TensorConfig {
                 name: "hidden_states".to_string(),
                 shape: vec![1, 64, 1024],
-                data_type: "FLOAT16".to_string(),
+                data_type: TensorDataType::Float16,
+                flexible_shape: None,
             },
         );
         ffn_prefill_in.insert(
@@ -454,7 +536,8 @@ mod tests {
             TensorConfig {
                 name: "position_ids".to_string(),
                 shape: vec![64],
-                data_type: "INT32".to_string(),
+                data_type: TensorDataType::Int32,
+                flexible_shape: None,
             },
         );
         ffn_prefill_in.insert(
@@ -462,7 +545,8 @@ mod tests {
             TensorConfig {
                 name: "causal_mask".to_string(),
                 shape: vec![1, 1, 64, 64],
-                data_type: "FLOAT32".to_string(),
+                data_type: TensorDataType::Float32,
+                flexible_shape: None,
             },
         );
         let mut ffn_prefill_out = HashMap::new();
@@ -471,7 +555,8 @@ mod tests {
             TensorConfig {
                 name: "output_hidden_states".to_string(),
                 shape: vec![1, 1, 1024],
-                data_type: "FLOAT16".to_string(),
+                data_type: TensorDataType::Float16,
+                flexible_shape: None,
             },
         );
         components.insert(
@@ -482,6 +567,8 @@ mod tests {
                 outputs: ffn_prefill_out,
                 functions: vec![],
                 input_order: None,
+                states: HashMap::new(),
+                chunks: Vec::new(),
             },
         );
 
@@ -492,7 +579,8 @@ mod tests {
             TensorConfig {
                 name: "hidden_states".to_string(),
                 shape: vec![1, 1, 1024],
-                data_type: "FLOAT16".to_string(),
+                data_type: TensorDataType::Float16,
+                flexible_shape: None,
             },
         );
         ffn_infer_in.insert(
@@ -500,7 +588,8 @@ mod tests {
             TensorConfig {
                 name: "position_ids".to_string(),
                 shape: vec![1],
-                data_type: "INT32".to_string(),
+                data_type: TensorDataType::Int32,
+                flexible_shape: None,
             },
         );
         let mut ffn_infer_out = HashMap::new();
@@ -509,7 +598,8 @@ mod tests {
             TensorConfig {
                 name: "output_hidden_states".to_string(),
                 shape: vec![1, 1, 1024],
-                data_type: "FLOAT16".to_string(),
+                data_type: TensorDataType::Float16,
+                flexible_shape: None,
             },
         );
         components.insert(
@@ -520,6 +610,8 @@ mod tests {
                 outputs: ffn_infer_out,
                 functions: vec![],
                 input_order: None,
+                states: HashMap::new(),
+                chunks: Vec::new(),
             },
         );
 
@@ -530,7 +622,8 @@ mod tests {
             TensorConfig {
                 name: "hidden_states".to_string(),
                 shape: vec![1, 1, 1024],
-                data_type: "FLOAT16".to_string(),
+                data_type: TensorDataType::Float16,
+                flexible_shape: None,
             },
         );
         let mut lm_out = HashMap::new();
@@ -539,7 +632,8 @@ mod tests {
             TensorConfig {
                 name: "logits".to_string(),
                 shape: vec![1, 1, 151_936],
-                data_type: "FLOAT32".to_string(),
+                data_type: TensorDataType::Float32,
+                flexible_shape: None,
             },
         );
         components.insert(
@@ -550,15 +644,19 @@ mod tests {
                 outputs: lm_out,
                 functions: vec![],
                 input_order: None,
+                states: HashMap::new(),
+                chunks: Vec::new(),
             },
         );
 
         ModelConfig {
+            schema_version: crate::config::model::CURRENT_SCHEMA_VERSION,
             model_info: ModelInfo {
                 model_id: Some("test/model".to_string()),
                 path: Some("/test".to_string()),
                 model_type: "qwen".to_string(),
                 discovered_at: None,
+                family: None,
             },
             shapes: ShapeConfig {
                 batch_size: 1,
@@ -577,9 +675,9 @@ mod tests {
         }
     }
 
-    fn create_test_qwen_config_standard() -> QwenConfig {
+    fn create_test_qwen_config_standard() -> DecoderConfig {
         let mc = create_test_model_config_standard();
-        QwenConfig::from_model_config(mc)
+        DecoderConfig::from_model_config(mc)
     }
 
     #[test]
@@ -609,7 +707,8 @@ mod tests {
                 TensorConfig {
                     name: "logits1".to_string(),
                     shape: vec![1, 1, 10],
-                    data_type: "FLOAT32".to_string(),
+                    data_type: TensorDataType::Float32,
+                    flexible_shape: None,
                 },
             );
             lm_head.outputs.insert(
@@ -617,7 +716,8 @@ mod tests {
                 TensorConfig {
                     name: "logits2".to_string(),
                     shape: vec![1, 1, 20],
-                    data_type: "FLOAT32".to_string(),
+                    data_type: TensorDataType::Float32,
+                    flexible_shape: None,
                 },
             );
         }
@@ -643,4 +743,97 @@ mod tests {
         assert!(mc.validate().is_ok());
         assert!(mc.validate_internal_wiring().is_ok());
     }
+
+    #[test]
+    fn test_mode_detection_tensors_use_declared_data_types() {
+        use candle_core::DType;
+
+        let mut cfg = create_test_qwen_config_standard();
+        let position_ids = cfg
+            .create_position_ids_with_mode_detection(&[5], false)
+            .unwrap();
+        assert_eq!(position_ids.dtype(), DType::I32);
+        assert_eq!(position_ids.to_vec1::<i32>().unwrap(), vec![5]);
+
+        // ffn_infer declares no causal_mask, so the prefill declaration applies
+        let mask = cfg
+            .create_causal_mask_with_mode_detection(5, 512, false)
+            .unwrap();
+        assert_eq!(mask.dtype(), DType::F32);
+        cfg.model_config
+            .components
+            .get_mut("ffn_prefill")
+            .unwrap()
+            .inputs
+            .get_mut("causal_mask")
+            .unwrap()
+            .data_type = TensorDataType::Float16;
+        for is_prefill in [true, false] {
+            let mask = cfg
+                .create_causal_mask_with_mode_detection(5, 512, is_prefill)
+                .unwrap();
+            assert_eq!(mask.dtype(), DType::F16);
+        }
+    }
+
+    #[test]
+    fn test_family_mask_value() {
+        use crate::decoder::DecoderFamily;
+
+        fn min_value(mask: candle_core::Tensor) -> f32 {
+            mask.flatten_all()
+                .unwrap()
+                .min(0)
+                .unwrap()
+                .to_scalar::<f32>()
+                .unwrap()
+        }
+
+        let cfg = create_test_qwen_config_standard();
+        let mask = cfg.create_ffn_causal_mask_tensor(64, 512).unwrap();
+        assert_eq!(min_value(mask), f32::NEG_INFINITY);
+
+        let cfg = cfg.with_family(DecoderFamily::qwen().with_mask_value(-1e4));
+        let prefill = cfg.create_ffn_causal_mask_tensor(64, 512).unwrap();
+        let infer = cfg
+            .create_causal_mask_with_mode_detection(5, 512, false)
+            .unwrap();
+        for mask in [prefill, infer] {
+            assert_eq!(min_value(mask), -1e4);
+        }
+    }
+
+    #[test]
+    fn test_generation_config_merges_stop_tokens() {
+        use crate::config::model::FamilyConfig;
+        use tokenizers::models::wordlevel::WordLevel;
+        use tokenizers::Tokenizer;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("generation_config.json"),
+            r#"{"eos_token_id": [2, 3]}"#,
+        )
+        .unwrap();
+        let vocab = [("<unk>", 0), ("<|im_end|>", 9)]
+            .into_iter()
+            .map(|(token, id)| (token.to_string(), id))
+            .collect();
+        let tokenizer = Tokenizer::new(
+            WordLevel::builder()
+                .vocab(vocab)
+                .unk_token("<unk>".to_string())
+                .build()
+                .unwrap(),
+        );
+
+        // A cached config whose recorded ids disagree with the files on disk
+        let mut cfg = create_test_qwen_config_standard();
+        cfg.model_config.model_info.family = Some(FamilyConfig {
+            eos_token_ids: vec![3, 7],
+            ..FamilyConfig::default()
+        });
+        let generation_config = cfg.generation_config(dir.path(), &tokenizer);
+        assert_eq!(generation_config.stop_token_ids, vec![2, 3, 7, 9]);
+    }
 }
@@ -1,13 +1,14 @@
-//! Embeddings computation and caching for Qwen models
+//! Embeddings computation and caching for decoder models
 //!
 //! This module contains methods for computing, caching, and retrieving embeddings
 //! with various optimization strategies for different model architectures.
 
-use crate::qwen::model::QwenModel;
+use crate::backend::InferenceBackend;
+use crate::decoder::model::DecoderModel;
 use candle_core::{Error as CandleError, Tensor};
 use tracing::{debug, trace};
 
-impl QwenModel {
+impl<B: InferenceBackend> DecoderModel<B> {
     /// Compute embeddings with caching and reuse optimization
     pub fn compute_embeddings(&mut self, tokens: &[i64]) -> Result<Tensor, CandleError> {
         // Check if we already have embeddings for this exact sequence
@@ -189,7 +190,7 @@ impl QwenModel {
     pub fn get_full_sequence_embeddings_for_infer(
         &mut self,
         tokens: &[i64],
-        _pos: usize,
+        pos: usize,
     ) -> Result<Tensor, CandleError> {
         // Get the expected FFN input shape - check ffn_infer first, then fall back to ffn_prefill
         let expected_shape =
@@ -212,6 +213,15 @@ impl QwenModel {
         if let Some(expected_shape) = expected_shape {
             let expected_seq_len = expected_shape[1]; // [1, 64, 1024] -> 64
 
+            // Single-token infer needs the current token's embedding, not the sequence start
+            if expected_seq_len == 1 && pos > 0 {
+                if let Some(embedding) = self.get_token_embedding_from_sequence(tokens, pos - 1)? {
+                    debug!("⚡ REUSING: Current token embedding from cached sequence");
+                    return Ok(embedding);
+                }
+                return self.get_infer_hidden_states(tokens, pos);
+            }
+
             // Check if we have cached embeddings that match this sequence
             if let Some((cached_tokens, cached_embeddings)) = &self.last_sequence_embeddings {
                 let current_tokens_len = tokens.len().min(expected_seq_len);
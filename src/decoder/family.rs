@@ -0,0 +1,241 @@
+//! Family hooks for decoder models
+//!
+//! ANEMLL converts every supported decoder to the same component layout (embeddings, chunked
+//! FFN prefill/infer, multipart LM head), so one runtime drives them all. What still differs
+//! between families is how text is framed: which special tokens end a turn, the chat format,
+//! and the value used for masked attention positions. [`DecoderFamily`] collects those.
+
+use crate::chat::ChatTemplate;
+use crate::config::ModelFamily;
+use crate::generation::push_unique;
+use crate::ModelConfig;
+use candle_core::Error as CandleError;
+use tokenizers::Tokenizer;
+use tracing::warn;
+
+const CHATML_TEMPLATE: &str = "{% for message in messages %}\
+{{ '<|im_start|>' + message['role'] + '\n' + message['content'] + '<|im_end|>\n' }}\
+{% endfor %}\
+{% if add_generation_prompt %}{{ '<|im_start|>assistant\n' }}{% endif %}";
+
+const LLAMA3_TEMPLATE: &str = "<|begin_of_text|>{% for message in messages %}\
+{{ '<|start_header_id|>' + message['role'] + '<|end_header_id|>\n\n' + message['content'] + '<|eot_id|>' }}\
+{% endfor %}\
+{% if add_generation_prompt %}{{ '<|start_header_id|>assistant<|end_header_id|>\n\n' }}{% endif %}";
+
+const GEMMA_TEMPLATE: &str = "<bos>{% for message in messages %}\
+{% if message['role'] == 'assistant' %}{{ '<start_of_turn>model\n' }}\
+{% else %}{{ '<start_of_turn>' + message['role'] + '\n' }}{% endif %}\
+{{ message['content'] + '<end_of_turn>\n' }}\
+{% endfor %}\
+{% if add_generation_prompt %}{{ '<start_of_turn>model\n' }}{% endif %}";
+
+const PHI3_TEMPLATE: &str = "{% for message in messages %}\
+{{ '<|' + message['role'] + '|>\n' + message['content'] + '<|end|>\n' }}\
+{% endfor %}\
+{% if add_generation_prompt %}{{ '<|assistant|>\n' }}{% endif %}";
+
+const MISTRAL_TEMPLATE: &str = "<s>{% for message in messages %}\
+{% if message['role'] == 'assistant' %}{{ message['content'] + '</s>' }}\
+{% else %}{{ '[INST] ' + message['content'] + ' [/INST]' }}{% endif %}\
+{% endfor %}";
+
+/// Per-family conventions layered on top of the shared decoder runtime
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecoderFamily {
+    pub family: ModelFamily,
+    /// Prefix of the `model_type` given to each component's backend (`qwen-embeddings`)
+    pub component_prefix: String,
+    /// Special tokens that end an assistant turn, resolved through the tokenizer
+    pub eos_tokens: Vec<String>,
+    /// Jinja chat template used when the model directory ships none
+    pub default_chat_template: Option<String>,
+    /// Value written to masked positions of the causal mask
+    pub mask_value: f32,
+}
+
+impl DecoderFamily {
+    /// Conventions for a known family
+    pub fn for_family(family: ModelFamily) -> Self {
+        let (eos_tokens, template): (&[&str], Option<&str>) = match family {
+            ModelFamily::Qwen | ModelFamily::Qwen2 | ModelFamily::Qwen2_5 | ModelFamily::Qwen3 => {
+                (&["<|im_end|>", "<|endoftext|>"], Some(CHATML_TEMPLATE))
+            }
+            ModelFamily::Llama => (
+                &["<|eot_id|>", "<|end_of_text|>", "<|eom_id|>"],
+                Some(LLAMA3_TEMPLATE),
+            ),
+            ModelFamily::Gemma | ModelFamily::Gemma2 | ModelFamily::Gemma3 => {
+                (&["<end_of_turn>", "<eos>"], Some(GEMMA_TEMPLATE))
+            }
+            ModelFamily::Phi => (&["<|endoftext|>"], None),
+            ModelFamily::Phi3 => (&["<|end|>", "<|endoftext|>"], Some(PHI3_TEMPLATE)),
+            ModelFamily::Mistral => (&["</s>"], Some(MISTRAL_TEMPLATE)),
+        };
+        let component_prefix = if family.is_qwen() {
+            "qwen"
+        } else {
+            family.as_str()
+        };
+        Self {
+            family,
+            component_prefix: component_prefix.to_string(),
+            eos_tokens: eos_tokens.iter().map(|token| token.to_string()).collect(),
+            default_chat_template: template.map(str::to_string),
+            mask_value: f32::NEG_INFINITY,
+        }
+    }
+
+    /// Conventions for the family named in `model_info.model_type`
+    ///
+    /// Unknown model types keep the Qwen conventions, which is what every config generated
+    /// before family detection describes.
+    pub fn from_model_config(model_config: &ModelConfig) -> Self {
+        let model_type = &model_config.model_info.model_type;
+        let family = ModelFamily::parse(model_type).unwrap_or_else(|| {
+            warn!(
+                "Unknown model_type '{}', using the {} conventions",
+                model_type,
+                ModelFamily::Qwen
+            );
+            ModelFamily::Qwen
+        });
+        Self::for_family(family)
+    }
+
+    pub fn qwen() -> Self {
+        Self::for_family(ModelFamily::Qwen)
+    }
+
+    pub fn with_component_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.component_prefix = prefix.into();
+        self
+    }
+
+    pub fn with_eos_tokens(mut self, tokens: Vec<String>) -> Self {
+        self.eos_tokens = tokens;
+        self
+    }
+
+    pub fn with_default_chat_template(mut self, template: Option<String>) -> Self {
+        self.default_chat_template = template;
+        self
+    }
+
+    pub fn with_mask_value(mut self, mask_value: f32) -> Self {
+        self.mask_value = mask_value;
+        self
+    }
+
+    /// Backend `model_type` for a component (`embeddings`, `ffn`, `lm-head`)
+    pub fn component_model_type(&self, component: &str) -> String {
+        format!("{}-{component}", self.component_prefix)
+    }
+
+    /// Ids of the family's EOS tokens that exist in `tokenizer`
+    pub fn eos_token_ids(&self, tokenizer: &Tokenizer) -> Vec<i64> {
+        let mut ids = Vec::new();
+        for token in &self.eos_tokens {
+            if let Some(id) = tokenizer.token_to_id(token) {
+                push_unique(&mut ids, id as i64);
+            }
+        }
+        ids
+    }
+
+    /// Parsed fallback chat template, or `None` when the family has none
+    pub fn chat_template(&self) -> Result<Option<ChatTemplate>, CandleError> {
+        self.default_chat_template
+            .as_ref()
+            .map(|source| ChatTemplate::new(source.clone()))
+            .transpose()
+    }
+}
+
+impl Default for DecoderFamily {
+    fn default() -> Self {
+        Self::qwen()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{ChatMessage, ChatTemplateOptions};
+
+    #[test]
+    fn test_family_from_model_config() {
+        let mut config = ModelConfig::default_qwen();
+        assert_eq!(
+            DecoderFamily::from_model_config(&config).family,
+            ModelFamily::Qwen
+        );
+
+        config.model_info.model_type = "llama".to_string();
+        let family = DecoderFamily::from_model_config(&config);
+        assert_eq!(family.family, ModelFamily::Llama);
+        assert_eq!(family.component_model_type("ffn"), "llama-ffn");
+        assert!(family.eos_tokens.contains(&"<|eot_id|>".to_string()));
+
+        config.model_info.model_type = "qwen3".to_string();
+        let family = DecoderFamily::from_model_config(&config);
+        assert_eq!(family.component_model_type("embeddings"), "qwen-embeddings");
+
+        config.model_info.model_type = "something-new".to_string();
+        assert_eq!(
+            DecoderFamily::from_model_config(&config),
+            DecoderFamily::qwen()
+        );
+    }
+
+    #[test]
+    fn test_builtin_chat_templates_render() {
+        let messages = vec![
+            ChatMessage::system("Be brief."),
+            ChatMessage::user("Hi"),
+            ChatMessage::assistant("Hello"),
+            ChatMessage::user("Bye"),
+        ];
+        let options = ChatTemplateOptions::default();
+        for family in ModelFamily::ALL {
+            let hooks = DecoderFamily::for_family(family);
+            let Some(source) = &hooks.default_chat_template else {
+                continue;
+            };
+            let template = ChatTemplate::new(source.clone())
+                .unwrap_or_else(|e| panic!("{family} template does not parse: {e}"));
+            let prompt = template.render(&messages, &options).unwrap();
+            assert!(prompt.contains("Bye"), "{family}: {prompt}");
+        }
+
+        let llama = DecoderFamily::for_family(ModelFamily::Llama)
+            .chat_template()
+            .unwrap()
+            .unwrap()
+            .render(&messages[1..2], &options)
+            .unwrap();
+        assert_eq!(
+            llama,
+            "<|begin_of_text|><|start_header_id|>user<|end_header_id|>\n\nHi<|eot_id|>\
+             <|start_header_id|>assistant<|end_header_id|>\n\n"
+        );
+
+        let gemma = DecoderFamily::for_family(ModelFamily::Gemma3)
+            .chat_template()
+            .unwrap()
+            .unwrap()
+            .render(&messages[1..3], &options)
+            .unwrap();
+        assert!(gemma.contains("<start_of_turn>model\nHello<end_of_turn>\n"));
+        assert!(gemma.ends_with("<start_of_turn>model\n"));
+
+        assert!(DecoderFamily::for_family(ModelFamily::Phi)
+            .chat_template()
+            .unwrap()
+            .is_none());
+        assert!(DecoderFamily::qwen()
+            .with_default_chat_template(Some("{% for message in messages %}".to_string()))
+            .chat_template()
+            .is_err());
+    }
+}
@@ -1,9 +1,10 @@
-//! Inference pipeline and text generation for Qwen models
+//! Inference pipeline and text generation for decoder models
 //!
 //! This module contains the high-level inference methods including forward_text,
 //! chat.py-style prefill/infer pipeline, and text generation utilities.
 
-use crate::qwen::model::QwenModel;
+use crate::backend::InferenceBackend;
+use crate::decoder::model::DecoderModel;
 use candle_core::{Error as CandleError, Tensor};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info, trace};
@@ -28,7 +29,7 @@ pub struct SequentialPrefillPlan {
     pub last_local_idx: usize,
 }
 
-impl QwenModel {
+impl DecoderModel {
     /// Static helper variant of plan_sequential_prefill for unit testing without a model instance.
     pub fn plan_sequential_prefill_static(
         token_count: usize,
@@ -65,17 +66,28 @@ impl QwenModel {
             last_local_idx,
         }
     }
+}
 
+impl<B: InferenceBackend> DecoderModel<B> {
     /// Build a deterministic plan for single-token sequential prefill over possibly multiple windows.
     /// This function is pure and unit-test friendly.
+    ///
+    /// Sequences longer than the context window are rejected; shorten them with
+    /// `generation_config.overflow_policy` first ([`DecoderModel::tokenize`] and
+    /// [`DecoderModel::fit_to_context`] do).
     pub fn plan_sequential_prefill(
         &self,
         token_count: usize,
         embeddings_len: usize,
         already_prefilled: usize,
-    ) -> SequentialPrefillPlan {
+    ) -> Result<SequentialPrefillPlan, CandleError> {
+        self.config.model_config.check_context_length(token_count)?;
         // Delegate to the static version to ensure identical behavior across tests and runtime.
-        QwenModel::plan_sequential_prefill_static(token_count, embeddings_len, already_prefilled)
+        Ok(DecoderModel::plan_sequential_prefill_static(
+            token_count,
+            embeddings_len,
+            already_prefilled,
+        ))
     }
 
     /// Generate a single token from text input - ADVANCED/DEBUG USE ONLY
@@ -122,19 +134,19 @@ impl QwenModel {
             let embeddings = self.compute_embeddings(&tokens)?; // padded to embeddings_input_shape
             let embed_seq_len = embeddings.dim(1)?;
             // Determine how many tokens we've already prefetched (if prompt grew)
-            let already_prefilled = self.last_single_token_prefill_len.unwrap_or(0);
-            if already_prefilled > context_pos {
-                // New prompt shorter than previous -> reset state
-                trace!("🔄 forward_text(single-token): prompt reset (previous prefilled {} > new {}), reinitializing state", already_prefilled, context_pos);
+            let already_prefilled = self.kv_cache_tokens.len();
+            if self.cached_prefix_len(&tokens) < already_prefilled {
+                // New prompt does not extend the prefilled one -> reset state
+                trace!("🔄 forward_text(single-token): prompt reset (previous prefilled {} tokens not a prefix of new {}), reinitializing state", already_prefilled, context_pos);
                 self.unified_state = None;
                 self.cached_causal_mask = None;
-                self.last_single_token_prefill_len = None;
                 self.initialize_states()?;
             }
-            let already_prefilled = self.last_single_token_prefill_len.unwrap_or(0);
+            let already_prefilled = self.kv_cache_tokens.len();
 
             // Construct a testable execution plan and use it to drive the calls
-            let plan = self.plan_sequential_prefill(context_pos, embed_seq_len, already_prefilled);
+            let plan =
+                self.plan_sequential_prefill(context_pos, embed_seq_len, already_prefilled)?;
 
             // Run sequential prefill across one or more windows (as needed by the plan)
             if self.unified_state.is_none() || self.cached_causal_mask.is_none() {
@@ -219,7 +231,7 @@ impl QwenModel {
                 let last_embed = embeddings.narrow(1, plan.last_local_idx, 1)?;
                 let logits = self.generate_next_token_with_infer(&last_embed, context_pos - 1)?;
                 let next_token = self.extract_next_token(&logits)?;
-                self.last_single_token_prefill_len = Some(context_pos);
+                self.kv_cache_tokens = tokens.clone();
                 let total_time = start_time.elapsed();
                 trace!("🎯 SINGLE-TOKEN TOTAL: {:?}", total_time);
                 return Ok(next_token);
@@ -230,7 +242,7 @@ impl QwenModel {
                 let last_embed = last_embeddings.narrow(1, plan.last_local_idx, 1)?;
                 let logits = self.generate_next_token_with_infer(&last_embed, context_pos - 1)?;
                 let next_token = self.extract_next_token(&logits)?;
-                self.last_single_token_prefill_len = Some(context_pos);
+                self.kv_cache_tokens = tokens.clone();
                 let total_time = start_time.elapsed();
                 trace!("🎯 MULTI-WINDOW SINGLE-TOKEN TOTAL: {:?}", total_time);
                 return Ok(next_token);
@@ -303,16 +315,31 @@ impl QwenModel {
             // For full-sequence models, send the complete embeddings once
             let embeddings = self.compute_embeddings(tokens)?;
             let causal_mask = self.cached_causal_mask.as_ref().unwrap().clone();
-            return self.prefill_full_sequence_chunk(&embeddings, context_pos - 1, &causal_mask);
+            self.prefill_full_sequence_chunk(&embeddings, context_pos - 1, &causal_mask)?;
+            self.kv_cache_tokens = tokens[..context_pos].to_vec();
+            return Ok(());
         }
 
         trace!("🔄 CHATPY-PREFILL: Using CHUNKED mode for non-CoreML model");
+        self.run_chatpy_prefill_chunks(tokens, 0, context_pos)
+    }
+
+    /// Chunked prefill of `tokens[start_pos..context_pos]` into the shared state.
+    ///
+    /// Chunks stay aligned to the batch size, so the chunk containing `start_pos` is
+    /// re-run from its start; those positions already hold the same tokens.
+    fn run_chatpy_prefill_chunks(
+        &mut self,
+        tokens: &[i64],
+        start_pos: usize,
+        context_pos: usize,
+    ) -> Result<(), CandleError> {
         let batch_size = self.config.batch_size(); // 64
         let device = self.config.device.clone(); // Clone to avoid borrowing issues
         let causal_mask = self.cached_causal_mask.as_ref().unwrap().clone(); // Clone mask
 
         // Process in 64-token chunks (CoreML model constraint)
-        let mut batch_pos = 0;
+        let mut batch_pos = start_pos - start_pos % batch_size;
         while batch_pos < context_pos {
             let batch_end = (batch_pos + batch_size).min(context_pos);
             let _current_batch_size = batch_end - batch_pos;
@@ -381,16 +408,46 @@ impl QwenModel {
             batch_pos = batch_end;
         }
 
+        self.kv_cache_tokens = tokens[..context_pos].to_vec();
         debug!(
             "✅ Optimized chat.py prefill: Processed {} tokens in {} chunks",
-            context_pos,
-            context_pos.div_ceil(batch_size)
+            context_pos - start_pos,
+            (context_pos - start_pos).div_ceil(batch_size)
         );
         Ok(())
     }
 
     /// Chat.py-style single token infer with embeddings caching optimization
     pub fn run_chatpy_infer(&mut self, tokens: &[i64], pos: usize) -> Result<i64, CandleError> {
+        let logits = self.run_chatpy_infer_logits(tokens, pos)?;
+        let next_token = self.extract_next_token(&logits)?;
+
+        trace!(
+            "✅ Optimized chat.py infer: Generated token {} at position {}",
+            next_token,
+            pos
+        );
+        Ok(next_token)
+    }
+
+    /// Run ffn_infer for the token at `pos - 1` against the shared state and return the LM head logits
+    fn run_chatpy_infer_logits(
+        &mut self,
+        tokens: &[i64],
+        pos: usize,
+    ) -> Result<Tensor, CandleError> {
+        let infer_output = self.run_chatpy_infer_hidden(tokens, pos)?;
+
+        // Run LM head (like chat.py)
+        self.run_lm_head_with_inputs(&infer_output)
+    }
+
+    /// Run ffn_infer for the token at `pos - 1`, writing its keys/values into the shared state
+    fn run_chatpy_infer_hidden(
+        &mut self,
+        tokens: &[i64],
+        pos: usize,
+    ) -> Result<Tensor, CandleError> {
         let context_length = self.config.context_length();
         let _causal_mask = self.cached_causal_mask.as_ref().unwrap().clone(); // Clone mask
 
@@ -434,16 +491,100 @@ impl QwenModel {
             &current_pos,
         )?;
 
-        // Run LM head and extract token (like chat.py)
-        let logits = self.run_lm_head_with_inputs(&infer_output)?;
-        let next_token = self.extract_next_token(&logits)?;
+        self.kv_cache_tokens.truncate(pos - 1);
+        let cached = self.kv_cache_tokens.len();
+        self.kv_cache_tokens.extend_from_slice(&tokens[cached..pos]);
+        Ok(infer_output)
+    }
 
-        trace!(
-            "✅ Optimized chat.py infer: Generated token {} at position {}",
-            next_token,
-            pos
+    /// Start incremental generation: prefill `tokens` into a fresh shared state and
+    /// return the logits (`[vocab]`) for the token that follows them.
+    ///
+    /// Continue with [`DecoderModel::infer_next_logits`] after appending each sampled token.
+    pub fn prefill_for_generation(&mut self, tokens: &[i64]) -> Result<Tensor, CandleError> {
+        if tokens.is_empty() {
+            return Err(CandleError::Msg("Empty token sequence".into()));
+        }
+        self.reset_states()?;
+        self.run_chatpy_prefill(tokens, tokens.len())?;
+        self.infer_next_logits(tokens)
+    }
+
+    /// Feed the last of `tokens` through ffn_infer at position `tokens.len() - 1`,
+    /// reusing the KV state built by previous calls, and return the next-token logits (`[vocab]`).
+    ///
+    /// Every earlier token must already be in the state (via [`DecoderModel::prefill_for_generation`]
+    /// or previous calls); nothing is re-tokenized or re-prefilled.
+    pub fn infer_next_logits(&mut self, tokens: &[i64]) -> Result<Tensor, CandleError> {
+        let pos = tokens.len();
+        if pos == 0 {
+            return Err(CandleError::Msg("Empty token sequence".into()));
+        }
+        if pos > self.config.context_length() {
+            return Err(CandleError::Msg(format!(
+                "Sequence of {pos} tokens exceeds context length {}",
+                self.config.context_length()
+            )));
+        }
+        if self.unified_state.is_none() {
+            return Err(CandleError::Msg(
+                "No unified state available - prefill must be run first".to_string(),
+            ));
+        }
+        let logits = self.run_chatpy_infer_logits(tokens, pos)?;
+        logits.squeeze(0)?.squeeze(0)
+    }
+
+    /// How many context tokens are in the KV cache, `None` while it is empty
+    pub fn last_single_token_prefill_len(&self) -> Option<usize> {
+        (!self.kv_cache_tokens.is_empty()).then_some(self.kv_cache_tokens.len())
+    }
+
+    /// Number of leading `tokens` whose keys/values are already in the shared state
+    pub fn cached_prefix_len(&self, tokens: &[i64]) -> usize {
+        self.kv_cache_tokens
+            .iter()
+            .zip(tokens)
+            .take_while(|(cached, token)| cached == token)
+            .count()
+    }
+
+    /// Like [`DecoderModel::prefill_for_generation`], but keeps the keys/values of the longest
+    /// prefix of `tokens` already in the shared state and only prefills the remainder.
+    ///
+    /// This is what makes multi-turn chat cheap: a new turn extends the previous
+    /// conversation, so only the appended tokens are run through the model.
+    pub fn prefill_reusing_cache(&mut self, tokens: &[i64]) -> Result<Tensor, CandleError> {
+        // The last token is always re-run: its ffn_infer pass produces the logits
+        let reusable = self
+            .cached_prefix_len(tokens)
+            .min(tokens.len().saturating_sub(1));
+        if reusable == 0 || self.unified_state.is_none() {
+            return self.prefill_for_generation(tokens);
+        }
+        let context_length = self.config.context_length();
+        if tokens.len() > context_length {
+            return Err(CandleError::Msg(format!(
+                "Sequence of {} tokens exceeds context length {context_length}",
+                tokens.len()
+            )));
+        }
+        debug!(
+            "Reusing {} cached tokens, prefilling {} new tokens",
+            reusable,
+            tokens.len() - reusable
         );
-        Ok(next_token)
+
+        if self.config.model_config.expects_full_sequence_prefill() {
+            // Full-sequence prefill always starts at position 0, so the new tokens
+            // are fed one at a time through ffn_infer instead
+            for pos in reusable + 1..tokens.len() {
+                self.run_chatpy_infer_hidden(tokens, pos)?;
+            }
+        } else {
+            self.run_chatpy_prefill_chunks(tokens, reusable, tokens.len())?;
+        }
+        self.infer_next_logits(tokens)
     }
 
     /// Performance benchmark for the current implementation
@@ -515,13 +656,13 @@ impl QwenModel {
         max_tokens: usize,
         temperature: f32,
     ) -> Result<String, CandleError> {
-        let tokens = self.generate_tokens_topk_temp(text, max_tokens, temperature, None)?;
-
-        // Decode tokens back to text
-        let token_ids: Vec<u32> = tokens.iter().map(|&id| id as u32).collect();
-        self.tokenizer
-            .decode(&token_ids, false)
-            .map_err(|e| CandleError::Msg(format!("Failed to decode tokens: {e}")))
+        let config = self
+            .generation_config
+            .clone()
+            .with_max_new_tokens(max_tokens)
+            .with_temperature(temperature)
+            .with_top_k(None);
+        self.generate_text_with_config(text, config)
     }
 
     /// Generate multiple tokens using temperature sampling with optional top-k
@@ -549,7 +690,7 @@ impl QwenModel {
             generated_tokens.push(next_token);
 
             // Stop if EOS
-            if next_token == 151_645 {
+            if self.generation_config.is_stop_token(next_token) {
                 break;
             }
 
@@ -573,6 +714,8 @@ impl QwenModel {
     }
 
     /// Generate tokens using combined top-k + temperature sampling similar to Python reference script.
+    /// Stops at the stop tokens and stop strings of `self.generation_config`; the returned ids include the stop token.
+    /// The prompt is prefilled once and generated tokens are fed incrementally through the infer path.
     /// If top_k is Some(k) and k > 0, restrict sampling to top-k logits; if temperature <= 0 use greedy within that set.
     pub fn generate_tokens_topk_temp(
         &mut self,
@@ -581,62 +724,13 @@ impl QwenModel {
         temperature: f32,
         top_k: Option<usize>,
     ) -> Result<Vec<i64>, CandleError> {
-        use crate::utils::sampling;
-        let mut generated_tokens = Vec::new();
-        let mut current_text = text.to_string();
-
-        for _ in 0..max_tokens {
-            // Ensure stateful pipeline progresses: run forward_text to build caches & get greedy logits via infer path
-            // Adapt forward_text to expose logits by duplicating last steps inline
-            if self.unified_state.is_none() || self.cached_causal_mask.is_none() {
-                self.initialize_states()?;
-            }
-            let tokens = self.tokenize(&current_text)?;
-            let context_pos = tokens.len();
-            // Cache embeddings & run chunked prefill only first time or when context grows
-            self.run_chatpy_prefill(&tokens, context_pos)?;
-            // Run infer to get logits tensor
-            let hidden_states = self.get_infer_hidden_states(&tokens, context_pos)?;
-            let position_ids = self
-                .config
-                .create_position_ids_with_mode_detection(&[(context_pos - 1) as i64], false)?;
-            let infer_causal_mask = self.config.create_causal_mask_with_mode_detection(
-                context_pos - 1,
-                self.config.context_length(),
-                false,
-            )?;
-            let current_pos = position_ids.clone();
-            let infer_output = self.run_ffn_infer_with_inputs(
-                &hidden_states,
-                &position_ids,
-                &infer_causal_mask,
-                &current_pos,
-            )?;
-            let logits_tensor = self.run_lm_head_with_inputs(&infer_output)?;
-            let flat_logits = logits_tensor.squeeze(0)?.squeeze(0)?; // [vocab]
-
-            // Sampling strategy
-            let next_token = if let Some(k) = top_k {
-                sampling::sample_top_k(&flat_logits, k, temperature)?
-            } else if temperature > 0.0 {
-                sampling::sample_with_temperature(&flat_logits, temperature)?
-            } else {
-                sampling::greedy_sample(&flat_logits)?
-            };
-
-            generated_tokens.push(next_token);
-            // Stop if EOS
-            if next_token == 151_645 {
-                // TODO: obtain dynamically from tokenizer special tokens
-                break;
-            }
-            if let Ok(decoded) = self.tokenizer.decode(&[next_token as u32], false) {
-                current_text.push_str(&decoded);
-            } else {
-                break;
-            }
-        }
-        Ok(generated_tokens)
+        let config = self
+            .generation_config
+            .clone()
+            .with_max_new_tokens(max_tokens)
+            .with_temperature(temperature)
+            .with_top_k(top_k);
+        self.generate_tokens_with_config(text, config)
     }
 
     /// 🚀 OPTIMIZATION: Try to get cached embeddings for a batch of tokens
@@ -705,10 +799,10 @@ impl QwenModel {
     /// * `max_tokens` - Maximum number of tokens to generate
     ///
     /// # Returns
-    /// Decoded text string ready for use
+    /// Decoded text string ready for use (without the stop token or stop strings)
     ///
     /// # Example
-    /// ```
+    /// ```rust,ignore
     /// let response = model.complete_text("What is the capital of France?", 50)?;
     /// println!("Response: {}", response);
     /// ```
@@ -717,11 +811,7 @@ impl QwenModel {
         prompt: &str,
         max_tokens: usize,
     ) -> Result<String, CandleError> {
-        let tokens = self.generate_tokens_topk_temp(prompt, max_tokens, 0.7, Some(50))?;
-        let tokens_u32: Vec<u32> = tokens.iter().map(|&t| t as u32).collect();
-        self.tokenizer
-            .decode(&tokens_u32, false)
-            .map_err(|e| CandleError::Msg(format!("Decoding failed: {e}")))
+        self.generate_text_with_params(prompt, max_tokens, 0.7, Some(50))
     }
 
     /// Generate text with full control over sampling parameters
@@ -739,7 +829,7 @@ impl QwenModel {
     /// Decoded text string ready for use
     ///
     /// # Example
-    /// ```
+    /// ```rust,ignore
     /// let response = model.generate_text_with_params(
     ///     "What is the capital of France?",
     ///     50,
@@ -754,10 +844,12 @@ impl QwenModel {
         temperature: f32,
         top_k: Option<usize>,
     ) -> Result<String, CandleError> {
-        let tokens = self.generate_tokens_topk_temp(prompt, max_tokens, temperature, top_k)?;
-        let tokens_u32: Vec<u32> = tokens.iter().map(|&t| t as u32).collect();
-        self.tokenizer
-            .decode(&tokens_u32, false)
-            .map_err(|e| CandleError::Msg(format!("Decoding failed: {e}")))
+        let config = self
+            .generation_config
+            .clone()
+            .with_max_new_tokens(max_tokens)
+            .with_temperature(temperature)
+            .with_top_k(top_k);
+        self.generate_text_with_config(prompt, config)
     }
 }
@@ -0,0 +1,29 @@
+//! Decoder-only LLMs for candle-coreml
+//!
+//! This module drives ANEMLL-style multi-component exports (embeddings, chunked FFN
+//! prefill/infer, LM head) with proper tokenization, state management, and inference
+//! pipeline. The runtime is model-agnostic: shapes and tensor contracts come from
+//! [`ModelConfig`](crate::ModelConfig), and the conventions that differ between Qwen, Llama,
+//! Gemma and friends live in [`DecoderFamily`].
+
+pub mod config;
+pub mod embeddings;
+pub mod family;
+pub mod inference;
+pub mod model;
+pub mod naming;
+pub mod reference;
+pub mod streaming;
+pub mod tensors;
+pub mod utilities;
+
+// Re-export main components from their respective modules
+pub use config::DecoderConfig;
+pub use family::DecoderFamily;
+pub use model::{DecoderModel, FfnChunk};
+pub use naming::ModelNamingConfig;
+pub use streaming::{IncrementalDecoder, StreamToken, TokenStream};
+
+// Re-export deprecated constants from config module for backward compatibility
+#[allow(deprecated)]
+pub use config::{QWEN_BATCH_SIZE, QWEN_CONTEXT_LENGTH, QWEN_HIDDEN_SIZE, QWEN_VOCAB_SIZE};
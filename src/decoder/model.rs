@@ -1,24 +1,52 @@
-//! Core Qwen model struct and loading logic
+//! Core decoder model struct and loading logic
 //!
-//! This module contains the QwenModel struct definition and all methods related to
+//! This module contains the DecoderModel struct definition and all methods related to
 //! model loading, component initialization, and state management.
 
-use crate::qwen::config::QwenConfig;
-use crate::{Config as CoreMLConfig, CoreMLModel, CoreMLState};
-use candle_core::{Error as CandleError, Tensor};
+use crate::backend::InferenceBackend;
+use crate::chat::{ChatMessage, ChatTemplate, ChatTemplateOptions};
+use crate::decoder::config::DecoderConfig;
+use crate::generation::GenerationConfig;
+use crate::pipeline::CoreMLPipeline;
+use crate::{Config as CoreMLConfig, CoreMLModel};
+use candle_core::{DType, Error as CandleError, Tensor};
 use std::path::Path;
 use tokenizers::Tokenizer;
 use tracing::{debug, trace, warn};
 
-/// Complete Qwen model with all components and state management
-pub struct QwenModel {
-    pub embeddings: CoreMLModel,
-    pub ffn_prefill: CoreMLModel,
-    pub ffn_infer: CoreMLModel,
-    pub lm_head: CoreMLModel,
+/// One FFN chunk of a chunked export after the first
+///
+/// The first chunk lives in [`DecoderModel::ffn_prefill`], [`DecoderModel::ffn_infer`] and
+/// [`DecoderModel::unified_state`]; each further chunk holds its own layers' KV cache.
+pub struct FfnChunk<B: InferenceBackend = CoreMLModel> {
+    pub prefill: B,
+    pub infer: B,
+    pub state: Option<B::State>,
+}
+
+impl<B: InferenceBackend> FfnChunk<B> {
+    pub fn new(prefill: B, infer: B) -> Self {
+        Self {
+            prefill,
+            infer,
+            state: None,
+        }
+    }
+}
+
+/// Complete decoder-only model with all components and state management
+///
+/// Components are executed through an [`InferenceBackend`]; the default backend is
+/// `CoreMLModel`, but any backend implementing the same contract can be plugged in.
+pub struct DecoderModel<B: InferenceBackend = CoreMLModel> {
+    pub embeddings: B,
+    pub ffn_prefill: B,
+    pub ffn_infer: B,
+    pub lm_head: B,
     pub tokenizer: Tokenizer,
-    pub config: QwenConfig,
-    pub unified_state: Option<CoreMLState>, // Single shared state for both prefill and infer
+    pub config: DecoderConfig,
+    pub unified_state: Option<B::State>, // Single shared state for both prefill and infer
+    pub ffn_chunks: Vec<FfnChunk<B>>, // Remaining FFN chunks, run in order after ffn_prefill/ffn_infer
     pub cached_causal_mask: Option<Tensor>, // Pre-computed causal mask (like chat.py)
     // Embeddings optimization
     pub last_sequence_embeddings: Option<(Vec<i64>, Tensor)>, // Cache last full sequence
@@ -26,11 +54,34 @@ pub struct QwenModel {
     pub cached_position_ids: Option<Tensor>, // Pre-computed position IDs for batch sizes
     pub cached_update_mask: Option<Tensor>,  // Pre-allocated update mask tensor
     pub cached_single_pos_tensor: Option<Tensor>, // Pre-allocated [1] tensor for current_pos
-    pub last_single_token_prefill_len: Option<usize>, // How many context tokens have been prefetched into KV cache in single-token mode
+    pub kv_cache_tokens: Vec<i64>, // Token ids whose keys/values are in unified_state (position i holds token i)
     pub cached_prefill_output: Option<Tensor>, // Cache prefill output hidden states for infer input
+    pub generation_config: GenerationConfig, // Default sampling and stop conditions
+    pub chat_template: Option<ChatTemplate>, // Prompt format from the model's tokenizer_config.json
 }
 
-impl QwenModel {
+impl<B: InferenceBackend> std::fmt::Debug for DecoderModel<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecoderModel")
+            .field("config", &self.config)
+            .field("has_state", &self.unified_state.is_some())
+            .field("ffn_chunks", &(self.ffn_chunks.len() + 1))
+            .finish_non_exhaustive()
+    }
+}
+
+impl DecoderModel {
+    /// Load a decoder model from the specified directory using CoreML components
+    /// Automatically checks for coreml/ subdirectory and supports both .mlmodelc and .mlpackage formats
+    pub fn load_from_directory<P: AsRef<Path>>(
+        model_dir: P,
+        config: Option<DecoderConfig>,
+    ) -> Result<Self, CandleError> {
+        Self::load_from_directory_with_backend(model_dir, config)
+    }
+}
+
+impl<B: InferenceBackend> DecoderModel<B> {
     /// Single-token prefill step used when prefill_is_single_token() is true.
     fn prefill_single_token_step(
         &mut self,
@@ -140,9 +191,15 @@ impl QwenModel {
         trace!("  causal_mask shape: {:?}", causal_mask.dims());
         trace!(
             "  current_pos: {:?}",
-            current_pos.to_vec1::<i64>().unwrap_or_default()
+            current_pos
+                .to_dtype(DType::I64)
+                .and_then(|t| t.to_vec1::<i64>())
+                .unwrap_or_default()
         );
-        if let Ok(pos_ids_vec) = position_ids.to_vec1::<i64>() {
+        if let Ok(pos_ids_vec) = position_ids
+            .to_dtype(DType::I64)
+            .and_then(|t| t.to_vec1::<i64>())
+        {
             trace!(
                 "  position_ids[0..16]: {:?}",
                 &pos_ids_vec[..16.min(pos_ids_vec.len())]
@@ -165,11 +222,12 @@ impl QwenModel {
 
     // Pattern/glob discovery removed. Explicit file paths are now required in ModelConfig.
 
-    /// Load Qwen model from the specified directory
-    /// Automatically checks for coreml/ subdirectory and supports both .mlmodelc and .mlpackage formats
-    pub fn load_from_directory<P: AsRef<Path>>(
+    /// Load a decoder model from the specified directory, loading every component with backend `B`
+    ///
+    /// Fails when the chat template shipped with the model does not parse.
+    pub fn load_from_directory_with_backend<P: AsRef<Path>>(
         model_dir: P,
-        config: Option<QwenConfig>,
+        config: Option<DecoderConfig>,
     ) -> Result<Self, CandleError> {
         let config = config.unwrap_or_default();
         let model_dir = model_dir.as_ref();
@@ -189,6 +247,12 @@ impl QwenModel {
 
         // Load tokenizer
         let tokenizer_path = model_dir.join("tokenizer.json");
+        if !tokenizer_path.exists() {
+            return Err(CandleError::Msg(format!(
+                "Tokenizer file not found: {}",
+                tokenizer_path.display()
+            )));
+        }
         let tokenizer = Tokenizer::from_file(&tokenizer_path)
             .map_err(|e| CandleError::Msg(format!("Failed to load tokenizer: {e}")))?;
 
@@ -207,7 +271,7 @@ impl QwenModel {
             output_name: "hidden_states".to_string(),
             max_sequence_length: config.context_length(),
             vocab_size: config.vocab_size(),
-            model_type: "qwen-embeddings".to_string(),
+            model_type: config.family.component_model_type("embeddings"),
         };
 
         // Require explicit file path for embeddings
@@ -226,7 +290,7 @@ impl QwenModel {
             "Loading embeddings component from {}",
             embeddings_path.display()
         );
-        let embeddings = CoreMLModel::load_from_file(&embeddings_path, &embeddings_config)?;
+        let embeddings = B::load(&embeddings_path, &embeddings_config, None)?;
 
         // Configure and load FFN models (both prefill and infer functions)
         let ffn_prefill_inputs =
@@ -252,7 +316,7 @@ impl QwenModel {
             output_name: "output_hidden_states".to_string(),
             max_sequence_length: config.context_length(),
             vocab_size: config.hidden_size(),
-            model_type: "qwen-ffn".to_string(),
+            model_type: config.family.component_model_type("ffn"),
         };
 
         // Require explicit file path for FFN prefill
@@ -277,10 +341,10 @@ impl QwenModel {
                 .map(|_| false)
                 .unwrap_or(false);
         let ffn_prefill = if ffn_prefill_has_function {
-            CoreMLModel::load_with_function(&ffn_path, &ffn_config_base, "prefill")?
+            B::load(&ffn_path, &ffn_config_base, Some("prefill"))?
         } else {
             // Split package with a dedicated prefill model (no functions)
-            CoreMLModel::load_from_file(&ffn_path, &ffn_config_base)?
+            B::load(&ffn_path, &ffn_config_base, None)?
         };
 
         // FFN Infer function (for token-by-token generation)
@@ -320,7 +384,7 @@ impl QwenModel {
                 output_name: "output_hidden_states".to_string(),
                 max_sequence_length: 1, // Single token for inference
                 vocab_size: config.hidden_size(),
-                model_type: "qwen-ffn-infer".to_string(),
+                model_type: config.family.component_model_type("ffn-infer"),
             };
             let has_func = !ffn_infer_component.functions.is_empty();
             (infer_path, infer_config, has_func)
@@ -336,11 +400,49 @@ impl QwenModel {
             ffn_infer_path.display()
         );
         let ffn_infer = if ffn_infer_has_function {
-            CoreMLModel::load_with_function(&ffn_infer_path, &ffn_infer_config, "infer")?
+            B::load(&ffn_infer_path, &ffn_infer_config, Some("infer"))?
         } else {
-            CoreMLModel::load_from_file(&ffn_infer_path, &ffn_infer_config)?
+            B::load(&ffn_infer_path, &ffn_infer_config, None)?
         };
 
+        // Remaining chunks of a chunked export share the first chunk's configuration
+        let prefill_chunks = ffn_component.chunk_files();
+        let infer_chunks = config
+            .model_config
+            .components
+            .get("ffn_infer")
+            .map(|c| c.chunk_files())
+            .unwrap_or_else(|| prefill_chunks.clone());
+        if infer_chunks.len() != prefill_chunks.len() {
+            return Err(CandleError::Msg(format!(
+                "ModelConfig.ffn_prefill has {} chunks but ffn_infer has {}",
+                prefill_chunks.len(),
+                infer_chunks.len()
+            )));
+        }
+        let mut ffn_chunks = Vec::new();
+        for (prefill_file, infer_file) in prefill_chunks.iter().zip(&infer_chunks).skip(1) {
+            let prefill_path = actual_model_dir.join(prefill_file);
+            let infer_path = actual_model_dir.join(infer_file);
+            debug!(
+                "Loading FFN chunk {} from {} / {}",
+                ffn_chunks.len() + 2,
+                prefill_path.display(),
+                infer_path.display()
+            );
+            let prefill = B::load(
+                &prefill_path,
+                &ffn_config_base,
+                ffn_prefill_has_function.then_some("prefill"),
+            )?;
+            let infer = B::load(
+                &infer_path,
+                &ffn_infer_config,
+                ffn_infer_has_function.then_some("infer"),
+            )?;
+            ffn_chunks.push(FfnChunk::new(prefill, infer));
+        }
+
         // Configure and load LM head
         let lm_output = config
             .model_config
@@ -352,7 +454,7 @@ impl QwenModel {
             output_name: lm_output,
             max_sequence_length: config.context_length(),
             vocab_size: config.vocab_size(),
-            model_type: "qwen-lm-head".to_string(),
+            model_type: config.family.component_model_type("lm-head"),
         };
 
         // Require explicit file path for LM head
@@ -368,7 +470,7 @@ impl QwenModel {
         })?;
         let lm_head_path = actual_model_dir.join(lm_head_file);
         debug!("Loading LM head component from {}", lm_head_path.display());
-        let lm_head = CoreMLModel::load_from_file(&lm_head_path, &lm_head_config)?;
+        let lm_head = B::load(&lm_head_path, &lm_head_config, None)?;
 
         // Optional runtime config wiring validation
         if let Err(e) = config.model_config.validate_internal_wiring() {
@@ -378,23 +480,99 @@ impl QwenModel {
             );
         }
 
-        Ok(Self {
+        let mut model = Self::from_components(
             embeddings,
             ffn_prefill,
             ffn_infer,
             lm_head,
             tokenizer,
             config,
-            unified_state: None,      // Single shared state
+        )
+        .with_ffn_chunks(ffn_chunks);
+        model.generation_config = model.config.generation_config(model_dir, &model.tokenizer);
+        // A template the model ships must parse; the family's only stands in for a missing one
+        model.chat_template = match ChatTemplate::from_model_dir(model_dir)? {
+            Some(template) => Some(template),
+            None => {
+                debug!(
+                    "No chat template in {}; using the built-in {} template",
+                    model_dir.display(),
+                    model.config.family.family
+                );
+                model.config.family.chat_template()?
+            }
+        };
+        Ok(model)
+    }
+
+    /// Assemble a model from already-loaded components
+    ///
+    /// This is the entry point for non-CoreML backends (scripted mocks, reference
+    /// implementations) that do not load from a model directory.
+    pub fn from_components(
+        embeddings: B,
+        ffn_prefill: B,
+        ffn_infer: B,
+        lm_head: B,
+        tokenizer: Tokenizer,
+        config: DecoderConfig,
+    ) -> Self {
+        let mut generation_config = GenerationConfig::for_tokenizer(&tokenizer);
+        for id in config.family.eos_token_ids(&tokenizer) {
+            generation_config = generation_config.with_stop_token(id);
+        }
+        Self {
+            embeddings,
+            ffn_prefill,
+            ffn_infer,
+            lm_head,
+            tokenizer,
+            config,
+            unified_state: None, // Single shared state
+            ffn_chunks: Vec::new(),
             cached_causal_mask: None, // Will be computed on first use
             last_sequence_embeddings: None,
             // 🚀 PERFORMANCE: Initialize cached tensors as None - will be allocated on first use
             cached_position_ids: None,
             cached_update_mask: None,
             cached_single_pos_tensor: None,
-            last_single_token_prefill_len: None,
+            kv_cache_tokens: Vec::new(),
             cached_prefill_output: None,
-        })
+            generation_config,
+            chat_template: None,
+        }
+    }
+
+    /// Assemble a model from a pipeline whose components are all loaded
+    ///
+    /// Shapes come from the pipeline's `ModelConfig`, family conventions from its
+    /// `model_info.model_type`. The pipeline's state is not reused;
+    /// [`DecoderModel::initialize_states`] creates a fresh one.
+    pub fn from_pipeline(
+        pipeline: CoreMLPipeline<B>,
+        tokenizer: Tokenizer,
+    ) -> Result<Self, CandleError> {
+        let missing = |name: &str| {
+            CandleError::Msg(format!("CoreMLPipeline has no '{name}' component loaded"))
+        };
+        let embeddings = pipeline.embeddings.ok_or_else(|| missing("embeddings"))?;
+        let ffn_prefill = pipeline.ffn_prefill.ok_or_else(|| missing("ffn_prefill"))?;
+        let ffn_infer = pipeline.ffn_infer.ok_or_else(|| missing("ffn_infer"))?;
+        let lm_head = pipeline.lm_head.ok_or_else(|| missing("lm_head"))?;
+        Ok(Self::from_components(
+            embeddings,
+            ffn_prefill,
+            ffn_infer,
+            lm_head,
+            tokenizer,
+            DecoderConfig::from_model_config(pipeline.config),
+        ))
+    }
+
+    /// Add the FFN chunks that follow `ffn_prefill`/`ffn_infer` in a chunked export
+    pub fn with_ffn_chunks(mut self, chunks: Vec<FfnChunk<B>>) -> Self {
+        self.ffn_chunks = chunks;
+        self
     }
 
     /// Initialize model states for efficient generation
@@ -403,6 +581,12 @@ impl QwenModel {
         // Create ONE unified state that both prefill and infer will share
         let unified_state = self.ffn_prefill.make_state()?;
         self.unified_state = Some(unified_state);
+        // Every further chunk keeps the KV cache of its own layers
+        for chunk in &mut self.ffn_chunks {
+            chunk.state = Some(chunk.prefill.make_state()?);
+        }
+        // A fresh state holds no keys/values yet
+        self.kv_cache_tokens.clear();
 
         // Pre-compute causal mask ONCE (like chat.py initialize_causal_mask)
         if self.cached_causal_mask.is_none() {
@@ -466,24 +650,62 @@ impl QwenModel {
     }
 
     /// Tokenize input text with length validation
+    ///
+    /// Prompts longer than the context window are handled by
+    /// `generation_config.overflow_policy` (an error by default).
     pub fn tokenize(&self, text: &str) -> Result<Vec<i64>, CandleError> {
-        let encoding = self
-            .tokenizer
-            .encode(text, true)
-            .map_err(|e| CandleError::Msg(format!("Tokenization failed: {e}")))?;
+        let tokens = self.encode(text, true)?;
+        self.fit_to_context(&tokens)
+    }
 
-        let tokens: Vec<i64> = encoding.get_ids().iter().map(|&id| id as i64).collect();
+    /// Apply `generation_config.overflow_policy` to a token sequence so it fits the context window
+    pub fn fit_to_context(&self, tokens: &[i64]) -> Result<Vec<i64>, CandleError> {
+        self.generation_config
+            .overflow_policy
+            .fit(tokens, self.config.context_length())
+    }
 
-        // Validate token length against context window (not batch size)
-        if tokens.len() > self.config.context_length() {
-            return Err(CandleError::Msg(format!(
-                "Input too long: {} tokens exceeds maximum context length of {} tokens supported by the model. \
-                Consider shortening your input.", 
-                tokens.len(), self.config.context_length()
-            )));
-        }
+    /// Render chat messages with the model's chat template
+    pub fn apply_chat_template(
+        &self,
+        messages: &[ChatMessage],
+        options: &ChatTemplateOptions,
+    ) -> Result<String, CandleError> {
+        let template = self.chat_template.as_ref().ok_or_else(|| {
+            CandleError::Msg(
+                "Model has no chat template; set DecoderModel::chat_template or load a model \
+                 directory whose tokenizer_config.json declares one"
+                    .to_string(),
+            )
+        })?;
+        template.render(messages, options)
+    }
 
-        Ok(tokens)
+    /// Tokenize a rendered chat prompt
+    ///
+    /// The template already spells out every special token, so the tokenizer's own
+    /// post-processing (e.g. an extra BOS) is skipped.
+    pub fn tokenize_chat(
+        &self,
+        messages: &[ChatMessage],
+        options: &ChatTemplateOptions,
+    ) -> Result<Vec<i64>, CandleError> {
+        let prompt = self.apply_chat_template(messages, options)?;
+        let tokens = self.encode(&prompt, false)?;
+        self.fit_to_context(&tokens)
+    }
+
+    /// Tokenize without any context length handling
+    pub(crate) fn encode(
+        &self,
+        text: &str,
+        add_special_tokens: bool,
+    ) -> Result<Vec<i64>, CandleError> {
+        let encoding = self
+            .tokenizer
+            .encode(text, add_special_tokens)
+            .map_err(|e| CandleError::Msg(format!("Tokenization failed: {e}")))?;
+        Ok(encoding.get_ids().iter().map(|&id| id as i64).collect())
     }
 
     /// Pad tokens to appropriate batch size for embeddings using dynamic configuration
@@ -542,7 +764,7 @@ impl QwenModel {
     }
 
     /// Get model configuration
-    pub fn config(&self) -> &QwenConfig {
+    pub fn config(&self) -> &DecoderConfig {
         &self.config
     }
 
@@ -678,7 +900,10 @@ impl QwenModel {
         trace!(
             "🔍 GENERATE_INFER: position_ids shape={:?} vals={:?}",
             position_ids.dims(),
-            position_ids.to_vec1::<i64>().unwrap_or_default()
+            position_ids
+                .to_dtype(DType::I64)
+                .and_then(|t| t.to_vec1::<i64>())
+                .unwrap_or_default()
         );
         trace!(
             "🔍 GENERATE_INFER: causal_mask shape={:?}",
@@ -687,7 +912,10 @@ impl QwenModel {
         trace!(
             "🔍 GENERATE_INFER: current_pos shape={:?} vals={:?}",
             current_pos.dims(),
-            current_pos.to_vec1::<i64>().unwrap_or_default()
+            current_pos
+                .to_dtype(DType::I64)
+                .and_then(|t| t.to_vec1::<i64>())
+                .unwrap_or_default()
         );
         let hidden_states = self.run_ffn_infer_with_inputs(
             token_embedding,
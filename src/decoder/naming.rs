@@ -1,6 +1,6 @@
 //! Model file naming patterns and discovery logic
 //!
-//! This module handles the various naming conventions used by different Qwen model variants,
+//! This module handles the various naming conventions used by different ANEMLL exports,
 //! including standard ANEMLL models and custom model formats.
 
 use crate::ModelConfig;
@@ -0,0 +1,842 @@
+//! Pure-Candle reference implementation of the Qwen3 components
+//!
+//! Builds the embeddings, FFN stack and LM head directly from the original
+//! HuggingFace safetensors weights and exposes them through [`InferenceBackend`]
+//! with the same tensor contract as the CoreML components described in
+//! `ModelConfig.components`:
+//!
+//! - embeddings: `input_ids [1, seq]` -> `hidden_states [1, seq, hidden]`
+//! - FFN (stateful): `hidden_states, position_ids, causal_mask, current_pos` ->
+//!   `output_hidden_states` (final-normed), with the KV cache held in [`ReferenceState`]
+//! - LM head: `hidden_states [1, 1, hidden]` -> `logits1..N` chunks
+//!
+//! A `DecoderModel<ReferenceComponent>` therefore runs the exact same orchestration as the
+//! CoreML pipeline on CPU anywhere, and [`divergence`] / [`compare_logits`] report how far
+//! an export drifts from the reference per layer and per logit.
+
+use crate::backend::InferenceBackend;
+use crate::config::basic::Config;
+use crate::config::model::ModelConfig;
+use crate::decoder::config::DecoderConfig;
+use crate::decoder::model::DecoderModel;
+use candle_core::{DType, Device, Error as CandleError, Module, Tensor, D};
+use candle_nn::{Embedding, Linear, RmsNorm, VarBuilder};
+use candle_transformers::utils::repeat_kv;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tokenizers::Tokenizer;
+use tracing::debug;
+
+/// HuggingFace `config.json` layout for Qwen3 checkpoints.
+pub use candle_transformers::models::qwen3::Config as Qwen3Config;
+
+/// Shared Qwen3 weights; every [`ReferenceComponent`] borrows the same instance.
+pub struct ReferenceWeights {
+    config: Qwen3Config,
+    embed_tokens: Embedding,
+    layers: Vec<ReferenceLayer>,
+    norm: RmsNorm,
+    lm_head: Linear,
+    cos: Tensor,
+    sin: Tensor,
+    device: Device,
+}
+
+struct ReferenceLayer {
+    input_layernorm: RmsNorm,
+    post_attention_layernorm: RmsNorm,
+    q_proj: Linear,
+    k_proj: Linear,
+    v_proj: Linear,
+    o_proj: Linear,
+    q_norm: RmsNorm,
+    k_norm: RmsNorm,
+    gate_proj: Linear,
+    up_proj: Linear,
+    down_proj: Linear,
+}
+
+fn linear(
+    in_dim: usize,
+    out_dim: usize,
+    bias: bool,
+    vb: VarBuilder,
+) -> Result<Linear, CandleError> {
+    if bias {
+        candle_nn::linear(in_dim, out_dim, vb)
+    } else {
+        candle_nn::linear_no_bias(in_dim, out_dim, vb)
+    }
+}
+
+impl ReferenceLayer {
+    fn new(cfg: &Qwen3Config, vb: VarBuilder) -> Result<Self, CandleError> {
+        let attn = vb.pp("self_attn");
+        let mlp = vb.pp("mlp");
+        let q_dim = cfg.num_attention_heads * cfg.head_dim;
+        let kv_dim = cfg.num_key_value_heads * cfg.head_dim;
+        Ok(Self {
+            input_layernorm: candle_nn::rms_norm(
+                cfg.hidden_size,
+                cfg.rms_norm_eps,
+                vb.pp("input_layernorm"),
+            )?,
+            post_attention_layernorm: candle_nn::rms_norm(
+                cfg.hidden_size,
+                cfg.rms_norm_eps,
+                vb.pp("post_attention_layernorm"),
+            )?,
+            q_proj: linear(
+                cfg.hidden_size,
+                q_dim,
+                cfg.attention_bias,
+                attn.pp("q_proj"),
+            )?,
+            k_proj: linear(
+                cfg.hidden_size,
+                kv_dim,
+                cfg.attention_bias,
+                attn.pp("k_proj"),
+            )?,
+            v_proj: linear(
+                cfg.hidden_size,
+                kv_dim,
+                cfg.attention_bias,
+                attn.pp("v_proj"),
+            )?,
+            o_proj: linear(
+                q_dim,
+                cfg.hidden_size,
+                cfg.attention_bias,
+                attn.pp("o_proj"),
+            )?,
+            q_norm: candle_nn::rms_norm(cfg.head_dim, cfg.rms_norm_eps, attn.pp("q_norm"))?,
+            k_norm: candle_nn::rms_norm(cfg.head_dim, cfg.rms_norm_eps, attn.pp("k_norm"))?,
+            gate_proj: candle_nn::linear_no_bias(
+                cfg.hidden_size,
+                cfg.intermediate_size,
+                mlp.pp("gate_proj"),
+            )?,
+            up_proj: candle_nn::linear_no_bias(
+                cfg.hidden_size,
+                cfg.intermediate_size,
+                mlp.pp("up_proj"),
+            )?,
+            down_proj: candle_nn::linear_no_bias(
+                cfg.intermediate_size,
+                cfg.hidden_size,
+                mlp.pp("down_proj"),
+            )?,
+        })
+    }
+}
+
+impl ReferenceWeights {
+    /// Load `config.json` and every `*.safetensors` file from a HuggingFace checkpoint directory.
+    pub fn load<P: AsRef<Path>>(weights_dir: P, device: &Device) -> Result<Self, CandleError> {
+        let weights_dir = weights_dir.as_ref();
+        let config_path = weights_dir.join("config.json");
+        let config_text = std::fs::read_to_string(&config_path).map_err(|e| {
+            CandleError::Msg(format!(
+                "Failed to read reference config {}: {e}",
+                config_path.display()
+            ))
+        })?;
+        let config: Qwen3Config = serde_json::from_str(&config_text).map_err(|e| {
+            CandleError::Msg(format!(
+                "Failed to parse reference config {}: {e}",
+                config_path.display()
+            ))
+        })?;
+
+        let mut safetensors: Vec<_> = std::fs::read_dir(weights_dir)
+            .map_err(|e| {
+                CandleError::Msg(format!(
+                    "Failed to list reference weights in {}: {e}",
+                    weights_dir.display()
+                ))
+            })?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "safetensors"))
+            .collect();
+        safetensors.sort();
+        if safetensors.is_empty() {
+            return Err(CandleError::Msg(format!(
+                "No .safetensors files found in {}",
+                weights_dir.display()
+            )));
+        }
+        debug!(
+            "Loading reference Qwen3 weights from {} file(s)",
+            safetensors.len()
+        );
+
+        // SAFETY: the files are memory-mapped read-only and not modified while loaded
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&safetensors, DType::F32, device)? };
+        Self::new(config, vb)
+    }
+
+    /// Build the reference weights from an arbitrary `VarBuilder` using HuggingFace tensor names.
+    pub fn new(config: Qwen3Config, vb: VarBuilder) -> Result<Self, CandleError> {
+        if config.use_sliding_window {
+            return Err(CandleError::Msg(
+                "Sliding-window attention is not supported by the reference implementation"
+                    .to_string(),
+            ));
+        }
+        let device = vb.device().clone();
+        let embed_tokens = candle_nn::embedding(
+            config.vocab_size,
+            config.hidden_size,
+            vb.pp("model.embed_tokens"),
+        )?;
+        let layers = (0..config.num_hidden_layers)
+            .map(|i| ReferenceLayer::new(&config, vb.pp("model.layers").pp(i)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let norm =
+            candle_nn::rms_norm(config.hidden_size, config.rms_norm_eps, vb.pp("model.norm"))?;
+        let lm_head = if config.tie_word_embeddings {
+            Linear::new(embed_tokens.embeddings().clone(), None)
+        } else {
+            candle_nn::linear_no_bias(config.hidden_size, config.vocab_size, vb.pp("lm_head"))?
+        };
+
+        // RoPE tables indexed by absolute position
+        let inv_freq: Vec<f32> = (0..config.head_dim)
+            .step_by(2)
+            .map(|i| 1f32 / config.rope_theta.powf(i as f64 / config.head_dim as f64) as f32)
+            .collect();
+        let inv_freq_len = inv_freq.len();
+        let inv_freq = Tensor::from_vec(inv_freq, (1, inv_freq_len), &device)?;
+        let positions = Tensor::arange(0u32, config.max_position_embeddings as u32, &device)?
+            .to_dtype(DType::F32)?
+            .reshape((config.max_position_embeddings, 1))?;
+        let freqs = positions.matmul(&inv_freq)?;
+
+        Ok(Self {
+            cos: freqs.cos()?,
+            sin: freqs.sin()?,
+            config,
+            embed_tokens,
+            layers,
+            norm,
+            lm_head,
+            device,
+        })
+    }
+
+    /// Checkpoint configuration.
+    pub fn config(&self) -> &Qwen3Config {
+        &self.config
+    }
+
+    /// Create an empty KV cache covering `context_length` positions.
+    pub fn make_state(&self, context_length: usize) -> Result<ReferenceState, CandleError> {
+        let shape = (
+            1,
+            self.config.num_key_value_heads,
+            context_length,
+            self.config.head_dim,
+        );
+        let layers = (0..self.layers.len())
+            .map(|_| {
+                Ok((
+                    Tensor::zeros(shape, DType::F32, &self.device)?,
+                    Tensor::zeros(shape, DType::F32, &self.device)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, CandleError>>()?;
+        Ok(ReferenceState { layers })
+    }
+
+    /// Token embeddings: `[1, seq]` ids -> `[1, seq, hidden]`.
+    pub fn embed(&self, input_ids: &Tensor) -> Result<Tensor, CandleError> {
+        self.embed_tokens.forward(&input_ids.to_dtype(DType::U32)?)
+    }
+
+    /// Run the decoder stack and return the hidden states after every layer,
+    /// followed by the final-normed output (`num_hidden_layers + 1` tensors).
+    ///
+    /// Keys/values for `position_ids` are written into `state`; attention covers the
+    /// whole cache and relies on `causal_mask` (`[1, 1, seq, context]`) to hide
+    /// positions that have not been filled yet, mirroring the CoreML stateful FFN.
+    pub fn ffn_layer_outputs(
+        &self,
+        hidden_states: &Tensor,
+        position_ids: &Tensor,
+        causal_mask: &Tensor,
+        state: &mut ReferenceState,
+    ) -> Result<Vec<Tensor>, CandleError> {
+        let cfg = &self.config;
+        let (batch, seq_len, _) = hidden_states.dims3()?;
+        let positions = position_ids.flatten_all()?.to_dtype(DType::U32)?;
+        let positions_vec = positions.to_vec1::<u32>()?;
+        if positions_vec.len() != seq_len {
+            return Err(CandleError::Msg(format!(
+                "position_ids length {} does not match hidden_states seq_len {seq_len}",
+                positions_vec.len()
+            )));
+        }
+        let cos = self.cos.index_select(&positions, 0)?;
+        let sin = self.sin.index_select(&positions, 0)?;
+        let causal_mask = causal_mask.to_dtype(DType::F32)?;
+        let scale = 1.0 / (cfg.head_dim as f64).sqrt();
+        let groups = cfg.num_attention_heads / cfg.num_key_value_heads;
+
+        let mut x = hidden_states.to_dtype(DType::F32)?;
+        let mut outputs = Vec::with_capacity(self.layers.len() + 1);
+        for (layer, (k_cache, v_cache)) in self.layers.iter().zip(state.layers.iter_mut()) {
+            let h = layer.input_layernorm.forward(&x)?;
+            let q = layer.q_proj.forward(&h)?.reshape((
+                batch,
+                seq_len,
+                cfg.num_attention_heads,
+                cfg.head_dim,
+            ))?;
+            let k = layer.k_proj.forward(&h)?.reshape((
+                batch,
+                seq_len,
+                cfg.num_key_value_heads,
+                cfg.head_dim,
+            ))?;
+            let v = layer
+                .v_proj
+                .forward(&h)?
+                .reshape((batch, seq_len, cfg.num_key_value_heads, cfg.head_dim))?
+                .transpose(1, 2)?
+                .contiguous()?;
+            let q = layer.q_norm.forward(&q)?.transpose(1, 2)?.contiguous()?;
+            let k = layer.k_norm.forward(&k)?.transpose(1, 2)?.contiguous()?;
+            let q = candle_nn::rotary_emb::rope(&q, &cos, &sin)?;
+            let k = candle_nn::rotary_emb::rope(&k, &cos, &sin)?;
+
+            write_cache(k_cache, &k, &positions_vec)?;
+            write_cache(v_cache, &v, &positions_vec)?;
+
+            let keys = repeat_kv(k_cache.clone(), groups)?.contiguous()?;
+            let values = repeat_kv(v_cache.clone(), groups)?.contiguous()?;
+            let scores =
+                (q.matmul(&keys.transpose(2, 3)?)? * scale)?.broadcast_add(&causal_mask)?;
+            let probs = candle_nn::ops::softmax_last_dim(&scores)?;
+            let attn = probs.matmul(&values)?.transpose(1, 2)?.reshape((
+                batch,
+                seq_len,
+                cfg.num_attention_heads * cfg.head_dim,
+            ))?;
+            x = (x + layer.o_proj.forward(&attn)?)?;
+
+            let h = layer.post_attention_layernorm.forward(&x)?;
+            let mlp = (cfg.hidden_act.forward(&layer.gate_proj.forward(&h)?)?
+                * layer.up_proj.forward(&h)?)?;
+            x = (x + layer.down_proj.forward(&mlp)?)?;
+            outputs.push(x.clone());
+        }
+        outputs.push(self.norm.forward(&x)?);
+        Ok(outputs)
+    }
+
+    /// Full-vocabulary logits for final-normed hidden states.
+    pub fn logits(&self, hidden_states: &Tensor) -> Result<Tensor, CandleError> {
+        self.lm_head.forward(&hidden_states.to_dtype(DType::F32)?)
+    }
+}
+
+/// Write `update` (`[1, kv_heads, seq, head_dim]`) into `cache` at the given positions,
+/// skipping positions beyond the cache length (padding rows of a final prefill batch).
+fn write_cache(cache: &mut Tensor, update: &Tensor, positions: &[u32]) -> Result<(), CandleError> {
+    let capacity = cache.dim(2)?;
+    let (_, heads, _, head_dim) = cache.dims4()?;
+    for (row, &pos) in positions.iter().enumerate() {
+        let pos = pos as usize;
+        if pos >= capacity {
+            continue;
+        }
+        let src = update.narrow(2, row, 1)?;
+        *cache = cache.slice_assign(&[0..1, 0..heads, pos..pos + 1, 0..head_dim], &src)?;
+    }
+    Ok(())
+}
+
+/// KV cache for the reference FFN stack: one `(keys, values)` pair per layer,
+/// each `[1, kv_heads, context_length, head_dim]`.
+#[derive(Debug, Clone)]
+pub struct ReferenceState {
+    pub layers: Vec<(Tensor, Tensor)>,
+}
+
+/// Which CoreML component a [`ReferenceComponent`] stands in for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceRole {
+    Embeddings,
+    Ffn,
+    LmHead,
+}
+
+/// One pipeline component backed by the shared reference weights.
+pub struct ReferenceComponent {
+    role: ReferenceRole,
+    weights: Arc<ReferenceWeights>,
+    config: Config,
+    context_length: usize,
+    /// LM head output names with their vocabulary chunk sizes, in order.
+    logits_chunks: Vec<(String, usize)>,
+    /// Number of trailing sequence positions returned by the FFN.
+    output_seq_len: Option<usize>,
+}
+
+impl ReferenceComponent {
+    /// Build the component for `role` following the tensor contract in `model_config`.
+    pub fn new(
+        role: ReferenceRole,
+        weights: Arc<ReferenceWeights>,
+        model_config: &ModelConfig,
+    ) -> Self {
+        let (model_type, output_name) = match role {
+            ReferenceRole::Embeddings => ("qwen-embeddings", "hidden_states".to_string()),
+            ReferenceRole::Ffn => ("qwen-ffn", "output_hidden_states".to_string()),
+            ReferenceRole::LmHead => (
+                "qwen-lm-head",
+                model_config
+                    .lm_head_primary_output_name()
+                    .unwrap_or_else(|| "logits1".to_string()),
+            ),
+        };
+        let vocab_size = weights.config.vocab_size;
+        let output_seq_len = model_config
+            .get_tensor_shape("ffn_prefill", "output_hidden_states", false)
+            .filter(|shape| shape.len() == 3)
+            .map(|shape| shape[1]);
+
+        Self {
+            config: Config {
+                input_names: vec![],
+                output_name,
+                max_sequence_length: model_config.shapes.context_length,
+                vocab_size,
+                model_type: model_type.to_string(),
+            },
+            logits_chunks: logits_chunks(model_config, vocab_size),
+            context_length: model_config.shapes.context_length,
+            output_seq_len,
+            role,
+            weights,
+        }
+    }
+
+    pub fn role(&self) -> ReferenceRole {
+        self.role
+    }
+
+    pub fn weights(&self) -> &Arc<ReferenceWeights> {
+        &self.weights
+    }
+}
+
+/// Split the vocabulary across the LM head outputs declared in the config
+/// (`logits1..N`), using their declared widths when they add up to the vocabulary.
+fn logits_chunks(model_config: &ModelConfig, vocab_size: usize) -> Vec<(String, usize)> {
+    let mut declared: Vec<(String, usize)> = model_config
+        .components
+        .get("lm_head")
+        .map(|lm_head| {
+            lm_head
+                .outputs
+                .iter()
+                .filter(|(name, _)| name.starts_with("logits"))
+                .map(|(name, tensor)| (name.clone(), tensor.shape.last().copied().unwrap_or(0)))
+                .collect()
+        })
+        .unwrap_or_default();
+    if declared.is_empty() {
+        return vec![("logits1".to_string(), vocab_size)];
+    }
+    declared.sort_by_key(|(name, _)| name[6..].parse::<usize>().unwrap_or(0));
+
+    if declared.iter().map(|(_, width)| width).sum::<usize>() != vocab_size {
+        let parts = declared.len();
+        let chunk = vocab_size.div_ceil(parts);
+        for (i, (_, width)) in declared.iter_mut().enumerate() {
+            *width = chunk.min(vocab_size.saturating_sub(i * chunk));
+        }
+    }
+    declared
+}
+
+impl InferenceBackend for ReferenceComponent {
+    type State = ReferenceState;
+
+    /// Reference components are built from safetensors weights, not CoreML packages.
+    fn load(
+        path: &Path,
+        _config: &Config,
+        _function_name: Option<&str>,
+    ) -> Result<Self, CandleError> {
+        Err(CandleError::Msg(format!(
+            "Reference components cannot be loaded from {}; use decoder::reference::load_reference_model",
+            path.display()
+        )))
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn forward_all(&self, inputs: &[&Tensor]) -> Result<HashMap<String, Tensor>, CandleError> {
+        let input = inputs.first().ok_or_else(|| {
+            CandleError::Msg(format!("{} called without inputs", self.config.model_type))
+        })?;
+        let mut outputs = HashMap::new();
+        match self.role {
+            ReferenceRole::Embeddings => {
+                outputs.insert(self.config.output_name.clone(), self.weights.embed(input)?);
+            }
+            ReferenceRole::LmHead => {
+                let logits = self.weights.logits(input)?;
+                let mut offset = 0;
+                for (name, width) in &self.logits_chunks {
+                    outputs.insert(name.clone(), logits.narrow(D::Minus1, offset, *width)?);
+                    offset += width;
+                }
+            }
+            ReferenceRole::Ffn => {
+                return Err(CandleError::Msg(
+                    "Reference FFN is stateful; use predict_with_state".to_string(),
+                ))
+            }
+        }
+        Ok(outputs)
+    }
+
+    fn make_state(&self) -> Result<ReferenceState, CandleError> {
+        self.weights.make_state(self.context_length)
+    }
+
+    fn predict_with_state(
+        &self,
+        inputs: &[&Tensor],
+        state: &mut ReferenceState,
+    ) -> Result<Tensor, CandleError> {
+        if self.role != ReferenceRole::Ffn {
+            return self.forward(inputs);
+        }
+        // [hidden_states, position_ids, causal_mask, current_pos] or, for infer with an
+        // update mask, [hidden_states, position_ids, update_mask, causal_mask, current_pos]
+        let (hidden_states, position_ids, causal_mask) = match inputs {
+            [hidden, positions, mask, _current_pos] => (hidden, positions, mask),
+            [hidden, positions, _update_mask, mask, _current_pos] => (hidden, positions, mask),
+            _ => {
+                return Err(CandleError::Msg(format!(
+                    "Reference FFN expects 4 or 5 inputs, got {}",
+                    inputs.len()
+                )))
+            }
+        };
+        let mut outputs =
+            self.weights
+                .ffn_layer_outputs(hidden_states, position_ids, causal_mask, state)?;
+        let output = outputs.pop().expect("final norm output is always present");
+        match self.output_seq_len {
+            Some(len) if len < output.dim(1)? => output.narrow(1, output.dim(1)? - len, len),
+            _ => Ok(output),
+        }
+    }
+}
+
+/// Load a `DecoderModel` whose components all run on the reference weights in `weights_dir`
+/// (HuggingFace checkpoint with `config.json`, `*.safetensors` and `tokenizer.json`).
+pub fn load_reference_model<P: AsRef<Path>>(
+    weights_dir: P,
+    config: DecoderConfig,
+) -> Result<DecoderModel<ReferenceComponent>, CandleError> {
+    let weights_dir = weights_dir.as_ref();
+    let tokenizer_path = weights_dir.join("tokenizer.json");
+    let tokenizer = Tokenizer::from_file(&tokenizer_path).map_err(|e| {
+        CandleError::Msg(format!(
+            "Failed to load tokenizer {}: {e}",
+            tokenizer_path.display()
+        ))
+    })?;
+    let weights = Arc::new(ReferenceWeights::load(weights_dir, &config.device)?);
+    Ok(reference_model(weights, tokenizer, config))
+}
+
+/// Assemble a `DecoderModel` from already-loaded reference weights.
+pub fn reference_model(
+    weights: Arc<ReferenceWeights>,
+    tokenizer: Tokenizer,
+    config: DecoderConfig,
+) -> DecoderModel<ReferenceComponent> {
+    let component = |role| ReferenceComponent::new(role, weights.clone(), &config.model_config);
+    DecoderModel::from_components(
+        component(ReferenceRole::Embeddings),
+        component(ReferenceRole::Ffn),
+        component(ReferenceRole::Ffn),
+        component(ReferenceRole::LmHead),
+        tokenizer,
+        config,
+    )
+}
+
+/// Element-wise divergence between a reference tensor and a candidate of the same shape.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Divergence {
+    pub max_abs: f32,
+    pub mean_abs: f32,
+    pub cosine_similarity: f32,
+}
+
+/// Compare two tensors element-wise (both are flattened and compared as F32).
+pub fn divergence(reference: &Tensor, candidate: &Tensor) -> Result<Divergence, CandleError> {
+    if reference.dims() != candidate.dims() {
+        return Err(CandleError::Msg(format!(
+            "Cannot compare tensors with shapes {:?} and {:?}",
+            reference.dims(),
+            candidate.dims()
+        )));
+    }
+    let a = reference
+        .to_dtype(DType::F32)?
+        .flatten_all()?
+        .to_vec1::<f32>()?;
+    let b = candidate
+        .to_dtype(DType::F32)?
+        .flatten_all()?
+        .to_vec1::<f32>()?;
+    let (mut max_abs, mut sum_abs, mut dot, mut norm_a, mut norm_b) =
+        (0f32, 0f64, 0f64, 0f64, 0f64);
+    for (&x, &y) in a.iter().zip(b.iter()) {
+        let diff = (x - y).abs();
+        max_abs = max_abs.max(diff);
+        sum_abs += diff as f64;
+        dot += x as f64 * y as f64;
+        norm_a += x as f64 * x as f64;
+        norm_b += y as f64 * y as f64;
+    }
+    let denom = (norm_a.sqrt() * norm_b.sqrt()).max(f64::MIN_POSITIVE);
+    Ok(Divergence {
+        max_abs,
+        mean_abs: (sum_abs / a.len().max(1) as f64) as f32,
+        cosine_similarity: (dot / denom) as f32,
+    })
+}
+
+/// Logit-level comparison: value divergence plus agreement of the top-k token ids.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogitDivergence {
+    pub values: Divergence,
+    pub reference_top_k: Vec<usize>,
+    pub candidate_top_k: Vec<usize>,
+}
+
+impl LogitDivergence {
+    /// Whether both sides pick the same greedy token.
+    pub fn top1_matches(&self) -> bool {
+        self.reference_top_k.first() == self.candidate_top_k.first()
+    }
+
+    /// Number of token ids shared by both top-k lists.
+    pub fn top_k_overlap(&self) -> usize {
+        self.reference_top_k
+            .iter()
+            .filter(|id| self.candidate_top_k.contains(id))
+            .count()
+    }
+}
+
+/// Compare two logits tensors over the vocabulary (any leading dims are flattened).
+pub fn compare_logits(
+    reference: &Tensor,
+    candidate: &Tensor,
+    top_k: usize,
+) -> Result<LogitDivergence, CandleError> {
+    let values = divergence(reference, candidate)?;
+    let top = |logits: &Tensor| -> Result<Vec<usize>, CandleError> {
+        let logits = logits
+            .to_dtype(DType::F32)?
+            .flatten_all()?
+            .to_vec1::<f32>()?;
+        let mut indexed: Vec<(usize, f32)> = logits.into_iter().enumerate().collect();
+        indexed.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(indexed.into_iter().take(top_k).map(|(i, _)| i).collect())
+    };
+    Ok(LogitDivergence {
+        values,
+        reference_top_k: top(reference)?,
+        candidate_top_k: top(candidate)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::model::{ComponentConfig, TensorConfig};
+    use crate::config::TensorDataType;
+    use candle_nn::{Activation, VarMap};
+    use candle_transformers::models::qwen3::ModelForCausalLM;
+    use tokenizers::models::wordlevel::WordLevel;
+
+    const CONTEXT: usize = 16;
+
+    fn tiny_config() -> Qwen3Config {
+        Qwen3Config {
+            vocab_size: 32,
+            hidden_size: 16,
+            intermediate_size: 32,
+            num_hidden_layers: 2,
+            num_attention_heads: 4,
+            head_dim: 4,
+            attention_bias: false,
+            num_key_value_heads: 2,
+            max_position_embeddings: 64,
+            sliding_window: None,
+            max_window_layers: 2,
+            tie_word_embeddings: false,
+            rope_theta: 10_000.0,
+            rms_norm_eps: 1e-6,
+            use_sliding_window: false,
+            hidden_act: Activation::Silu,
+        }
+    }
+
+    fn tensor(name: &str, shape: Vec<usize>) -> (String, TensorConfig) {
+        (
+            name.to_string(),
+            TensorConfig {
+                name: name.to_string(),
+                shape,
+                data_type: match name {
+                    "input_ids" | "position_ids" | "current_pos" => TensorDataType::Int32,
+                    _ => TensorDataType::Float32,
+                },
+                flexible_shape: None,
+            },
+        )
+    }
+
+    /// Single-token prefill/infer contract with the vocabulary split over two logits chunks.
+    fn model_config(cfg: &Qwen3Config) -> ModelConfig {
+        let component = |inputs: Vec<_>, outputs: Vec<_>| ComponentConfig {
+            file_path: None,
+            inputs: inputs.into_iter().collect(),
+            outputs: outputs.into_iter().collect(),
+            functions: vec![],
+            input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
+        };
+        let mut config = ModelConfig::default_qwen();
+        config.shapes.batch_size = 1;
+        config.shapes.context_length = CONTEXT;
+        config.shapes.hidden_size = cfg.hidden_size;
+        config.shapes.vocab_size = cfg.vocab_size;
+        config.components.insert(
+            "embeddings".to_string(),
+            component(
+                vec![tensor("input_ids", vec![1, 1])],
+                vec![tensor("hidden_states", vec![1, 1, cfg.hidden_size])],
+            ),
+        );
+        config.components.insert(
+            "ffn_prefill".to_string(),
+            component(
+                vec![
+                    tensor("hidden_states", vec![1, 1, cfg.hidden_size]),
+                    tensor("position_ids", vec![1]),
+                    tensor("causal_mask", vec![1, 1, 1, CONTEXT]),
+                    tensor("current_pos", vec![1]),
+                ],
+                vec![tensor("output_hidden_states", vec![1, 1, cfg.hidden_size])],
+            ),
+        );
+        config.components.insert(
+            "lm_head".to_string(),
+            component(
+                vec![tensor("hidden_states", vec![1, 1, cfg.hidden_size])],
+                vec![
+                    tensor("logits1", vec![1, 1, cfg.vocab_size / 2]),
+                    tensor("logits2", vec![1, 1, cfg.vocab_size / 2]),
+                ],
+            ),
+        );
+        config
+    }
+
+    fn tokenizer() -> Tokenizer {
+        Tokenizer::new(
+            WordLevel::builder()
+                .vocab([("<unk>".to_string(), 0)].into_iter().collect())
+                .unk_token("<unk>".to_string())
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_reference_pipeline_matches_candle_transformers_qwen3() {
+        let cfg = tiny_config();
+        let varmap = VarMap::new();
+        let vb = VarBuilder::from_varmap(&varmap, DType::F32, &Device::Cpu);
+        // Building the upstream model first initialises every weight in the shared VarMap
+        let mut upstream = ModelForCausalLM::new(&cfg, vb.clone()).unwrap();
+        let weights = Arc::new(ReferenceWeights::new(cfg.clone(), vb).unwrap());
+        let mut model = reference_model(
+            weights,
+            tokenizer(),
+            DecoderConfig::from_model_config(model_config(&cfg)),
+        );
+
+        let tokens: [i64; 5] = [3, 7, 1, 12, 5];
+        let input = Tensor::new(&[[3u32, 7, 1, 12, 5]], &Device::Cpu).unwrap();
+        let expected = upstream.forward(&input, 0).unwrap();
+
+        model.initialize_states().unwrap();
+        let mut hidden = None;
+        for (pos, &token) in tokens.iter().enumerate() {
+            let token_embed = model
+                .run_embeddings_with_inputs(&Tensor::new(&[[token]], &Device::Cpu).unwrap())
+                .unwrap();
+            let position_ids = Tensor::new(&[pos as i64], &Device::Cpu).unwrap();
+            let mask: Vec<f32> = (0..CONTEXT)
+                .map(|j| if j <= pos { 0.0 } else { f32::NEG_INFINITY })
+                .collect();
+            let mask = Tensor::from_vec(mask, (1, 1, 1, CONTEXT), &Device::Cpu).unwrap();
+            hidden = Some(
+                model
+                    .run_ffn_prefill_with_inputs(&token_embed, &position_ids, &mask, &position_ids)
+                    .unwrap(),
+            );
+        }
+        let logits = model.run_lm_head_with_inputs(&hidden.unwrap()).unwrap();
+
+        let report = compare_logits(&expected, &logits, 5).unwrap();
+        assert!(report.values.max_abs < 1e-4, "{report:?}");
+        assert!(report.top1_matches());
+        assert_eq!(report.top_k_overlap(), 5);
+    }
+
+    #[test]
+    fn test_logits_chunks_follow_declared_outputs() {
+        let cfg = tiny_config();
+        let chunks = logits_chunks(&model_config(&cfg), cfg.vocab_size);
+        assert_eq!(
+            chunks,
+            vec![("logits1".to_string(), 16), ("logits2".to_string(), 16)]
+        );
+
+        // Declared widths that do not cover the vocabulary are replaced by an even split
+        let uneven = logits_chunks(&model_config(&cfg), 30);
+        assert_eq!(uneven[0].1 + uneven[1].1, 30);
+    }
+
+    #[test]
+    fn test_divergence_reports_max_and_cosine() {
+        let a = Tensor::new(&[1f32, 0.0, 2.0], &Device::Cpu).unwrap();
+        let b = Tensor::new(&[1f32, 0.5, 2.0], &Device::Cpu).unwrap();
+        let d = divergence(&a, &b).unwrap();
+        assert!((d.max_abs - 0.5).abs() < 1e-6);
+        assert!((d.mean_abs - 0.5 / 3.0).abs() < 1e-6);
+        assert!(d.cosine_similarity > 0.97 && d.cosine_similarity < 1.0);
+        assert!(divergence(&a, &a.reshape((3, 1)).unwrap()).is_err());
+    }
+}
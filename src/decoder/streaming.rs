@@ -0,0 +1,862 @@
+//! Streaming token generation for decoder models
+//!
+//! Generation works on token ids: the prompt is prefilled once and each sampled token
+//! is fed through `ffn_infer` at the next position of the shared state.
+//! [`DecoderModel::generate_stream`] returns a [`TokenStream`] iterator that yields each
+//! sampled token together with the text it adds to the output. Text is decoded
+//! incrementally so multi-byte UTF-8 characters split across byte-level BPE tokens
+//! are only emitted once complete. Generation ends at the stop tokens and stop strings
+//! of a [`GenerationConfig`]. Dropping the iterator (or returning `false` from the
+//! [`DecoderModel::generate_stream_with_callback`] callback) stops generation early.
+//! With `parse_thinking` set, `<think>` reasoning is reported apart from the answer, and
+//! `max_thinking_tokens` closes over-long reasoning blocks.
+
+use crate::backend::InferenceBackend;
+use crate::chat::{ChatMessage, ChatTemplateOptions};
+use crate::decoder::model::DecoderModel;
+use crate::generation::{
+    FinishReason, GenerationConfig, GenerationOutput, StopSequenceMatcher, ThinkingParser,
+    TokenLogprob, THINK_END, THINK_START,
+};
+use crate::utils::sampling::{self, Penalties, Sampler};
+use candle_core::Error as CandleError;
+use rand::rngs::StdRng;
+use std::collections::VecDeque;
+use tokenizers::Tokenizer;
+
+/// One generated token and the text it contributes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamToken {
+    pub token_id: i64,
+    /// Newly completed text; empty while a multi-byte character is still incomplete.
+    /// Only answer text when `parse_thinking` is set.
+    pub text: String,
+    /// Newly completed reasoning text (always empty unless `parse_thinking` is set)
+    pub reasoning: String,
+    /// Log-probability of `token_id` under the model's distribution; 0.0 for tokens
+    /// forced to close a reasoning block, which were not sampled
+    pub logprob: f32,
+    /// Most likely alternatives at this step (empty unless `top_logprobs` is configured,
+    /// and for forced tokens)
+    pub top_logprobs: Vec<TokenLogprob>,
+}
+
+/// Incremental detokenizer that only emits complete UTF-8 text.
+///
+/// Decodes a sliding window of token ids and returns the suffix added by the newest
+/// token, holding back output while the decoded text ends in a replacement character
+/// (a partial multi-byte sequence).
+#[derive(Debug, Clone, Default)]
+pub struct IncrementalDecoder {
+    tokens: Vec<u32>,
+    prefix_offset: usize,
+    read_offset: usize,
+}
+
+impl IncrementalDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a token and return the text that became complete because of it.
+    pub fn push(&mut self, tokenizer: &Tokenizer, token_id: u32) -> Result<String, CandleError> {
+        self.tokens.push(token_id);
+        let prefix = decode(
+            tokenizer,
+            &self.tokens[self.prefix_offset..self.read_offset],
+        )?;
+        let full = decode(tokenizer, &self.tokens[self.prefix_offset..])?;
+
+        if full.len() > prefix.len() && !full.ends_with('\u{FFFD}') {
+            if let Some(delta) = full.get(prefix.len()..) {
+                let delta = delta.to_string();
+                self.prefix_offset = self.read_offset;
+                self.read_offset = self.tokens.len();
+                return Ok(delta);
+            }
+        }
+        Ok(String::new())
+    }
+
+    /// Return any text still held back (lossily decoded) and reset the window.
+    pub fn flush(&mut self, tokenizer: &Tokenizer) -> Result<String, CandleError> {
+        let prefix = decode(
+            tokenizer,
+            &self.tokens[self.prefix_offset..self.read_offset],
+        )?;
+        let full = decode(tokenizer, &self.tokens[self.prefix_offset..])?;
+        self.prefix_offset = self.tokens.len();
+        self.read_offset = self.tokens.len();
+        Ok(full.get(prefix.len()..).unwrap_or_default().to_string())
+    }
+}
+
+/// Text fed to the model to close a reasoning block that used up `max_thinking_tokens`.
+const FORCED_THINK_END: &str = "\n</think>\n\n";
+
+fn decode(tokenizer: &Tokenizer, tokens: &[u32]) -> Result<String, CandleError> {
+    tokenizer
+        .decode(tokens, false)
+        .map_err(|e| CandleError::Msg(format!("Failed to decode tokens: {e}")))
+}
+
+/// Iterator over streamed tokens; see [`DecoderModel::generate_stream`].
+pub struct TokenStream<'a, B: InferenceBackend> {
+    model: &'a mut DecoderModel<B>,
+    /// Prompt text still to be tokenized (consumed by the first step)
+    prompt: Option<String>,
+    /// Prompt followed by the generated tokens
+    tokens: Vec<i64>,
+    /// Keep the cached prefix of the prompt in the shared state instead of resetting it
+    reuse_cache: bool,
+    decoder: IncrementalDecoder,
+    stop_matcher: StopSequenceMatcher,
+    /// Tracks reasoning blocks when `parse_thinking` or `max_thinking_tokens` is set
+    thinking: Option<ThinkingParser>,
+    thinking_tokens: usize,
+    /// Tokens emitted instead of sampled ones (the forced end of a reasoning block)
+    forced_tokens: VecDeque<i64>,
+    thinking_closed: bool,
+    config: GenerationConfig,
+    sampler: Sampler,
+    penalties: Penalties,
+    rng: StdRng,
+    generated: usize,
+    finished: bool,
+    finish_reason: Option<FinishReason>,
+}
+
+impl<B: InferenceBackend> TokenStream<'_, B> {
+    /// Number of tokens produced so far.
+    pub fn generated(&self) -> usize {
+        self.generated
+    }
+
+    /// Why the stream ended; `None` while tokens are still being produced.
+    pub fn finish_reason(&self) -> Option<FinishReason> {
+        self.finish_reason
+    }
+
+    fn finish(&mut self, reason: FinishReason) {
+        self.finished = true;
+        self.finish_reason.get_or_insert(reason);
+    }
+
+    /// Prompt and generated token ids so far.
+    pub fn tokens(&self) -> &[i64] {
+        &self.tokens
+    }
+
+    fn step(&mut self) -> Result<StreamToken, CandleError> {
+        // The prompt is prefilled once; every later step feeds only the newest token
+        // through ffn_infer at the next position of the shared state
+        let context_length = self.model.config.context_length();
+        let overflow_policy = self.config.overflow_policy;
+        let logits = if self.generated == 0 {
+            if let Some(prompt) = self.prompt.take() {
+                self.tokens = self.model.encode(&prompt, true)?;
+            }
+            self.tokens = overflow_policy.fit(&self.tokens, context_length)?;
+            if self.thinking.is_some() && self.prompt_opens_thinking() {
+                self.thinking = Some(ThinkingParser::in_thinking());
+            }
+            if self.reuse_cache {
+                self.model.prefill_reusing_cache(&self.tokens)?
+            } else {
+                self.model.prefill_for_generation(&self.tokens)?
+            }
+        } else if self.tokens.len() > context_length {
+            // The context is full: cut the sequence back and re-prefill the kept tokens
+            let window = overflow_policy
+                .generation_window(context_length)
+                .expect("generation stops at a full context under OverflowPolicy::Error");
+            self.tokens = overflow_policy.fit(&self.tokens, window)?;
+            self.model.prefill_for_generation(&self.tokens)?
+        } else {
+            self.model.infer_next_logits(&self.tokens)?
+        };
+        let logits = self
+            .penalties
+            .apply(&logits, &self.tokens)?
+            .to_vec1::<f32>()?;
+        let forced = self.forced_tokens.pop_front();
+        let token_id = match forced {
+            Some(token_id) => token_id,
+            None => self.sampler.sample_slice_with_rng(&logits, &mut self.rng),
+        };
+        self.generated += 1;
+
+        // Forced tokens replace whatever was sampled, so the distribution says nothing about them
+        let (logprob, top_logprobs) = if forced.is_some() {
+            (0.0, Vec::new())
+        } else {
+            let logprobs = sampling::log_softmax(&logits);
+            let logprob = logprobs
+                .get(token_id as usize)
+                .copied()
+                .unwrap_or(f32::NEG_INFINITY);
+            let top_logprobs = self
+                .config
+                .top_logprobs
+                .map(|n| {
+                    sampling::top_n_logprobs(&logprobs, n)
+                        .into_iter()
+                        .map(|(token_id, logprob)| TokenLogprob { token_id, logprob })
+                        .collect()
+                })
+                .unwrap_or_default();
+            (logprob, top_logprobs)
+        };
+
+        // Stop tokens end generation and contribute no text of their own
+        let mut text = if self.config.is_stop_token(token_id) {
+            self.finish(FinishReason::Eos);
+            String::new()
+        } else {
+            self.tokens.push(token_id);
+            self.decoder.push(&self.model.tokenizer, token_id as u32)?
+        };
+        let context_full = self.tokens.len() > context_length
+            && overflow_policy.generation_window(context_length).is_none();
+        if self.generated >= self.config.max_new_tokens || context_full {
+            self.finish(FinishReason::Length);
+        }
+        if self.finished {
+            text.push_str(&self.decoder.flush(&self.model.tokenizer)?);
+        }
+
+        let matched = self.stop_matcher.push(&text);
+        text = matched.text;
+        if matched.stopped {
+            // A stop string completed by the final token still counts as a stop string
+            self.finished = true;
+            self.finish_reason = Some(FinishReason::StopString);
+        } else if self.finished {
+            text.push_str(&self.stop_matcher.flush());
+        }
+
+        let mut reasoning = String::new();
+        if let Some(parser) = self.thinking.as_mut() {
+            let mut split = parser.push(&text);
+            if self.finished {
+                let rest = parser.flush();
+                split.reasoning.push_str(&rest.reasoning);
+                split.answer.push_str(&rest.answer);
+            } else if parser.is_thinking() {
+                self.thinking_tokens += 1;
+                let over_budget = self
+                    .config
+                    .max_thinking_tokens
+                    .is_some_and(|max| self.thinking_tokens >= max);
+                if over_budget && !self.thinking_closed {
+                    self.thinking_closed = true;
+                    self.forced_tokens = self.model.encode(FORCED_THINK_END, false)?.into();
+                }
+            }
+            if self.config.parse_thinking {
+                text = split.answer;
+                reasoning = split.reasoning;
+            }
+        }
+        Ok(StreamToken {
+            token_id,
+            text,
+            reasoning,
+            logprob,
+            top_logprobs,
+        })
+    }
+
+    /// Whether the prompt ends inside an open reasoning block (a template that starts
+    /// the assistant turn with `<think>`): its last `<think>` token is not followed by a
+    /// `</think>` token.
+    fn prompt_opens_thinking(&self) -> bool {
+        let tokenizer = &self.model.tokenizer;
+        let Some(start) = tokenizer.token_to_id(THINK_START) else {
+            return false;
+        };
+        let end = tokenizer.token_to_id(THINK_END);
+        let last_tag = self
+            .tokens
+            .iter()
+            .rev()
+            .map(|&id| id as u32)
+            .find(|&id| id == start || Some(id) == end);
+        last_tag == Some(start)
+    }
+}
+
+impl<B: InferenceBackend> Iterator for TokenStream<'_, B> {
+    type Item = Result<StreamToken, CandleError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished || self.generated >= self.config.max_new_tokens {
+            return None;
+        }
+        let item = self.step();
+        if item.is_err() {
+            self.finished = true;
+        }
+        Some(item)
+    }
+}
+
+impl<B: InferenceBackend> DecoderModel<B> {
+    /// Stream generated tokens one at a time.
+    ///
+    /// Uses the same sampling as `generate_tokens_topk_temp` (top-k and/or temperature,
+    /// greedy when `temperature <= 0`, plus any top-p/min-p/typical settings from
+    /// `self.generation_config`) and stops at a stop token from
+    /// `self.generation_config` or after `max_tokens`.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// for token in model.generate_stream("What is the capital of France?", 50, 0.7, Some(50)) {
+    ///     print!("{}", token?.text);
+    /// }
+    /// ```
+    pub fn generate_stream(
+        &mut self,
+        prompt: &str,
+        max_tokens: usize,
+        temperature: f32,
+        top_k: Option<usize>,
+    ) -> TokenStream<'_, B> {
+        let config = self
+            .generation_config
+            .clone()
+            .with_max_new_tokens(max_tokens)
+            .with_temperature(temperature)
+            .with_top_k(top_k);
+        self.generate_stream_with_config(prompt, config)
+    }
+
+    /// Stream generated tokens using explicit generation settings.
+    ///
+    /// With `config.seed` set, the same prompt and seed reproduce the same tokens.
+    /// The stream ends at the first stop token or stop string. Stop tokens are still
+    /// yielded (with empty text); stop strings are removed from the emitted text, even
+    /// when they span several tokens. Prompts and generations that outgrow the context
+    /// window are handled by `config.overflow_policy`.
+    pub fn generate_stream_with_config(
+        &mut self,
+        prompt: &str,
+        config: GenerationConfig,
+    ) -> TokenStream<'_, B> {
+        self.start_stream(Some(prompt.to_string()), Vec::new(), false, config)
+    }
+
+    /// Stream generated tokens for an already tokenized prompt.
+    ///
+    /// The prompt is prefilled once and each sampled token is fed straight into the
+    /// `ffn_infer` path at the next position, so the ids are never re-tokenized.
+    pub fn generate_stream_from_tokens(
+        &mut self,
+        prompt_tokens: &[i64],
+        config: GenerationConfig,
+    ) -> TokenStream<'_, B> {
+        self.start_stream(None, prompt_tokens.to_vec(), false, config)
+    }
+
+    /// Stream generated tokens for a prompt that extends an earlier one.
+    ///
+    /// Unlike [`DecoderModel::generate_stream_from_tokens`], the shared state is not reset:
+    /// the longest prefix of `prompt_tokens` already in the KV cache is kept and only the
+    /// remaining tokens are prefilled (see [`DecoderModel::prefill_reusing_cache`]).
+    pub fn continue_stream_from_tokens(
+        &mut self,
+        prompt_tokens: &[i64],
+        config: GenerationConfig,
+    ) -> TokenStream<'_, B> {
+        self.start_stream(None, prompt_tokens.to_vec(), true, config)
+    }
+
+    fn start_stream(
+        &mut self,
+        prompt: Option<String>,
+        tokens: Vec<i64>,
+        reuse_cache: bool,
+        config: GenerationConfig,
+    ) -> TokenStream<'_, B> {
+        let sampler = config.sampler();
+        TokenStream {
+            model: self,
+            prompt,
+            tokens,
+            reuse_cache,
+            decoder: IncrementalDecoder::new(),
+            stop_matcher: StopSequenceMatcher::new(&config.stop_strings),
+            thinking: (config.parse_thinking || config.max_thinking_tokens.is_some())
+                .then(ThinkingParser::new),
+            thinking_tokens: 0,
+            forced_tokens: VecDeque::new(),
+            thinking_closed: false,
+            sampler,
+            penalties: config.penalties(),
+            rng: sampler.rng(),
+            config,
+            generated: 0,
+            finished: false,
+            finish_reason: None,
+        }
+    }
+
+    /// Stream generated tokens to `on_token`; return `false` from the callback to stop.
+    ///
+    /// Returns every token id produced, including the one for which the callback
+    /// requested the stop.
+    pub fn generate_stream_with_callback<F>(
+        &mut self,
+        prompt: &str,
+        max_tokens: usize,
+        temperature: f32,
+        top_k: Option<usize>,
+        mut on_token: F,
+    ) -> Result<Vec<i64>, CandleError>
+    where
+        F: FnMut(&StreamToken) -> bool,
+    {
+        let mut tokens = Vec::new();
+        for token in self.generate_stream(prompt, max_tokens, temperature, top_k) {
+            let token = token?;
+            tokens.push(token.token_id);
+            if !on_token(&token) {
+                break;
+            }
+        }
+        Ok(tokens)
+    }
+
+    /// Generate token ids with explicit generation settings.
+    ///
+    /// The returned ids include the stop token (or the tokens completing a stop string).
+    pub fn generate_tokens_with_config(
+        &mut self,
+        prompt: &str,
+        config: GenerationConfig,
+    ) -> Result<Vec<i64>, CandleError> {
+        self.generate_stream_with_config(prompt, config)
+            .map(|token| token.map(|t| t.token_id))
+            .collect()
+    }
+
+    /// Generate with explicit settings and return tokens, text, log-probabilities and
+    /// the finish reason.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let config = model.generation_config.clone().with_top_logprobs(Some(5));
+    /// let output = model.generate("Fix: teh cat", config)?;
+    /// println!("{} ({:?}, logprob {})", output.text, output.finish_reason, output.total_logprob());
+    /// ```
+    pub fn generate(
+        &mut self,
+        prompt: &str,
+        config: GenerationConfig,
+    ) -> Result<GenerationOutput, CandleError> {
+        collect_output(self.generate_stream_with_config(prompt, config))
+    }
+
+    /// Like [`DecoderModel::generate`], for an already tokenized prompt.
+    pub fn generate_from_tokens(
+        &mut self,
+        prompt_tokens: &[i64],
+        config: GenerationConfig,
+    ) -> Result<GenerationOutput, CandleError> {
+        collect_output(self.generate_stream_from_tokens(prompt_tokens, config))
+    }
+
+    /// Like [`DecoderModel::generate_from_tokens`], reusing the cached prefix of the prompt;
+    /// see [`DecoderModel::continue_stream_from_tokens`].
+    pub fn continue_from_tokens(
+        &mut self,
+        prompt_tokens: &[i64],
+        config: GenerationConfig,
+    ) -> Result<GenerationOutput, CandleError> {
+        collect_output(self.continue_stream_from_tokens(prompt_tokens, config))
+    }
+
+    /// Generate the assistant's reply to a conversation.
+    ///
+    /// Messages are formatted with the model's [`ChatTemplate`](crate::ChatTemplate), so the
+    /// prompt matches the format of the loaded model family.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let messages = [ChatMessage::system("You fix typos."), ChatMessage::user("teh cat")];
+    /// let options = ChatTemplateOptions::default().with_enable_thinking(false);
+    /// let reply = model.generate_chat(&messages, &options, model.generation_config.clone())?;
+    /// ```
+    pub fn generate_chat(
+        &mut self,
+        messages: &[ChatMessage],
+        options: &ChatTemplateOptions,
+        config: GenerationConfig,
+    ) -> Result<GenerationOutput, CandleError> {
+        let prompt_tokens = self.tokenize_chat(messages, options)?;
+        self.generate_from_tokens(&prompt_tokens, config)
+    }
+
+    /// Generate text with explicit generation settings.
+    ///
+    /// Stop tokens and stop strings are not included in the returned text.
+    pub fn generate_text_with_config(
+        &mut self,
+        prompt: &str,
+        config: GenerationConfig,
+    ) -> Result<String, CandleError> {
+        self.generate_stream_with_config(prompt, config)
+            .map(|token| token.map(|t| t.text))
+            .collect()
+    }
+}
+
+fn collect_output<B: InferenceBackend>(
+    mut stream: TokenStream<'_, B>,
+) -> Result<GenerationOutput, CandleError> {
+    let mut output = GenerationOutput {
+        tokens: Vec::new(),
+        text: String::new(),
+        reasoning: stream.config.parse_thinking.then(String::new),
+        logprobs: Vec::new(),
+        top_logprobs: stream.config.top_logprobs.map(|_| Vec::new()),
+        finish_reason: FinishReason::Length,
+    };
+    for token in stream.by_ref() {
+        let token = token?;
+        output.tokens.push(token.token_id);
+        output.text.push_str(&token.text);
+        if let Some(reasoning) = output.reasoning.as_mut() {
+            reasoning.push_str(&token.reasoning);
+        }
+        output.logprobs.push(token.logprob);
+        if let Some(top) = output.top_logprobs.as_mut() {
+            top.push(token.top_logprobs);
+        }
+    }
+    output.finish_reason = stream.finish_reason().unwrap_or(FinishReason::Length);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::basic::Config;
+    use crate::testing::typo_fixer_model_config;
+    use crate::DecoderConfig;
+    use candle_core::{DType, Device, Tensor};
+    use std::cell::RefCell;
+    use std::collections::{HashMap, VecDeque};
+    use std::path::Path;
+    use tokenizers::decoders::byte_level::ByteLevel;
+    use tokenizers::models::wordlevel::WordLevel;
+    use tokenizers::pre_tokenizers::whitespace::WhitespaceSplit;
+
+    const HIDDEN: usize = 8;
+    const VOCAB: usize = 8;
+
+    /// Backend whose LM head emits one-hot logits for a queue of token ids.
+    struct ScriptedBackend {
+        config: Config,
+        next_tokens: RefCell<VecDeque<usize>>,
+    }
+
+    impl InferenceBackend for ScriptedBackend {
+        type State = ();
+
+        fn load(_: &Path, config: &Config, _: Option<&str>) -> Result<Self, CandleError> {
+            Ok(Self {
+                config: config.clone(),
+                next_tokens: RefCell::default(),
+            })
+        }
+
+        fn config(&self) -> &Config {
+            &self.config
+        }
+
+        fn forward_all(&self, inputs: &[&Tensor]) -> Result<HashMap<String, Tensor>, CandleError> {
+            let output = if self.config.model_type == "qwen-lm-head" {
+                let token = self.next_tokens.borrow_mut().pop_front().unwrap_or(0);
+                let mut logits = vec![0f32; VOCAB];
+                logits[token] = 10.0;
+                Tensor::from_vec(logits, (1, 1, VOCAB), &Device::Cpu)?
+            } else {
+                Tensor::zeros((1, inputs[0].dim(1)?, HIDDEN), DType::F32, &Device::Cpu)?
+            };
+            Ok(HashMap::from([(self.config.output_name.clone(), output)]))
+        }
+
+        fn make_state(&self) -> Result<(), CandleError> {
+            Ok(())
+        }
+
+        fn predict_with_state(&self, _: &[&Tensor], _: &mut ()) -> Result<Tensor, CandleError> {
+            Tensor::zeros((1, 1, HIDDEN), DType::F32, &Device::Cpu)
+        }
+    }
+
+    /// Byte-level vocabulary where "é" (0xC3 0xA9) is split across tokens 2 and 3.
+    fn byte_level_tokenizer() -> Tokenizer {
+        let vocab = [
+            ("<unk>", 0),
+            ("hi", 1),
+            ("Ã", 2),
+            ("©", 3),
+            ("Ġok", 4),
+            ("<think>", 5),
+            ("</think>", 6),
+        ]
+        .into_iter()
+        .map(|(word, id)| (word.to_string(), id))
+        .collect();
+        let mut tokenizer = Tokenizer::new(
+            WordLevel::builder()
+                .vocab(vocab)
+                .unk_token("<unk>".to_string())
+                .build()
+                .unwrap(),
+        );
+        tokenizer.with_pre_tokenizer(Some(WhitespaceSplit));
+        tokenizer.with_decoder(Some(ByteLevel::default()));
+        tokenizer
+    }
+
+    fn scripted_model(tokens: &[usize]) -> DecoderModel<ScriptedBackend> {
+        let backend = |model_type: &str, output_name: &str| ScriptedBackend {
+            config: Config {
+                input_names: vec![],
+                output_name: output_name.to_string(),
+                max_sequence_length: 32,
+                vocab_size: VOCAB,
+                model_type: model_type.to_string(),
+            },
+            next_tokens: RefCell::new(tokens.iter().copied().collect()),
+        };
+        DecoderModel::from_components(
+            backend("qwen-embeddings", "hidden_states"),
+            backend("qwen-ffn", "output_hidden_states"),
+            backend("qwen-ffn-infer", "output_hidden_states"),
+            backend("qwen-lm-head", "logits1"),
+            byte_level_tokenizer(),
+            DecoderConfig::from_model_config(typo_fixer_model_config(4, 32, HIDDEN, VOCAB)),
+        )
+    }
+
+    #[test]
+    fn test_incremental_decoder_holds_back_split_utf8() {
+        let tokenizer = byte_level_tokenizer();
+        let mut decoder = IncrementalDecoder::new();
+        assert_eq!(decoder.push(&tokenizer, 1).unwrap(), "hi");
+        assert_eq!(decoder.push(&tokenizer, 2).unwrap(), "");
+        assert_eq!(decoder.push(&tokenizer, 3).unwrap(), "é");
+        assert_eq!(decoder.push(&tokenizer, 4).unwrap(), " ok");
+        assert_eq!(decoder.flush(&tokenizer).unwrap(), "");
+    }
+
+    #[test]
+    fn test_generate_stream_yields_tokens_and_text_deltas() {
+        let mut model = scripted_model(&[2, 3, 4, 1]);
+        let tokens: Vec<StreamToken> = model
+            .generate_stream("hi", 4, 0.0, None)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let ids: Vec<i64> = tokens.iter().map(|t| t.token_id).collect();
+        assert_eq!(ids, vec![2, 3, 4, 1]);
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["", "é", " ok", "hi"]);
+    }
+
+    #[test]
+    fn test_generate_stream_flushes_incomplete_text_at_the_end() {
+        let mut model = scripted_model(&[1, 2]);
+        let text: String = model
+            .generate_stream("hi", 2, 0.0, None)
+            .map(|t| t.unwrap().text)
+            .collect();
+        assert_eq!(text, "hi\u{FFFD}");
+    }
+
+    #[test]
+    fn test_generate_stream_stops_early() {
+        let mut model = scripted_model(&[1, 4, 1, 4]);
+        let mut seen = Vec::new();
+        let ids = model
+            .generate_stream_with_callback("hi", 10, 0.0, None, |token| {
+                seen.push(token.text.clone());
+                seen.len() < 2
+            })
+            .unwrap();
+        assert_eq!(ids, vec![1, 4]);
+        assert_eq!(seen, vec!["hi", " ok"]);
+
+        let mut stream = model.generate_stream("hi", 10, 0.0, None);
+        assert!(stream.next().is_some());
+        drop(stream);
+    }
+
+    #[test]
+    fn test_stop_token_ends_stream_without_text() {
+        let mut model = scripted_model(&[1, 4, 1]);
+        let config = GenerationConfig::default()
+            .with_temperature(0.0)
+            .with_top_k(None)
+            .with_stop_token(4);
+        let tokens: Vec<StreamToken> = model
+            .generate_stream_with_config("hi", config)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let ids: Vec<i64> = tokens.iter().map(|t| t.token_id).collect();
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(ids, vec![1, 4]);
+        assert_eq!(texts, vec!["hi", ""]);
+    }
+
+    #[test]
+    fn test_stop_string_spanning_tokens_is_excluded_from_text() {
+        let config = GenerationConfig::default()
+            .with_temperature(0.0)
+            .with_top_k(None)
+            .with_stop_string("okhi");
+
+        let mut model = scripted_model(&[1, 4, 1, 4]);
+        let text = model
+            .generate_text_with_config("hi", config.clone())
+            .unwrap();
+        assert_eq!(text, "hi ");
+
+        let mut model = scripted_model(&[1, 4, 1, 4]);
+        let ids = model.generate_tokens_with_config("hi", config).unwrap();
+        assert_eq!(ids, vec![1, 4, 1]);
+    }
+
+    #[test]
+    fn test_seeded_generation_is_reproducible() {
+        let script = [1, 4, 2, 3, 1, 4, 1, 4, 2, 3];
+        let config = GenerationConfig::default()
+            .with_max_new_tokens(script.len())
+            .with_temperature(8.0)
+            .with_top_k(None)
+            .with_seed(Some(42));
+
+        let run = |config: GenerationConfig| {
+            scripted_model(&script)
+                .generate_tokens_with_config("hi", config)
+                .unwrap()
+        };
+        let first = run(config.clone());
+        assert_eq!(first, run(config.clone()));
+        assert_ne!(first, run(config.with_seed(Some(43))));
+    }
+
+    #[test]
+    fn test_penalties_use_prompt_tokens_within_window() {
+        let config = GenerationConfig::default()
+            .with_max_new_tokens(1)
+            .with_temperature(0.0)
+            .with_frequency_penalty(20.0);
+
+        // "hi" (token 1) is in the prompt, so the scripted favourite is penalized away
+        let ids = scripted_model(&[1])
+            .generate_tokens_with_config("hi", config.clone())
+            .unwrap();
+        assert_ne!(ids, vec![1]);
+
+        let ids = scripted_model(&[1])
+            .generate_tokens_with_config("hi", config.with_penalty_window(Some(0)))
+            .unwrap();
+        assert_eq!(ids, vec![1]);
+    }
+
+    #[test]
+    fn test_generate_reports_logprobs_and_finish_reason() {
+        let greedy = GenerationConfig::default()
+            .with_temperature(0.0)
+            .with_top_k(None);
+
+        let output = scripted_model(&[1, 4, 1])
+            .generate(
+                "hi",
+                greedy.clone().with_stop_token(1).with_top_logprobs(Some(2)),
+            )
+            .unwrap();
+        assert_eq!(output.tokens, vec![1]);
+        assert_eq!(output.text, "");
+        assert_eq!(output.finish_reason, FinishReason::Eos);
+        // One-hot logits: 10.0 for the scripted token, 0.0 for the other VOCAB - 1
+        let expected = -(1.0 + (VOCAB as f32 - 1.0) * (-10f32).exp()).ln();
+        assert!((output.logprobs[0] - expected).abs() < 1e-6);
+        let top = &output.top_logprobs.as_ref().unwrap()[0];
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].token_id, 1);
+        assert!(top[1].logprob < top[0].logprob);
+
+        let output = scripted_model(&[1, 4, 1, 4])
+            .generate("hi", greedy.clone().with_stop_string("okhi"))
+            .unwrap();
+        assert_eq!(output.finish_reason, FinishReason::StopString);
+        assert_eq!(output.text, "hi ");
+        assert_eq!(output.logprobs.len(), 3);
+        assert_eq!(output.top_logprobs, None);
+
+        let output = scripted_model(&[1, 4])
+            .generate("hi", greedy.with_max_new_tokens(2))
+            .unwrap();
+        assert_eq!(output.finish_reason, FinishReason::Length);
+        assert_eq!(output.text, "hi ok");
+    }
+
+    #[test]
+    fn test_thinking_is_split_and_budget_forces_the_closing_tag() {
+        let greedy_config = |max_new_tokens| {
+            GenerationConfig::default()
+                .with_temperature(0.0)
+                .with_top_k(None)
+                .with_max_new_tokens(max_new_tokens)
+        };
+        let config = greedy_config(5)
+            .with_parse_thinking(true)
+            .with_max_thinking_tokens(Some(2));
+
+        // The model would keep thinking; after two thinking tokens "</think>" is forced
+        let output = scripted_model(&[5, 1, 1, 1, 4])
+            .generate("hi", config.clone().with_top_logprobs(Some(2)))
+            .unwrap();
+        assert_eq!(output.tokens, vec![5, 1, 6, 1, 4]);
+        assert_eq!(output.reasoning.as_deref(), Some("hi"));
+        assert_eq!(output.text, "hi ok");
+        // The forced token reports no distribution of its own
+        let top_logprobs = output.top_logprobs.unwrap();
+        assert_eq!(output.logprobs[2], 0.0);
+        assert!(top_logprobs[2].is_empty());
+        assert!(output.logprobs[1] < 0.0);
+        assert_eq!(top_logprobs[1][0].token_id, 1);
+
+        // A prompt ending in "<think>" starts inside the reasoning block
+        let output = scripted_model(&[1, 6, 4])
+            .generate("hi <think>", config.clone().with_max_new_tokens(3))
+            .unwrap();
+        assert_eq!(output.tokens, vec![1, 6, 4]);
+        assert_eq!(output.reasoning.as_deref(), Some("hi"));
+        assert_eq!(output.text, " ok");
+
+        // The block stays open after reasoning text in the prompt, but not once it is closed
+        let output = scripted_model(&[6, 4])
+            .generate("<think> hi", config.clone().with_max_new_tokens(2))
+            .unwrap();
+        assert_eq!(output.reasoning.as_deref(), Some(""));
+        assert_eq!(output.text, " ok");
+        let output = scripted_model(&[1, 4])
+            .generate("<think> hi </think>", config.with_max_new_tokens(2))
+            .unwrap();
+        assert_eq!(output.reasoning.as_deref(), Some(""));
+        assert_eq!(output.text, "hi ok");
+
+        let output = scripted_model(&[5, 1, 6, 4])
+            .generate("hi", greedy_config(4))
+            .unwrap();
+        assert_eq!(output.reasoning, None);
+        assert_eq!(output.text, "<think>hi</think> ok");
+    }
+}
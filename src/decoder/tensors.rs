@@ -1,16 +1,20 @@
-//! Tensor creation and manipulation utilities for Qwen models
+//! Tensor creation and manipulation utilities for decoder models
 //!
-//! This module contains all tensor creation functions that are used by the QwenModel
+//! This module contains all tensor creation functions that are used by the DecoderModel
 //! for preparing inputs, masks, and managing tensor operations.
 
-use crate::qwen::model::QwenModel;
+use crate::backend::InferenceBackend;
+use crate::decoder::model::DecoderModel;
 use crate::utils::mask;
 use candle_core::{Error as CandleError, Tensor};
 use tracing::trace;
 
-impl QwenModel {
+impl<B: InferenceBackend> DecoderModel<B> {
     /// Create input tensor for embeddings with proper shape validation
     pub fn create_embeddings_input_tensor(&self, tokens: &[i64]) -> Result<Tensor, CandleError> {
+        self.config
+            .model_config
+            .check_context_length(tokens.len())?;
         let padded_tokens = self.pad_tokens(tokens);
 
         // Get expected shape from ModelConfig
@@ -46,7 +50,10 @@ impl QwenModel {
             )));
         }
 
-        Tensor::from_vec(padded_tokens, shape, &self.config.device)
+        let tensor = Tensor::from_vec(padded_tokens, shape, &self.config.device)?;
+        self.config
+            .model_config
+            .to_declared_dtype(tensor, "embeddings", "input_ids")
     }
     /// Create single-token embeddings input tensor for infer mode
     /// This produces [1, 1] shape regardless of the model's batch configuration
@@ -55,7 +62,10 @@ impl QwenModel {
             "🔍 SINGLE TOKEN: Creating [1, 1] shape input tensor for token {}",
             token
         );
-        Tensor::from_vec(vec![token], (1, 1), &self.config.device)
+        let tensor = Tensor::from_vec(vec![token], (1, 1), &self.config.device)?;
+        self.config
+            .model_config
+            .to_declared_dtype(tensor, "embeddings", "input_ids")
     }
     /// Create position tensor with dynamic shape validation
     pub fn create_position_tensor(&self, positions: Vec<i64>) -> Result<Tensor, CandleError> {
@@ -148,7 +158,7 @@ impl QwenModel {
         context_len: usize,
     ) -> Result<Tensor, CandleError> {
         // Create causal mask data
-        let mut mask_data = vec![f32::NEG_INFINITY; seq_len * context_len];
+        let mut mask_data = vec![self.config.family.mask_value; seq_len * context_len];
         for i in 0..seq_len {
             for j in 0..=i.min(context_len - 1) {
                 mask_data[i * context_len + j] = 0.0;
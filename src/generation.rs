@@ -0,0 +1,868 @@
+//! Generation settings shared by the text generation APIs
+//!
+//! [`GenerationConfig`] bundles the sampling parameters and stop conditions used by
+//! `DecoderModel` generation. Stop token ids can be derived from a model directory
+//! (`generation_config.json`, `config.json`, `tokenizer_config.json`) or from the
+//! tokenizer's special tokens, so tokenizers other than Qwen's stop correctly.
+//! [`StopSequenceMatcher`] applies stop strings to the decoded text stream.
+//! [`OverflowPolicy`] decides what happens to sequences longer than the context window.
+//! [`ThinkingParser`] separates `<think>...</think>` reasoning from the final answer.
+
+use crate::utils::sampling::{Penalties, Sampler};
+use candle_core::Error as CandleError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use tokenizers::Tokenizer;
+use tracing::debug;
+
+/// Qwen `<|im_end|>` token id, used when no other EOS information is available.
+pub const DEFAULT_EOS_TOKEN_ID: i64 = 151_645;
+
+/// Opening tag of a reasoning block (Qwen3 and other reasoning models)
+pub const THINK_START: &str = "<think>";
+/// Closing tag of a reasoning block
+pub const THINK_END: &str = "</think>";
+
+/// Special tokens probed in the tokenizer vocabulary when no config declares an EOS id.
+const KNOWN_EOS_TOKENS: &[&str] = &[
+    "<|im_end|>",
+    "<|endoftext|>",
+    "<|eot_id|>",
+    "<|end_of_text|>",
+    "</s>",
+    "<eos>",
+];
+
+/// Sampling parameters and stop conditions for text generation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenerationConfig {
+    /// Maximum number of tokens to generate
+    pub max_new_tokens: usize,
+    /// Sampling temperature (<= 0 means greedy)
+    pub temperature: f32,
+    /// Restrict sampling to the k most likely tokens
+    pub top_k: Option<usize>,
+    /// Nucleus sampling: keep the smallest set of tokens with cumulative probability >= top_p
+    pub top_p: Option<f32>,
+    /// Drop tokens with probability below min_p times the most likely token's
+    pub min_p: Option<f32>,
+    /// Locally typical sampling mass
+    pub typical_p: Option<f32>,
+    /// Repetition penalty over recent tokens (1.0 disables it)
+    pub repetition_penalty: f32,
+    /// Penalty subtracted from every token already present in the window
+    pub presence_penalty: f32,
+    /// Penalty subtracted per occurrence of a token in the window
+    pub frequency_penalty: f32,
+    /// Number of most recent prompt + generated tokens the penalties look at (None = all)
+    pub penalty_window: Option<usize>,
+    /// Number of top alternatives (with logprobs) to record at each step (None = don't record)
+    pub top_logprobs: Option<usize>,
+    /// Seed for reproducible sampling (None draws a fresh seed per generation)
+    pub seed: Option<u64>,
+    /// Token ids that end generation (the stop token itself is not part of the text)
+    pub stop_token_ids: Vec<i64>,
+    /// Strings that end generation when they appear in the decoded output (excluded from the text)
+    pub stop_strings: Vec<String>,
+    /// What to do with prompts (and generations) that outgrow the context window
+    pub overflow_policy: OverflowPolicy,
+    /// Split `<think>...</think>` reasoning from the answer text
+    pub parse_thinking: bool,
+    /// Tokens allowed inside a reasoning block before `</think>` is forced (None = unlimited)
+    pub max_thinking_tokens: Option<usize>,
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        Self {
+            max_new_tokens: 128,
+            temperature: 0.7,
+            top_k: Some(50),
+            top_p: None,
+            min_p: None,
+            typical_p: None,
+            repetition_penalty: 1.0,
+            presence_penalty: 0.0,
+            frequency_penalty: 0.0,
+            penalty_window: None,
+            top_logprobs: None,
+            seed: None,
+            stop_token_ids: vec![DEFAULT_EOS_TOKEN_ID],
+            stop_strings: Vec::new(),
+            overflow_policy: OverflowPolicy::Error,
+            parse_thinking: false,
+            max_thinking_tokens: None,
+        }
+    }
+}
+
+impl GenerationConfig {
+    /// Default settings with stop tokens taken from the tokenizer's special tokens.
+    pub fn for_tokenizer(tokenizer: &Tokenizer) -> Self {
+        let stop_token_ids = tokenizer_eos_ids(tokenizer);
+        Self {
+            stop_token_ids: if stop_token_ids.is_empty() {
+                vec![DEFAULT_EOS_TOKEN_ID]
+            } else {
+                stop_token_ids
+            },
+            ..Self::default()
+        }
+    }
+
+    /// Default settings with stop tokens read from a model directory.
+    ///
+    /// EOS ids are collected from `generation_config.json` and `config.json`
+    /// (`eos_token_id`, a single id or a list) and from the `eos_token` named in
+    /// `tokenizer_config.json`. Falls back to [`GenerationConfig::for_tokenizer`].
+    pub fn from_model_dir<P: AsRef<Path>>(model_dir: P, tokenizer: &Tokenizer) -> Self {
+        let model_dir = model_dir.as_ref();
+        let stop_token_ids = model_eos_token_ids(model_dir, Some(tokenizer));
+        if stop_token_ids.is_empty() {
+            return Self::for_tokenizer(tokenizer);
+        }
+        debug!(
+            "Stop token ids from {}: {:?}",
+            model_dir.display(),
+            stop_token_ids
+        );
+        Self {
+            stop_token_ids,
+            ..Self::default()
+        }
+    }
+
+    /// Set the maximum number of generated tokens
+    pub fn with_max_new_tokens(mut self, max_new_tokens: usize) -> Self {
+        self.max_new_tokens = max_new_tokens;
+        self
+    }
+
+    /// Set the sampling temperature
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    /// Set top-k sampling (None disables it)
+    pub fn with_top_k(mut self, top_k: Option<usize>) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Set nucleus (top-p) sampling (None disables it)
+    pub fn with_top_p(mut self, top_p: Option<f32>) -> Self {
+        self.top_p = top_p;
+        self
+    }
+
+    /// Set min-p sampling (None disables it)
+    pub fn with_min_p(mut self, min_p: Option<f32>) -> Self {
+        self.min_p = min_p;
+        self
+    }
+
+    /// Set locally typical sampling (None disables it)
+    pub fn with_typical_p(mut self, typical_p: Option<f32>) -> Self {
+        self.typical_p = typical_p;
+        self
+    }
+
+    /// Set the repetition penalty (1.0 disables it)
+    pub fn with_repetition_penalty(mut self, penalty: f32) -> Self {
+        self.repetition_penalty = penalty;
+        self
+    }
+
+    /// Set the presence penalty
+    pub fn with_presence_penalty(mut self, penalty: f32) -> Self {
+        self.presence_penalty = penalty;
+        self
+    }
+
+    /// Set the frequency penalty
+    pub fn with_frequency_penalty(mut self, penalty: f32) -> Self {
+        self.frequency_penalty = penalty;
+        self
+    }
+
+    /// Set how many recent tokens the penalties consider (None = all)
+    pub fn with_penalty_window(mut self, window: Option<usize>) -> Self {
+        self.penalty_window = window;
+        self
+    }
+
+    /// Record the `n` most likely alternatives at each step (None disables it)
+    pub fn with_top_logprobs(mut self, n: Option<usize>) -> Self {
+        self.top_logprobs = n;
+        self
+    }
+
+    /// Set the sampling seed; the same prompt, seed and model give the same tokens
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Add a stop token id
+    pub fn with_stop_token(mut self, token_id: i64) -> Self {
+        push_unique(&mut self.stop_token_ids, token_id);
+        self
+    }
+
+    /// Add a stop string
+    pub fn with_stop_string(mut self, stop: impl Into<String>) -> Self {
+        self.stop_strings.push(stop.into());
+        self
+    }
+
+    /// Set the context overflow policy
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Report reasoning separately from the answer text
+    pub fn with_parse_thinking(mut self, parse_thinking: bool) -> Self {
+        self.parse_thinking = parse_thinking;
+        self
+    }
+
+    /// Limit the length of reasoning blocks (None = unlimited)
+    pub fn with_max_thinking_tokens(mut self, max_thinking_tokens: Option<usize>) -> Self {
+        self.max_thinking_tokens = max_thinking_tokens;
+        self
+    }
+
+    /// Sampling pipeline described by this config.
+    pub fn sampler(&self) -> Sampler {
+        Sampler::new(self.temperature)
+            .with_top_k(self.top_k)
+            .with_top_p(self.top_p)
+            .with_min_p(self.min_p)
+            .with_typical_p(self.typical_p)
+            .with_seed(self.seed)
+    }
+
+    /// Logit penalties described by this config.
+    pub fn penalties(&self) -> Penalties {
+        Penalties::new()
+            .with_repetition_penalty(self.repetition_penalty)
+            .with_presence_penalty(self.presence_penalty)
+            .with_frequency_penalty(self.frequency_penalty)
+            .with_window(self.penalty_window)
+    }
+
+    /// Whether `token_id` ends generation.
+    pub fn is_stop_token(&self, token_id: i64) -> bool {
+        self.stop_token_ids.contains(&token_id)
+    }
+}
+
+/// How token sequences longer than the model's context window are handled.
+///
+/// The policy is applied when a prompt is tokenized, before it is prefilled, and when
+/// generation fills the context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum OverflowPolicy {
+    /// Reject prompts that do not fit; generation stops with [`FinishReason::Length`]
+    /// once the context is full
+    #[default]
+    Error,
+    /// Drop the oldest tokens
+    TruncateLeft,
+    /// Keep the first `keep_prefix` tokens (e.g. the system prompt) and drop the oldest
+    /// tokens after them
+    TruncateMiddle { keep_prefix: usize },
+    /// Keep only the most recent `window` tokens
+    SlidingWindow { window: usize },
+}
+
+impl OverflowPolicy {
+    /// Fit `tokens` into at most `limit` tokens.
+    ///
+    /// Sequences that already fit are returned unchanged; a sliding window cuts longer
+    /// ones back to its window size.
+    pub fn fit(&self, tokens: &[i64], limit: usize) -> Result<Vec<i64>, CandleError> {
+        if tokens.len() <= limit {
+            return Ok(tokens.to_vec());
+        }
+        let limit = match *self {
+            OverflowPolicy::SlidingWindow { window } => window.min(limit),
+            _ => limit,
+        };
+        if limit == 0 {
+            return Err(CandleError::Msg(format!(
+                "Cannot fit {} tokens into an empty context window",
+                tokens.len()
+            )));
+        }
+        let keep_prefix = match *self {
+            OverflowPolicy::Error => {
+                return Err(CandleError::Msg(format!(
+                    "Input too long: {} tokens exceeds maximum context length of {limit} tokens \
+                     supported by the model. Consider shortening your input or setting an \
+                     OverflowPolicy that truncates.",
+                    tokens.len()
+                )))
+            }
+            OverflowPolicy::TruncateLeft | OverflowPolicy::SlidingWindow { .. } => 0,
+            // Always keep at least the most recent token
+            OverflowPolicy::TruncateMiddle { keep_prefix } => keep_prefix.min(limit - 1),
+        };
+        debug!(
+            "Context overflow ({:?}): keeping {} of {} tokens",
+            self,
+            limit,
+            tokens.len()
+        );
+        let mut fitted = tokens[..keep_prefix].to_vec();
+        fitted.extend_from_slice(&tokens[tokens.len() - (limit - keep_prefix)..]);
+        Ok(fitted)
+    }
+
+    /// Length the sequence is cut back to (and re-prefilled at) when generation fills a
+    /// context of `context_length` tokens; `None` means generation stops instead.
+    ///
+    /// Cutting back well below the limit means the re-prefill happens once per window
+    /// rather than once per generated token.
+    pub fn generation_window(&self, context_length: usize) -> Option<usize> {
+        match *self {
+            OverflowPolicy::Error => None,
+            OverflowPolicy::SlidingWindow { window } => {
+                Some(window.min(context_length.saturating_sub(1)).max(1))
+            }
+            OverflowPolicy::TruncateLeft | OverflowPolicy::TruncateMiddle { .. } => {
+                Some((context_length / 2).max(1))
+            }
+        }
+    }
+}
+
+/// Why generation ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// A stop token (EOS) was generated
+    Eos,
+    /// A stop string appeared in the output
+    StopString,
+    /// `max_new_tokens` was reached, or the context window filled up
+    Length,
+}
+
+/// A candidate token and its log-probability.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TokenLogprob {
+    pub token_id: i64,
+    pub logprob: f32,
+}
+
+/// Result of a complete generation run.
+///
+/// Log-probabilities come from the model's distribution (softmax of the logits after
+/// penalties, before temperature and sampling filters).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenerationOutput {
+    /// Generated token ids, including a final stop token
+    pub tokens: Vec<i64>,
+    /// Generated text without stop tokens or stop strings (only the answer when
+    /// `GenerationConfig::parse_thinking` is set)
+    pub text: String,
+    /// Reasoning text from the `<think>` block, when `GenerationConfig::parse_thinking` is set
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    /// Log-probability of each generated token
+    pub logprobs: Vec<f32>,
+    /// Top alternatives at each step, when `GenerationConfig::top_logprobs` is set
+    pub top_logprobs: Option<Vec<Vec<TokenLogprob>>>,
+    pub finish_reason: FinishReason,
+}
+
+impl GenerationOutput {
+    /// Sum of the generated tokens' log-probabilities.
+    pub fn total_logprob(&self) -> f32 {
+        self.logprobs.iter().sum()
+    }
+}
+
+fn tokenizer_eos_ids(tokenizer: &Tokenizer) -> Vec<i64> {
+    let mut ids = Vec::new();
+    for token in KNOWN_EOS_TOKENS {
+        if let Some(id) = tokenizer.token_to_id(token) {
+            push_unique(&mut ids, id as i64);
+        }
+    }
+    ids
+}
+
+/// EOS ids declared by the Hugging Face files in `model_dir`
+///
+/// `eos_token_id` of `generation_config.json` and `config.json` (a single id or a list),
+/// then the `eos_token` named in `tokenizer_config.json`, resolved through `tokenizer`.
+pub(crate) fn model_eos_token_ids(model_dir: &Path, tokenizer: Option<&Tokenizer>) -> Vec<i64> {
+    let mut ids = Vec::new();
+    for file in ["generation_config.json", "config.json"] {
+        if let Some(json) = read_json(&model_dir.join(file)) {
+            collect_ids(&json["eos_token_id"], &mut ids);
+        }
+    }
+    if let Some(json) = read_json(&model_dir.join("tokenizer_config.json")) {
+        let eos_token = match &json["eos_token"] {
+            Value::String(token) => Some(token.as_str()),
+            Value::Object(token) => token.get("content").and_then(Value::as_str),
+            _ => None,
+        };
+        let id = eos_token
+            .zip(tokenizer)
+            .and_then(|(token, t)| t.token_to_id(token));
+        if let Some(id) = id {
+            push_unique(&mut ids, id as i64);
+        }
+    }
+    ids
+}
+
+pub(crate) fn read_json(path: &Path) -> Option<Value> {
+    let text = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+pub(crate) fn collect_ids(value: &Value, ids: &mut Vec<i64>) {
+    match value {
+        Value::Number(n) => {
+            if let Some(id) = n.as_i64() {
+                push_unique(ids, id);
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_ids(item, ids)),
+        _ => {}
+    }
+}
+
+pub(crate) fn push_unique(ids: &mut Vec<i64>, id: i64) {
+    if !ids.contains(&id) {
+        ids.push(id);
+    }
+}
+
+/// Result of feeding decoded text to a [`StopSequenceMatcher`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StopMatch {
+    /// Text that is safe to emit
+    pub text: String,
+    /// Whether a stop string was found (generation should end)
+    pub stopped: bool,
+}
+
+/// Matches stop strings on a stream of decoded text deltas.
+///
+/// Text that could be the beginning of a stop string is held back until it either
+/// completes the stop string (and is dropped) or diverges (and is released), so stop
+/// strings spanning several tokens are detected and never leak into the output.
+#[derive(Debug, Clone, Default)]
+pub struct StopSequenceMatcher {
+    stops: Vec<String>,
+    pending: String,
+}
+
+impl StopSequenceMatcher {
+    pub fn new(stops: &[String]) -> Self {
+        Self {
+            stops: stops.iter().filter(|s| !s.is_empty()).cloned().collect(),
+            pending: String::new(),
+        }
+    }
+
+    /// Feed a decoded text delta.
+    pub fn push(&mut self, delta: &str) -> StopMatch {
+        self.pending.push_str(delta);
+
+        let earliest = self
+            .stops
+            .iter()
+            .filter_map(|stop| self.pending.find(stop.as_str()))
+            .min();
+        if let Some(index) = earliest {
+            let text = self.pending[..index].to_string();
+            self.pending.clear();
+            return StopMatch {
+                text,
+                stopped: true,
+            };
+        }
+
+        let held = self.partial_stop_suffix_len();
+        let emit_len = self.pending.len() - held;
+        let text = self.pending[..emit_len].to_string();
+        self.pending.drain(..emit_len);
+        StopMatch {
+            text,
+            stopped: false,
+        }
+    }
+
+    /// Release any held-back text (generation ended without a stop string).
+    pub fn flush(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Length of the longest suffix of the pending text that is a proper prefix of a stop string.
+    fn partial_stop_suffix_len(&self) -> usize {
+        self.pending
+            .char_indices()
+            .map(|(start, _)| &self.pending[start..])
+            .find(|suffix| self.stops.iter().any(|stop| stop.starts_with(suffix)))
+            .map_or(0, str::len)
+    }
+}
+
+/// Text split by a [`ThinkingParser`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ThinkingDelta {
+    /// Text inside the reasoning block
+    pub reasoning: String,
+    /// Text after the reasoning block (or all text when there is none)
+    pub answer: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ThinkingState {
+    /// Waiting to see whether the output opens with `<think>`
+    Start,
+    Thinking,
+    Answer,
+}
+
+/// Splits a stream of decoded text deltas into reasoning and answer text.
+///
+/// A reasoning block is only recognised at the start of the output. Like
+/// [`StopSequenceMatcher`], text that could be the beginning of a tag is held back
+/// until it is resolved. Newlines around the reasoning are dropped, as the Qwen chat
+/// templates do.
+#[derive(Debug, Clone)]
+pub struct ThinkingParser {
+    state: ThinkingState,
+    pending: String,
+    strip_newlines: bool,
+}
+
+impl Default for ThinkingParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ThinkingParser {
+    /// Parser for output that may open with `<think>`.
+    pub fn new() -> Self {
+        Self {
+            state: ThinkingState::Start,
+            pending: String::new(),
+            strip_newlines: false,
+        }
+    }
+
+    /// Parser for output of a prompt that already ends with `<think>`.
+    pub fn in_thinking() -> Self {
+        Self {
+            state: ThinkingState::Thinking,
+            pending: String::new(),
+            strip_newlines: true,
+        }
+    }
+
+    /// Whether the text seen so far is inside a reasoning block.
+    pub fn is_thinking(&self) -> bool {
+        self.state == ThinkingState::Thinking
+    }
+
+    /// Feed a decoded text delta.
+    pub fn push(&mut self, delta: &str) -> ThinkingDelta {
+        self.pending.push_str(delta);
+        let mut out = ThinkingDelta::default();
+        loop {
+            match self.state {
+                ThinkingState::Start => {
+                    let trimmed = self.pending.trim_start();
+                    if trimmed.starts_with(THINK_START) {
+                        let end = self.pending.len() - trimmed.len() + THINK_START.len();
+                        self.pending.drain(..end);
+                        self.state = ThinkingState::Thinking;
+                        self.strip_newlines = true;
+                    } else if THINK_START.starts_with(trimmed) {
+                        // Empty or a partial tag: wait for more text
+                        return out;
+                    } else {
+                        self.state = ThinkingState::Answer;
+                    }
+                }
+                ThinkingState::Thinking => {
+                    self.strip_leading_newlines();
+                    if let Some(index) = self.pending.find(THINK_END) {
+                        out.reasoning
+                            .push_str(self.pending[..index].trim_end_matches('\n'));
+                        self.pending.drain(..index + THINK_END.len());
+                        self.state = ThinkingState::Answer;
+                        self.strip_newlines = true;
+                        continue;
+                    }
+                    // Hold back a partial closing tag and trailing newlines (dropped if
+                    // the tag follows)
+                    let held = partial_suffix_len(&self.pending, THINK_END);
+                    let emit_len = self.pending[..self.pending.len() - held]
+                        .trim_end_matches('\n')
+                        .len();
+                    out.reasoning.push_str(&self.pending[..emit_len]);
+                    self.pending.drain(..emit_len);
+                    return out;
+                }
+                ThinkingState::Answer => {
+                    self.strip_leading_newlines();
+                    out.answer.push_str(&self.pending);
+                    self.pending.clear();
+                    return out;
+                }
+            }
+        }
+    }
+
+    /// Release any held-back text (generation ended).
+    ///
+    /// An unterminated reasoning block is reported as reasoning.
+    pub fn flush(&mut self) -> ThinkingDelta {
+        let text = std::mem::take(&mut self.pending);
+        match self.state {
+            ThinkingState::Thinking => ThinkingDelta {
+                reasoning: text.trim_end_matches('\n').to_string(),
+                answer: String::new(),
+            },
+            ThinkingState::Start | ThinkingState::Answer => ThinkingDelta {
+                reasoning: String::new(),
+                answer: text,
+            },
+        }
+    }
+
+    /// Drop newlines right after a tag; stays armed until other text arrives.
+    fn strip_leading_newlines(&mut self) {
+        if self.strip_newlines {
+            let stripped = self.pending.trim_start_matches('\n');
+            self.pending.drain(..self.pending.len() - stripped.len());
+            if !self.pending.is_empty() {
+                self.strip_newlines = false;
+            }
+        }
+    }
+}
+
+/// Split complete output text into its reasoning block (if any) and the answer.
+///
+/// Text with a closing `</think>` but no opening tag is treated as the output of a
+/// prompt that already opened the block.
+pub fn split_thinking(text: &str) -> (Option<String>, String) {
+    let opens = text.trim_start().starts_with(THINK_START);
+    if !opens && !text.contains(THINK_END) {
+        return (None, text.to_string());
+    }
+    let mut parser = if opens {
+        ThinkingParser::new()
+    } else {
+        ThinkingParser::in_thinking()
+    };
+    let mut parts = parser.push(text);
+    let rest = parser.flush();
+    parts.reasoning.push_str(&rest.reasoning);
+    parts.answer.push_str(&rest.answer);
+    (Some(parts.reasoning), parts.answer)
+}
+
+/// Length of the longest suffix of `text` that is a proper prefix of `tag`.
+fn partial_suffix_len(text: &str, tag: &str) -> usize {
+    text.char_indices()
+        .map(|(start, _)| &text[start..])
+        .find(|suffix| tag.starts_with(suffix))
+        .map_or(0, str::len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokenizers::models::wordlevel::WordLevel;
+
+    fn tokenizer(vocab: &[(&str, u32)]) -> Tokenizer {
+        let mut entries: std::collections::HashMap<String, u32> = vocab
+            .iter()
+            .map(|(token, id)| (token.to_string(), *id))
+            .collect();
+        entries.insert("<unk>".to_string(), 0);
+        Tokenizer::new(
+            WordLevel::builder()
+                .vocab(entries.into_iter().collect())
+                .unk_token("<unk>".to_string())
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_stop_string_spanning_deltas_is_detected_and_removed() {
+        let mut matcher = StopSequenceMatcher::new(&["\n\nUser:".to_string()]);
+        assert_eq!(matcher.push("Paris.").text, "Paris.");
+        let partial = matcher.push("\n");
+        assert_eq!(partial.text, "");
+        assert!(!partial.stopped);
+        assert_eq!(matcher.push("\nUs").text, "");
+        let done = matcher.push("er: next");
+        assert!(done.stopped);
+        assert_eq!(done.text, "");
+    }
+
+    #[test]
+    fn test_held_back_text_is_released_when_it_diverges() {
+        let mut matcher = StopSequenceMatcher::new(&["END".to_string()]);
+        assert_eq!(matcher.push("the E").text, "the ");
+        assert_eq!(matcher.push("N").text, "");
+        assert_eq!(matcher.push("D!").text, "");
+        let mut matcher = StopSequenceMatcher::new(&["END".to_string()]);
+        matcher.push("E");
+        assert_eq!(matcher.push("nd").text, "End");
+        matcher.push("E");
+        assert_eq!(matcher.flush(), "E");
+    }
+
+    #[test]
+    fn test_from_model_dir_reads_eos_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("generation_config.json"),
+            r#"{"eos_token_id": [2, 7], "temperature": 0.6}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("config.json"), r#"{"eos_token_id": 2}"#).unwrap();
+        std::fs::write(
+            dir.path().join("tokenizer_config.json"),
+            r#"{"eos_token": {"content": "</s>"}}"#,
+        )
+        .unwrap();
+
+        let config = GenerationConfig::from_model_dir(dir.path(), &tokenizer(&[("</s>", 9)]));
+        assert_eq!(config.stop_token_ids, vec![2, 7, 9]);
+        assert!(config.is_stop_token(9));
+    }
+
+    #[test]
+    fn test_eos_falls_back_to_tokenizer_special_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = GenerationConfig::from_model_dir(dir.path(), &tokenizer(&[("<|eot_id|>", 5)]));
+        assert_eq!(config.stop_token_ids, vec![5]);
+
+        let config = GenerationConfig::for_tokenizer(&tokenizer(&[]));
+        assert_eq!(config.stop_token_ids, vec![DEFAULT_EOS_TOKEN_ID]);
+    }
+
+    #[test]
+    fn test_sampler_carries_all_sampling_settings() {
+        let config = GenerationConfig::default()
+            .with_temperature(0.6)
+            .with_top_k(Some(20))
+            .with_top_p(Some(0.95))
+            .with_min_p(Some(0.05));
+        let sampler = config.sampler();
+        assert_eq!(sampler.temperature, 0.6);
+        assert_eq!(sampler.top_k, Some(20));
+        assert_eq!(sampler.top_p, Some(0.95));
+        assert_eq!(sampler.min_p, Some(0.05));
+        assert_eq!(sampler.typical_p, None);
+
+        let parsed: GenerationConfig = serde_json::from_str(r#"{"top_p": 0.8}"#).unwrap();
+        assert_eq!(parsed.top_p, Some(0.8));
+        assert_eq!(parsed.top_k, GenerationConfig::default().top_k);
+    }
+
+    #[test]
+    fn test_overflow_policies_fit_tokens() {
+        let tokens: Vec<i64> = (0..10).collect();
+        assert_eq!(OverflowPolicy::Error.fit(&tokens, 10).unwrap(), tokens);
+        let err = OverflowPolicy::Error.fit(&tokens, 8).unwrap_err();
+        assert!(err.to_string().contains("Input too long"), "{err}");
+
+        assert_eq!(
+            OverflowPolicy::TruncateLeft.fit(&tokens, 4).unwrap(),
+            vec![6, 7, 8, 9]
+        );
+        assert_eq!(
+            OverflowPolicy::TruncateMiddle { keep_prefix: 2 }
+                .fit(&tokens, 5)
+                .unwrap(),
+            vec![0, 1, 7, 8, 9]
+        );
+        assert_eq!(
+            OverflowPolicy::SlidingWindow { window: 3 }
+                .fit(&tokens, 8)
+                .unwrap(),
+            vec![7, 8, 9]
+        );
+        assert_eq!(
+            OverflowPolicy::SlidingWindow { window: 3 }
+                .fit(&tokens, 10)
+                .unwrap(),
+            tokens
+        );
+
+        assert_eq!(OverflowPolicy::Error.generation_window(16), None);
+        assert_eq!(OverflowPolicy::TruncateLeft.generation_window(16), Some(8));
+        assert_eq!(
+            OverflowPolicy::SlidingWindow { window: 32 }.generation_window(16),
+            Some(15)
+        );
+
+        let parsed: OverflowPolicy =
+            serde_json::from_str(r#"{"type": "truncate_middle", "keep_prefix": 12}"#).unwrap();
+        assert_eq!(parsed, OverflowPolicy::TruncateMiddle { keep_prefix: 12 });
+    }
+
+    #[test]
+    fn test_thinking_parser_splits_streamed_reasoning() {
+        let mut parser = ThinkingParser::new();
+        let mut reasoning = String::new();
+        let mut answer = String::new();
+        for delta in [
+            "<thi", "nk>\n", "Two plus", " two.", "\n</th", "ink>\n\n", "4",
+        ] {
+            let split = parser.push(delta);
+            reasoning.push_str(&split.reasoning);
+            answer.push_str(&split.answer);
+        }
+        assert!(!parser.is_thinking());
+        assert_eq!(parser.flush(), ThinkingDelta::default());
+        assert_eq!(reasoning, "Two plus two.");
+        assert_eq!(answer, "4");
+
+        // Output without a reasoning block passes through untouched
+        let mut parser = ThinkingParser::new();
+        assert_eq!(parser.push("<b>").answer, "<b>");
+        assert_eq!(split_thinking("Paris."), (None, "Paris.".to_string()));
+    }
+
+    #[test]
+    fn test_split_thinking_handles_open_and_unterminated_blocks() {
+        assert_eq!(
+            split_thinking("<think>\n\n</think>\n\nHi"),
+            (Some(String::new()), "Hi".to_string())
+        );
+        // The prompt opened the block
+        assert_eq!(
+            split_thinking("hmm\n</think>\n\nHi"),
+            (Some("hmm".to_string()), "Hi".to_string())
+        );
+        // Cut off while thinking
+        assert_eq!(
+            split_thinking("<think>\nstill going\n"),
+            (Some("still going".to_string()), String::new())
+        );
+    }
+}
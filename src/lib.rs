@@ -1,12 +1,18 @@
+pub mod backend;
 pub mod builder;
 pub mod cache;
+pub mod chat;
 pub mod config;
 pub mod conversion;
+pub mod decoder;
 pub mod download;
+pub mod generation;
 pub mod model;
 pub mod pipeline;
 pub mod qwen;
 pub mod state;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod unified_model_loader;
 pub mod utils;
 
@@ -27,12 +33,19 @@ pub mod model_downloader {
     pub use crate::download::unified::*;
 }
 
+pub use backend::InferenceBackend;
 pub use builder::CoreMLModelBuilder;
 pub use cache::CacheManager;
+pub use chat::{ChatMessage, ChatSession, ChatTemplate, ChatTemplateOptions};
 pub use config::{
-    ComponentConfig, Config, ConfigGenerator, ModelConfig, NamingConfig, ShapeConfig, TensorConfig,
+    ComponentConfig, Config, ConfigDiagnostic, ConfigGenerator, ConfigOverride, DimRange,
+    FlexibleShape, ModelConfig, ModelFamily, NamingConfig, ShapeConfig, TensorConfig,
+    TensorDataType,
 };
+pub use decoder::{DecoderConfig, DecoderFamily, DecoderModel};
+pub use generation::{FinishReason, GenerationConfig, GenerationOutput, OverflowPolicy};
 pub use model::CoreMLModel;
+pub use pipeline::CoreMLPipeline;
 pub use qwen::{ModelNamingConfig, QwenConfig, QwenModel};
 pub use state::CoreMLState;
 pub use unified_model_loader::{CachedModelInfo, UnifiedModelLoader};
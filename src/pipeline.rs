@@ -4,20 +4,24 @@
 //! CoreML components from a ModelConfig and supports prefill/infer/head
 //! execution with a shared CoreMLState.
 
-use crate::{CoreMLModel, CoreMLState, ModelConfig};
+use crate::backend::InferenceBackend;
+use crate::{CoreMLModel, ModelConfig};
 use anyhow::Result;
 
 /// A minimal execution contract for multi-component CoreML models.
-pub struct CoreMLPipeline {
-    pub embeddings: Option<CoreMLModel>,
-    pub ffn_prefill: Option<CoreMLModel>,
-    pub ffn_infer: Option<CoreMLModel>,
-    pub lm_head: Option<CoreMLModel>,
-    pub state: Option<CoreMLState>,
+///
+/// Components run through an [`InferenceBackend`], so the same pipeline can be
+/// driven by CoreML (the default) or by any other executor.
+pub struct CoreMLPipeline<B: InferenceBackend = CoreMLModel> {
+    pub embeddings: Option<B>,
+    pub ffn_prefill: Option<B>,
+    pub ffn_infer: Option<B>,
+    pub lm_head: Option<B>,
+    pub state: Option<B::State>,
     pub config: ModelConfig,
 }
 
-impl CoreMLPipeline {
+impl<B: InferenceBackend> CoreMLPipeline<B> {
     pub fn new(config: ModelConfig) -> Self {
         Self {
             embeddings: None,
@@ -29,13 +33,14 @@ impl CoreMLPipeline {
         }
     }
 
-    /// Provide a placeholder for future loading by paths if needed; currently, Qwen handles loading.
+    /// Provide already-loaded components; [`DecoderModel::from_pipeline`](crate::DecoderModel::from_pipeline)
+    /// turns a complete pipeline into a runnable model.
     pub fn with_loaded_components(
         mut self,
-        embeddings: Option<CoreMLModel>,
-        ffn_prefill: Option<CoreMLModel>,
-        ffn_infer: Option<CoreMLModel>,
-        lm_head: Option<CoreMLModel>,
+        embeddings: Option<B>,
+        ffn_prefill: Option<B>,
+        ffn_infer: Option<B>,
+        lm_head: Option<B>,
     ) -> Self {
         self.embeddings = embeddings;
         self.ffn_prefill = ffn_prefill;
@@ -44,7 +49,7 @@ impl CoreMLPipeline {
         self
     }
 
-    pub fn init_state(&mut self, model_for_state: &CoreMLModel) -> Result<()> {
+    pub fn init_state(&mut self, model_for_state: &B) -> Result<()> {
         if self.state.is_none() {
             self.state = Some(model_for_state.make_state()?);
         }
@@ -1,21 +1,25 @@
 //! Qwen model integration for candle-coreml
 //!
-//! This module provides a complete implementation of the Qwen multi-component architecture
-//! with proper tokenization, state management, and inference pipeline.
+//! Qwen is the [`DecoderFamily`](crate::decoder::DecoderFamily) the decoder runtime started
+//! from; [`QwenModel`] and [`QwenConfig`] are kept as names for it so existing code keeps
+//! compiling. New code can use [`DecoderModel`] directly.
 
-pub mod config;
-pub mod embeddings;
-pub mod inference;
-pub mod model;
-pub mod naming;
-pub mod tensors;
-pub mod utilities;
+pub use crate::decoder::{
+    config, embeddings, inference, model, naming, reference, streaming, tensors, utilities,
+};
+pub use crate::decoder::{
+    DecoderConfig, DecoderModel, FfnChunk, IncrementalDecoder, ModelNamingConfig, StreamToken,
+    TokenStream,
+};
 
-// Re-export main components from their respective modules
-pub use config::QwenConfig;
-pub use model::QwenModel;
-pub use naming::ModelNamingConfig;
+use crate::model::CoreMLModel;
+
+/// A [`DecoderModel`] loaded with Qwen conventions
+pub type QwenModel<B = CoreMLModel> = DecoderModel<B>;
+
+/// Configuration of a [`QwenModel`]
+pub type QwenConfig = DecoderConfig;
 
 // Re-export deprecated constants from config module for backward compatibility
 #[allow(deprecated)]
-pub use config::{QWEN_BATCH_SIZE, QWEN_CONTEXT_LENGTH, QWEN_HIDDEN_SIZE, QWEN_VOCAB_SIZE};
+pub use crate::decoder::{QWEN_BATCH_SIZE, QWEN_CONTEXT_LENGTH, QWEN_HIDDEN_SIZE, QWEN_VOCAB_SIZE};
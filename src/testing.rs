@@ -0,0 +1,565 @@
+//! Scripted mock backend and fixture loaders for pipeline regression tests
+//!
+//! Enabled with the `testing` cargo feature. [`MockComponent`] implements
+//! [`InferenceBackend`] by replaying recorded calls: every `forward_all` /
+//! `predict_with_state` consumes the next [`MockCall`], checks the received inputs
+//! against the recorded ones within a tolerance, and returns the recorded outputs.
+//!
+//! The fixture loaders read the tensors captured from the Python reference pipeline
+//! (`tests/fixtures/flex_pipeline/` and `tests/fixtures/typo_fixer_tensors.json`) so
+//! `DecoderModel` orchestration can be exercised end to end without CoreML.
+
+use crate::backend::InferenceBackend;
+use crate::config::basic::Config;
+use crate::config::model::{ComponentConfig, ModelConfig, TensorConfig};
+use candle_core::{DType, Device, Error as CandleError, Tensor};
+use serde_json::Value;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::Path;
+use tokenizers::models::wordlevel::WordLevel;
+use tokenizers::pre_tokenizers::whitespace::WhitespaceSplit;
+use tokenizers::Tokenizer;
+
+/// Default absolute tolerance used when comparing received inputs with recordings.
+pub const DEFAULT_TOLERANCE: f32 = 1e-3;
+
+/// One recorded component invocation: the inputs it should receive and the outputs it returns.
+#[derive(Debug, Clone, Default)]
+pub struct MockCall {
+    /// Expected inputs by position; `None` entries are not checked.
+    pub expected_inputs: Vec<Option<Tensor>>,
+    /// Named outputs returned for this call.
+    pub outputs: HashMap<String, Tensor>,
+}
+
+impl MockCall {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect `tensor` as the input at position `index`.
+    pub fn expect_input(mut self, index: usize, tensor: Tensor) -> Self {
+        if self.expected_inputs.len() <= index {
+            self.expected_inputs.resize(index + 1, None);
+        }
+        self.expected_inputs[index] = Some(tensor);
+        self
+    }
+
+    /// Return `tensor` under the output name `name`.
+    pub fn returning(mut self, name: &str, tensor: Tensor) -> Self {
+        self.outputs.insert(name.to_string(), tensor);
+        self
+    }
+}
+
+/// State handed out by [`MockComponent::make_state`]; counts stateful predictions.
+#[derive(Debug, Default)]
+pub struct MockState {
+    pub predictions: usize,
+}
+
+/// Component backend that replays a script of [`MockCall`]s in order.
+#[derive(Debug)]
+pub struct MockComponent {
+    config: Config,
+    script: Vec<MockCall>,
+    cursor: Cell<usize>,
+    tolerance: f32,
+}
+
+impl MockComponent {
+    /// Create a component with an empty script.
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            script: Vec::new(),
+            cursor: Cell::new(0),
+            tolerance: DEFAULT_TOLERANCE,
+        }
+    }
+
+    /// Convenience constructor that only sets the model type and primary output name.
+    pub fn named(model_type: &str, output_name: &str) -> Self {
+        Self::new(Config {
+            input_names: vec![],
+            output_name: output_name.to_string(),
+            max_sequence_length: 0,
+            vocab_size: 0,
+            model_type: model_type.to_string(),
+        })
+    }
+
+    /// Append a recorded call to the script.
+    pub fn then(mut self, call: MockCall) -> Self {
+        self.script.push(call);
+        self
+    }
+
+    /// Set the absolute tolerance used for input comparisons.
+    pub fn with_tolerance(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Number of recorded calls consumed so far.
+    pub fn calls_made(&self) -> usize {
+        self.cursor.get()
+    }
+
+    /// Number of recorded calls that have not been replayed yet.
+    pub fn remaining_calls(&self) -> usize {
+        self.script.len() - self.cursor.get()
+    }
+
+    fn replay(&self, inputs: &[&Tensor]) -> Result<HashMap<String, Tensor>, CandleError> {
+        let index = self.cursor.get();
+        let call = self.script.get(index).ok_or_else(|| {
+            CandleError::Msg(format!(
+                "MockComponent '{}': unexpected call #{} (only {} recorded)",
+                self.config.model_type,
+                index + 1,
+                self.script.len()
+            ))
+        })?;
+        self.cursor.set(index + 1);
+
+        for (i, expected) in call.expected_inputs.iter().enumerate() {
+            let Some(expected) = expected else {
+                continue;
+            };
+            let actual = inputs.get(i).ok_or_else(|| {
+                CandleError::Msg(format!(
+                    "MockComponent '{}' call #{}: missing input {i} (received {})",
+                    self.config.model_type,
+                    index + 1,
+                    inputs.len()
+                ))
+            })?;
+            self.check_input(index, i, actual, expected)?;
+        }
+
+        Ok(call.outputs.clone())
+    }
+
+    fn check_input(
+        &self,
+        call: usize,
+        input: usize,
+        actual: &Tensor,
+        expected: &Tensor,
+    ) -> Result<(), CandleError> {
+        if actual.dims() != expected.dims() {
+            return Err(CandleError::Msg(format!(
+                "MockComponent '{}' call #{}: input {input} has shape {:?}, recorded {:?}",
+                self.config.model_type,
+                call + 1,
+                actual.dims(),
+                expected.dims()
+            )));
+        }
+
+        let actual = actual
+            .to_dtype(DType::F32)?
+            .flatten_all()?
+            .to_vec1::<f32>()?;
+        let expected = expected
+            .to_dtype(DType::F32)?
+            .flatten_all()?
+            .to_vec1::<f32>()?;
+        for (offset, (a, e)) in actual.iter().zip(expected.iter()).enumerate() {
+            // Exact equality covers matching infinities in causal masks
+            if a == e || (a - e).abs() <= self.tolerance {
+                continue;
+            }
+            return Err(CandleError::Msg(format!(
+                "MockComponent '{}' call #{}: input {input} differs from recording at element {offset} ({a} vs {e}, tolerance {})",
+                self.config.model_type,
+                call + 1,
+                self.tolerance
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl InferenceBackend for MockComponent {
+    type State = MockState;
+
+    /// Mock components have no on-disk form; loading yields an empty script.
+    fn load(
+        _path: &Path,
+        config: &Config,
+        _function_name: Option<&str>,
+    ) -> Result<Self, CandleError> {
+        Ok(Self::new(config.clone()))
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn forward_all(&self, inputs: &[&Tensor]) -> Result<HashMap<String, Tensor>, CandleError> {
+        self.replay(inputs)
+    }
+
+    fn make_state(&self) -> Result<MockState, CandleError> {
+        Ok(MockState::default())
+    }
+
+    fn predict_with_state(
+        &self,
+        inputs: &[&Tensor],
+        state: &mut MockState,
+    ) -> Result<Tensor, CandleError> {
+        state.predictions += 1;
+        self.forward(inputs)
+    }
+}
+
+/// Build a whitespace-split word-level tokenizer from `(word, id)` pairs.
+///
+/// Lets tests feed recorded token ids through text-based entry points such as
+/// `DecoderModel::forward_text` without a real BPE vocabulary.
+pub fn word_level_tokenizer(vocab: &[(&str, u32)]) -> Result<Tokenizer, CandleError> {
+    let mut entries: HashMap<String, u32> = vocab
+        .iter()
+        .map(|(word, id)| (word.to_string(), *id))
+        .collect();
+    entries.entry("<unk>".to_string()).or_insert(0);
+    let model = WordLevel::builder()
+        .vocab(entries.into_iter().collect())
+        .unk_token("<unk>".to_string())
+        .build()
+        .map_err(|e| CandleError::Msg(format!("Failed to build word-level tokenizer: {e}")))?;
+    let mut tokenizer = Tokenizer::new(model);
+    tokenizer.with_pre_tokenizer(Some(WhitespaceSplit));
+    Ok(tokenizer)
+}
+
+/// Model configuration matching the recorded typo-fixer pipeline: 64-token full-sequence
+/// prefill, single-token infer, context 256 and a single `logits1` LM head output.
+pub fn typo_fixer_model_config(
+    batch_size: usize,
+    context_length: usize,
+    hidden_size: usize,
+    vocab_size: usize,
+) -> ModelConfig {
+    let tensor = |name: &str, shape: Vec<usize>, data_type: &str| {
+        (
+            name.to_string(),
+            TensorConfig {
+                name: name.to_string(),
+                shape,
+                data_type: data_type.into(),
+                flexible_shape: None,
+            },
+        )
+    };
+    let component = |inputs: Vec<(String, TensorConfig)>, outputs: Vec<(String, TensorConfig)>| {
+        ComponentConfig {
+            file_path: None,
+            inputs: inputs.into_iter().collect(),
+            outputs: outputs.into_iter().collect(),
+            functions: vec![],
+            input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
+        }
+    };
+
+    let mut config = ModelConfig::default_qwen();
+    config.model_info.model_id = Some("typo-fixer-mock".to_string());
+    config.shapes.batch_size = batch_size;
+    config.shapes.context_length = context_length;
+    config.shapes.hidden_size = hidden_size;
+    config.shapes.vocab_size = vocab_size;
+    config.components.insert(
+        "embeddings".to_string(),
+        component(
+            vec![tensor("input_ids", vec![1, batch_size], "INT32")],
+            vec![tensor(
+                "hidden_states",
+                vec![1, batch_size, hidden_size],
+                "FLOAT16",
+            )],
+        ),
+    );
+    config.components.insert(
+        "ffn_prefill".to_string(),
+        component(
+            vec![
+                tensor("hidden_states", vec![1, batch_size, hidden_size], "FLOAT16"),
+                tensor("position_ids", vec![batch_size], "INT32"),
+                tensor(
+                    "causal_mask",
+                    vec![1, 1, batch_size, context_length],
+                    "FLOAT16",
+                ),
+                tensor("current_pos", vec![1], "INT32"),
+            ],
+            vec![tensor(
+                "output_hidden_states",
+                vec![1, 1, hidden_size],
+                "FLOAT16",
+            )],
+        ),
+    );
+    config.components.insert(
+        "ffn_infer".to_string(),
+        component(
+            vec![
+                tensor("hidden_states", vec![1, 1, hidden_size], "FLOAT16"),
+                tensor("position_ids", vec![1], "INT32"),
+                tensor("causal_mask", vec![1, 1, 1, context_length], "FLOAT16"),
+                tensor("current_pos", vec![1], "INT32"),
+            ],
+            vec![tensor(
+                "output_hidden_states",
+                vec![1, 1, hidden_size],
+                "FLOAT16",
+            )],
+        ),
+    );
+    config.components.insert(
+        "lm_head".to_string(),
+        component(
+            vec![tensor("hidden_states", vec![1, 1, hidden_size], "FLOAT16")],
+            vec![tensor("logits1", vec![1, 1, vocab_size], "FLOAT32")],
+        ),
+    );
+    config
+}
+
+/// Tensors recorded by `tests/fixtures/flex_pipeline/regenerate_corrected_data.py`.
+#[derive(Debug, Clone)]
+pub struct FlexPipelineFixture {
+    pub prompt: String,
+    pub input_ids: Vec<i64>,
+    pub context_pos: usize,
+    pub context_length: usize,
+    /// Full `[1, 1, context, context]` causal mask.
+    pub causal_mask: Tensor,
+    pub current_token: i64,
+    pub infer_input_hidden_states: Tensor,
+    pub infer_output_hidden_states: Tensor,
+    pub final_logits: Tensor,
+    /// Top-5 token ids predicted from `final_logits`, best first.
+    pub top_predictions: Vec<i64>,
+}
+
+impl FlexPipelineFixture {
+    /// Load the `corrected_step_*.json` files from `dir`.
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, CandleError> {
+        let dir = dir.as_ref();
+        let tokens = read_fixture_json(&dir.join("corrected_step_1_tokens.json"))?;
+        let mask = read_fixture_json(&dir.join("corrected_step_2_causal_mask.json"))?;
+        let infer = read_fixture_json(&dir.join("corrected_step_5_infer_and_logits.json"))?;
+
+        let input_ids = json_i64_vec(&tokens["data"]["input_ids"])?;
+        let causal_mask = json_tensor(&mask["data"]["causal_mask"])?;
+        let current_token = json_i64_vec(&infer["data"]["current_token"])?;
+
+        Ok(Self {
+            prompt: tokens["metadata"]["prompt"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            input_ids,
+            context_pos: json_usize(&tokens["data"]["context_pos"])?,
+            context_length: json_usize(&mask["data"]["context_length"])?,
+            causal_mask,
+            current_token: *current_token
+                .first()
+                .ok_or_else(|| CandleError::Msg("Fixture current_token is empty".to_string()))?,
+            infer_input_hidden_states: json_tensor(&infer["data"]["infer_input_hidden_states"])?,
+            infer_output_hidden_states: json_tensor(&infer["data"]["infer_output_hidden_states"])?,
+            final_logits: json_tensor(&infer["data"]["final_logits"]["logits"])?,
+            top_predictions: json_i64_vec(&infer["data"]["top_predictions"]["indices"])?,
+        })
+    }
+}
+
+/// Tensors recorded in `tests/fixtures/typo_fixer_tensors.json`.
+#[derive(Debug, Clone)]
+pub struct TypoFixerFixture {
+    pub prompt: String,
+    pub tokens: Vec<i64>,
+    /// Embeddings for `tokens` padded to `batch_size`: `[1, batch_size, hidden_size]`.
+    pub embeddings: Tensor,
+    pub prefill_outputs: Vec<Tensor>,
+    pub final_logits: Tensor,
+    pub generated_token: i64,
+    pub batch_size: usize,
+    pub context_length: usize,
+    pub hidden_size: usize,
+    pub vocab_size: usize,
+}
+
+impl TypoFixerFixture {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CandleError> {
+        let json = read_fixture_json(path.as_ref())?;
+        let metadata = &json["metadata"];
+        let prefill_outputs = json["prefill_outputs"]
+            .as_array()
+            .ok_or_else(|| CandleError::Msg("Fixture prefill_outputs is not an array".to_string()))?
+            .iter()
+            .map(shaped_tensor)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            prompt: json["test_prompt"].as_str().unwrap_or_default().to_string(),
+            tokens: json_i64_vec(&json["tokens"])?,
+            embeddings: shaped_tensor(&json["embeddings"])?,
+            prefill_outputs,
+            final_logits: shaped_tensor(&json["final_logits"])?,
+            generated_token: json["generated_token"]
+                .as_i64()
+                .ok_or_else(|| CandleError::Msg("Fixture generated_token missing".to_string()))?,
+            batch_size: json_usize(&metadata["batch_size"])?,
+            context_length: json_usize(&metadata["context_length"])?,
+            hidden_size: json_usize(&metadata["hidden_size"])?,
+            vocab_size: json_usize(&metadata["vocab_size"])?,
+        })
+    }
+}
+
+/// Bare number tokens Python's `json` module emits for non-finite floats
+const NON_FINITE_LITERALS: [(&str, f32); 3] = [
+    ("-Infinity", f32::NEG_INFINITY),
+    ("Infinity", f32::INFINITY),
+    ("NaN", f32::NAN),
+];
+
+/// Read a fixture file, accepting the non-standard `Infinity`/`-Infinity`/`NaN` numbers
+/// Python's `json` module emits.
+fn read_fixture_json(path: &Path) -> Result<Value, CandleError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| CandleError::Msg(format!("Failed to read fixture {}: {e}", path.display())))?;
+    serde_json::from_str(&quote_non_finite_numbers(&text))
+        .map_err(|e| CandleError::Msg(format!("Failed to parse fixture {}: {e}", path.display())))
+}
+
+/// Turn bare non-finite number tokens into strings that [`json_f32_vec`] maps back to
+/// their values; string literals are left untouched.
+fn quote_non_finite_numbers(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let (mut in_string, mut escaped) = (false, false);
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if !in_string {
+            if let Some((literal, _)) = NON_FINITE_LITERALS
+                .iter()
+                .find(|(literal, _)| rest.starts_with(literal))
+            {
+                out.push('"');
+                out.push_str(literal);
+                out.push('"');
+                rest = &rest[literal.len()..];
+                continue;
+            }
+            in_string = c == '"';
+        } else if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            in_string = false;
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+fn json_usize(value: &Value) -> Result<usize, CandleError> {
+    value.as_u64().map(|v| v as usize).ok_or_else(|| {
+        CandleError::Msg(format!("Expected unsigned integer in fixture, got {value}"))
+    })
+}
+
+fn json_i64_vec(value: &Value) -> Result<Vec<i64>, CandleError> {
+    let mut out = Vec::new();
+    flatten_json(value, &mut |v| {
+        v.as_i64()
+            .map(|n| out.push(n))
+            .ok_or_else(|| CandleError::Msg(format!("Expected integer in fixture, got {v}")))
+    })?;
+    Ok(out)
+}
+
+/// Convert a nested JSON array into an F32 tensor, inferring the shape from nesting.
+fn json_tensor(value: &Value) -> Result<Tensor, CandleError> {
+    let mut shape = Vec::new();
+    let mut cursor = value;
+    while let Value::Array(items) = cursor {
+        shape.push(items.len());
+        match items.first() {
+            Some(first) => cursor = first,
+            None => break,
+        }
+    }
+    let data = json_f32_vec(value)?;
+    Tensor::from_vec(data, shape, &Device::Cpu)
+}
+
+/// Convert a `{ "shape": [...], "data": [...] }` object into an F32 tensor.
+fn shaped_tensor(value: &Value) -> Result<Tensor, CandleError> {
+    let shape: Vec<usize> = value["shape"]
+        .as_array()
+        .ok_or_else(|| CandleError::Msg("Fixture tensor is missing 'shape'".to_string()))?
+        .iter()
+        .map(json_usize)
+        .collect::<Result<_, _>>()?;
+    let data = json_f32_vec(&value["data"])?;
+    Tensor::from_vec(data, shape, &Device::Cpu)
+}
+
+fn json_f32_vec(value: &Value) -> Result<Vec<f32>, CandleError> {
+    let mut out = Vec::new();
+    flatten_json(value, &mut |v| match v {
+        Value::String(token) => NON_FINITE_LITERALS
+            .iter()
+            .find(|(literal, _)| literal == token)
+            .map(|(_, number)| out.push(*number))
+            .ok_or_else(|| CandleError::Msg(format!("Expected number in fixture, got {v}"))),
+        _ => v
+            .as_f64()
+            .map(|n| out.push(n as f32))
+            .ok_or_else(|| CandleError::Msg(format!("Expected number in fixture, got {v}"))),
+    })?;
+    Ok(out)
+}
+
+fn flatten_json(
+    value: &Value,
+    visit: &mut dyn FnMut(&Value) -> Result<(), CandleError>,
+) -> Result<(), CandleError> {
+    match value {
+        Value::Array(items) => items.iter().try_for_each(|item| flatten_json(item, visit)),
+        other => visit(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixture_json_keeps_non_finite_values() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("fixture.json");
+        std::fs::write(
+            &path,
+            r#"{"note": "NaN and -Infinity \" Infinity", "data": [[0.5, -Infinity], [Infinity, NaN]]}"#,
+        )
+        .unwrap();
+
+        let json = read_fixture_json(&path).unwrap();
+        assert_eq!(json["note"], "NaN and -Infinity \" Infinity");
+        let data = json_f32_vec(&json["data"]).unwrap();
+        assert_eq!(data[..3], [0.5, f32::NEG_INFINITY, f32::INFINITY]);
+        assert!(data[3].is_nan());
+    }
+}
@@ -3,18 +3,22 @@
 //! This module provides a simplified API that replaces hardcoded paths with
 //! automatic HuggingFace downloading and config generation.
 
+use crate::config::generator::AUTO_MODEL_TYPE;
 use crate::config::model::ModelConfig;
+use crate::config::overrides::ConfigOverride;
 use crate::download::unified::ensure_model_downloaded;
-use crate::{CacheManager, ConfigGenerator, QwenConfig, QwenModel};
+use crate::{CacheManager, ConfigGenerator, DecoderConfig, DecoderModel};
 use anyhow::Result;
 use serde_json::Value;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tracing::{debug, info};
 
 /// Unified model loader that handles downloading, config generation, and model loading
 pub struct UnifiedModelLoader {
     cache_manager: CacheManager,
     pub config_generator: ConfigGenerator,
+    /// Overrides applied after the model directory and environment overrides
+    pub config_overrides: Vec<ConfigOverride>,
 }
 
 impl UnifiedModelLoader {
@@ -26,12 +30,39 @@ impl UnifiedModelLoader {
         Ok(Self {
             cache_manager,
             config_generator,
+            config_overrides: Vec::new(),
         })
     }
 
+    /// Create a loader that downloads and caches under `cache_base` instead of the user
+    /// cache directory
+    pub fn with_cache_base<P: Into<PathBuf>>(cache_base: P) -> Result<Self> {
+        let cache_base = cache_base.into();
+        let cache_manager = CacheManager::with_cache_base(&cache_base)?;
+        let config_generator = ConfigGenerator::builder()
+            .with_cache_root(cache_base)
+            .build()?;
+
+        Ok(Self {
+            cache_manager,
+            config_generator,
+            config_overrides: Vec::new(),
+        })
+    }
+
+    /// Add an override layer on top of the model directory and environment overrides
+    ///
+    /// Later layers win; see [`crate::config::overrides`] for the patch format.
+    pub fn with_config_override(mut self, config_override: ConfigOverride) -> Self {
+        self.config_overrides.push(config_override);
+        self
+    }
+
     /// Load a model by HuggingFace model ID with automatic downloading and config generation
     ///
-    /// This replaces the pattern of hardcoded paths in config files.
+    /// This replaces the pattern of hardcoded paths in config files. The cached or generated
+    /// config is layered with overrides before loading (see
+    /// [`UnifiedModelLoader::apply_config_overrides`]).
     ///
     /// # Example
     /// ```rust,no_run
@@ -41,7 +72,7 @@ impl UnifiedModelLoader {
     /// let _model = loader.load_model("mazhewitt/qwen-typo-fixer-coreml")?;
     /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
-    pub fn load_model(&self, model_id: &str) -> Result<QwenModel> {
+    pub fn load_model(&self, model_id: &str) -> Result<DecoderModel> {
         info!("🚀 Loading model: {}", model_id);
 
         // Step 1: Check if we have a cached config
@@ -68,9 +99,9 @@ impl UnifiedModelLoader {
                                 .generate_config_from_directory_enhanced(
                                     &clean_path,
                                     model_id,
-                                    "qwen",
+                                    AUTO_MODEL_TYPE,
                                 )?;
-                            return self.load_model_from_config(&config);
+                            return self.load_model_with_overrides(&config);
                         }
                     }
 
@@ -87,15 +118,15 @@ impl UnifiedModelLoader {
                                     .generate_config_from_directory_enhanced(
                                         &model_path,
                                         model_id,
-                                        "qwen",
+                                        AUTO_MODEL_TYPE,
                                     )?;
-                                return self.load_model_from_config(&config);
+                                return self.load_model_with_overrides(&config);
                             }
                         }
                     }
 
                     info!("✅ Cached config validated, using it");
-                    return self.load_model_from_config(&cached_config);
+                    return self.load_model_with_overrides(&cached_config);
                 } else {
                     // Log why we are regenerating
                     if let Err(e) = valid_basic {
@@ -118,9 +149,9 @@ impl UnifiedModelLoader {
                                 .generate_config_from_directory_enhanced(
                                     &model_path,
                                     model_id,
-                                    "qwen",
+                                    AUTO_MODEL_TYPE,
                                 )?;
-                            return self.load_model_from_config(&config);
+                            return self.load_model_with_overrides(&config);
                         } else {
                             info!("⚠️  Cached model path missing, will re-download");
                         }
@@ -144,13 +175,48 @@ impl UnifiedModelLoader {
         info!("🔍 Generating config from downloaded model");
         let config = self
             .config_generator
-            .generate_config_from_directory_enhanced(
-                &model_path,
-                model_id,
-                "qwen", // Auto-detect this in the future
-            )?;
+            .generate_config_from_directory_enhanced(&model_path, model_id, AUTO_MODEL_TYPE)?;
 
         // Step 4: Load the model using the generated config
+        self.load_model_with_overrides(&config)
+    }
+
+    /// Layer overrides onto a cached or generated config
+    ///
+    /// Layers are applied in order: `config-override.json` in the model directory, then
+    /// `CANDLE_COREML_CONFIG_OVERRIDE`, then [`UnifiedModelLoader::config_overrides`].
+    pub fn apply_config_overrides(&self, config: &ModelConfig) -> Result<ModelConfig> {
+        self.apply_overrides_with_env(config, ConfigOverride::from_env()?)
+    }
+
+    /// [`UnifiedModelLoader::apply_config_overrides`] with the environment layer given
+    fn apply_overrides_with_env(
+        &self,
+        config: &ModelConfig,
+        env_override: Option<ConfigOverride>,
+    ) -> Result<ModelConfig> {
+        let mut layers = Vec::new();
+        if let Some(model_dir) = &config.model_info.path {
+            layers.extend(ConfigOverride::from_model_dir(model_dir)?);
+        }
+        layers.extend(env_override);
+        layers.extend(self.config_overrides.iter().cloned());
+
+        if layers.is_empty() {
+            return Ok(config.clone());
+        }
+        for layer in &layers {
+            info!("🩹 Applying config override from {}", layer.source);
+        }
+        config.apply_overrides(&layers)
+    }
+
+    fn load_model_with_overrides(&self, config: &ModelConfig) -> Result<DecoderModel> {
+        let config = self.apply_config_overrides(config)?;
+        debug!(
+            "Final model config:\n{}",
+            serde_json::to_string_pretty(&config)?
+        );
         self.load_model_from_config(&config)
     }
 
@@ -217,11 +283,11 @@ impl UnifiedModelLoader {
     }
 
     /// Load a model from a pre-existing config (useful for advanced use cases)
-    pub fn load_model_from_config(&self, config: &ModelConfig) -> Result<QwenModel> {
+    pub fn load_model_from_config(&self, config: &ModelConfig) -> Result<DecoderModel> {
         info!("🔧 Loading model from config");
 
-        // Convert ModelConfig to QwenConfig
-        let qwen_config = QwenConfig::from_model_config(config.clone());
+        // Convert ModelConfig to DecoderConfig
+        let decoder_config = DecoderConfig::from_model_config(config.clone());
 
         // Extract the model directory from the config
         let model_dir = config
@@ -230,8 +296,8 @@ impl UnifiedModelLoader {
             .as_ref()
             .ok_or_else(|| anyhow::Error::msg("Model config missing path"))?;
 
-        // Load the QwenModel
-        let mut model = QwenModel::load_from_directory(model_dir, Some(qwen_config))?;
+        // Load the DecoderModel
+        let mut model = DecoderModel::load_from_directory(model_dir, Some(decoder_config))?;
         model.initialize_states()?;
 
         info!("✅ Model loaded successfully");
@@ -248,7 +314,7 @@ impl UnifiedModelLoader {
         let model_path = self.ensure_model_available(model_id)?;
 
         self.config_generator
-            .generate_config_from_directory_enhanced(&model_path, model_id, "qwen")
+            .generate_config_from_directory_enhanced(&model_path, model_id, AUTO_MODEL_TYPE)
     }
 
     /// List all cached models and their status
@@ -429,4 +495,42 @@ mod tests {
         assert_eq!(info.size_human(), "1.5 GB");
         assert!(info.is_complete());
     }
+
+    #[test]
+    fn test_config_override_layers() {
+        let model_dir = tempfile::TempDir::new().unwrap();
+        let mut config = ModelConfig::load_from_file("configs/anemll-qwen3-0.6b.json").unwrap();
+        config.model_info.path = Some(model_dir.path().display().to_string());
+
+        std::fs::write(
+            model_dir
+                .path()
+                .join(crate::config::overrides::MODEL_DIR_OVERRIDE_FILE),
+            r#"{"shapes": {"context_length": 1024, "batch_size": 8}}"#,
+        )
+        .unwrap();
+        let cache_base = tempfile::TempDir::new().unwrap();
+        let loader = UnifiedModelLoader::with_cache_base(cache_base.path())
+            .unwrap()
+            .with_config_override(ConfigOverride::new(
+                "test",
+                serde_json::json!({"shapes": {"batch_size": 16}}),
+            ));
+
+        // The environment layer is passed in so the developer's environment cannot leak in
+        let merged = loader.apply_overrides_with_env(&config, None).unwrap();
+        assert_eq!(merged.shapes.context_length, 1024);
+        assert_eq!(merged.shapes.batch_size, 16);
+
+        // It sits between the model directory and the loader's own layers
+        let env_override = ConfigOverride::from_env_value(
+            r#"{"shapes": {"context_length": 2048, "batch_size": 4}}"#,
+        )
+        .unwrap();
+        let merged = loader
+            .apply_overrides_with_env(&config, Some(env_override))
+            .unwrap();
+        assert_eq!(merged.shapes.context_length, 2048);
+        assert_eq!(merged.shapes.batch_size, 16);
+    }
 }
@@ -109,6 +109,9 @@ pub mod mask {
 /// Utilities for sampling from model outputs
 pub mod sampling {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use serde::{Deserialize, Serialize};
 
     /// Sample a token using temperature scaling
     ///
@@ -118,6 +121,8 @@ pub mod sampling {
     /// - temperature > 1.0: More random
     /// - temperature < 1.0: More deterministic
     ///
+    /// Uses the thread-local RNG; see [`sample_with_temperature_rng`] for reproducible sampling.
+    ///
     /// # Arguments
     /// * `logits` - Model output logits tensor
     /// * `temperature` - Temperature for scaling
@@ -125,6 +130,15 @@ pub mod sampling {
     /// # Returns
     /// Sampled token ID
     pub fn sample_with_temperature(logits: &Tensor, temperature: f32) -> Result<i64, CandleError> {
+        sample_with_temperature_rng(logits, temperature, &mut rand::rng())
+    }
+
+    /// Sample a token using temperature scaling, drawing from `rng`
+    pub fn sample_with_temperature_rng<R: Rng + ?Sized>(
+        logits: &Tensor,
+        temperature: f32,
+        rng: &mut R,
+    ) -> Result<i64, CandleError> {
         if temperature <= 0.0 {
             // Greedy sampling - return most likely token
             return greedy_sample(logits);
@@ -139,7 +153,7 @@ pub mod sampling {
         let probs_vec = probs.to_vec1::<f32>()?;
 
         // Sample from the distribution
-        let random_val: f32 = rand::random();
+        let random_val: f32 = rng.random();
 
         let mut cumulative = 0.0;
         for (i, &prob) in probs_vec.iter().enumerate() {
@@ -167,6 +181,16 @@ pub mod sampling {
 
     /// Top-k sampling - sample from the k most likely tokens
     pub fn sample_top_k(logits: &Tensor, k: usize, temperature: f32) -> Result<i64, CandleError> {
+        sample_top_k_rng(logits, k, temperature, &mut rand::rng())
+    }
+
+    /// Top-k sampling, drawing from `rng`
+    pub fn sample_top_k_rng<R: Rng + ?Sized>(
+        logits: &Tensor,
+        k: usize,
+        temperature: f32,
+        rng: &mut R,
+    ) -> Result<i64, CandleError> {
         let logits_vec = logits.to_vec1::<f32>()?;
 
         // Get indices sorted by logit value (descending)
@@ -196,7 +220,370 @@ pub mod sampling {
         }
 
         let filtered_tensor = Tensor::from_vec(filtered_logits, logits.shape(), logits.device())?;
-        sample_with_temperature(&filtered_tensor, temperature)
+        sample_with_temperature_rng(&filtered_tensor, temperature, rng)
+    }
+
+    /// Nucleus (top-p) sampling - sample from the smallest set of tokens whose
+    /// probability mass reaches `p`
+    pub fn sample_top_p(logits: &Tensor, p: f32, temperature: f32) -> Result<i64, CandleError> {
+        Sampler::new(temperature).with_top_p(Some(p)).sample(logits)
+    }
+
+    /// Min-p sampling - drop tokens less likely than `min_p` times the most likely token
+    pub fn sample_min_p(logits: &Tensor, min_p: f32, temperature: f32) -> Result<i64, CandleError> {
+        Sampler::new(temperature)
+            .with_min_p(Some(min_p))
+            .sample(logits)
+    }
+
+    /// Locally typical sampling - keep the tokens whose information content is closest
+    /// to the distribution's entropy, up to probability mass `typical_p`
+    pub fn sample_typical(
+        logits: &Tensor,
+        typical_p: f32,
+        temperature: f32,
+    ) -> Result<i64, CandleError> {
+        Sampler::new(temperature)
+            .with_typical_p(Some(typical_p))
+            .sample(logits)
+    }
+
+    /// Composable sampling pipeline
+    ///
+    /// Filters are applied in a fixed order: top-k on the raw logits, then
+    /// temperature scaling and softmax, then typical, top-p and min-p on the resulting
+    /// probabilities. Every filter keeps at least one token. A temperature `<= 0`
+    /// selects the most likely token that survives top-k (greedy).
+    ///
+    /// With a `seed`, [`Sampler::rng`] is deterministic, so the same seed and logits
+    /// always produce the same tokens.
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    pub struct Sampler {
+        /// Sampling temperature (<= 0 means greedy)
+        pub temperature: f32,
+        /// Keep only the k most likely tokens (None or 0 disables it)
+        pub top_k: Option<usize>,
+        /// Keep the smallest set of tokens with cumulative probability >= top_p
+        pub top_p: Option<f32>,
+        /// Drop tokens with probability below min_p * max probability
+        pub min_p: Option<f32>,
+        /// Locally typical sampling mass
+        pub typical_p: Option<f32>,
+        /// Seed for reproducible sampling (None uses OS entropy)
+        pub seed: Option<u64>,
+    }
+
+    impl Default for Sampler {
+        fn default() -> Self {
+            Self::new(1.0)
+        }
+    }
+
+    impl Sampler {
+        pub fn new(temperature: f32) -> Self {
+            Self {
+                temperature,
+                top_k: None,
+                top_p: None,
+                min_p: None,
+                typical_p: None,
+                seed: None,
+            }
+        }
+
+        /// Greedy decoding (always the most likely token)
+        pub fn greedy() -> Self {
+            Self::new(0.0)
+        }
+
+        /// Set top-k filtering
+        pub fn with_top_k(mut self, top_k: Option<usize>) -> Self {
+            self.top_k = top_k;
+            self
+        }
+
+        /// Set nucleus (top-p) filtering
+        pub fn with_top_p(mut self, top_p: Option<f32>) -> Self {
+            self.top_p = top_p;
+            self
+        }
+
+        /// Set min-p filtering
+        pub fn with_min_p(mut self, min_p: Option<f32>) -> Self {
+            self.min_p = min_p;
+            self
+        }
+
+        /// Set locally typical filtering
+        pub fn with_typical_p(mut self, typical_p: Option<f32>) -> Self {
+            self.typical_p = typical_p;
+            self
+        }
+
+        /// Set the RNG seed
+        pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+            self.seed = seed;
+            self
+        }
+
+        /// Fresh RNG for a generation run, seeded from `seed` when set
+        pub fn rng(&self) -> StdRng {
+            match self.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_os_rng(),
+            }
+        }
+
+        pub fn is_greedy(&self) -> bool {
+            self.temperature <= 0.0
+        }
+
+        /// Sample a token id from `[vocab]` logits
+        ///
+        /// Draws from a fresh [`Sampler::rng`]; use [`Sampler::sample_with_rng`] to carry
+        /// one RNG across the steps of a generation.
+        pub fn sample(&self, logits: &Tensor) -> Result<i64, CandleError> {
+            if self.seed.is_some() {
+                self.sample_with_rng(logits, &mut self.rng())
+            } else {
+                self.sample_with_rng(logits, &mut rand::rng())
+            }
+        }
+
+        /// Sample a token id from `[vocab]` logits, drawing from `rng`
+        pub fn sample_with_rng<R: Rng + ?Sized>(
+            &self,
+            logits: &Tensor,
+            rng: &mut R,
+        ) -> Result<i64, CandleError> {
+            Ok(self.sample_slice_with_rng(&logits.to_vec1::<f32>()?, rng))
+        }
+
+        /// Sample a token id from a row of logits, drawing from `rng`
+        pub fn sample_slice_with_rng<R: Rng + ?Sized>(&self, logits: &[f32], rng: &mut R) -> i64 {
+            let candidates = self.candidates(logits);
+            let total: f32 = candidates.iter().map(|&(_, p)| p).sum();
+            let mut remaining = rng.random::<f32>() * total;
+            for &(idx, prob) in &candidates {
+                remaining -= prob;
+                if remaining <= 0.0 {
+                    return idx as i64;
+                }
+            }
+            // Fallback to the least likely candidate if numerical issues
+            candidates.last().map_or(0, |&(idx, _)| idx as i64)
+        }
+
+        /// Tokens that survive the filters with their renormalized probabilities,
+        /// sorted from most to least likely
+        pub fn candidates(&self, logits: &[f32]) -> Vec<(usize, f32)> {
+            let mut indexed: Vec<(usize, f32)> = logits.iter().copied().enumerate().collect();
+            indexed.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            if let Some(k) = self.top_k.filter(|&k| k > 0) {
+                indexed.truncate(k);
+            }
+            if indexed.is_empty() {
+                return vec![(0, 1.0)];
+            }
+            if self.is_greedy() {
+                return vec![(indexed[0].0, 1.0)];
+            }
+
+            let max_logit = indexed[0].1;
+            let mut probs: Vec<(usize, f32)> = indexed
+                .into_iter()
+                .map(|(idx, logit)| (idx, ((logit - max_logit) / self.temperature).exp()))
+                .collect();
+            normalize(&mut probs);
+
+            if let Some(mass) = self.typical_p.filter(|&m| m < 1.0) {
+                probs = typical_filter(probs, mass);
+            }
+            if let Some(p) = self.top_p.filter(|&p| p < 1.0) {
+                let mut cumulative = 0.0;
+                let keep = probs
+                    .iter()
+                    .position(|&(_, prob)| {
+                        cumulative += prob;
+                        cumulative >= p
+                    })
+                    .map_or(probs.len(), |i| i + 1);
+                probs.truncate(keep.max(1));
+                normalize(&mut probs);
+            }
+            if let Some(min_p) = self.min_p.filter(|&m| m > 0.0) {
+                let threshold = probs[0].1 * min_p;
+                probs.retain(|&(_, prob)| prob >= threshold);
+                normalize(&mut probs);
+            }
+            probs
+        }
+    }
+
+    /// Log-softmax of a row of logits
+    pub fn log_softmax(logits: &[f32]) -> Vec<f32> {
+        let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let log_sum = logits.iter().map(|&l| (l - max).exp()).sum::<f32>().ln() + max;
+        logits.iter().map(|&l| l - log_sum).collect()
+    }
+
+    /// The `n` most likely `(token_id, logprob)` pairs, most likely first
+    pub fn top_n_logprobs(logprobs: &[f32], n: usize) -> Vec<(i64, f32)> {
+        let mut indexed: Vec<(i64, f32)> = logprobs
+            .iter()
+            .enumerate()
+            .map(|(idx, &lp)| (idx as i64, lp))
+            .collect();
+        indexed.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        indexed.truncate(n);
+        indexed
+    }
+
+    /// Repetition, presence and frequency penalties over a look-back window
+    ///
+    /// Applied to `[.., vocab]` logits (e.g. the concatenated output of
+    /// `DecoderModel::combine_lm_head_outputs`) before sampling. The history is the prompt
+    /// followed by the generated tokens; only its last `window` entries are considered.
+    /// - repetition: positive logits are divided by and negative logits multiplied by
+    ///   the penalty (1.0 disables it)
+    /// - presence: subtracted once from every token that occurs in the window
+    /// - frequency: subtracted once per occurrence in the window
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    pub struct Penalties {
+        pub repetition_penalty: f32,
+        pub presence_penalty: f32,
+        pub frequency_penalty: f32,
+        /// Number of most recent tokens considered (None = whole history)
+        pub window: Option<usize>,
+    }
+
+    impl Default for Penalties {
+        fn default() -> Self {
+            Self {
+                repetition_penalty: 1.0,
+                presence_penalty: 0.0,
+                frequency_penalty: 0.0,
+                window: None,
+            }
+        }
+    }
+
+    impl Penalties {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Set the repetition penalty (1.0 disables it)
+        pub fn with_repetition_penalty(mut self, penalty: f32) -> Self {
+            self.repetition_penalty = penalty;
+            self
+        }
+
+        /// Set the presence penalty
+        pub fn with_presence_penalty(mut self, penalty: f32) -> Self {
+            self.presence_penalty = penalty;
+            self
+        }
+
+        /// Set the frequency penalty
+        pub fn with_frequency_penalty(mut self, penalty: f32) -> Self {
+            self.frequency_penalty = penalty;
+            self
+        }
+
+        /// Set the look-back window
+        pub fn with_window(mut self, window: Option<usize>) -> Self {
+            self.window = window;
+            self
+        }
+
+        /// Whether applying these penalties leaves logits unchanged
+        pub fn is_noop(&self) -> bool {
+            self.repetition_penalty == 1.0
+                && self.presence_penalty == 0.0
+                && self.frequency_penalty == 0.0
+        }
+
+        /// Apply the penalties to `logits`, whose last dimension is the vocabulary
+        pub fn apply(&self, logits: &Tensor, history: &[i64]) -> Result<Tensor, CandleError> {
+            if self.is_noop() || history.is_empty() {
+                return Ok(logits.clone());
+            }
+            let vocab = logits.dim(logits.rank() - 1)?;
+            let mut values = logits
+                .to_dtype(candle_core::DType::F32)?
+                .flatten_all()?
+                .to_vec1::<f32>()?;
+            for row in values.chunks_mut(vocab) {
+                self.apply_to_slice(row, history);
+            }
+            Tensor::from_vec(values, logits.shape(), logits.device())?.to_dtype(logits.dtype())
+        }
+
+        /// Apply the penalties to a single row of logits in place
+        pub fn apply_to_slice(&self, logits: &mut [f32], history: &[i64]) {
+            let start = self
+                .window
+                .map_or(0, |window| history.len().saturating_sub(window));
+            let mut counts: HashMap<usize, usize> = HashMap::new();
+            for &token in &history[start..] {
+                if token >= 0 && (token as usize) < logits.len() {
+                    *counts.entry(token as usize).or_default() += 1;
+                }
+            }
+            for (token, count) in counts {
+                let logit = &mut logits[token];
+                if *logit > 0.0 {
+                    *logit /= self.repetition_penalty;
+                } else {
+                    *logit *= self.repetition_penalty;
+                }
+                *logit -= self.presence_penalty + self.frequency_penalty * count as f32;
+            }
+        }
+    }
+
+    /// Apply repetition/presence/frequency penalties to `logits`; see [`Penalties`]
+    pub fn apply_penalties(
+        logits: &Tensor,
+        history: &[i64],
+        penalties: &Penalties,
+    ) -> Result<Tensor, CandleError> {
+        penalties.apply(logits, history)
+    }
+
+    fn normalize(probs: &mut [(usize, f32)]) {
+        let total: f32 = probs.iter().map(|&(_, p)| p).sum();
+        if total > 0.0 {
+            probs.iter_mut().for_each(|(_, p)| *p /= total);
+        }
+    }
+
+    /// Keep tokens ordered by |-ln p - H| until `mass` is covered, then restore probability order
+    fn typical_filter(probs: Vec<(usize, f32)>, mass: f32) -> Vec<(usize, f32)> {
+        let entropy: f32 = probs
+            .iter()
+            .filter(|&&(_, p)| p > 0.0)
+            .map(|&(_, p)| -p * p.ln())
+            .sum();
+        let mut by_typicality: Vec<(f32, (usize, f32))> = probs
+            .into_iter()
+            .map(|(idx, p)| ((-p.ln() - entropy).abs(), (idx, p)))
+            .collect();
+        by_typicality.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut kept = Vec::new();
+        let mut cumulative = 0.0;
+        for (_, (idx, p)) in by_typicality {
+            kept.push((idx, p));
+            cumulative += p;
+            if cumulative >= mass {
+                break;
+            }
+        }
+        kept.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        normalize(&mut kept);
+        kept
     }
 }
 
@@ -287,3 +674,135 @@ pub mod multi_component {
         Tensor::cat(&chunk_refs, chunks[0].dims().len() - 1)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::sampling::Sampler;
+
+    const LOGITS: [f32; 4] = [0.0, 3.0, 2.0, 1.0];
+
+    fn ids(candidates: &[(usize, f32)]) -> Vec<usize> {
+        candidates.iter().map(|&(idx, _)| idx).collect()
+    }
+
+    #[test]
+    fn test_sampler_filters_compose() {
+        let sampler = Sampler::new(1.0);
+        assert_eq!(ids(&sampler.candidates(&LOGITS)), vec![1, 2, 3, 0]);
+        // softmax([3, 2, 1, 0]) = [0.644, 0.237, 0.087, 0.032]
+        assert_eq!(
+            ids(&sampler.with_top_p(Some(0.8)).candidates(&LOGITS)),
+            vec![1, 2]
+        );
+        assert_eq!(
+            ids(&sampler.with_min_p(Some(0.1)).candidates(&LOGITS)),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            ids(&sampler
+                .with_top_k(Some(3))
+                .with_top_p(Some(0.99))
+                .with_min_p(Some(0.2))
+                .candidates(&LOGITS)),
+            vec![1, 2]
+        );
+
+        let kept = sampler.with_top_p(Some(0.8)).candidates(&LOGITS);
+        let total: f32 = kept.iter().map(|&(_, p)| p).sum();
+        assert!((total - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_typical_sampling_can_drop_the_most_likely_token() {
+        // One mildly preferred token in a flat tail: the tail tokens are the "typical" ones
+        let mut logits = vec![0.0f32; 50];
+        logits[0] = 2.0;
+        let kept = Sampler::new(1.0)
+            .with_typical_p(Some(0.5))
+            .candidates(&logits);
+        assert!(!kept.iter().any(|&(idx, _)| idx == 0));
+        assert!(!kept.is_empty());
+    }
+
+    #[test]
+    fn test_greedy_and_single_candidate_are_deterministic() {
+        let logits = candle_core::Tensor::new(&LOGITS, &candle_core::Device::Cpu).unwrap();
+        assert_eq!(Sampler::greedy().sample(&logits).unwrap(), 1);
+        let sampler = Sampler::new(0.8).with_top_k(Some(5)).with_top_p(Some(0.1));
+        assert_eq!(sampler.sample(&logits).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_seeded_sampling_is_reproducible() {
+        let logits =
+            candle_core::Tensor::new(&[0.5f32, 0.4, 0.3, 0.2, 0.1], &candle_core::Device::Cpu)
+                .unwrap();
+        let sampler = Sampler::new(1.0).with_seed(Some(7));
+        let draw = |sampler: &Sampler| {
+            let mut rng = sampler.rng();
+            (0..32)
+                .map(|_| sampler.sample_with_rng(&logits, &mut rng).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let first = draw(&sampler);
+        assert_eq!(first, draw(&sampler));
+        assert_ne!(first, draw(&sampler.with_seed(Some(8))));
+        assert!(first.windows(2).any(|w| w[0] != w[1]));
+
+        let mut a = sampler.rng();
+        let mut b = sampler.rng();
+        assert_eq!(
+            super::sampling::sample_top_k_rng(&logits, 3, 1.0, &mut a).unwrap(),
+            super::sampling::sample_top_k_rng(&logits, 3, 1.0, &mut b).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_penalties_respect_window_and_counts() {
+        use super::sampling::Penalties;
+
+        let history = [1, 1, 2, 3, 3, 3];
+        let mut logits = [1.0f32, 2.0, -2.0, 4.0];
+        Penalties::new()
+            .with_repetition_penalty(2.0)
+            .with_window(Some(4))
+            .apply_to_slice(&mut logits, &history);
+        // Window is [2, 3, 3, 3]: token 1 is outside it
+        assert_eq!(logits, [1.0, 2.0, -4.0, 2.0]);
+
+        let mut logits = [0.0f32; 4];
+        Penalties::new()
+            .with_presence_penalty(0.5)
+            .with_frequency_penalty(0.25)
+            .apply_to_slice(&mut logits, &history);
+        assert_eq!(logits, [0.0, -1.0, -0.75, -1.25]);
+    }
+
+    #[test]
+    fn test_penalties_apply_to_combined_chunked_logits() {
+        use super::multi_component::combine_chunked_logits;
+        use super::sampling::{apply_penalties, greedy_sample, Penalties};
+        use candle_core::{Device, Tensor};
+        use std::collections::HashMap;
+
+        let chunk = |values: &[f32]| {
+            Tensor::new(values, &Device::Cpu)
+                .unwrap()
+                .reshape((1, 1, 2))
+        };
+        let outputs = HashMap::from([
+            ("logits1".to_string(), chunk(&[0.0, 1.0]).unwrap()),
+            ("logits2".to_string(), chunk(&[5.0, 4.5]).unwrap()),
+        ]);
+        let logits = combine_chunked_logits(outputs, 2).unwrap();
+        let penalized = apply_penalties(
+            &logits,
+            &[2, 7, -1],
+            &Penalties::new().with_repetition_penalty(1.5),
+        )
+        .unwrap();
+        assert_eq!(penalized.dims(), &[1, 1, 4]);
+        let flat = penalized.flatten_all().unwrap();
+        assert_eq!(greedy_sample(&flat).unwrap(), 3);
+    }
+}
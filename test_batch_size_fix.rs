@@ -1,5 +1,5 @@
 use candle_coreml::config_generator::shape_inference::ShapeInference;
-use candle_coreml::model_config::{ComponentConfig, TensorConfig};
+use candle_coreml::model_config::{ComponentConfig, TensorConfig, TensorDataType};
 use std::collections::HashMap;
 
 fn main() {
@@ -14,7 +14,8 @@ fn main() {
     ffn_prefill_inputs.insert("hidden_states".to_string(), TensorConfig {
         name: "hidden_states".to_string(),
         shape: vec![128, 12, 1024],  // batch=128, seq=12, hidden=1024
-        data_type: "FLOAT16".to_string(),
+        data_type: TensorDataType::Float16,
+        flexible_shape: None,
     });
     
     let ffn_prefill = ComponentConfig {
@@ -23,6 +24,8 @@ fn main() {
         outputs: HashMap::new(),
         functions: vec!["prefill".to_string()],
         input_order: None,
+        states: HashMap::new(),
+        chunks: Vec::new(),
     };
     components.insert("ffn_prefill".to_string(), ffn_prefill);
     
@@ -31,7 +34,8 @@ fn main() {
     ffn_infer_inputs.insert("hidden_states".to_string(), TensorConfig {
         name: "hidden_states".to_string(),
         shape: vec![1, 1, 1024],  // batch=1, seq=1, hidden=1024
-        data_type: "FLOAT16".to_string(),
+        data_type: TensorDataType::Float16,
+        flexible_shape: None,
     });
     
     let ffn_infer = ComponentConfig {
@@ -40,6 +44,8 @@ fn main() {
         outputs: HashMap::new(),
         functions: vec!["infer".to_string()],
         input_order: None,
+        states: HashMap::new(),
+        chunks: Vec::new(),
     };
     components.insert("ffn_infer".to_string(), ffn_infer);
     
@@ -48,7 +54,8 @@ fn main() {
     embeddings_inputs.insert("input_ids".to_string(), TensorConfig {
         name: "input_ids".to_string(),
         shape: vec![1, 12],  // batch=1, seq=12
-        data_type: "INT32".to_string(),
+        data_type: TensorDataType::Int32,
+        flexible_shape: None,
     });
     
     let embeddings = ComponentConfig {
@@ -57,6 +64,8 @@ fn main() {
         outputs: HashMap::new(),
         functions: vec![],
         input_order: None,
+        states: HashMap::new(),
+        chunks: Vec::new(),
     };
     components.insert("embeddings".to_string(), embeddings);
     
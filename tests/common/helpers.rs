@@ -1,6 +1,6 @@
 //! Common test helper functions and utilities
 
-use candle_coreml::model_config::{ComponentConfig, ModelConfig, ModelInfo, ShapeConfig, TensorConfig};
+use candle_coreml::model_config::{ComponentConfig, ModelConfig, ModelInfo, ShapeConfig, TensorConfig, TensorDataType};
 use std::collections::HashMap;
 use tempfile::TempDir;
 
@@ -17,14 +17,16 @@ pub fn create_test_model_config(
     inputs.insert("input_ids".to_string(), TensorConfig {
         name: "input_ids".to_string(),
         shape: vec![batch_size, context_length],
-        data_type: "INT32".to_string(),
+        data_type: TensorDataType::Int32,
+        flexible_shape: None,
     });
     
     let mut outputs = HashMap::new();
     outputs.insert("hidden_states".to_string(), TensorConfig {
         name: "hidden_states".to_string(),
         shape: vec![batch_size, context_length, hidden_size],
-        data_type: "FLOAT16".to_string(),
+        data_type: TensorDataType::Float16,
+        flexible_shape: None,
     });
     
     components.insert("embeddings".to_string(), ComponentConfig {
@@ -33,9 +35,12 @@ pub fn create_test_model_config(
         outputs,
         functions: vec![],
         input_order: None,
+        states: HashMap::new(),
+        chunks: Vec::new(),
     });
     
     ModelConfig {
+        schema_version: candle_coreml::model_config::CURRENT_SCHEMA_VERSION,
         model_info: ModelInfo { 
             model_id: "test-model".into(), 
             cache_dir: "/tmp".into() 
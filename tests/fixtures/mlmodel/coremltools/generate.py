@@ -0,0 +1,104 @@
+#!/usr/bin/env python3
+"""Write model.mlmodel specs produced by real coremltools.
+
+Unlike ../generate_fixtures.py, which encodes the wire format by hand, these
+files come from ct.convert and ct.utils.save_multifunction, so they check the
+decoder against what coremltools actually writes. Only the spec is kept; the
+weights of the MLProgram are not needed to read signatures.
+
+Requires torch and coremltools >= 8 (states and multifunction models).
+
+Usage: python3 tests/fixtures/mlmodel/coremltools/generate.py
+"""
+
+import shutil
+import tempfile
+from pathlib import Path
+
+import coremltools as ct
+import numpy as np
+import torch
+
+OUT_DIR = Path(__file__).parent
+HIDDEN = 8
+
+
+class Project(torch.nn.Module):
+    def __init__(self):
+        super().__init__()
+        self.proj = torch.nn.Linear(HIDDEN, HIDDEN)
+
+    def forward(self, hidden_states, residual):
+        return self.proj(hidden_states) * residual.mean()
+
+
+class CachedProject(torch.nn.Module):
+    def __init__(self):
+        super().__init__()
+        self.proj = torch.nn.Linear(HIDDEN, HIDDEN)
+        self.register_buffer("kv_cache", torch.zeros(1, HIDDEN, dtype=torch.float16))
+
+    def forward(self, hidden_states):
+        self.kv_cache += hidden_states.sum(dim=1)
+        return self.proj(hidden_states) * self.kv_cache
+
+
+def flexible_shapes():
+    # Neural network spec: one enumerated input and one range input
+    example = (torch.zeros(1, 1, HIDDEN), torch.zeros(1, 1, HIDDEN))
+    traced = torch.jit.trace(Project().eval(), example)
+    mlmodel = ct.convert(
+        traced,
+        inputs=[
+            ct.TensorType(
+                name="hidden_states",
+                shape=ct.EnumeratedShapes(
+                    shapes=[(1, 1, HIDDEN), (1, 4, HIDDEN)], default=(1, 1, HIDDEN)
+                ),
+            ),
+            ct.TensorType(name="residual", shape=(1, ct.RangeDim(1, 4, default=1), HIDDEN)),
+        ],
+        outputs=[ct.TensorType(name="output_hidden_states")],
+        convert_to="neuralnetwork",
+    )
+    path = OUT_DIR / "flexible_shapes.mlmodel"
+    mlmodel.save(str(path))
+    return path
+
+
+def stateful_multifunction(tmp):
+    # MLProgram with infer (1 token) and prefill (4 tokens) functions sharing a state
+    packages = {}
+    for name, batch in [("infer", 1), ("prefill", 4)]:
+        traced = torch.jit.trace(CachedProject().eval(), torch.zeros(1, batch, HIDDEN))
+        mlmodel = ct.convert(
+            traced,
+            inputs=[ct.TensorType(name="hidden_states", shape=(1, batch, HIDDEN), dtype=np.float16)],
+            outputs=[ct.TensorType(name="output_hidden_states", dtype=np.float16)],
+            states=[
+                ct.StateType(
+                    wrapped_type=ct.TensorType(shape=(1, HIDDEN), dtype=np.float16),
+                    name="kv_cache",
+                )
+            ],
+            minimum_deployment_target=ct.target.iOS18,
+        )
+        packages[name] = tmp / f"{name}.mlpackage"
+        mlmodel.save(str(packages[name]))
+
+    descriptor = ct.utils.MultiFunctionDescriptor()
+    for name, package in packages.items():
+        descriptor.add_function(str(package), src_function_name="main", target_function_name=name)
+    descriptor.default_function_name = "infer"
+    combined = tmp / "combined.mlpackage"
+    ct.utils.save_multifunction(descriptor, str(combined))
+
+    path = OUT_DIR / "stateful_multifunction.mlmodel"
+    shutil.copyfile(combined / "Data" / "com.apple.CoreML" / "model.mlmodel", path)
+    return path
+
+
+if __name__ == "__main__":
+    with tempfile.TemporaryDirectory() as tmp:
+        for path in [flexible_shapes(), stateful_multifunction(Path(tmp))]:
+            print(f"wrote {path} ({path.stat().st_size} bytes)")
@@ -0,0 +1,209 @@
+#!/usr/bin/env python3
+"""Write the small model.mlmodel fixtures used by tests/test_coreml_metadata_extraction.rs.
+
+The protobuf wire format is encoded by hand so the fixtures can be regenerated
+without coremltools or the protobuf package. Field numbers follow
+coremltools/mlmodel/format/{Model,FeatureTypes,MIL}.proto.
+
+Usage: python3 tests/fixtures/mlmodel/generate_fixtures.py
+"""
+
+from pathlib import Path
+
+OUT_DIR = Path(__file__).parent
+
+# ArrayFeatureType.ArrayDataType
+FLOAT32, FLOAT16, INT32 = 65568, 65552, 131104
+# MILSpec.DataType
+MIL_FLOAT16, MIL_INT32 = 10, 23
+
+
+def varint(value):
+    value &= (1 << 64) - 1
+    out = bytearray()
+    while value >= 0x80:
+        out.append((value & 0x7F) | 0x80)
+        value >>= 7
+    out.append(value)
+    return bytes(out)
+
+
+def int_field(field, value):
+    return varint(field << 3) + varint(value)
+
+
+def bytes_field(field, payload):
+    if isinstance(payload, str):
+        payload = payload.encode()
+    return varint(field << 3 | 2) + varint(len(payload)) + payload
+
+
+def packed(field, values):
+    return bytes_field(field, b"".join(varint(v) for v in values))
+
+
+# --- CoreML.Specification (FeatureTypes.proto / Model.proto) ---
+
+
+def array_type(shape=(), data_type=FLOAT32, enumerated=None, ranges=None):
+    body = packed(1, shape) if shape else b""
+    body += int_field(2, data_type)
+    if enumerated:
+        body += bytes_field(21, b"".join(bytes_field(1, packed(1, s)) for s in enumerated))
+    if ranges:
+        body += bytes_field(
+            31,
+            b"".join(bytes_field(1, int_field(1, lo) + int_field(2, hi)) for lo, hi in ranges),
+        )
+    return body
+
+
+def multi_array(shape=(), data_type=FLOAT32, enumerated=None, ranges=None):
+    return bytes_field(5, array_type(shape, data_type, enumerated, ranges))
+
+
+def state_feature(shape, data_type=FLOAT16):
+    # FeatureType.stateType -> StateFeatureType.arrayType
+    return bytes_field(8, bytes_field(1, array_type(shape, data_type)))
+
+
+def feature(name, feature_type):
+    return bytes_field(1, name) + bytes_field(3, feature_type)
+
+
+def description(inputs=(), outputs=(), states=(), functions=()):
+    body = b"".join(bytes_field(1, f) for f in inputs)
+    body += b"".join(bytes_field(10, f) for f in outputs)
+    body += b"".join(bytes_field(13, f) for f in states)
+    for name, f_inputs, f_outputs, f_states in functions:
+        body += bytes_field(
+            20,
+            bytes_field(1, name)
+            + b"".join(bytes_field(2, f) for f in f_inputs)
+            + b"".join(bytes_field(3, f) for f in f_outputs)
+            + b"".join(bytes_field(6, f) for f in f_states),
+        )
+    return bytes_field(2, body)
+
+
+def model(version, desc, program=b""):
+    return int_field(1, version) + desc + program
+
+
+# --- CoreML.Specification.MILSpec (MIL.proto) ---
+
+
+def tensor_type(data_type, dims):
+    # None marks an unknown dimension
+    body = int_field(1, data_type) + int_field(2, len(dims))
+    for d in dims:
+        dimension = bytes_field(2, b"") if d is None else bytes_field(1, int_field(1, d))
+        body += bytes_field(3, dimension)
+    return bytes_field(1, body)
+
+
+def state_type(wrapped):
+    return bytes_field(5, bytes_field(1, wrapped))
+
+
+def named_value(name, value_type):
+    return bytes_field(1, name) + bytes_field(2, value_type)
+
+
+def operation(op_type, outputs):
+    return bytes_field(1, op_type) + b"".join(bytes_field(3, o) for o in outputs)
+
+
+def block(outputs, operations):
+    return b"".join(bytes_field(2, o) for o in outputs) + b"".join(
+        bytes_field(3, op) for op in operations
+    )
+
+
+def mil_function(inputs, opset, blk):
+    return (
+        b"".join(bytes_field(1, i) for i in inputs)
+        + bytes_field(2, opset)
+        + bytes_field(3, bytes_field(1, opset) + bytes_field(2, blk))
+    )
+
+
+def program(functions):
+    body = int_field(1, 1)
+    for name, function in functions:
+        body += bytes_field(2, bytes_field(1, name) + bytes_field(2, function))
+    return bytes_field(502, body)
+
+
+def embeddings():
+    # Default shape plus enumerated shapes for single-token and batch inputs
+    ids = feature("input_ids", multi_array((1, 1), INT32, enumerated=[(1, 1), (1, 64)]))
+    hidden = feature("hidden_states", multi_array((1, 1, 1024), FLOAT16))
+    return model(7, description([ids], [hidden]))
+
+
+def lm_head():
+    # No default shape: the sequence dimension is a range
+    hidden = feature("hidden_states", multi_array((), FLOAT16, ranges=[(1, 1), (1, 64), (1024, 1024)]))
+    logits = feature("logits1", multi_array((1, 1, 16384), FLOAT16))
+    return model(7, description([hidden], [logits]))
+
+
+def ffn_program():
+    # Two-function MLProgram (prefill/infer) with a KV-cache state; the model
+    # description only covers the default function, as in ANEMLL chunks, and
+    # declares the state like coremltools does
+    def function(batch):
+        inputs = [
+            named_value("hidden_states", tensor_type(MIL_FLOAT16, [1, batch, 1024])),
+            named_value("position_ids", tensor_type(MIL_INT32, [batch])),
+            named_value("causal_mask", tensor_type(MIL_FLOAT16, [1, 1, batch, 512])),
+            named_value("current_pos", tensor_type(MIL_INT32, [1])),
+            named_value("model_model_kv_cache_0", state_type(tensor_type(MIL_FLOAT16, [56, 8, 512, 128]))),
+        ]
+        ops = [
+            operation("linear", [named_value("var_12", tensor_type(MIL_FLOAT16, [1, batch, 3072]))]),
+            operation("add", [named_value("output_hidden_states", tensor_type(MIL_FLOAT16, [1, batch, 1024]))]),
+        ]
+        return mil_function(inputs, "CoreML8", block(["output_hidden_states"], ops))
+
+    hidden = feature("hidden_states", multi_array((1, 1, 1024), FLOAT16))
+    out = feature("output_hidden_states", multi_array((1, 1, 1024), FLOAT16))
+    cache = feature("model_model_kv_cache_0", state_feature((56, 8, 512, 128)))
+    return model(
+        9,
+        description([hidden], [out], [cache]),
+        program([("infer", function(1)), ("prefill", function(64))]),
+    )
+
+
+def ffn_multifunction():
+    # Multifunction model whose description lists each function's IO and states
+    def io(batch):
+        return (
+            [feature("hidden_states", multi_array((1, batch, 1024), FLOAT16))],
+            [feature("output_hidden_states", multi_array((1, batch, 1024), FLOAT16))],
+            [feature("model_model_kv_cache_0", state_feature((56, 8, 512, 128)))],
+        )
+
+    functions = [("infer", *io(1)), ("prefill", *io(64))]
+    unknown_dim = tensor_type(MIL_FLOAT16, [1, None, 1024])
+    prog = program(
+        [
+            (name, mil_function([named_value("hidden_states", unknown_dim)], "CoreML8", block([], [])))
+            for name in ("infer", "prefill")
+        ]
+    )
+    return model(9, description(*io(1), functions=functions), prog)
+
+
+if __name__ == "__main__":
+    for name, build in [
+        ("embeddings", embeddings),
+        ("lm_head", lm_head),
+        ("ffn_program", ffn_program),
+        ("ffn_multifunction", ffn_multifunction),
+    ]:
+        path = OUT_DIR / f"{name}.mlmodel"
+        path.write_bytes(build())
+        print(f"wrote {path} ({path.stat().st_size} bytes)")
@@ -0,0 +1,619 @@
+//! Fixture-driven pipeline regression tests
+//!
+//! Runs `QwenModel` orchestration end to end against `MockComponent` backends that
+//! replay the tensors recorded from the Python reference pipeline. Requires the
+//! `testing` feature: `cargo test --features testing --test mock_pipeline`.
+
+use candle_core::{DType, Device, Tensor};
+use candle_coreml::qwen::FfnChunk;
+use candle_coreml::testing::{
+    typo_fixer_model_config, word_level_tokenizer, FlexPipelineFixture, MockCall, MockComponent,
+    TypoFixerFixture,
+};
+use candle_coreml::{
+    ChatSession, ChatTemplate, FinishReason, GenerationConfig, OverflowPolicy, QwenConfig,
+    QwenModel,
+};
+
+const FLEX_PIPELINE_DIR: &str = "tests/fixtures/flex_pipeline";
+const TYPO_FIXER_TENSORS: &str = "tests/fixtures/typo_fixer_tensors.json";
+const BATCH_SIZE: usize = 64;
+const HIDDEN_SIZE: usize = 1024;
+
+fn padded_ids(tokens: &[i64], len: usize) -> Tensor {
+    let mut ids = tokens.to_vec();
+    ids.resize(len, 0);
+    Tensor::from_vec(ids, (1, len), &Device::Cpu).unwrap()
+}
+
+fn positions(range: std::ops::Range<i64>) -> Tensor {
+    let values: Vec<i64> = range.collect();
+    let len = values.len();
+    Tensor::from_vec(values, (len,), &Device::Cpu).unwrap()
+}
+
+/// `[1, BATCH_SIZE, HIDDEN_SIZE]` embeddings with `row` set and every other position zero.
+fn embeddings_with_row(row: &Tensor, index: usize) -> Tensor {
+    let before = Tensor::zeros((1, index, HIDDEN_SIZE), DType::F32, &Device::Cpu).unwrap();
+    let after = Tensor::zeros(
+        (1, BATCH_SIZE - index - 1, HIDDEN_SIZE),
+        DType::F32,
+        &Device::Cpu,
+    )
+    .unwrap();
+    Tensor::cat(&[&before, row, &after], 1).unwrap()
+}
+
+fn flex_pipeline_model(fixture: &FlexPipelineFixture) -> QwenModel<MockComponent> {
+    let context_length = fixture.context_length;
+    let vocab_size = fixture.final_logits.dim(2).unwrap();
+    let last = fixture.context_pos - 1;
+    assert_eq!(fixture.input_ids[last], fixture.current_token);
+
+    let embeddings_out = embeddings_with_row(&fixture.infer_input_hidden_states, last);
+    let zeros_hidden = Tensor::zeros((1, 1, HIDDEN_SIZE), DType::F32, &Device::Cpu).unwrap();
+
+    let embeddings = MockComponent::named("qwen-embeddings", "hidden_states").then(
+        MockCall::new()
+            .expect_input(0, padded_ids(&fixture.input_ids, BATCH_SIZE))
+            .returning("hidden_states", embeddings_out.clone()),
+    );
+    let ffn_prefill = MockComponent::named("qwen-ffn-prefill", "output_hidden_states").then(
+        MockCall::new()
+            .expect_input(0, embeddings_out)
+            .expect_input(1, positions(0..BATCH_SIZE as i64))
+            .expect_input(2, fixture.causal_mask.narrow(2, 0, BATCH_SIZE).unwrap())
+            .expect_input(3, positions(0..1))
+            .returning("output_hidden_states", zeros_hidden),
+    );
+    let ffn_infer = MockComponent::named("qwen-ffn-infer", "output_hidden_states").then(
+        MockCall::new()
+            .expect_input(0, fixture.infer_input_hidden_states.clone())
+            .expect_input(1, positions(last as i64..last as i64 + 1))
+            .expect_input(2, fixture.causal_mask.narrow(2, last, 1).unwrap())
+            .expect_input(3, positions(last as i64..last as i64 + 1))
+            .returning(
+                "output_hidden_states",
+                fixture.infer_output_hidden_states.clone(),
+            ),
+    );
+    let lm_head = MockComponent::named("qwen-lm-head", "logits1").then(
+        MockCall::new()
+            .expect_input(0, fixture.infer_output_hidden_states.clone())
+            .returning("logits1", fixture.final_logits.clone()),
+    );
+
+    let words: Vec<String> = fixture
+        .input_ids
+        .iter()
+        .map(|id| format!("t{id}"))
+        .collect();
+    let vocab: Vec<(&str, u32)> = words
+        .iter()
+        .zip(&fixture.input_ids)
+        .map(|(word, &id)| (word.as_str(), id as u32))
+        .collect();
+
+    QwenModel::from_components(
+        embeddings,
+        ffn_prefill,
+        ffn_infer,
+        lm_head,
+        word_level_tokenizer(&vocab).unwrap(),
+        QwenConfig::from_model_config(typo_fixer_model_config(
+            BATCH_SIZE,
+            context_length,
+            HIDDEN_SIZE,
+            vocab_size,
+        )),
+    )
+}
+
+fn assert_script_consumed(model: &QwenModel<MockComponent>) {
+    assert_eq!(model.embeddings.remaining_calls(), 0, "embeddings");
+    assert_eq!(model.ffn_prefill.remaining_calls(), 0, "ffn_prefill");
+    assert_eq!(model.ffn_infer.remaining_calls(), 0, "ffn_infer");
+    assert_eq!(model.lm_head.remaining_calls(), 0, "lm_head");
+}
+
+#[test]
+fn test_forward_text_replays_flex_pipeline_fixture() {
+    let fixture = FlexPipelineFixture::load(FLEX_PIPELINE_DIR).unwrap();
+    let mut model = flex_pipeline_model(&fixture);
+
+    let text: Vec<String> = fixture
+        .input_ids
+        .iter()
+        .map(|id| format!("t{id}"))
+        .collect();
+    let next_token = model.forward_text(&text.join(" ")).unwrap();
+
+    assert_eq!(next_token, fixture.top_predictions[0]);
+    assert_script_consumed(&model);
+    assert_eq!(model.unified_state.as_ref().unwrap().predictions, 2);
+}
+
+#[test]
+fn test_chunked_ffn_chains_hidden_states_through_every_chunk() {
+    let fixture = FlexPipelineFixture::load(FLEX_PIPELINE_DIR).unwrap();
+    let mut model = flex_pipeline_model(&fixture);
+    let last = fixture.context_pos - 1;
+
+    // Split the FFN in two: the first chunk hands intermediate hidden states to the second
+    let prefill_mid = Tensor::ones((1, BATCH_SIZE, HIDDEN_SIZE), DType::F32, &Device::Cpu).unwrap();
+    let infer_mid = (&fixture.infer_output_hidden_states * 0.5).unwrap();
+    let zeros_hidden = Tensor::zeros((1, 1, HIDDEN_SIZE), DType::F32, &Device::Cpu).unwrap();
+    model.ffn_prefill = MockComponent::named("qwen-ffn-prefill", "output_hidden_states").then(
+        MockCall::new()
+            .expect_input(1, positions(0..BATCH_SIZE as i64))
+            .returning("output_hidden_states", prefill_mid.clone()),
+    );
+    model.ffn_infer = MockComponent::named("qwen-ffn-infer", "output_hidden_states").then(
+        MockCall::new()
+            .expect_input(0, fixture.infer_input_hidden_states.clone())
+            .returning("output_hidden_states", infer_mid.clone()),
+    );
+    let second = FfnChunk::new(
+        MockComponent::named("qwen-ffn-prefill", "output_hidden_states").then(
+            MockCall::new()
+                .expect_input(0, prefill_mid)
+                .expect_input(1, positions(0..BATCH_SIZE as i64))
+                .returning("output_hidden_states", zeros_hidden),
+        ),
+        MockComponent::named("qwen-ffn-infer", "output_hidden_states").then(
+            MockCall::new()
+                .expect_input(0, infer_mid)
+                .expect_input(1, positions(last as i64..last as i64 + 1))
+                .returning(
+                    "output_hidden_states",
+                    fixture.infer_output_hidden_states.clone(),
+                ),
+        ),
+    );
+    let mut model = model.with_ffn_chunks(vec![second]);
+
+    let text: Vec<String> = fixture
+        .input_ids
+        .iter()
+        .map(|id| format!("t{id}"))
+        .collect();
+    let next_token = model.forward_text(&text.join(" ")).unwrap();
+
+    assert_eq!(next_token, fixture.top_predictions[0]);
+    assert_script_consumed(&model);
+    let chunk = &model.ffn_chunks[0];
+    assert_eq!(chunk.prefill.remaining_calls(), 0);
+    assert_eq!(chunk.infer.remaining_calls(), 0);
+    // Each chunk keeps its own KV cache state
+    assert_eq!(model.unified_state.as_ref().unwrap().predictions, 2);
+    assert_eq!(chunk.state.as_ref().unwrap().predictions, 2);
+}
+
+#[test]
+fn test_chatpy_prefill_and_infer_replay_flex_pipeline_fixture() {
+    let fixture = FlexPipelineFixture::load(FLEX_PIPELINE_DIR).unwrap();
+    let mut model = flex_pipeline_model(&fixture);
+    model.initialize_states().unwrap();
+
+    model
+        .run_chatpy_prefill(&fixture.input_ids, fixture.context_pos)
+        .unwrap();
+    let next_token = model
+        .run_chatpy_infer(&fixture.input_ids, fixture.context_pos)
+        .unwrap();
+
+    assert_eq!(next_token, fixture.top_predictions[0]);
+    assert_script_consumed(&model);
+}
+
+#[test]
+fn test_mock_rejects_inputs_that_diverge_from_recording() {
+    let fixture = FlexPipelineFixture::load(FLEX_PIPELINE_DIR).unwrap();
+    let mut model = flex_pipeline_model(&fixture);
+    model.initialize_states().unwrap();
+
+    // Dropping the last prompt token changes the padded input_ids the embeddings receive
+    let truncated = &fixture.input_ids[..fixture.context_pos - 1];
+    let err = model
+        .run_chatpy_prefill(truncated, truncated.len())
+        .unwrap_err();
+    assert!(err.to_string().contains("differs from recording"), "{err}");
+}
+
+#[test]
+fn test_chatpy_pipeline_replays_typo_fixer_tensors() {
+    let fixture = TypoFixerFixture::load(TYPO_FIXER_TENSORS).unwrap();
+    assert_eq!(fixture.batch_size, BATCH_SIZE);
+    assert_eq!(fixture.hidden_size, HIDDEN_SIZE);
+    let last = fixture.tokens.len() - 1;
+    let last_embedding = fixture.embeddings.narrow(1, last, 1).unwrap();
+    let prefill_output = fixture.prefill_outputs[0].clone();
+
+    let embeddings = MockComponent::named("qwen-embeddings", "hidden_states").then(
+        MockCall::new()
+            .expect_input(0, padded_ids(&fixture.tokens, fixture.batch_size))
+            .returning("hidden_states", fixture.embeddings.clone()),
+    );
+    let ffn_prefill = MockComponent::named("qwen-ffn-prefill", "output_hidden_states").then(
+        MockCall::new()
+            .expect_input(0, fixture.embeddings.clone())
+            .returning("output_hidden_states", prefill_output.clone()),
+    );
+    let ffn_infer = MockComponent::named("qwen-ffn-infer", "output_hidden_states").then(
+        MockCall::new()
+            .expect_input(0, last_embedding)
+            .returning("output_hidden_states", prefill_output.clone()),
+    );
+    let lm_head = MockComponent::named("qwen-lm-head", "logits1").then(
+        MockCall::new()
+            .expect_input(0, prefill_output)
+            .returning("logits1", fixture.final_logits.clone()),
+    );
+
+    let mut model = QwenModel::from_components(
+        embeddings,
+        ffn_prefill,
+        ffn_infer,
+        lm_head,
+        word_level_tokenizer(&[]).unwrap(),
+        QwenConfig::from_model_config(typo_fixer_model_config(
+            fixture.batch_size,
+            fixture.context_length,
+            fixture.hidden_size,
+            fixture.vocab_size,
+        )),
+    );
+    model.initialize_states().unwrap();
+
+    let context_pos = fixture.tokens.len();
+    model.compute_embeddings(&fixture.tokens).unwrap();
+    model
+        .run_chatpy_prefill(&fixture.tokens, context_pos)
+        .unwrap();
+    let next_token = model
+        .run_chatpy_infer(&fixture.tokens, context_pos)
+        .unwrap();
+
+    assert_eq!(next_token, fixture.generated_token);
+    assert_script_consumed(&model);
+}
+
+#[test]
+fn test_incremental_generation_makes_one_infer_call_per_step() {
+    const BATCH: usize = 4;
+    const HIDDEN: usize = 2;
+    const VOCAB: usize = 4;
+    let tensor = |values: &[f32], shape: &[usize]| {
+        Tensor::from_vec(values.to_vec(), shape, &Device::Cpu).unwrap()
+    };
+    let one_hot = |token: usize| {
+        let mut logits = [0.0f32; VOCAB];
+        logits[token] = 10.0;
+        tensor(&logits, &[1, 1, VOCAB])
+    };
+    let position = |pos: i64| positions(pos..pos + 1);
+
+    // Prompt "a b" = [1, 2]; the model then generates 3, 1, 2
+    let prompt_embeddings = tensor(
+        &[0.1, 0.1, 0.2, 0.2, 0.0, 0.0, 0.0, 0.0],
+        &[1, BATCH, HIDDEN],
+    );
+    let embed = |token: i64| tensor(&[token as f32; HIDDEN], &[1, 1, HIDDEN]);
+    let infer_out = |step: usize| tensor(&[step as f32; HIDDEN], &[1, 1, HIDDEN]);
+
+    let embeddings = MockComponent::named("qwen-embeddings", "hidden_states")
+        .then(
+            MockCall::new()
+                .expect_input(0, padded_ids(&[1, 2], BATCH))
+                .returning("hidden_states", prompt_embeddings.clone()),
+        )
+        // Only the newly sampled token is embedded at each later step
+        .then(
+            MockCall::new()
+                .expect_input(0, Tensor::new(&[[3i64]], &Device::Cpu).unwrap())
+                .returning("hidden_states", embed(3)),
+        )
+        .then(
+            MockCall::new()
+                .expect_input(0, Tensor::new(&[[1i64]], &Device::Cpu).unwrap())
+                .returning("hidden_states", embed(1)),
+        );
+    let ffn_prefill = MockComponent::named("qwen-ffn-prefill", "output_hidden_states").then(
+        MockCall::new()
+            .expect_input(0, prompt_embeddings.clone())
+            .expect_input(1, positions(0..BATCH as i64))
+            .returning("output_hidden_states", infer_out(0)),
+    );
+    let ffn_infer = MockComponent::named("qwen-ffn-infer", "output_hidden_states")
+        .then(
+            MockCall::new()
+                .expect_input(0, prompt_embeddings.narrow(1, 1, 1).unwrap())
+                .expect_input(1, position(1))
+                .expect_input(3, position(1))
+                .returning("output_hidden_states", infer_out(1)),
+        )
+        .then(
+            MockCall::new()
+                .expect_input(0, embed(3))
+                .expect_input(1, position(2))
+                .expect_input(3, position(2))
+                .returning("output_hidden_states", infer_out(2)),
+        )
+        .then(
+            MockCall::new()
+                .expect_input(0, embed(1))
+                .expect_input(1, position(3))
+                .expect_input(3, position(3))
+                .returning("output_hidden_states", infer_out(3)),
+        );
+    let lm_head = [(1, 3), (2, 1), (3, 2)].into_iter().fold(
+        MockComponent::named("qwen-lm-head", "logits1"),
+        |lm, (step, token)| {
+            lm.then(
+                MockCall::new()
+                    .expect_input(0, infer_out(step))
+                    .returning("logits1", one_hot(token)),
+            )
+        },
+    );
+
+    let mut model = QwenModel::from_components(
+        embeddings,
+        ffn_prefill,
+        ffn_infer,
+        lm_head,
+        word_level_tokenizer(&[("a", 1), ("b", 2), ("c", 3)]).unwrap(),
+        QwenConfig::from_model_config(typo_fixer_model_config(BATCH, 16, HIDDEN, VOCAB)),
+    );
+    let config = GenerationConfig::default()
+        .with_max_new_tokens(3)
+        .with_temperature(0.0);
+
+    let output = model.generate_from_tokens(&[1, 2], config).unwrap();
+    assert_eq!(output.tokens, vec![3, 1, 2]);
+
+    assert_eq!(model.ffn_prefill.calls_made(), 1);
+    assert_eq!(model.ffn_infer.calls_made(), 3);
+    assert_script_consumed(&model);
+    // One prefill prediction plus exactly one infer prediction per generated token
+    assert_eq!(model.unified_state.as_ref().unwrap().predictions, 4);
+}
+
+#[test]
+fn test_chat_session_prefills_only_the_new_turn() {
+    const BATCH: usize = 4;
+    const HIDDEN: usize = 2;
+    const VOCAB: usize = 4;
+    let tensor = |values: &[f32], shape: &[usize]| {
+        Tensor::from_vec(values.to_vec(), shape, &Device::Cpu).unwrap()
+    };
+    let one_hot = |token: usize| {
+        let mut logits = [0.0f32; VOCAB];
+        logits[token] = 10.0;
+        tensor(&logits, &[1, 1, VOCAB])
+    };
+    let position = |pos: i64| positions(pos..pos + 1);
+    let embed = |token: i64| tensor(&[token as f32; HIDDEN], &[1, 1, HIDDEN]);
+    let infer_out = |step: usize| tensor(&[step as f32; HIDDEN], &[1, 1, HIDDEN]);
+
+    // Turn 1 renders "a b" = [1, 2] and replies "c" (3); turn 2 renders "a b c a" =
+    // [1, 2, 3, 1], of which only "c a" is new and is fed through ffn_infer
+    let prompt_embeddings = tensor(
+        &[0.1, 0.1, 0.2, 0.2, 0.0, 0.0, 0.0, 0.0],
+        &[1, BATCH, HIDDEN],
+    );
+    let embeddings = MockComponent::named("qwen-embeddings", "hidden_states")
+        .then(
+            MockCall::new()
+                .expect_input(0, padded_ids(&[1, 2], BATCH))
+                .returning("hidden_states", prompt_embeddings.clone()),
+        )
+        .then(MockCall::new().returning("hidden_states", embed(3)))
+        .then(MockCall::new().returning("hidden_states", embed(1)));
+    let ffn_prefill = MockComponent::named("qwen-ffn-prefill", "output_hidden_states")
+        .then(MockCall::new().returning("output_hidden_states", infer_out(0)));
+    let ffn_infer = MockComponent::named("qwen-ffn-infer", "output_hidden_states")
+        .then(
+            MockCall::new()
+                .expect_input(1, position(1))
+                .returning("output_hidden_states", infer_out(1)),
+        )
+        .then(
+            MockCall::new()
+                .expect_input(0, embed(3))
+                .expect_input(1, position(2))
+                .returning("output_hidden_states", infer_out(2)),
+        )
+        .then(
+            MockCall::new()
+                .expect_input(0, embed(1))
+                .expect_input(1, position(3))
+                .returning("output_hidden_states", infer_out(3)),
+        );
+    // The catch-up step for "c" needs no logits
+    let lm_head = MockComponent::named("qwen-lm-head", "logits1")
+        .then(
+            MockCall::new()
+                .expect_input(0, infer_out(1))
+                .returning("logits1", one_hot(3)),
+        )
+        .then(
+            MockCall::new()
+                .expect_input(0, infer_out(3))
+                .returning("logits1", one_hot(2)),
+        );
+
+    let mut model = QwenModel::from_components(
+        embeddings,
+        ffn_prefill,
+        ffn_infer,
+        lm_head,
+        word_level_tokenizer(&[("a", 1), ("b", 2), ("c", 3)]).unwrap(),
+        QwenConfig::from_model_config(typo_fixer_model_config(BATCH, 16, HIDDEN, VOCAB)),
+    );
+    model.chat_template =
+        Some(ChatTemplate::new("{% for m in messages %}{{ m.content }} {% endfor %}").unwrap());
+    let config = GenerationConfig::default()
+        .with_max_new_tokens(1)
+        .with_temperature(0.0);
+    let mut session = ChatSession::new(model).with_generation_config(config);
+
+    assert_eq!(session.send("a b").unwrap().text, "c");
+    assert_eq!(session.cached_tokens(), 2);
+    assert_eq!(session.send("a").unwrap().text, "b");
+    assert_eq!(session.cached_tokens(), 4);
+    assert_eq!(session.messages().len(), 4);
+
+    let model = session.into_model();
+    assert_eq!(model.ffn_prefill.calls_made(), 1);
+    assert_script_consumed(&model);
+    // The state survived both turns: one prefill plus three infer predictions
+    assert_eq!(model.unified_state.as_ref().unwrap().predictions, 4);
+}
+
+#[test]
+fn test_chat_session_reports_context_overflow() {
+    let model = QwenModel::from_components(
+        MockComponent::named("qwen-embeddings", "hidden_states"),
+        MockComponent::named("qwen-ffn-prefill", "output_hidden_states"),
+        MockComponent::named("qwen-ffn-infer", "output_hidden_states"),
+        MockComponent::named("qwen-lm-head", "logits1"),
+        word_level_tokenizer(&[("a", 1)]).unwrap(),
+        QwenConfig::from_model_config(typo_fixer_model_config(4, 8, 2, 4)),
+    );
+    let mut session = ChatSession::new(model)
+        .with_generation_config(GenerationConfig::default().with_max_new_tokens(4))
+        .with_history_truncation(false);
+    session.model_mut().chat_template =
+        Some(ChatTemplate::new("{% for m in messages %}{{ m.content }} {% endfor %}").unwrap());
+
+    let err = session.send("a a a a a").unwrap_err().to_string();
+    assert!(err.contains("does not fit the context window"), "{err}");
+    assert!(session.messages().is_empty());
+}
+
+#[test]
+fn test_chat_session_restores_truncated_history_on_error() {
+    let repeat = |name: &str, output: &str, tensor: Tensor| {
+        (0..4).fold(MockComponent::named(name, output), |component, _| {
+            component.then(MockCall::new().returning(output, tensor.clone()))
+        })
+    };
+    let hidden = Tensor::zeros((1, 4, 2), DType::F32, &Device::Cpu).unwrap();
+    let infer_out = Tensor::zeros((1, 1, 2), DType::F32, &Device::Cpu).unwrap();
+    let logits = Tensor::new(&[[[0.0f32, 0.0, 0.0, 10.0]]], &Device::Cpu).unwrap();
+    let model = QwenModel::from_components(
+        repeat("qwen-embeddings", "hidden_states", hidden),
+        repeat(
+            "qwen-ffn-prefill",
+            "output_hidden_states",
+            infer_out.clone(),
+        ),
+        repeat("qwen-ffn-infer", "output_hidden_states", infer_out),
+        repeat("qwen-lm-head", "logits1", logits),
+        word_level_tokenizer(&[("a", 1), ("b", 2), ("c", 3)]).unwrap(),
+        QwenConfig::from_model_config(typo_fixer_model_config(4, 8, 2, 4)),
+    );
+    let config = GenerationConfig::default()
+        .with_max_new_tokens(1)
+        .with_temperature(0.0);
+    let mut session = ChatSession::new(model).with_generation_config(config);
+    session.model_mut().chat_template =
+        Some(ChatTemplate::new("{% for m in messages %}{{ m.content }} {% endfor %}").unwrap());
+
+    assert_eq!(session.send("a").unwrap().text, "c");
+    let history = session.messages().to_vec();
+    assert_eq!(history.len(), 2);
+
+    // Dropping the first turn is not enough, so the send fails after truncating
+    let err = session.send("a a a a a a a a").unwrap_err().to_string();
+    assert!(err.contains("does not fit the context window"), "{err}");
+    assert_eq!(session.messages(), history.as_slice());
+}
+
+#[test]
+fn test_overflow_policy_when_generation_fills_the_context() {
+    const BATCH: usize = 4;
+    const CONTEXT: usize = 4;
+    let repeat = |name: &str, output: &str, tensor: Tensor| {
+        (0..16).fold(MockComponent::named(name, output), |component, _| {
+            component.then(MockCall::new().returning(output, tensor.clone()))
+        })
+    };
+    let model = || {
+        let hidden = Tensor::zeros((1, BATCH, 2), DType::F32, &Device::Cpu).unwrap();
+        let infer_out = Tensor::zeros((1, 1, 2), DType::F32, &Device::Cpu).unwrap();
+        let logits = Tensor::new(&[[[0.0f32, 0.0, 0.0, 10.0]]], &Device::Cpu).unwrap();
+        QwenModel::from_components(
+            repeat("qwen-embeddings", "hidden_states", hidden),
+            repeat(
+                "qwen-ffn-prefill",
+                "output_hidden_states",
+                infer_out.clone(),
+            ),
+            repeat("qwen-ffn-infer", "output_hidden_states", infer_out),
+            repeat("qwen-lm-head", "logits1", logits),
+            word_level_tokenizer(&[("a", 1), ("b", 2), ("c", 3)]).unwrap(),
+            QwenConfig::from_model_config(typo_fixer_model_config(BATCH, CONTEXT, 2, 4)),
+        )
+    };
+    let config = GenerationConfig::default()
+        .with_max_new_tokens(5)
+        .with_temperature(0.0);
+
+    // By default generation stops once the newest token no longer fits the KV cache
+    let mut strict = model();
+    let output = strict
+        .generate_from_tokens(&[1, 2], config.clone())
+        .unwrap();
+    assert_eq!(output.tokens, vec![3, 3, 3]);
+    assert_eq!(output.finish_reason, FinishReason::Length);
+    assert_eq!(strict.ffn_prefill.calls_made(), 1);
+
+    // A sliding window re-prefills the most recent tokens and keeps going
+    let mut sliding = model();
+    let config = config.with_overflow_policy(OverflowPolicy::SlidingWindow { window: 2 });
+    let output = sliding
+        .generate_from_tokens(&[1, 2], config.clone())
+        .unwrap();
+    assert_eq!(output.tokens.len(), 5);
+    assert_eq!(sliding.ffn_prefill.calls_made(), 2);
+
+    // Overlong prompts are cut to the window before the first prefill
+    let mut long_prompt = model();
+    let output = long_prompt
+        .generate_from_tokens(&[1, 2, 1, 2, 1, 2], config.with_max_new_tokens(1))
+        .unwrap();
+    assert_eq!(output.tokens, vec![3]);
+    assert_eq!(long_prompt.kv_cache_tokens, vec![1, 2]);
+}
+
+#[test]
+fn test_load_rejects_a_broken_model_chat_template() {
+    let dir = tempfile::tempdir().unwrap();
+    word_level_tokenizer(&[("a", 1)])
+        .unwrap()
+        .save(dir.path().join("tokenizer.json"), false)
+        .unwrap();
+    let mut model_config = typo_fixer_model_config(4, 8, 2, 4);
+    for (name, component) in model_config.components.iter_mut() {
+        component.file_path = Some(format!("{name}.mlmodelc"));
+    }
+    let load = || {
+        QwenModel::<MockComponent>::load_from_directory_with_backend(
+            dir.path(),
+            Some(QwenConfig::from_model_config(model_config.clone())),
+        )
+    };
+
+    // Without a template of its own the model uses the family's
+    let model = load().unwrap();
+    assert!(model.chat_template.is_some());
+
+    std::fs::write(
+        dir.path().join("tokenizer_config.json"),
+        r#"{"chat_template": "{% for message in messages %}"}"#,
+    )
+    .unwrap();
+    assert!(load().is_err());
+}
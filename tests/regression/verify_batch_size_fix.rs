@@ -3,7 +3,7 @@
 #[cfg(test)]
 mod batch_size_fix_tests {
     use candle_coreml::config_generator::shape_inference::ShapeInference;
-    use candle_coreml::model_config::{ComponentConfig, TensorConfig};
+    use candle_coreml::model_config::{ComponentConfig, TensorConfig, TensorDataType};
     use std::collections::HashMap;
 
     #[test]
@@ -18,7 +18,8 @@ mod batch_size_fix_tests {
         ffn_prefill_inputs.insert("hidden_states".to_string(), TensorConfig {
             name: "hidden_states".to_string(),
             shape: vec![128, 12, 1024],  // batch=128, seq=12, hidden=1024
-            data_type: "FLOAT16".to_string(),
+            data_type: TensorDataType::Float16,
+            flexible_shape: None,
         });
         
         let ffn_prefill = ComponentConfig {
@@ -27,6 +28,8 @@ mod batch_size_fix_tests {
             outputs: HashMap::new(),
             functions: vec!["prefill".to_string()],
             input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
         };
         components.insert("ffn_prefill".to_string(), ffn_prefill);
         
@@ -35,7 +38,8 @@ mod batch_size_fix_tests {
         ffn_infer_inputs.insert("hidden_states".to_string(), TensorConfig {
             name: "hidden_states".to_string(),
             shape: vec![1, 1, 1024],  // batch=1, seq=1, hidden=1024
-            data_type: "FLOAT16".to_string(),
+            data_type: TensorDataType::Float16,
+            flexible_shape: None,
         });
         
         let ffn_infer = ComponentConfig {
@@ -44,6 +48,8 @@ mod batch_size_fix_tests {
             outputs: HashMap::new(),
             functions: vec!["infer".to_string()],
             input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
         };
         components.insert("ffn_infer".to_string(), ffn_infer);
         
@@ -52,7 +58,8 @@ mod batch_size_fix_tests {
         embeddings_outputs.insert("hidden_states".to_string(), TensorConfig {
             name: "hidden_states".to_string(),
             shape: vec![128, 12, 1024],  // Should match prefill batch size
-            data_type: "FLOAT16".to_string(),
+            data_type: TensorDataType::Float16,
+            flexible_shape: None,
         });
         
         let embeddings = ComponentConfig {
@@ -61,6 +68,8 @@ mod batch_size_fix_tests {
             outputs: embeddings_outputs,
             functions: vec![],
             input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
         };
         components.insert("embeddings".to_string(), embeddings);
         
@@ -94,7 +103,8 @@ mod batch_size_fix_tests {
         ffn_inputs.insert("hidden_states".to_string(), TensorConfig {
             name: "hidden_states".to_string(),
             shape: vec![64, 256, 1024],
-            data_type: "FLOAT16".to_string(),
+            data_type: TensorDataType::Float16,
+            flexible_shape: None,
         });
         
         let ffn = ComponentConfig {
@@ -103,6 +113,8 @@ mod batch_size_fix_tests {
             outputs: HashMap::new(),
             functions: vec![],
             input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
         };
         components.insert("ffn".to_string(), ffn);
         
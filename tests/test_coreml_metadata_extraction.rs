@@ -1,7 +1,230 @@
 //! Direct test for CoreML metadata extraction
+//!
+//! The fixture tests decode the small `.mlmodel` specs in `tests/fixtures/mlmodel`
+//! (see `generate_fixtures.py` there) and run on any platform. Those fixtures are encoded by
+//! hand, so `test_coremltools_fixtures` also checks specs written by coremltools itself; it
+//! is ignored until `tests/fixtures/mlmodel/coremltools/generate.py` has been run.
 
+use candle_coreml::config_generator::file_discovery::ManifestSource;
+use candle_coreml::config_generator::manifest_parser::ManifestParser;
+use candle_coreml::config_generator::schema_extractor::{
+    flexible_shape_from_metadata, SchemaExtractor,
+};
 use candle_coreml::config_generator::CoreMLMetadataExtractor;
-use std::path::Path;
+use candle_coreml::model_config::{DimRange, FlexibleShape};
+use candle_coreml::TensorDataType;
+use std::path::{Path, PathBuf};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/mlmodel")
+        .join(name)
+}
+
+#[test]
+#[ignore = "needs specs from tests/fixtures/mlmodel/coremltools/generate.py (torch + coremltools)"]
+fn test_coremltools_fixtures() {
+    let flexible = fixture("coremltools/flexible_shapes.mlmodel");
+    let stateful = fixture("coremltools/stateful_multifunction.mlmodel");
+    let extractor = CoreMLMetadataExtractor::new();
+
+    let spec = extractor.extract_spec(&flexible).unwrap();
+    assert_eq!(
+        spec.inputs["hidden_states"].flexible_shape,
+        Some(FlexibleShape::Enumerated(vec![
+            vec![1, 1, 8],
+            vec![1, 4, 8]
+        ]))
+    );
+    assert!(matches!(
+        spec.inputs["residual"].flexible_shape,
+        Some(FlexibleShape::Range(_))
+    ));
+    assert!(spec.inputs["residual"].accepts_shape(&[1, 3, 8]));
+    assert!(spec.outputs.contains_key("output_hidden_states"));
+
+    let spec = extractor.extract_spec(&stateful).unwrap();
+    let mut names: Vec<&String> = spec.functions.keys().collect();
+    names.sort();
+    assert_eq!(names, ["infer", "prefill"]);
+    assert_eq!(
+        spec.functions["prefill"].0["hidden_states"].shape,
+        vec![1, 4, 8]
+    );
+    assert!(!spec.functions["infer"].0.contains_key("kv_cache"));
+    for states in spec.function_states.values() {
+        assert_eq!(states["kv_cache"].shape, vec![1, 8]);
+        assert_eq!(states["kv_cache"].data_type, TensorDataType::Float16);
+    }
+}
+
+#[test]
+fn test_fixture_multi_array_signatures() {
+    let extractor = CoreMLMetadataExtractor::new();
+
+    let (inputs, outputs) = extractor
+        .extract_tensor_signatures(&fixture("embeddings.mlmodel"))
+        .unwrap();
+    assert_eq!(inputs["input_ids"].shape, vec![1, 1]);
+    assert_eq!(inputs["input_ids"].data_type, TensorDataType::Int32);
+    assert_eq!(outputs["hidden_states"].shape, vec![1, 1, 1024]);
+    assert_eq!(outputs["hidden_states"].data_type, TensorDataType::Float16);
+
+    // No default shape: range upper bounds are reported
+    let (inputs, outputs) = extractor
+        .extract_tensor_signatures(&fixture("lm_head.mlmodel"))
+        .unwrap();
+    assert_eq!(inputs["hidden_states"].shape, vec![1, 64, 1024]);
+    assert_eq!(outputs["logits1"].shape, vec![1, 1, 16384]);
+}
+
+#[test]
+fn test_fixture_flexible_shapes_are_kept() {
+    let extractor = CoreMLMetadataExtractor::new();
+
+    let (inputs, _) = extractor
+        .extract_tensor_signatures(&fixture("embeddings.mlmodel"))
+        .unwrap();
+    let input_ids = &inputs["input_ids"];
+    assert_eq!(
+        input_ids.flexible_shape,
+        Some(FlexibleShape::Enumerated(vec![vec![1, 1], vec![1, 64]]))
+    );
+    assert_eq!(input_ids.shape_for_length(1, 5), vec![1, 64]);
+
+    let (inputs, _) = extractor
+        .extract_tensor_signatures(&fixture("lm_head.mlmodel"))
+        .unwrap();
+    let hidden_states = &inputs["hidden_states"];
+    assert!(hidden_states.accepts_shape(&[1, 5, 1024]));
+    assert_eq!(hidden_states.shape_for_length(1, 5), vec![1, 5, 1024]);
+
+    // ANEMLL metadata.json spells the same information as strings
+    let entry = serde_json::json!({
+        "name": "input_ids",
+        "dataType": "Int32",
+        "shape": "[1, 1]",
+        "shapeRange": "[[1, 1], [1, -1]]"
+    });
+    assert_eq!(
+        flexible_shape_from_metadata(&entry),
+        Some(FlexibleShape::Range(vec![
+            DimRange {
+                lower: 1,
+                upper: Some(1)
+            },
+            DimRange {
+                lower: 1,
+                upper: None
+            },
+        ]))
+    );
+}
+
+#[test]
+fn test_fixture_ml_program_functions() {
+    let extractor = CoreMLMetadataExtractor::new();
+    let (inputs, outputs, functions) = extractor
+        .extract_full_metadata(&fixture("ffn_program.mlmodel"))
+        .unwrap();
+    assert_eq!(inputs["hidden_states"].shape, vec![1, 1, 1024]);
+    assert!(outputs.contains_key("output_hidden_states"));
+
+    let mut names: Vec<&String> = functions.keys().collect();
+    names.sort();
+    assert_eq!(names, ["infer", "prefill"]);
+    let (prefill_inputs, prefill_outputs) = &functions["prefill"];
+    assert_eq!(prefill_inputs["hidden_states"].shape, vec![1, 64, 1024]);
+    assert_eq!(
+        prefill_inputs["position_ids"].data_type,
+        TensorDataType::Int32
+    );
+    // State inputs are reported as states, not inputs
+    assert!(!prefill_inputs.contains_key("model_model_kv_cache_0"));
+    // Output types come from the operation producing them
+    assert_eq!(prefill_outputs.len(), 1);
+    assert_eq!(
+        prefill_outputs["output_hidden_states"].shape,
+        vec![1, 64, 1024]
+    );
+    assert_eq!(
+        prefill_outputs["output_hidden_states"].data_type,
+        TensorDataType::Float16
+    );
+
+    // The description's state feature carries the wrapped array
+    let spec = extractor
+        .extract_spec(&fixture("ffn_program.mlmodel"))
+        .unwrap();
+    let cache = &spec.states["model_model_kv_cache_0"];
+    assert_eq!(cache.shape, vec![56, 8, 512, 128]);
+    assert_eq!(cache.data_type, TensorDataType::Float16);
+    assert_eq!(
+        spec.function_states["prefill"]["model_model_kv_cache_0"].shape,
+        vec![56, 8, 512, 128]
+    );
+}
+
+#[test]
+fn test_fixture_multifunction_description() {
+    let extractor = CoreMLMetadataExtractor::new();
+    let (_, _, functions) = extractor
+        .extract_full_metadata(&fixture("ffn_multifunction.mlmodel"))
+        .unwrap();
+    // The description's typed IO wins over the program's unknown dimensions
+    assert_eq!(
+        functions["prefill"].0["hidden_states"].shape,
+        vec![1, 64, 1024]
+    );
+    assert_eq!(
+        functions["infer"].1["output_hidden_states"].shape,
+        vec![1, 1, 1024]
+    );
+    let spec = extractor
+        .extract_spec(&fixture("ffn_multifunction.mlmodel"))
+        .unwrap();
+    assert_eq!(
+        spec.function_states["infer"]["model_model_kv_cache_0"].shape,
+        vec![56, 8, 512, 128]
+    );
+    assert_eq!(spec.states.len(), 1);
+
+    assert!(extractor
+        .extract_full_metadata(&fixture("missing.mlmodel"))
+        .is_err());
+}
+
+#[test]
+fn test_fixture_functions_become_ffn_components() {
+    let model_path = fixture("ffn_program.mlmodel");
+    let components = ManifestParser::new()
+        .parse_package_enhanced(
+            Path::new("qwen_FFN_PF_chunk_01of01.mlpackage"),
+            &ManifestSource::ModelFile(model_path),
+            &serde_json::Value::Null,
+            &SchemaExtractor::new(),
+        )
+        .unwrap();
+
+    let mut names: Vec<(&str, &[String])> = components
+        .iter()
+        .map(|(name, config)| (name.as_str(), config.functions.as_slice()))
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            ("ffn_infer", &["infer".to_string()][..]),
+            ("ffn_prefill", &["prefill".to_string()][..])
+        ]
+    );
+    for (_, config) in &components {
+        assert_eq!(
+            config.states["model_model_kv_cache_0"].shape,
+            vec![56, 8, 512, 128]
+        );
+    }
+}
 
 #[test]
 fn test_direct_metadata_extraction() {
@@ -25,7 +25,8 @@ fn create_mock_component_config(file_path: Option<String>) -> ComponentConfig {
         TensorConfig {
             name: "input".to_string(),
             shape: vec![1, 512],
-            data_type: "Float32".to_string(),
+            data_type: "Float32".into(),
+            flexible_shape: None,
         },
     );
 
@@ -35,7 +36,8 @@ fn create_mock_component_config(file_path: Option<String>) -> ComponentConfig {
         TensorConfig {
             name: "output".to_string(),
             shape: vec![1, 512, 1024],
-            data_type: "Float32".to_string(),
+            data_type: "Float32".into(),
+            flexible_shape: None,
         },
     );
 
@@ -45,6 +47,8 @@ fn create_mock_component_config(file_path: Option<String>) -> ComponentConfig {
         outputs,
         functions: vec![],
         input_order: None,
+        states: HashMap::new(),
+        chunks: Vec::new(),
     }
 }
 
@@ -56,6 +60,8 @@ fn create_empty_component_config(file_path: Option<String>) -> ComponentConfig {
         outputs: HashMap::new(), // Empty outputs - this causes the bug
         functions: vec![],
         input_order: None,
+        states: HashMap::new(),
+        chunks: Vec::new(),
     }
 }
 
@@ -1,6 +1,6 @@
 //! Regression test: infer position_ids must be length-1 even if config mistakenly reports a vector.
 
-use candle_coreml::model_config::{ComponentConfig, ModelConfig, ModelInfo, NamingConfig, ShapeConfig, TensorConfig};
+use candle_coreml::model_config::{ComponentConfig, ModelConfig, ModelInfo, NamingConfig, ShapeConfig, TensorConfig, TensorDataType};
 use candle_coreml::QwenConfig;
 use std::collections::HashMap;
 
@@ -16,6 +16,8 @@ fn misconfigured_infer_config() -> ModelConfig {
             outputs: HashMap::new(),
             functions: vec![],
             input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
         },
     );
 
@@ -28,6 +30,8 @@ fn misconfigured_infer_config() -> ModelConfig {
             outputs: HashMap::new(),
             functions: vec![],
             input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
         },
     );
 
@@ -35,12 +39,12 @@ fn misconfigured_infer_config() -> ModelConfig {
     let mut infer_inputs = HashMap::new();
     infer_inputs.insert(
         "position_ids".to_string(),
-        TensorConfig { name: "position_ids".to_string(), shape: vec![128], data_type: "INT32".to_string() },
+        TensorConfig { name: "position_ids".to_string(), shape: vec![128], data_type: TensorDataType::Int32, flexible_shape: None },
     );
     let mut infer_outputs = HashMap::new();
     infer_outputs.insert(
         "output_hidden_states".to_string(),
-        TensorConfig { name: "output_hidden_states".to_string(), shape: vec![1, 1, 1024], data_type: "FLOAT16".to_string() },
+        TensorConfig { name: "output_hidden_states".to_string(), shape: vec![1, 1, 1024], data_type: TensorDataType::Float16, flexible_shape: None },
     );
     components.insert(
         "ffn_infer".to_string(),
@@ -51,12 +55,12 @@ fn misconfigured_infer_config() -> ModelConfig {
     let mut lm_in = HashMap::new();
     lm_in.insert(
         "hidden_states".to_string(),
-        TensorConfig { name: "hidden_states".to_string(), shape: vec![1, 1, 1024], data_type: "FLOAT16".to_string() },
+        TensorConfig { name: "hidden_states".to_string(), shape: vec![1, 1, 1024], data_type: TensorDataType::Float16, flexible_shape: None },
     );
     let mut lm_out = HashMap::new();
     lm_out.insert(
         "logits".to_string(),
-        TensorConfig { name: "logits".to_string(), shape: vec![1, 1, 1000], data_type: "FLOAT32".to_string() },
+        TensorConfig { name: "logits".to_string(), shape: vec![1, 1, 1000], data_type: TensorDataType::Float32, flexible_shape: None },
     );
     components.insert(
         "lm_head".to_string(),
@@ -64,7 +68,8 @@ fn misconfigured_infer_config() -> ModelConfig {
     );
 
     ModelConfig {
-        model_info: ModelInfo { model_id: Some("test/misconfigured-infer".to_string()), path: None, model_type: "qwen".to_string(), discovered_at: None },
+        schema_version: candle_coreml::model_config::CURRENT_SCHEMA_VERSION,
+        model_info: ModelInfo { model_id: Some("test/misconfigured-infer".to_string()), path: None, model_type: "qwen".to_string(), discovered_at: None, family: None },
         shapes: ShapeConfig { batch_size: 1, context_length: 128, hidden_size: 1024, vocab_size: 1000 },
         components,
         naming: NamingConfig { embeddings_pattern: None, ffn_prefill_pattern: None, ffn_infer_pattern: None, lm_head_pattern: None },
@@ -5,7 +5,7 @@
 
 #[cfg(test)]
 mod tests {
-    use candle_coreml::model_config::{ComponentConfig, ModelConfig, ShapeConfig, TensorConfig};
+    use candle_coreml::model_config::{ComponentConfig, ModelConfig, ShapeConfig, TensorConfig, TensorDataType};
     use candle_coreml::qwen::{QwenConfig, QwenModel};
 
     use std::collections::HashMap;
@@ -38,7 +38,8 @@ mod tests {
             TensorConfig {
                 name: "input_ids".to_string(),
                 shape: vec![1, 64], // Batch processing shape
-                data_type: "INT32".to_string(),
+                data_type: TensorDataType::Int32,
+                flexible_shape: None,
             },
         );
 
@@ -50,6 +51,8 @@ mod tests {
                 outputs: HashMap::new(),
                 functions: vec!["main".to_string()],
                 input_order: None,
+                states: HashMap::new(),
+                chunks: Vec::new(),
             },
         );
 
@@ -60,7 +63,8 @@ mod tests {
             TensorConfig {
                 name: "position_ids".to_string(),
                 shape: vec![64], // Batch size for prefill
-                data_type: "INT64".to_string(),
+                data_type: TensorDataType::Int64,
+                flexible_shape: None,
             },
         );
         ffn_prefill_inputs.insert(
@@ -68,7 +72,8 @@ mod tests {
             TensorConfig {
                 name: "causal_mask".to_string(),
                 shape: vec![1, 1, 64, 512], // Batch-sized mask for prefill
-                data_type: "FLOAT32".to_string(),
+                data_type: TensorDataType::Float32,
+                flexible_shape: None,
             },
         );
 
@@ -80,6 +85,8 @@ mod tests {
                 outputs: HashMap::new(),
                 functions: vec!["prefill".to_string()],
                 input_order: None,
+                states: HashMap::new(),
+                chunks: Vec::new(),
             },
         );
 
@@ -90,7 +97,8 @@ mod tests {
             TensorConfig {
                 name: "position_ids".to_string(),
                 shape: vec![1], // Single position for infer
-                data_type: "INT64".to_string(),
+                data_type: TensorDataType::Int64,
+                flexible_shape: None,
             },
         );
         ffn_infer_inputs.insert(
@@ -98,7 +106,8 @@ mod tests {
             TensorConfig {
                 name: "causal_mask".to_string(),
                 shape: vec![1, 1, 1, 512], // Single-row mask for infer
-                data_type: "FLOAT32".to_string(),
+                data_type: TensorDataType::Float32,
+                flexible_shape: None,
             },
         );
 
@@ -110,15 +119,19 @@ mod tests {
                 outputs: HashMap::new(),
                 functions: vec!["infer".to_string()],
                 input_order: None,
+                states: HashMap::new(),
+                chunks: Vec::new(),
             },
         );
 
         ModelConfig {
+            schema_version: candle_coreml::model_config::CURRENT_SCHEMA_VERSION,
             model_info: crate::model_config::ModelInfo {
                 model_id: Some("test/infer-compatible".to_string()),
                 path: None,
                 model_type: "qwen".to_string(),
                 discovered_at: None,
+                family: None,
             },
             shapes: ShapeConfig {
                 batch_size: 64, // For prefill
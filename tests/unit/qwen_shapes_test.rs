@@ -5,7 +5,7 @@
 
 #[cfg(test)]
 mod tests {
-    use candle_coreml::model_config::{ComponentConfig, ModelConfig, ShapeConfig, TensorConfig};
+    use candle_coreml::model_config::{ComponentConfig, ModelConfig, ShapeConfig, TensorConfig, TensorDataType};
     use candle_coreml::qwen::QwenConfig;
 
     use std::collections::HashMap;
@@ -20,7 +20,8 @@ mod tests {
             TensorConfig {
                 name: "input_ids".to_string(),
                 shape: vec![1, 64], // [batch=1, seq_len=64]
-                data_type: "INT32".to_string(),
+                data_type: TensorDataType::Int32,
+                flexible_shape: None,
             },
         );
 
@@ -30,7 +31,8 @@ mod tests {
             TensorConfig {
                 name: "hidden_states".to_string(),
                 shape: vec![1, 64, 1024], // [batch=1, seq_len=64, hidden=1024]
-                data_type: "FLOAT16".to_string(),
+                data_type: TensorDataType::Float16,
+                flexible_shape: None,
             },
         );
 
@@ -42,6 +44,8 @@ mod tests {
                 outputs: embeddings_outputs,
                 functions: vec!["main".to_string()],
                 input_order: None,
+                states: HashMap::new(),
+                chunks: Vec::new(),
             },
         );
 
@@ -52,7 +56,8 @@ mod tests {
             TensorConfig {
                 name: "hidden_states".to_string(),
                 shape: vec![1, 64, 1024],
-                data_type: "FLOAT16".to_string(),
+                data_type: TensorDataType::Float16,
+                flexible_shape: None,
             },
         );
         ffn_prefill_inputs.insert(
@@ -60,7 +65,8 @@ mod tests {
             TensorConfig {
                 name: "position_ids".to_string(),
                 shape: vec![64], // Batch-sized position ids
-                data_type: "INT64".to_string(),
+                data_type: TensorDataType::Int64,
+                flexible_shape: None,
             },
         );
         ffn_prefill_inputs.insert(
@@ -68,7 +74,8 @@ mod tests {
             TensorConfig {
                 name: "causal_mask".to_string(),
                 shape: vec![1, 1, 64, 512], // [1, 1, batch_size, context_length]
-                data_type: "FLOAT32".to_string(),
+                data_type: TensorDataType::Float32,
+                flexible_shape: None,
             },
         );
         ffn_prefill_inputs.insert(
@@ -76,7 +83,8 @@ mod tests {
             TensorConfig {
                 name: "current_pos".to_string(),
                 shape: vec![1],
-                data_type: "INT64".to_string(),
+                data_type: TensorDataType::Int64,
+                flexible_shape: None,
             },
         );
 
@@ -86,7 +94,8 @@ mod tests {
             TensorConfig {
                 name: "hidden_states".to_string(),
                 shape: vec![1, 64, 1024],
-                data_type: "FLOAT16".to_string(),
+                data_type: TensorDataType::Float16,
+                flexible_shape: None,
             },
         );
 
@@ -98,6 +107,8 @@ mod tests {
                 outputs: ffn_prefill_outputs,
                 functions: vec!["prefill".to_string()],
                 input_order: None,
+                states: HashMap::new(),
+                chunks: Vec::new(),
             },
         );
 
@@ -108,7 +119,8 @@ mod tests {
             TensorConfig {
                 name: "hidden_states".to_string(),
                 shape: vec![1, 1, 1024], // Single token
-                data_type: "FLOAT16".to_string(),
+                data_type: TensorDataType::Float16,
+                flexible_shape: None,
             },
         );
 
@@ -118,7 +130,8 @@ mod tests {
             TensorConfig {
                 name: "logits".to_string(),
                 shape: vec![1, 1, 151936],
-                data_type: "FLOAT32".to_string(),
+                data_type: TensorDataType::Float32,
+                flexible_shape: None,
             },
         );
 
@@ -130,15 +143,19 @@ mod tests {
                 outputs: lm_head_outputs,
                 functions: vec!["main".to_string()],
                 input_order: None,
+                states: HashMap::new(),
+                chunks: Vec::new(),
             },
         );
 
         ModelConfig {
+            schema_version: candle_coreml::model_config::CURRENT_SCHEMA_VERSION,
             model_info: crate::model_config::ModelInfo {
                 model_id: Some("test/model".to_string()),
                 path: None,
                 model_type: "qwen".to_string(),
                 discovered_at: None,
+                family: None,
             },
             shapes: ShapeConfig {
                 batch_size: 64,
@@ -6,31 +6,31 @@
 
 #[cfg(test)]
 mod tensor_adaptation_tests {
-    use candle_coreml::model_config::{ComponentConfig, ModelConfig, ModelInfo, NamingConfig, ShapeConfig, TensorConfig};
+    use candle_coreml::model_config::{ComponentConfig, ModelConfig, ModelInfo, NamingConfig, ShapeConfig, TensorConfig, TensorDataType};
     use std::collections::HashMap;
 
     fn make_cfg(prefill_seq: usize, infer_seq: Option<usize>, hidden: usize) -> ModelConfig {
         let mut components = HashMap::new();
         // Minimal embeddings to satisfy wiring
         let mut emb_in = HashMap::new();
-        emb_in.insert("input_ids".into(), TensorConfig { name: "input_ids".into(), shape: vec![1, prefill_seq], data_type: "INT32".into() });
+        emb_in.insert("input_ids".into(), TensorConfig { name: "input_ids".into(), shape: vec![1, prefill_seq], data_type: "INT32".into(), flexible_shape: None });
         let mut emb_out = HashMap::new();
-        emb_out.insert("hidden_states".into(), TensorConfig { name: "hidden_states".into(), shape: vec![1, prefill_seq, hidden], data_type: "FLOAT16".into() });
+        emb_out.insert("hidden_states".into(), TensorConfig { name: "hidden_states".into(), shape: vec![1, prefill_seq, hidden], data_type: "FLOAT16".into(), flexible_shape: None });
         components.insert("embeddings".into(), ComponentConfig { file_path: None, inputs: emb_in, outputs: emb_out, functions: vec![], input_order: None });
 
         // FFN prefill (sequence = prefill_seq)
         let mut pre_in = HashMap::new();
-        pre_in.insert("hidden_states".into(), TensorConfig { name: "hidden_states".into(), shape: vec![1, prefill_seq, hidden], data_type: "FLOAT16".into() });
-        pre_in.insert("position_ids".into(), TensorConfig { name: "position_ids".into(), shape: vec![1, prefill_seq], data_type: "INT32".into() });
-        pre_in.insert("causal_mask".into(), TensorConfig { name: "causal_mask".into(), shape: vec![1, 1, prefill_seq.max(1), prefill_seq.max(1)], data_type: "FLOAT16".into() });
+        pre_in.insert("hidden_states".into(), TensorConfig { name: "hidden_states".into(), shape: vec![1, prefill_seq, hidden], data_type: "FLOAT16".into(), flexible_shape: None });
+        pre_in.insert("position_ids".into(), TensorConfig { name: "position_ids".into(), shape: vec![1, prefill_seq], data_type: "INT32".into(), flexible_shape: None });
+        pre_in.insert("causal_mask".into(), TensorConfig { name: "causal_mask".into(), shape: vec![1, 1, prefill_seq.max(1), prefill_seq.max(1)], data_type: "FLOAT16".into(), flexible_shape: None });
         components.insert("ffn_prefill".into(), ComponentConfig { file_path: None, inputs: pre_in, outputs: HashMap::new(), functions: vec!["prefill".into()], input_order: None });
 
         // Optional infer component
         if let Some(s) = infer_seq {
             let mut infer_in = HashMap::new();
-            infer_in.insert("hidden_states".into(), TensorConfig { name: "hidden_states".into(), shape: vec![1, s, hidden], data_type: "FLOAT16".into() });
-            infer_in.insert("position_ids".into(), TensorConfig { name: "position_ids".into(), shape: vec![1], data_type: "INT32".into() });
-            infer_in.insert("causal_mask".into(), TensorConfig { name: "causal_mask".into(), shape: vec![1, 1, s, prefill_seq.max(1)], data_type: "FLOAT16".into() });
+            infer_in.insert("hidden_states".into(), TensorConfig { name: "hidden_states".into(), shape: vec![1, s, hidden], data_type: "FLOAT16".into(), flexible_shape: None });
+            infer_in.insert("position_ids".into(), TensorConfig { name: "position_ids".into(), shape: vec![1], data_type: "INT32".into(), flexible_shape: None });
+            infer_in.insert("causal_mask".into(), TensorConfig { name: "causal_mask".into(), shape: vec![1, 1, s, prefill_seq.max(1)], data_type: "FLOAT16".into(), flexible_shape: None });
             components.insert("ffn_infer".into(), ComponentConfig { file_path: None, inputs: infer_in, outputs: HashMap::new(), functions: vec!["infer".into()], input_order: None });
         }
 
@@ -81,7 +81,7 @@ mod tensor_adaptation_tests {
 #[cfg(test)]
 mod embeddings_creation_tests {
     use candle_core::Device;
-    use candle_coreml::model_config::{ComponentConfig, ModelConfig, ModelInfo, ShapeConfig, TensorConfig};
+    use candle_coreml::model_config::{ComponentConfig, ModelConfig, ModelInfo, ShapeConfig, TensorConfig, TensorDataType};
     use candle_coreml::QwenConfig;
     use std::collections::HashMap;
 
@@ -91,13 +91,15 @@ mod embeddings_creation_tests {
         inputs.insert("input_ids".to_string(), TensorConfig {
             name: "input_ids".to_string(),
             shape: vec![1, 12],
-            data_type: "INT32".to_string(),
+            data_type: TensorDataType::Int32,
+            flexible_shape: None,
         });
         let mut outputs = HashMap::new();
         outputs.insert("hidden_states".to_string(), TensorConfig {
             name: "hidden_states".to_string(),
             shape: vec![1, 12, 1024],
-            data_type: "FLOAT16".to_string(),
+            data_type: TensorDataType::Float16,
+            flexible_shape: None,
         });
         components.insert("embeddings".to_string(), ComponentConfig {
             file_path: None,
@@ -105,6 +107,8 @@ mod embeddings_creation_tests {
             outputs,
             functions: vec![],
             input_order: None,
+            states: HashMap::new(),
+            chunks: Vec::new(),
         });
 
         ModelConfig {